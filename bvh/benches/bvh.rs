@@ -1,6 +1,6 @@
 use bvh::{aabb::Aabb, Bvh};
 use rand::{thread_rng, Rng};
-use utility::Vec3;
+use utility::{Ray, Vec3};
 
 fn main() {
     divan::main();
@@ -32,3 +32,38 @@ fn creation(bencher: divan::Bencher) {
         .with_inputs(|| random_primitives())
         .bench_refs(|v| Bvh::new(v));
 }
+
+// the single threaded build for comparison with creation
+#[divan::bench]
+fn creation_serial(bencher: divan::Bencher) {
+    bencher
+        .with_inputs(|| random_primitives())
+        .bench_refs(|v| Bvh::new_serial(v));
+}
+
+// rays from random points inside the primitives' bounds in random directions, returns the
+// number of primitives a renderer would have to intersect
+#[divan::bench]
+fn traversal(bencher: divan::Bencher) {
+    let mut rng = thread_rng();
+    let mut prims = random_primitives();
+    let bvh = Bvh::new(&mut prims);
+    let rays: Vec<Ray> = (0..10_000)
+        .map(|_| {
+            let mut v = || Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 2.0 - Vec3::ONE;
+            Ray::new(10.0 * v(), v())
+        })
+        .collect();
+    bencher
+        .counter(divan::counter::ItemsCount::new(rays.len()))
+        .bench(|| {
+            rays.iter()
+                .map(|ray| {
+                    bvh.traverse(ray)
+                        .into_iter()
+                        .map(|r| r.len())
+                        .sum::<usize>()
+                })
+                .sum::<usize>()
+        });
+}
@@ -11,6 +11,9 @@ mod split;
 
 use std::f32::EPSILON;
 
+// subtrees over at least this many primitives build their children in parallel
+const PARALLEL_THRESHOLD: usize = 4096;
+
 pub struct BoundingData {
     idx: usize,
     bounds: Aabb,
@@ -34,8 +37,20 @@ pub struct Bvh {
 }
 
 impl Bvh {
+    // the top levels are built in parallel, the tree is the same as new_serial's
     pub fn new<T: Aabound>(primitives: &mut [T]) -> Self {
+        Self::build(primitives, true)
+    }
+
+    pub fn new_serial<T: Aabound>(primitives: &mut [T]) -> Self {
+        Self::build(primitives, false)
+    }
+
+    fn build<T: Aabound>(primitives: &mut [T], parallel: bool) -> Self {
         let mut bvh = Self { nodes: Vec::new() };
+        if primitives.is_empty() {
+            return bvh;
+        }
 
         // generate bounding data & index data for primitives
         let mut prim_data = primitives
@@ -46,7 +61,11 @@ impl Bvh {
 
         let mut order = Vec::new();
 
-        bvh.construct_node(0, &mut order, &mut prim_data);
+        if parallel {
+            (bvh.nodes, order) = Self::construct_parallel(0, &mut prim_data);
+        } else {
+            bvh.construct_node(0, &mut order, &mut prim_data);
+        }
 
         utility::sort_by_indices(primitives, order);
 
@@ -59,60 +78,110 @@ impl Bvh {
         prim_order: &mut Vec<usize>,
         prim_data: &mut [BoundingData],
     ) -> usize {
-        let num_prim = prim_data.len();
+        let (bounds, mid_idx) = Self::partition(prim_data);
+
+        let node_idx = self.nodes.len();
+
+        // create parent node without children
+        self.nodes.push(Node::new(bounds, idx, prim_data.len()));
 
+        match mid_idx {
+            Some(mid_idx) => {
+                // split and recursively construct child nodes
+                let (left, right) = prim_data.split_at_mut(mid_idx);
+
+                self.nodes[node_idx].left = self.construct_node(idx, prim_order, left);
+                self.nodes[node_idx].right =
+                    self.construct_node(idx + left.len(), prim_order, right);
+            }
+            None => prim_order.extend(prim_data.iter().map(|v| v.idx)),
+        }
+
+        node_idx
+    }
+
+    // construct_node building the two children of large nodes concurrently, returns the
+    // nodes (indexed from the subtree's root) & the primitive order of the subtree
+    fn construct_parallel(idx: usize, prim_data: &mut [BoundingData]) -> (Vec<Node>, Vec<usize>) {
+        if prim_data.len() < PARALLEL_THRESHOLD {
+            let mut bvh = Self { nodes: Vec::new() };
+            let mut order = Vec::new();
+            bvh.construct_node(idx, &mut order, prim_data);
+            return (bvh.nodes, order);
+        }
+
+        let (bounds, mid_idx) = Self::partition(prim_data);
+        let mut nodes = vec![Node::new(bounds, idx, prim_data.len())];
+        let Some(mid_idx) = mid_idx else {
+            return (nodes, prim_data.iter().map(|v| v.idx).collect());
+        };
+
+        let (left, right) = prim_data.split_at_mut(mid_idx);
+        let ((left, mut order), (right, right_order)) = rayon::join(
+            || Self::construct_parallel(idx, left),
+            || Self::construct_parallel(idx + mid_idx, right),
+        );
+
+        // laid out depth first like construct_node: node, left subtree, right subtree
+        let offset = |subtree: Vec<Node>, by: usize| {
+            subtree.into_iter().map(move |mut node| {
+                if node.left != 0 {
+                    node.left += by;
+                    node.right += by;
+                }
+                node
+            })
+        };
+        let right_start = 1 + left.len();
+        nodes[0].left = 1;
+        nodes[0].right = right_start;
+        nodes.extend(offset(left, 1));
+        nodes.extend(offset(right, right_start));
+        order.extend(right_order);
+        (nodes, order)
+    }
+
+    // bounds of the primitives & where to split them (after reordering prim_data), None
+    // if they should stay in a single leaf
+    fn partition(prim_data: &mut [BoundingData]) -> (Aabb, Option<usize>) {
         let bounds = prim_data
             .iter()
             .map(|v| v.bounds)
             .reduce(Aabb::merge)
             .unwrap();
 
-        let node_idx = self.nodes.len();
+        if prim_data.len() == 1 {
+            return (bounds, None);
+        }
 
-        // create parent node without children
-        self.nodes.push(Node::new(bounds, idx, num_prim));
+        let centroid_bounds = prim_data
+            .iter()
+            .map(|v| Aabb::new(v.centroid, v.centroid))
+            .reduce(Aabb::merge)
+            .unwrap();
 
-        if num_prim == 1 {
-            prim_order.push(prim_data[0].idx);
-        } else {
-            let centroid_bounds = prim_data
-                .iter()
-                .map(|v| Aabb::new(v.centroid, v.centroid))
-                .reduce(Aabb::merge)
-                .unwrap();
-
-            // use the axis with the maximum extend to split with
-            let max_axis = utility::max_axis(&centroid_bounds.extent());
-
-            if centroid_bounds.max[max_axis] - centroid_bounds.min[max_axis] < 100.0 * EPSILON {
-                // the maximum axis is small enough that it's not worth splitting
-                for idx in prim_data.iter().map(|v| v.idx) {
-                    prim_order.push(idx)
-                }
-            } else {
-                let mid_idx = split(&bounds, &centroid_bounds, max_axis, prim_data);
-
-                if mid_idx != 0 {
-                    // split and recursively construct child nodes
-                    let (left, right) = prim_data.split_at_mut(mid_idx);
-
-                    self.nodes[node_idx].left = self.construct_node(idx, prim_order, left);
-                    self.nodes[node_idx].right =
-                        self.construct_node(idx + left.len(), prim_order, right);
-                } else {
-                    // SAH indicates that the split isn't worth it
-                    for idx in prim_data.iter().map(|v| v.idx) {
-                        prim_order.push(idx)
-                    }
-                }
-            }
+        // use the axis with the maximum extend to split with
+        let max_axis = utility::max_axis(&centroid_bounds.extent());
+
+        if centroid_bounds.max[max_axis] - centroid_bounds.min[max_axis] < 100.0 * EPSILON {
+            // the maximum axis is small enough that it's not worth splitting
+            return (bounds, None);
         }
 
-        node_idx
+        // SAH indicates that the split isn't worth it if it's 0
+        let mid_idx = split(&bounds, &centroid_bounds, max_axis, prim_data);
+        (bounds, (mid_idx != 0).then_some(mid_idx))
+    }
+
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.nodes.first().map(|node| node.bounds)
     }
 
     pub fn traverse(&self, ray: &Ray) -> Vec<Range<usize>> {
         let mut ranges = Vec::new();
+        if self.nodes.is_empty() {
+            return ranges;
+        }
 
         let mut node_stack = VecDeque::from([0]);
         while !node_stack.is_empty() {
@@ -136,6 +205,9 @@ impl Bvh {
     }
     pub fn traverse_steps(&self, ray: &Ray) -> usize {
         let mut steps = 0;
+        if self.nodes.is_empty() {
+            return steps;
+        }
 
         let mut node_stack = VecDeque::from([0]);
         while !node_stack.is_empty() {
@@ -158,7 +230,7 @@ impl Bvh {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Node {
     bounds: Aabb,
     left: usize,
@@ -178,3 +250,34 @@ impl Node {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn parallel_build() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut prims: Vec<Aabb> = (0..4 * PARALLEL_THRESHOLD)
+            .map(|_| {
+                let min = Vec3::new(
+                    rng.gen_range(-100.0..100.0),
+                    rng.gen_range(-100.0..100.0),
+                    rng.gen_range(-100.0..100.0),
+                );
+                Aabb::new(min, min + Vec3::splat(rng.gen_range(0.01..2.0)))
+            })
+            .collect();
+        let mut serial_prims = prims.clone();
+
+        let bvh = Bvh::new(&mut prims);
+        let serial = Bvh::new_serial(&mut serial_prims);
+        assert_eq!(bvh.nodes, serial.nodes);
+        assert_eq!(prims, serial_prims);
+
+        let ray = Ray::new(Vec3::new(-150.0, 0.0, 0.0), Vec3::new(1.0, 0.1, -0.05));
+        assert_eq!(bvh.traverse(&ray), serial.traverse(&ray));
+    }
+}
@@ -3,6 +3,8 @@ use crate::BoundingData;
 
 const NUM_BUCKETS: usize = 12;
 const MAX_IN_NODE: usize = 255;
+// cost of traversing a node relative to intersecting a primitive
+const TRAVERSAL_COST: f32 = 0.125;
 
 #[macro_export]
 macro_rules! partition {
@@ -41,6 +43,7 @@ impl Bucket {
         Self { count: 1, bounds }
     }
     pub fn add(&mut self, new: Aabb) {
+        self.count += 1;
         self.bounds = Aabb::merge(self.bounds, new);
     }
     pub fn merge(a: Self, b: Self) -> Self {
@@ -84,40 +87,29 @@ pub fn split(
         }
     }
 
-    // determine optimal split of buckets
-    let mut costs = [0.0; NUM_BUCKETS - 1];
-    for (i, cost) in costs.iter_mut().enumerate().take(NUM_BUCKETS - 1) {
-        let (left_bucket, right_bucket) = buckets.split_at(i);
-
-        let left = left_bucket.iter().cloned().flatten().reduce(Bucket::merge);
-
-        let (left_sa, left_count) = if let Some(bucket) = left {
-            (bucket.bounds.surface_area(), bucket.count)
-        } else {
-            (0.0, 0)
+    // determine optimal split of buckets, split i puts buckets [0, i) on the left
+    // the first & last buckets are never empty so neither side of a split is
+    let cost = |i: usize| {
+        let side = |b: &[Option<Bucket>]| {
+            b.iter()
+                .flatten()
+                .copied()
+                .reduce(Bucket::merge)
+                .map_or(0.0, |b| b.count as f32 * b.bounds.surface_area())
         };
+        let (left, right) = buckets.split_at(i);
+        TRAVERSAL_COST + (side(left) + side(right)) / bounds.surface_area()
+    };
 
-        let right = right_bucket.iter().cloned().flatten().reduce(Bucket::merge);
-
-        let (right_sa, right_count) = if let Some(bucket) = right {
-            (bucket.bounds.surface_area(), bucket.count)
-        } else {
-            (0.0, 0)
-        };
-
-        *cost = 0.125 * (left_count as f32 * left_sa + right_count as f32 * right_sa)
-            / bounds.surface_area();
-    }
-
-    let (mc_idx, min_cost) = costs
-        .into_iter()
-        .enumerate()
+    let (mc_idx, min_cost) = (1..NUM_BUCKETS)
+        .map(|i| (i, cost(i)))
         .reduce(|a, b| if a.1 < b.1 { a } else { b })
         .unwrap();
 
+    // splitting has to be cheaper than intersecting every primitive in a leaf
     if len > MAX_IN_NODE || min_cost < len as f32 {
         let closure = |node_data: &BoundingData| -> bool {
-            bucket_idx(axis, node_data, min_val, centroid_extent) <= mc_idx
+            bucket_idx(axis, node_data, min_val, centroid_extent) < mc_idx
         };
         return partition!(node_data, closure);
     }
@@ -141,3 +133,58 @@ fn split_equal(axis: usize, node_data: &mut [BoundingData]) -> usize {
     node_data[0..len].sort_by(|a, b| utility::float_cmp(a.centroid[axis], b.centroid[axis]));
     len / 2
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{aabb::Aabb, Bvh};
+    use utility::Vec3;
+
+    #[test]
+    fn sah_split() {
+        // a large cluster & a small one far apart along x, the cheapest split is the gap
+        // between them rather than the median
+        let mut prims: Vec<Aabb> = (0..90)
+            .map(|i| {
+                let x = if i < 60 { 0.01 } else { 100.0 } * i as f32;
+                let min = Vec3::new(x, (i % 7) as f32, (i % 3) as f32);
+                Aabb::new(min, min + Vec3::ONE)
+            })
+            .collect();
+        let bvh = Bvh::new(&mut prims);
+
+        let root = &bvh.nodes[0];
+        let (left, right) = (&bvh.nodes[root.left], &bvh.nodes[root.right]);
+        assert_eq!((left.num_prim, right.num_prim), (60, 30));
+        assert!(left.bounds.max.x < right.bounds.min.x);
+
+        // leaves cover every primitive once & bound them
+        let mut covered = vec![false; prims.len()];
+        for node in bvh.nodes.iter().filter(|n| n.left == 0) {
+            for i in node.prim_idx..node.prim_idx + node.num_prim {
+                assert!(!covered[i]);
+                covered[i] = true;
+                assert_eq!(Aabb::merge(node.bounds, prims[i]), node.bounds);
+            }
+        }
+        assert!(covered.into_iter().all(|c| c));
+    }
+
+    #[test]
+    fn sah_split_counts() {
+        // six boxes spread along x then fifty stacked at x = 10, the stack has to stay
+        // together as it's by far the most expensive, if each bucket counted as one
+        // primitive it'd be cheaper to split the spread boxes instead
+        let mut prims: Vec<Aabb> = (0..56)
+            .map(|i| {
+                let x = if i < 6 { i as f32 } else { 10.0 };
+                let min = Vec3::new(x, 0.0, 0.0);
+                Aabb::new(min, min + Vec3::ONE)
+            })
+            .collect();
+        let bvh = Bvh::new(&mut prims);
+
+        let root = &bvh.nodes[0];
+        let (left, right) = (&bvh.nodes[root.left], &bvh.nodes[root.right]);
+        assert_eq!((left.num_prim, right.num_prim), (6, 50));
+    }
+}
@@ -1,8 +1,12 @@
 use std::{
     cmp::Ordering,
-    ops::{Add, AddAssign, Div, DivAssign, Index, Mul, MulAssign, Neg, Sub, SubAssign},
+    ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign},
 };
 
+mod mat4;
+
+pub use mat4::Mat4;
+
 pub fn sort_by_indices<T>(vec: &mut [T], mut indices: Vec<usize>) {
     for index in 0..vec.len() {
         if indices[index] != index {
@@ -312,6 +316,36 @@ impl Vec3 {
         2.0 * self.dot(normal) * normal - *self
     }
 
+    // refracts self (pointing away from the surface) through a surface with relative
+    // index of refraction eta (behind / in front of the normal), self can be on either
+    // side of the surface, None on total internal reflection
+    #[inline]
+    pub fn refract(&self, mut normal: Self, mut eta: f32) -> Option<Self> {
+        let mut cos_i = self.dot(normal);
+        // leaving through the back of the surface
+        if cos_i < 0.0 {
+            eta = 1.0 / eta;
+            cos_i = -cos_i;
+            normal = -normal;
+        }
+        let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+        if sin2_t >= 1.0 {
+            return None;
+        }
+        let cos_t = (1.0 - sin2_t).sqrt();
+        Some(-*self / eta + (cos_i / eta - cos_t) * normal)
+    }
+
+    // self flipped if needed to be in the same hemisphere as reference
+    #[inline]
+    pub fn faceforward(self, reference: Self) -> Self {
+        if self.dot(reference) < 0.0 {
+            -self
+        } else {
+            self
+        }
+    }
+
     #[inline]
     pub fn component_min(self) -> f32 {
         self.x.min(self.y.min(self.z))
@@ -346,12 +380,50 @@ impl Vec3 {
     }
     #[inline]
     pub fn is_finite(&self) -> bool {
-        self.x.is_finite() || self.y.is_finite() || self.z.is_finite()
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
     }
     #[inline]
     pub fn hadamard(&self, other: Self) -> Self {
         Self::new(self.x * other.x, self.y * other.y, self.z * other.z)
     }
+    #[inline]
+    pub fn lerp(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+    #[inline]
+    pub fn clamp(self, min: Self, max: Self) -> Self {
+        Vec3::new(
+            self.x.clamp(min.x, max.x),
+            self.y.clamp(min.y, max.y),
+            self.z.clamp(min.z, max.z),
+        )
+    }
+    #[inline]
+    pub fn saturate(self) -> Self {
+        self.clamp(Self::ZERO, Self::ONE)
+    }
+    #[inline]
+    pub fn distance(self, other: Self) -> f32 {
+        (other - self).mag()
+    }
+    #[inline]
+    pub fn distance_sq(self, other: Self) -> f32 {
+        (other - self).mag_sq()
+    }
+    #[inline]
+    pub fn powf(self, e: f32) -> Self {
+        Vec3::new(self.x.powf(e), self.y.powf(e), self.z.powf(e))
+    }
+    // component i (x, y, z) or None past the last
+    #[inline]
+    pub fn get(&self, i: usize) -> Option<f32> {
+        match i {
+            0 => Some(self.x),
+            1 => Some(self.y),
+            2 => Some(self.z),
+            _ => None,
+        }
+    }
 }
 
 impl Vec2 {
@@ -439,6 +511,23 @@ impl Vec2 {
     pub fn hadamard(&self, other: Self) -> Self {
         Self::new(self.x * other.x, self.y * other.y)
     }
+    #[inline]
+    pub fn lerp(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+    #[inline]
+    pub fn clamp(self, min: Self, max: Self) -> Self {
+        Vec2::new(self.x.clamp(min.x, max.x), self.y.clamp(min.y, max.y))
+    }
+    // component i (x, y) or None past the last
+    #[inline]
+    pub fn get(&self, i: usize) -> Option<f32> {
+        match i {
+            0 => Some(self.x),
+            1 => Some(self.y),
+            _ => None,
+        }
+    }
 }
 
 impl_operator!(Add, add, +);
@@ -495,7 +584,163 @@ impl Index<usize> for Vec3 {
             0 => &self.x,
             1 => &self.y,
             2 => &self.z,
-            _ => unreachable!(),
+            _ => panic!("index out of bounds: Vec3 has 3 components but the index is {index}"),
         }
     }
 }
+
+impl IndexMut<usize> for Vec3 {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        match index {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            2 => &mut self.z,
+            _ => panic!("index out of bounds: Vec3 has 3 components but the index is {index}"),
+        }
+    }
+}
+
+impl Index<usize> for Vec2 {
+    type Output = f32;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        match index {
+            0 => &self.x,
+            1 => &self.y,
+            _ => panic!("index out of bounds: Vec2 has 2 components but the index is {index}"),
+        }
+    }
+}
+
+impl IndexMut<usize> for Vec2 {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        match index {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            _ => panic!("index out of bounds: Vec2 has 2 components but the index is {index}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: Vec3 = Vec3::new(-0.5, 0.25, 2.0);
+    const B: Vec3 = Vec3::new(1.5, -3.0, 0.75);
+
+    #[test]
+    fn lerp() {
+        let t = 0.3;
+        assert_eq!(A.lerp(B, 0.0), A);
+        assert_eq!(A.lerp(B, 1.0), B);
+        let expected = Vec3::new(
+            A.x + (B.x - A.x) * t,
+            A.y + (B.y - A.y) * t,
+            A.z + (B.z - A.z) * t,
+        );
+        assert_eq!(A.lerp(B, t), expected);
+    }
+
+    #[test]
+    fn clamp() {
+        let (min, max) = (Vec3::new(0.0, -1.0, 0.5), Vec3::new(1.0, 1.0, 1.0));
+        let expected = Vec3::new(
+            A.x.clamp(min.x, max.x),
+            A.y.clamp(min.y, max.y),
+            A.z.clamp(min.z, max.z),
+        );
+        assert_eq!(A.clamp(min, max), expected);
+        assert_eq!(A.clamp(min, max), Vec3::new(0.0, 0.25, 1.0));
+
+        // below, within & above the range
+        let v = Vec2::new(-2.0, 0.5);
+        assert_eq!(v.clamp(Vec2::ZERO, Vec2::ONE), Vec2::new(0.0, 0.5));
+        assert_eq!(
+            v.clamp(Vec2::new(-1.0, 0.75), Vec2::ONE),
+            Vec2::new(-1.0, 0.75)
+        );
+        assert_eq!(Vec2::new(3.0, 3.0).clamp(Vec2::ZERO, Vec2::ONE), Vec2::ONE);
+    }
+
+    #[test]
+    fn distance() {
+        assert_eq!(A.distance_sq(B), (B - A).mag_sq());
+        assert_eq!(A.distance(B), B.distance(A));
+        assert_eq!(Vec3::ZERO.distance(Vec3::new(3.0, 0.0, 4.0)), 5.0);
+        let (a, b) = (Vec2::new(1.0, 2.0), Vec2::new(3.0, -2.0));
+        assert_eq!(a.lerp(b, 0.25), Vec2::new(1.5, 1.0));
+    }
+
+    #[test]
+    fn refract() {
+        let n = Vec3::new(0.0, 0.0, 1.0);
+        let (sin_i, eta) = (0.5f32, 1.5);
+        let wo = Vec3::new(-sin_i, 0.0, (1.0 - sin_i * sin_i).sqrt());
+        let wt = wo.refract(n, eta).unwrap();
+        // snell's law: sin_i = eta * sin_t on the other side
+        assert!((wt.mag() - 1.0).abs() < 1e-6);
+        assert!(wt.z < 0.0 && wt.x > 0.0);
+        assert!((sin_i - eta * wt.x).abs() < 1e-6);
+        // flipping the normal flips the relative index and the path is reversible
+        assert_eq!(wo.refract(-n, 1.0 / eta), Some(wt));
+        let back = wt.refract(n, eta).unwrap();
+        assert!((back - wo).mag() < 1e-6);
+
+        // total internal reflection past the critical angle going into a less dense medium
+        let critical = (1.0 / eta).asin();
+        let grazing = |angle: f32| Vec3::new(angle.sin(), 0.0, -angle.cos());
+        assert!(grazing(critical + 0.01).refract(n, eta).is_none());
+        assert!(grazing(critical - 0.01).refract(n, eta).is_some());
+        // head on is unchanged
+        assert_eq!(n.refract(n, eta), Some(-n));
+    }
+
+    #[test]
+    fn faceforward() {
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        assert_eq!(n.faceforward(A), n);
+        assert_eq!(n.faceforward(B), -n);
+    }
+
+    #[test]
+    fn saturate() {
+        assert_eq!(A.saturate(), Vec3::new(0.0, 0.25, 1.0));
+        assert_eq!(B.saturate(), Vec3::new(1.0, 0.0, 0.75));
+    }
+
+    #[test]
+    fn is_finite() {
+        assert!(A.is_finite());
+        assert!(!Vec3::new(1.0, f32::INFINITY, 0.0).is_finite());
+        assert!(!Vec3::new(f32::NAN, 0.0, 0.0).is_finite());
+        assert!(!Vec3::splat(f32::NEG_INFINITY).is_finite());
+    }
+
+    #[test]
+    fn index() {
+        assert_eq!([0, 1, 2].map(|i| A[i]), [A.x, A.y, A.z]);
+        assert_eq!([0, 1, 2].map(|i| A.get(i)), [A.x, A.y, A.z].map(Some));
+        assert_eq!(A.get(3), None);
+        let mut v = A;
+        v[max_axis(&A)] = 0.0;
+        assert_eq!(v, Vec3::new(A.x, A.y, 0.0));
+
+        let mut v = Vec2::new(1.0, 2.0);
+        v[1] = 3.0;
+        assert_eq!((v[0], v.get(1), v.get(2)), (1.0, Some(3.0), None));
+    }
+
+    #[test]
+    #[should_panic(expected = "index is 3")]
+    fn index_out_of_bounds() {
+        let _ = A[3];
+    }
+
+    #[test]
+    fn powf() {
+        let v = Vec3::new(0.5, 2.0, 0.0);
+        let e = 1.0 / 2.2;
+        assert_eq!(v.powf(e), Vec3::new(v.x.powf(e), v.y.powf(e), v.z.powf(e)));
+    }
+}
@@ -0,0 +1,192 @@
+use crate::Vec3;
+use std::ops::Mul;
+
+// row major affine (or projective) transform acting on column vectors, so a * b
+// applies b first
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f32; 4]; 4],
+}
+
+impl Mat4 {
+    pub const IDENTITY: Self = Self::new([
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]);
+    #[inline]
+    pub const fn new(m: [[f32; 4]; 4]) -> Self {
+        Self { m }
+    }
+    #[inline]
+    pub const fn from_translation(t: Vec3) -> Self {
+        Self::new([
+            [1.0, 0.0, 0.0, t.x],
+            [0.0, 1.0, 0.0, t.y],
+            [0.0, 0.0, 1.0, t.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    #[inline]
+    pub const fn from_scale(s: Vec3) -> Self {
+        Self::new([
+            [s.x, 0.0, 0.0, 0.0],
+            [0.0, s.y, 0.0, 0.0],
+            [0.0, 0.0, s.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    // scales, then rotates, then translates
+    #[inline]
+    pub fn from_trs(translation: Vec3, rotation: Self, scale: Vec3) -> Self {
+        Self::from_translation(translation) * rotation * Self::from_scale(scale)
+    }
+    #[inline]
+    pub fn mul_point(&self, p: Vec3) -> Vec3 {
+        let m = &self.m;
+        let row = |r: [f32; 4]| r[0] * p.x + r[1] * p.y + r[2] * p.z + r[3];
+        let w = row(m[3]);
+        let p = Vec3::new(row(m[0]), row(m[1]), row(m[2]));
+        if w == 1.0 {
+            p
+        } else {
+            p / w
+        }
+    }
+    // ignores translation, normals need the inverse transpose (see normal_matrix)
+    #[inline]
+    pub fn mul_dir(&self, d: Vec3) -> Vec3 {
+        let row = |r: [f32; 4]| r[0] * d.x + r[1] * d.y + r[2] * d.z;
+        Vec3::new(row(self.m[0]), row(self.m[1]), row(self.m[2]))
+    }
+    #[inline]
+    pub fn transpose(&self) -> Self {
+        Self::new(std::array::from_fn(|r| {
+            std::array::from_fn(|c| self.m[c][r])
+        }))
+    }
+    // None for singular matrices
+    pub fn inverse(&self) -> Option<Self> {
+        // cofactor expansion using the 2x2 determinants of the top & bottom two rows
+        let m = &self.m;
+        let low = |a: usize, b: usize, c: usize, d: usize| m[a][c] * m[b][d] - m[a][d] * m[b][c];
+        let s = [
+            low(0, 1, 0, 1),
+            low(0, 1, 0, 2),
+            low(0, 1, 0, 3),
+            low(0, 1, 1, 2),
+            low(0, 1, 1, 3),
+            low(0, 1, 2, 3),
+        ];
+        let c = [
+            low(2, 3, 0, 1),
+            low(2, 3, 0, 2),
+            low(2, 3, 0, 3),
+            low(2, 3, 1, 2),
+            low(2, 3, 1, 3),
+            low(2, 3, 2, 3),
+        ];
+        let det = s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0];
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        let inv = 1.0 / det;
+        Some(Self::new([
+            [
+                (m[1][1] * c[5] - m[1][2] * c[4] + m[1][3] * c[3]) * inv,
+                (-m[0][1] * c[5] + m[0][2] * c[4] - m[0][3] * c[3]) * inv,
+                (m[3][1] * s[5] - m[3][2] * s[4] + m[3][3] * s[3]) * inv,
+                (-m[2][1] * s[5] + m[2][2] * s[4] - m[2][3] * s[3]) * inv,
+            ],
+            [
+                (-m[1][0] * c[5] + m[1][2] * c[2] - m[1][3] * c[1]) * inv,
+                (m[0][0] * c[5] - m[0][2] * c[2] + m[0][3] * c[1]) * inv,
+                (-m[3][0] * s[5] + m[3][2] * s[2] - m[3][3] * s[1]) * inv,
+                (m[2][0] * s[5] - m[2][2] * s[2] + m[2][3] * s[1]) * inv,
+            ],
+            [
+                (m[1][0] * c[4] - m[1][1] * c[2] + m[1][3] * c[0]) * inv,
+                (-m[0][0] * c[4] + m[0][1] * c[2] - m[0][3] * c[0]) * inv,
+                (m[3][0] * s[4] - m[3][1] * s[2] + m[3][3] * s[0]) * inv,
+                (-m[2][0] * s[4] + m[2][1] * s[2] - m[2][3] * s[0]) * inv,
+            ],
+            [
+                (-m[1][0] * c[3] + m[1][1] * c[1] - m[1][2] * c[0]) * inv,
+                (m[0][0] * c[3] - m[0][1] * c[1] + m[0][2] * c[0]) * inv,
+                (-m[3][0] * s[3] + m[3][1] * s[1] - m[3][2] * s[0]) * inv,
+                (m[2][0] * s[3] - m[2][1] * s[1] + m[2][2] * s[0]) * inv,
+            ],
+        ]))
+    }
+    // transforms normals so they stay perpendicular to transformed surfaces (with
+    // mul_dir), None for singular matrices
+    #[inline]
+    pub fn normal_matrix(&self) -> Option<Self> {
+        self.inverse().map(|m| m.transpose())
+    }
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(std::array::from_fn(|r| {
+            std::array::from_fn(|c| (0..4).map(|k| self.m[r][k] * rhs.m[k][c]).sum())
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Mat4, b: Mat4) -> bool {
+        (0..4).all(|r| (0..4).all(|c| (a.m[r][c] - b.m[r][c]).abs() < 1e-5))
+    }
+
+    #[test]
+    fn inverse() {
+        // rotation of 30 degrees about Z
+        let (s, c) = 30f32.to_radians().sin_cos();
+        let rot = Mat4::new([
+            [c, -s, 0.0, 0.0],
+            [s, c, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        let m = Mat4::from_trs(Vec3::new(1.0, -2.0, 3.0), rot, Vec3::new(2.0, 0.5, 4.0));
+        let inv = m.inverse().unwrap();
+        assert!(close(m * inv, Mat4::IDENTITY));
+        assert!(close(inv * m, Mat4::IDENTITY));
+        assert!(close(rot.inverse().unwrap(), rot.transpose()));
+        assert_eq!(m.transpose().transpose(), m);
+
+        let p = Vec3::new(0.3, 0.7, -1.1);
+        assert!((inv.mul_point(m.mul_point(p)) - p).mag() < 1e-5);
+        assert_eq!(
+            m.mul_point(Vec3::ZERO),
+            Vec3::new(1.0, -2.0, 3.0),
+            "points are translated"
+        );
+        assert_eq!(
+            Mat4::from_translation(Vec3::ONE).mul_dir(p),
+            p,
+            "directions aren't"
+        );
+        assert!(Mat4::from_scale(Vec3::new(1.0, 0.0, 1.0))
+            .inverse()
+            .is_none());
+
+        // normals stay perpendicular to transformed tangents
+        let (n, t) = (Vec3::new(0.0, 1.0, 1.0), Vec3::new(0.0, 1.0, -1.0));
+        let normal = m.normal_matrix().unwrap().mul_dir(n);
+        assert!(normal.dot(m.mul_dir(t)).abs() < 1e-5);
+    }
+}
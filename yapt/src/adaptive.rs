@@ -0,0 +1,145 @@
+use crate::prelude::*;
+
+// samples per pixel of each pass, pixels are only checked for convergence between passes
+pub const PASS_SAMPLES: u64 = 16;
+
+// per pixel statistics for --noise-threshold, the canvas already holds the sums so
+// only the sample counts & sums of squared luminance are kept
+pub struct Adaptive {
+    samples: Vec<u32>,
+    sum_sq: Vec<f32>,
+    threshold: f32,
+    // samples per pixel queued for the pixels still being sampled
+    queued: u64,
+    // splats done once the current pass is finished
+    pass_end: u64,
+}
+
+impl Adaptive {
+    #[must_use]
+    pub fn new(pixels: usize, threshold: f32) -> Self {
+        Self {
+            samples: vec![0; pixels],
+            sum_sq: vec![0.0; pixels],
+            threshold,
+            queued: 0,
+            pass_end: 0,
+        }
+    }
+    pub fn add(&mut self, idx: usize, rgb: Vec3) {
+        self.samples[idx] += 1;
+        self.sum_sq[idx] += crate::luminance(rgb).powi(2);
+    }
+    #[must_use]
+    pub fn samples(&self) -> &[u32] {
+        &self.samples
+    }
+    #[must_use]
+    pub fn pass_end(&self) -> u64 {
+        self.pass_end
+    }
+    // relative standard error of the mean luminance of a pixel with canvas value sum
+    #[must_use]
+    pub fn error(&self, idx: usize, sum: Vec3) -> f32 {
+        let n = self.samples[idx] as f32;
+        if n < 2.0 {
+            return f32::INFINITY;
+        }
+        let mean = crate::luminance(sum) / n;
+        let variance = ((self.sum_sq[idx] / n - mean * mean) * n / (n - 1.0)).max(0.0);
+        // dark pixels are compared to a floor rather than ~0
+        (variance / n).sqrt() / mean.max(0.01)
+    }
+    // samples of the first pass (over every pixel)
+    pub fn first_pass(&mut self, pixels: u64, max_samples: u64) -> u64 {
+        self.queued = PASS_SAMPLES.min(max_samples);
+        self.pass_end = pixels * self.queued;
+        self.queued
+    }
+    // samples & canvas indices of the pixels to sample next, those above the threshold
+    // with fewer than max_samples, None once every pixel is done
+    pub fn next_pass(&mut self, canvas: &[Vec3], max_samples: u64) -> Option<(u64, Vec<u64>)> {
+        let samples = PASS_SAMPLES.min(max_samples.saturating_sub(self.queued));
+        if samples == 0 {
+            return None;
+        }
+        // converged pixels don't get new samples so they stay converged
+        let pixels: Vec<u64> = (0..canvas.len())
+            .filter(|&i| self.samples[i] as u64 == self.queued)
+            .filter(|&i| self.error(i, canvas[i]) > self.threshold)
+            .map(|i| i as u64)
+            .collect();
+        if pixels.is_empty() {
+            return None;
+        }
+        self.queued += samples;
+        self.pass_end += pixels.len() as u64 * samples;
+        Some((samples, pixels))
+    }
+    // canvas scaled so normalising by splats (as if every pixel had the average number
+    // of samples) divides each pixel by its own number of samples
+    #[must_use]
+    pub fn normalised(&self, canvas: &[Vec3], splats: u64) -> Vec<Vec3> {
+        let average = splats as f32 / canvas.len() as f32;
+        canvas
+            .iter()
+            .zip(&self.samples)
+            .map(|(&v, &n)| {
+                if n == 0 {
+                    Vec3::ZERO
+                } else {
+                    v * (average / n as f32)
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn converges() {
+        // a flat pixel, a noisy one & a black one
+        let mut rng = StdRng::seed_from_u64(0);
+        let sample = |rng: &mut StdRng, i: usize| match i {
+            0 => Vec3::splat(0.5),
+            1 => Vec3::splat(rng.gen_range(0.0..2.0)),
+            _ => Vec3::ZERO,
+        };
+        let mut adaptive = Adaptive::new(3, 0.05);
+        let mut canvas = vec![Vec3::ZERO; 3];
+        let mut splats = 0;
+        let mut pixels: Vec<u64> = vec![0, 1, 2];
+        let mut samples = adaptive.first_pass(3, 256);
+        loop {
+            for _ in 0..samples {
+                for &i in &pixels {
+                    let rgb = sample(&mut rng, i as usize);
+                    canvas[i as usize] += rgb;
+                    adaptive.add(i as usize, rgb);
+                    splats += 1;
+                }
+            }
+            assert_eq!(splats, adaptive.pass_end());
+            match adaptive.next_pass(&canvas, 256) {
+                Some(next) => (samples, pixels) = next,
+                None => break,
+            }
+            assert_eq!(pixels, [1]);
+        }
+
+        let spp = adaptive.samples();
+        assert_eq!([spp[0], spp[2]], [PASS_SAMPLES as u32; 2]);
+        assert!(spp[1] > PASS_SAMPLES as u32 && spp[1] <= 256);
+        assert!(adaptive.error(1, canvas[1]) <= 0.05 || spp[1] == 256);
+
+        // each pixel is divided by its own sample count
+        let normalised = adaptive.normalised(&canvas, splats);
+        let mult = 3.0 / splats as f32;
+        assert!((normalised[0] * mult - Vec3::splat(0.5)).mag() < 1e-5);
+        assert!((normalised[1] * mult - canvas[1] / spp[1] as f32).mag() < 1e-5);
+    }
+}
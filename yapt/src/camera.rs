@@ -23,9 +23,78 @@ pub const PLACEHOLDER: Cam = Cam {
     },
     width: 1024,
     height: 1024,
+    hfov: 60.0,
+    aperture: 0.0,
+    focus_dist: 1.0,
+    projection: Projection::Perspective,
+    ortho_width: 1.1547006,
 };
 
-#[derive(Debug)]
+#[derive(clap::ValueEnum, Copy, Clone, Default, Debug, PartialEq)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    // parallel rays along the forward axis from a plane ortho_width wide
+    #[value(name = "ortho")]
+    Orthographic,
+    // full sphere around the origin with longitude along u and latitude along v, the
+    // centre of the image looks along the forward axis
+    Equirectangular,
+}
+
+impl fmt::Display for Projection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Perspective => "perspective",
+            Self::Orthographic => "ortho",
+            Self::Equirectangular => "equirectangular",
+        };
+        write!(f, "{s}")
+    }
+}
+
+// order the rotations of euler angles are applied in (XYZ matches blender's default)
+#[derive(clap::ValueEnum, Copy, Clone, Default, Debug, PartialEq)]
+pub enum EulerOrder {
+    #[default]
+    Xyz,
+    Xzy,
+    Yxz,
+    Yzx,
+    Zxy,
+    Zyx,
+}
+
+impl fmt::Display for EulerOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Xyz => "xyz",
+            Self::Xzy => "xzy",
+            Self::Yxz => "yxz",
+            Self::Yzx => "yzx",
+            Self::Zxy => "zxy",
+            Self::Zyx => "zyx",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl EulerOrder {
+    // axes in the order they are applied
+    #[must_use]
+    const fn axes(self) -> [usize; 3] {
+        match self {
+            Self::Xyz => [0, 1, 2],
+            Self::Xzy => [0, 2, 1],
+            Self::Yxz => [1, 0, 2],
+            Self::Yzx => [1, 2, 0],
+            Self::Zxy => [2, 0, 1],
+            Self::Zyx => [2, 1, 0],
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Cam {
     pub lower_left: Vec3,
     pub up: Vec3,
@@ -33,6 +102,15 @@ pub struct Cam {
     pub origin: Vec3,
     width: u32,
     height: u32,
+    // horizontal field of view in degrees
+    pub hfov: f32,
+    // lens diameter, 0 is a pinhole camera (everything in focus)
+    pub aperture: f32,
+    // distance along the forward axis of the plane in focus
+    pub focus_dist: f32,
+    pub projection: Projection,
+    // width of the image in world units for orthographic cameras
+    pub ortho_width: f32,
 }
 
 impl Cam {
@@ -40,8 +118,21 @@ impl Cam {
     // match blender default where default is up = Y, forward = -Z (still in space with Z up)
     #[must_use]
     pub fn new_rot(
+        origin: Vec3,
+        rotation: Vec3,
+        hfov: f32,
+        render_settings: &RenderSettings,
+        degrees: bool,
+    ) -> Self {
+        let q = Quaternion::from_euler(rotation, degrees);
+        Self::new_quat(origin, q, hfov, render_settings)
+    }
+    // same as new_rot but with the rotations applied in the given order
+    #[must_use]
+    pub fn new_euler(
         origin: Vec3,
         mut rotation: Vec3,
+        order: EulerOrder,
         hfov: f32,
         render_settings: &RenderSettings,
         degrees: bool,
@@ -50,18 +141,13 @@ impl Cam {
             rotation *= std::f32::consts::PI / 180.0;
         }
 
-        rotation *= 0.5;
-
-        let (sx, cx) = rotation.x.sin_cos();
-        let (sy, cy) = rotation.y.sin_cos();
-        let (sz, cz) = rotation.z.sin_cos();
-
-        let q = Quaternion::new(
-            cx * cy * cz + sx * sy * sz,
-            sx * cy * cz - cx * sy * sz,
-            cx * sy * cz + sx * cy * sz,
-            cx * cy * sz - sx * sy * cz,
-        );
+        let mut q = Quaternion::new(1.0, 0.0, 0.0, 0.0);
+        for axis in order.axes() {
+            let (s, c) = (0.5 * rotation[axis]).sin_cos();
+            let mut v = Vec3::ZERO;
+            v[axis] = s;
+            q = Quaternion::new(c, v.x, v.y, v.z).hamilton(q);
+        }
 
         Self::new_quat(origin, q, hfov, render_settings)
     }
@@ -84,8 +170,7 @@ impl Cam {
         let forward: Quaternion = Vec3::new(0.0, 0.0, -1.0).into();
         let forward = q.hamilton(forward).hamilton(qp).xyz();
 
-        let aspect_ratio =
-            u32::from(render_settings.width) as f32 / u32::from(render_settings.height) as f32;
+        let aspect_ratio = render_settings.aspect_ratio();
         let right_mag = 2.0 * (0.5 * hfov.to_radians()).tan();
         let up_mag = right_mag / aspect_ratio;
 
@@ -104,6 +189,11 @@ impl Cam {
             origin,
             width: render_settings.width.into(),
             height: render_settings.height.into(),
+            hfov,
+            aperture: 0.0,
+            focus_dist: 1.0,
+            projection: Projection::Perspective,
+            ortho_width: right_mag,
         }
     }
     #[must_use]
@@ -113,12 +203,12 @@ impl Cam {
         mut up: Vec3,
         hfov: f32,
         focus_dist: f32,
+        roll: f32,
         render_settings: &RenderSettings,
     ) -> Self {
         let forward = (look_at - origin).normalised();
         up.normalise();
-        let aspect_ratio =
-            u32::from(render_settings.width) as f32 / u32::from(render_settings.height) as f32;
+        let aspect_ratio = render_settings.aspect_ratio();
 
         let right_mag = focus_dist * 2.0 * (0.5 * hfov.to_radians()).tan();
         let up_mag = right_mag / aspect_ratio;
@@ -138,45 +228,521 @@ impl Cam {
             origin,
             width: render_settings.width.into(),
             height: render_settings.height.into(),
+            hfov,
+            aperture: 0.0,
+            focus_dist,
+            projection: Projection::Perspective,
+            ortho_width: right_mag,
+        }
+        .rolled(roll)
+    }
+    // unit vector through the centre of the (full) image
+    #[must_use]
+    fn forward(&self) -> Vec3 {
+        self.up.cross(self.right).normalised()
+    }
+    // unit right, up & forward axes
+    #[must_use]
+    pub fn basis(&self) -> [Vec3; 3] {
+        [
+            self.right.normalised(),
+            self.up.normalised(),
+            self.forward(),
+        ]
+    }
+    // the same camera (fov, lens & projection) moved to origin looking along forward
+    #[must_use]
+    pub fn reoriented(
+        &self,
+        origin: Vec3,
+        forward: Vec3,
+        up: Vec3,
+        render_settings: &RenderSettings,
+    ) -> Self {
+        Self {
+            aperture: self.aperture,
+            focus_dist: self.focus_dist,
+            projection: self.projection,
+            ortho_width: self.ortho_width,
+            ..Self::new(
+                origin,
+                origin + forward,
+                up,
+                self.hfov,
+                1.0,
+                0.0,
+                render_settings,
+            )
+        }
+    }
+    // blender style XYZ euler angles in degrees (as taken by --cam-rotation)
+    // the rotation takes the default camera (up = Y, forward = -Z) to this one
+    #[must_use]
+    pub fn euler_xyz(&self) -> Vec3 {
+        let [right, up, forward] = self.basis();
+        // columns of the rotation matrix R = Rz * Ry * Rx
+        let back = -forward;
+        let cy = right.x.hypot(right.y);
+        let (x, z) = if cy > 1e-6 {
+            (up.z.atan2(back.z), right.y.atan2(right.x))
+        } else {
+            // gimbal lock, only x + z is defined
+            ((-back.y).atan2(up.y), 0.0)
+        };
+        let y = (-right.z).atan2(cy);
+        Vec3::new(x, y, z) * (180.0 / std::f32::consts::PI)
+    }
+    // camera block of the form "cam.0": { "pos": [...], "rot": [...], "hfov": ... }
+    // pos, rot & hfov map to --cam-origin, --cam-rotation & --cam-fov
+    #[must_use]
+    pub fn export_json(&self, idx: usize) -> serde_json::Value {
+        let rot = self.euler_xyz();
+        serde_json::json!({
+            format!("cam.{idx}"): {
+                "pos": [self.origin.x, self.origin.y, self.origin.z],
+                "rot": [rot.x, rot.y, rot.z],
+                "hfov": self.hfov,
+            }
+        })
+    }
+    // rotates the camera about the forward axis by roll degrees (right hand rule
+    // about forward, so up tilts towards right and the image turns anticlockwise)
+    #[must_use]
+    pub fn rolled(mut self, roll: f32) -> Self {
+        if roll == 0.0 {
+            return self;
         }
+        let axis = self.forward();
+        let (sin, cos) = roll.to_radians().sin_cos();
+        // rodrigues' rotation formula
+        let rotate = |v: Vec3| v * cos + axis.cross(v) * sin + axis * axis.dot(v) * (1.0 - cos);
+        self.lower_left = self.origin + rotate(self.lower_left - self.origin);
+        self.right = rotate(self.right);
+        self.up = rotate(self.up);
+        self
     }
+    // replace the scene's camera transform/fov/lens/projection with those from the
+    // render settings
     #[must_use]
-    pub fn get_ray(&self, i: u64, rng: &mut impl MinRng) -> ([f32; 2], Ray) {
+    pub fn with_overrides(self, render_settings: &RenderSettings) -> Self {
+        let rs = render_settings;
+        let aperture = rs.cam_aperture.unwrap_or(self.aperture);
+        let focus_dist = rs.cam_focus_dist.unwrap_or(self.focus_dist);
+        let projection = rs.cam_projection.unwrap_or(self.projection);
+        // a perspective camera made orthographic keeps the width of its image plane
+        let ortho_width = rs
+            .cam_ortho_width
+            .or((self.projection == Projection::Orthographic).then_some(self.ortho_width));
+        let mut cam = self.with_transform_overrides(rs);
+        cam.aperture = aperture;
+        cam.focus_dist = focus_dist;
+        cam.projection = projection;
+        if let Some(width) = ortho_width {
+            cam.ortho_width = width;
+        }
+        cam
+    }
+    #[must_use]
+    fn with_transform_overrides(self, rs: &RenderSettings) -> Self {
+        if rs.cam_origin.is_none()
+            && rs.cam_rotation.is_none()
+            && rs.cam_fov.is_none()
+            && rs.cam_roll == 0.0
+        {
+            return self;
+        }
+        let vec = |v: &[f32]| Vec3::new(v[0], v[1], v[2]);
+        let origin = rs.cam_origin.as_deref().map_or(self.origin, vec);
+        let hfov = rs.cam_fov.unwrap_or(self.hfov);
+        match rs.cam_rotation.as_deref() {
+            Some(rotation) => Self::new_euler(
+                origin,
+                vec(rotation),
+                rs.cam_euler_order,
+                hfov,
+                rs,
+                !rs.cam_radians,
+            )
+            .rolled(rs.cam_roll),
+            // keep the scene's orientation
+            None => {
+                let forward = self.forward();
+                Self::new(
+                    origin,
+                    origin + forward,
+                    self.up,
+                    hfov,
+                    1.0,
+                    rs.cam_roll,
+                    rs,
+                )
+            }
+        }
+    }
+    // without jitter the ray goes through the pixel centre (aliased but deterministic)
+    #[must_use]
+    pub fn get_ray(&self, i: u64, jitter: bool, rng: &mut impl MinRng) -> ([f32; 2], Ray) {
+        let jitter = if jitter {
+            [rng.gen(), rng.gen()]
+        } else {
+            [0.5; 2]
+        };
+        let lens = self.sample_lens(rng);
+        self.get_lens_ray(i, jitter, lens)
+    }
+    // uniform sample of the unit square for get_lens_ray, pinhole cameras don't
+    // consume any random numbers
+    #[must_use]
+    pub fn sample_lens(&self, rng: &mut impl MinRng) -> [f32; 2] {
+        if self.aperture == 0.0 {
+            return [0.5; 2];
+        }
+        [rng.gen(), rng.gen()]
+    }
+    // ray through pixel i offset by jitter (in pixels) from its corner, from the centre of
+    // the lens
+    #[must_use]
+    pub fn get_jittered_ray(&self, i: u64, jitter: [f32; 2]) -> ([f32; 2], Ray) {
+        self.get_lens_ray(i, jitter, [0.5; 2])
+    }
+    // same as get_jittered_ray but from the point on the lens given by a sample of the
+    // unit square ([0.5, 0.5] is the centre)
+    #[must_use]
+    pub fn get_lens_ray(&self, i: u64, jitter: [f32; 2], lens: [f32; 2]) -> ([f32; 2], Ray) {
         let (u, v) = (i % self.width as u64, i / self.width as u64);
         let (u, v) = (
-            (u as f32 + rng.gen()) / self.width as f32,
-            (v as f32 + rng.gen()) / self.height as f32,
+            (u as f32 + jitter[0]) / self.width as f32,
+            (v as f32 + jitter[1]) / self.height as f32,
         );
-
-        (
-            [u, v],
-            Ray::new(
-                self.origin,
-                self.lower_left + self.right * u + self.up * (1.0 - v) - self.origin,
-            ),
-        )
+        ([u, v], self.ray(u, v, lens))
     }
     #[must_use]
     pub fn get_centre_ray(&self, i: u64) -> Ray {
-        let (u, v) = (i % self.width as u64, i / self.width as u64);
-        let (u, v) = (
-            (u as f32 + 0.5) / self.width as f32,
-            (v as f32 + 0.5) / self.height as f32,
-        );
-        Ray::new(
-            self.origin,
-            self.lower_left + self.right * u + self.up * (1.0 - v) - self.origin,
-        )
+        self.get_jittered_ray(i, [0.5; 2]).1
     }
     #[must_use]
     pub fn get_random_ray(&self, rng: &mut impl MinRng) -> ([f32; 2], Ray) {
         let (u, v) = (rng.gen(), rng.gen());
-        (
-            [u, v],
-            Ray::new(
-                self.origin,
-                self.lower_left + self.right * u + self.up * (1.0 - v) - self.origin,
-            ),
-        )
+        let lens = self.sample_lens(rng);
+        ([u, v], self.ray(u, v, lens))
+    }
+    #[must_use]
+    fn ray(&self, u: f32, v: f32, lens: [f32; 2]) -> Ray {
+        if self.projection == Projection::Equirectangular {
+            let (sin_theta, cos_theta) = (PI * v).sin_cos();
+            let (sin_phi, cos_phi) = (2.0 * PI * u).sin_cos();
+            let local = Vec3::from_spherical(sin_theta, cos_theta, sin_phi, cos_phi);
+            // local +Z is up and phi = PI (u = 0.5) is forward
+            let dir = -local.x * self.forward() - local.y * self.right.normalised()
+                + local.z * self.up.normalised();
+            return Ray::new(self.origin, dir);
+        }
+        let dir = self.lower_left + self.right * u + self.up * (1.0 - v) - self.origin;
+        if self.projection == Projection::Orthographic {
+            // the offset on the image plane scaled from its width to ortho_width
+            let forward = self.forward();
+            let dist = dir.dot(forward);
+            let scale = self.ortho_width / (2.0 * dist * (0.5 * self.hfov.to_radians()).tan());
+            return Ray::new(self.origin + (dir - forward * dist) * scale, forward);
+        }
+        if self.aperture == 0.0 {
+            return Ray::new(self.origin, dir);
+        }
+        // the pinhole ray's point on the focal plane stays sharp from anywhere on the lens
+        let focus = dir * (self.focus_dist / dir.dot(self.forward()));
+        let [x, y] = concentric_disc_sampling(lens);
+        let offset = 0.5 * self.aperture * (x * self.right.normalised() + y * self.up.normalised());
+        Ray::new(self.origin + offset, focus - offset)
+    }
+}
+
+// maps the unit square to the unit disc keeping relative areas (Shirley & Chiu 1997)
+#[must_use]
+pub fn concentric_disc_sampling(u: [f32; 2]) -> [f32; 2] {
+    let (a, b) = (2.0 * u[0] - 1.0, 2.0 * u[1] - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return [0.0; 2];
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, FRAC_PI_4 * (b / a))
+    } else {
+        (b, FRAC_PI_2 - FRAC_PI_4 * (a / b))
+    };
+    [r * theta.cos(), r * theta.sin()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_aspect() {
+        // full horizontal & vertical field of view in degrees
+        let coverage = |cam: &Cam| {
+            let angle = |v: Vec3| 2.0 * (0.5 * v.mag() / cam.focus_dist).atan().to_degrees();
+            (angle(cam.right), angle(cam.up))
+        };
+        let square_rs = RenderSettings::default();
+        let wide_rs = RenderSettings {
+            pixel_aspect: 2.0,
+            ..Default::default()
+        };
+        let new = |hfov, rs| Cam::new_rot(Vec3::ZERO, Vec3::ZERO, hfov, rs, true);
+        // tan of half an angle in degrees
+        let tan = |deg: f32| (0.5 * deg.to_radians()).tan();
+
+        // the horizontal field of view is kept so twice as wide pixels see half as much
+        // vertically
+        let (square, wide) = (
+            coverage(&new(60.0, &square_rs)),
+            coverage(&new(60.0, &wide_rs)),
+        );
+        assert!((square.0 - 60.0).abs() < 1e-3 && (wide.0 - 60.0).abs() < 1e-3);
+        assert!((tan(square.1) - 2.0 * tan(wide.1)).abs() < 1e-5);
+
+        // cameras with a vertical field of view (the hfov derived from the displayed
+        // aspect ratio) keep their vertical coverage and are stretched horizontally
+        let vfov = 40.0;
+        let hfov = |rs: &RenderSettings| 2.0 * (tan(vfov) * rs.aspect_ratio()).atan().to_degrees();
+        let square = coverage(&new(hfov(&square_rs), &square_rs));
+        let wide = coverage(&new(hfov(&wide_rs), &wide_rs));
+        assert!((square.1 - vfov).abs() < 1e-3 && (wide.1 - vfov).abs() < 1e-3);
+        assert!((tan(wide.0) - 2.0 * tan(square.0)).abs() < 1e-5);
+        assert!(wide.0 > square.0);
+    }
+
+    #[test]
+    fn no_jitter() {
+        let rs = RenderSettings::default();
+        let cam = Cam::new(Vec3::ZERO, -Vec3::Z, Vec3::Y, 60.0, 1.0, 0.0, &rs);
+        let mut rng = rand::thread_rng();
+        let (uv, ray) = cam.get_ray(1234, false, &mut rng);
+        for _ in 0..8 {
+            let (other_uv, other) = cam.get_ray(1234, false, &mut rng);
+            assert_eq!(uv, other_uv);
+            assert_eq!((ray.origin, ray.dir), (other.origin, other.dir));
+        }
+        let centre = cam.get_centre_ray(1234);
+        assert_eq!((ray.origin, ray.dir), (centre.origin, centre.dir));
+
+        let (jittered, _) = cam.get_ray(1234, true, &mut rng);
+        assert_ne!(jittered, uv);
+    }
+
+    #[test]
+    fn roll() {
+        let rs = RenderSettings::default();
+        let new = |roll| Cam::new(Vec3::ZERO, -Vec3::Z, Vec3::Y, 60.0, 1.0, roll, &rs);
+        let (level, dutch) = (new(0.0), new(45.0));
+        // direction through an offset (in units of half the image) from the centre
+        let dir = |cam: &Cam, x: f32, y: f32| {
+            let centre = cam.lower_left + 0.5 * cam.right + 0.5 * cam.up;
+            (centre + 0.5 * x * cam.right + 0.5 * y * cam.up - cam.origin).normalised()
+        };
+
+        assert!((dir(&dutch, 0.0, 0.0) - dir(&level, 0.0, 0.0)).mag() < 1e-5);
+        let (s, c) = 45f32.to_radians().sin_cos();
+        for (x, y) in [(1.0, 0.0), (0.0, 1.0), (0.3, -0.7)] {
+            // what the level camera sees at R(-45)p the rolled camera sees at p
+            // (the image turns anticlockwise by 45 degrees)
+            let rotated = dir(&level, x * c + y * s, y * c - x * s);
+            assert!((dir(&dutch, x, y) - rotated).mag() < 1e-5);
+        }
+        // camera dips to the right so the horizon rises to the right
+        assert!(dutch.right.y < 0.0);
+
+        let rs = RenderSettings {
+            cam_roll: 45.0,
+            ..Default::default()
+        };
+        let cam = new(0.0).with_overrides(&rs);
+        assert!((cam.right - dutch.right).mag() < 1e-5 && (cam.up - dutch.up).mag() < 1e-5);
+    }
+
+    #[test]
+    fn reoriented() {
+        let rs = RenderSettings::default();
+        let cam = Cam::new_rot(Vec3::ONE, Vec3::new(63.5, 10.0, 66.2), 45.0, &rs, true);
+        let [right, up, forward] = cam.basis();
+        assert!(right.dot(up).abs() < 1e-5 && right.dot(forward).abs() < 1e-5);
+
+        // rebuilding from its own pose is a no-op
+        let same = cam.reoriented(cam.origin, forward, up, &rs);
+        assert!((same.lower_left - cam.lower_left).mag() < 1e-5);
+        assert!((same.right - cam.right).mag() < 1e-5 && (same.up - cam.up).mag() < 1e-5);
+
+        let moved = cam.reoriented(Vec3::ZERO, -Vec3::Z, Vec3::Y, &rs);
+        assert_eq!(moved.origin, Vec3::ZERO);
+        assert!((moved.basis()[2] + Vec3::Z).mag() < 1e-5);
+        assert_eq!(moved.hfov, cam.hfov);
+    }
+
+    #[test]
+    fn export_round_trip() {
+        let rs = RenderSettings::default();
+        let rot = Vec3::new(63.5, 10.0, 66.2);
+        let euler = Cam::new_rot(Vec3::ZERO, rot, 60.0, &rs, true).euler_xyz();
+        assert!((euler - rot).mag() < 1e-3, "{euler}");
+
+        let cam = Cam::new(
+            Vec3::new(1.0, -2.0, 3.0),
+            Vec3::new(0.0, 1.0, 0.5),
+            Vec3::Z,
+            50.0,
+            1.0,
+            20.0,
+            &rs,
+        );
+        let json = cam.export_json(0);
+        let block = &json["cam.0"];
+        let vec = |key: &str| -> Vec<f32> {
+            let v = block[key].as_array().unwrap();
+            v.iter().map(|v| v.as_f64().unwrap() as f32).collect()
+        };
+        let rs = RenderSettings {
+            cam_origin: Some(vec("pos")),
+            cam_rotation: Some(vec("rot")),
+            cam_fov: Some(block["hfov"].as_f64().unwrap() as f32),
+            ..Default::default()
+        };
+        let imported = crate::camera::PLACEHOLDER.with_overrides(&rs);
+        assert_eq!(imported.origin, cam.origin);
+        assert!((imported.lower_left - cam.lower_left).mag() < 1e-4);
+        assert!((imported.right - cam.right).mag() < 1e-4);
+        assert!((imported.up - cam.up).mag() < 1e-4);
+    }
+
+    #[test]
+    fn depth_of_field() {
+        let rs = RenderSettings::default();
+        let pinhole = Cam::new(Vec3::ZERO, -Vec3::Z, Vec3::Y, 60.0, 1.0, 0.0, &rs);
+        let rs = RenderSettings {
+            cam_aperture: Some(0.5),
+            cam_focus_dist: Some(4.0),
+            ..Default::default()
+        };
+        let lens = pinhole.clone().with_overrides(&rs);
+        let mut rng = rand::thread_rng();
+
+        // pinhole rays don't depend on the lens sample
+        let (_, a) = pinhole.get_jittered_ray(1234, [0.3, 0.7]);
+        let (_, b) = pinhole.get_lens_ray(1234, [0.3, 0.7], [rng.gen(), rng.gen()]);
+        assert_eq!((a.origin, a.dir), (b.origin, b.dir));
+
+        // rays through a pixel meet on the focal plane but not in front of it
+        let at = |ray: &Ray, z: f32| ray.origin + ray.dir * ((z - ray.origin.z) / ray.dir.z);
+        let (_, centre) = lens.get_jittered_ray(1234, [0.5; 2]);
+        let (mut focal, mut near) = (0.0f32, 0.0f32);
+        for _ in 0..100 {
+            let l = [rng.gen(), rng.gen()];
+            let (_, ray) = lens.get_lens_ray(1234, [0.5; 2], l);
+            assert!(ray.origin.mag() <= 0.25 + 1e-5);
+            focal = focal.max((at(&ray, -4.0) - at(&centre, -4.0)).mag());
+            near = near.max((at(&ray, -2.0) - at(&centre, -2.0)).mag());
+        }
+        assert!(focal < 1e-4 && near > 0.05, "{focal} {near}");
+
+        for _ in 0..100 {
+            let [x, y] = concentric_disc_sampling([rng.gen(), rng.gen()]);
+            assert!(x * x + y * y <= 1.0 + 1e-5);
+        }
+        assert_eq!(concentric_disc_sampling([1.0, 0.5]), [1.0, 0.0]);
+    }
+
+    #[test]
+    fn orthographic() {
+        let rs = RenderSettings {
+            cam_projection: Some(Projection::Orthographic),
+            cam_ortho_width: Some(4.0),
+            width: std::num::NonZeroU32::new(8).unwrap(),
+            height: std::num::NonZeroU32::new(4).unwrap(),
+            ..Default::default()
+        };
+        let cam = Cam::new(Vec3::Z, Vec3::ZERO, Vec3::Y, 60.0, 1.0, 0.0, &rs).with_overrides(&rs);
+
+        let rays: Vec<_> = (0..32)
+            .map(|i| cam.get_jittered_ray(i, [0.0; 2]).1)
+            .collect();
+        for ray in &rays {
+            assert!((ray.dir + Vec3::Z).mag() < 1e-5);
+            assert!((ray.origin.z - 1.0).abs() < 1e-5);
+        }
+        // corner of the image at (-2, 1) and 0.5 world units between pixels
+        assert!((rays[0].origin - Vec3::new(-2.0, 1.0, 1.0)).mag() < 1e-5);
+        assert!((rays[1].origin.x - rays[0].origin.x - 0.5).abs() < 1e-5);
+        assert!((rays[8].origin.y - rays[0].origin.y + 0.5).abs() < 1e-5);
+
+        // keeps the width of the image plane without an explicit width
+        let rs = RenderSettings {
+            cam_projection: Some(Projection::Orthographic),
+            ..Default::default()
+        };
+        let cam = Cam::new(Vec3::ZERO, -Vec3::Z, Vec3::Y, 90.0, 3.0, 0.0, &rs).with_overrides(&rs);
+        assert!((cam.ortho_width - 6.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn equirectangular() {
+        let rs = RenderSettings {
+            cam_projection: Some(Projection::Equirectangular),
+            cam_rotation: Some(vec![0.0, 90.0, 0.0]),
+            width: std::num::NonZeroU32::new(8).unwrap(),
+            height: std::num::NonZeroU32::new(4).unwrap(),
+            ..Default::default()
+        };
+        // looking down -X
+        let cam = Cam::new(Vec3::ZERO, -Vec3::Z, Vec3::Y, 60.0, 1.0, 0.0, &rs).with_overrides(&rs);
+        let dir = |u: f32, v: f32| cam.ray(u, v, [0.5; 2]).dir;
+
+        assert!((dir(0.5, 0.5) + Vec3::X).mag() < 1e-5);
+        // right is -Z
+        assert!((dir(0.75, 0.5) + Vec3::Z).mag() < 1e-5);
+        assert!((dir(0.25, 0.5) - Vec3::Z).mag() < 1e-5);
+        assert!((dir(0.0, 0.5) - Vec3::X).mag() < 1e-5);
+        assert!((dir(0.3, 0.0) - Vec3::Y).mag() < 1e-5);
+        assert!((dir(0.3, 1.0) + Vec3::Y).mag() < 1e-5);
+
+        // every pixel of the frame splats inside it
+        let mut rng = rand_pcg::Pcg64Mcg::new(0);
+        for i in 0..32 {
+            let (uv, ray) = cam.get_ray(i, true, &mut rng);
+            assert!(uv[0] < 1.0 && uv[1] < 1.0);
+            assert!((ray.dir.mag() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    fn forward(cam: &Cam) -> Vec3 {
+        (cam.lower_left + 0.5 * cam.right + 0.5 * cam.up - cam.origin).normalised()
+    }
+
+    #[test]
+    fn euler_override() {
+        let rs = RenderSettings::default();
+        let euler = |rotation: [f32; 3], order| {
+            let cam = Cam::new_euler(Vec3::ZERO, rotation.into(), order, 60.0, &rs, true);
+            forward(&cam)
+        };
+
+        // looking along +Y then yaw 90 degrees to -X
+        assert!((euler([90.0, 0.0, 0.0], EulerOrder::Xyz) - Vec3::Y).mag() < 1e-5);
+        assert!((euler([90.0, 0.0, 90.0], EulerOrder::Xyz) + Vec3::X).mag() < 1e-5);
+        // order matters
+        assert!((euler([90.0, 0.0, 90.0], EulerOrder::Zyx) - Vec3::Y).mag() < 1e-5);
+
+        // xyz matches new_rot
+        let rot = Vec3::new(63.5, 10.0, 66.2);
+        let a = Cam::new_rot(Vec3::ZERO, rot, 60.0, &rs, true);
+        let b = Cam::new_euler(Vec3::ZERO, rot, EulerOrder::Xyz, 60.0, &rs, true);
+        assert!((a.lower_left - b.lower_left).mag() < 1e-5);
+        assert!((a.up - b.up).mag() < 1e-5);
+
+        // degrees by default
+        let rs = RenderSettings {
+            cam_rotation: Some(vec![90.0, 0.0, 90.0]),
+            ..Default::default()
+        };
+        let cam = crate::camera::PLACEHOLDER.with_overrides(&rs);
+        assert!((forward(&cam) + Vec3::X).mag() < 1e-5);
+        assert_eq!(cam.hfov, crate::camera::PLACEHOLDER.hfov);
     }
 }
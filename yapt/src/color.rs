@@ -0,0 +1,229 @@
+use crate::prelude::*;
+
+pub type Mat3 = [[f32; 3]; 3];
+
+// RGB colour spaces by their primaries, the working space is what scene colours
+// (and the canvas) are in while the output space is what images are written in
+#[derive(clap::ValueEnum, Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub enum Primaries {
+    Srgb,
+    // same primaries as sRGB but with the BT.709 transfer function for 8 bit output
+    Rec709,
+    #[default]
+    Rec2020,
+    // ACES AP1 with a ~D60 white point
+    #[value(name = "acescg")]
+    AcesCg,
+}
+
+impl fmt::Display for Primaries {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Srgb => "srgb",
+            Self::Rec709 => "rec709",
+            Self::Rec2020 => "rec2020",
+            Self::AcesCg => "acescg",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl Primaries {
+    const D65: [f32; 2] = [0.3127, 0.3290];
+
+    // xy of red, green, blue and white
+    #[must_use]
+    pub fn chromaticities(self) -> [[f32; 2]; 4] {
+        match self {
+            Self::Srgb | Self::Rec709 => [[0.64, 0.33], [0.30, 0.60], [0.15, 0.06], Self::D65],
+            Self::Rec2020 => [[0.708, 0.292], [0.170, 0.797], [0.131, 0.046], Self::D65],
+            Self::AcesCg => [
+                [0.713, 0.293],
+                [0.165, 0.830],
+                [0.128, 0.044],
+                [0.32168, 0.33767],
+            ],
+        }
+    }
+    // the space with these chromaticities (sRGB rather than Rec.709)
+    #[must_use]
+    pub fn from_chromaticities(c: [[f32; 2]; 4]) -> Option<Self> {
+        [Self::Srgb, Self::Rec2020, Self::AcesCg]
+            .into_iter()
+            .find(|p| {
+                p.chromaticities()
+                    .iter()
+                    .flatten()
+                    .zip(c.iter().flatten())
+                    .all(|(a, b)| (a - b).abs() < 1e-3)
+            })
+    }
+    // linear value to the 8 bit encoding
+    #[must_use]
+    pub fn encode(self, v: f32) -> f32 {
+        match self {
+            // ACEScg has no display encoding so uses the sRGB curve
+            Self::Srgb | Self::AcesCg => {
+                if v <= 0.0031308 {
+                    12.92 * v
+                } else {
+                    1.055 * v.powf(1.0 / 2.4) - 0.055
+                }
+            }
+            Self::Rec709 | Self::Rec2020 => {
+                if v < 0.018 {
+                    4.5 * v
+                } else {
+                    1.099 * v.powf(0.45) - 0.099
+                }
+            }
+        }
+    }
+    #[must_use]
+    pub fn decode(self, v: f32) -> f32 {
+        match self {
+            Self::Srgb | Self::AcesCg => {
+                if v <= 0.04045 {
+                    v / 12.92
+                } else {
+                    ((v + 0.055) / 1.055).powf(2.4)
+                }
+            }
+            Self::Rec709 | Self::Rec2020 => {
+                if v < 0.081 {
+                    v / 4.5
+                } else {
+                    ((v + 0.099) / 1.099).powf(1.0 / 0.45)
+                }
+            }
+        }
+    }
+}
+
+// linear RGB with the given chromaticities to CIE XYZ
+#[must_use]
+pub fn rgb_to_xyz(c: [[f32; 2]; 4]) -> Mat3 {
+    let xyz = |[x, y]: [f32; 2]| [x / y, 1.0, (1.0 - x - y) / y];
+    let [r, g, b] = [xyz(c[0]), xyz(c[1]), xyz(c[2])];
+    let m = [[r[0], g[0], b[0]], [r[1], g[1], b[1]], [r[2], g[2], b[2]]];
+    // scale the primaries so (1, 1, 1) is the white point
+    let s = mul_vec(inverse(m), xyz(c[3]));
+    m.map(|row| [row[0] * s[0], row[1] * s[1], row[2] * s[2]])
+}
+
+// Bradford chromatic adaptation between white points (as xy)
+#[must_use]
+fn adapt(from: [f32; 2], to: [f32; 2]) -> Mat3 {
+    const BRADFORD: Mat3 = [
+        [0.8951, 0.2664, -0.1614],
+        [-0.7502, 1.7135, 0.0367],
+        [0.0389, -0.0685, 1.0296],
+    ];
+    let cone = |[x, y]: [f32; 2]| mul_vec(BRADFORD, [x / y, 1.0, (1.0 - x - y) / y]);
+    let (a, b) = (cone(from), cone(to));
+    let scale = [
+        [b[0] / a[0], 0.0, 0.0],
+        [0.0, b[1] / a[1], 0.0],
+        [0.0, 0.0, b[2] / a[2]],
+    ];
+    mul(inverse(BRADFORD), mul(scale, BRADFORD))
+}
+
+// converts linear RGB from one set of primaries to another
+#[derive(Copy, Clone, Debug)]
+pub struct ColorTransform {
+    pub from: Primaries,
+    pub to: Primaries,
+    matrix: Mat3,
+}
+
+impl ColorTransform {
+    #[must_use]
+    pub fn new(from: Primaries, to: Primaries) -> Self {
+        let (a, b) = (from.chromaticities(), to.chromaticities());
+        let matrix = mul(
+            inverse(rgb_to_xyz(b)),
+            mul(adapt(a[3], b[3]), rgb_to_xyz(a)),
+        );
+        Self { from, to, matrix }
+    }
+    #[must_use]
+    pub fn apply(&self, rgb: Vec3) -> Vec3 {
+        if self.from == self.to {
+            return rgb;
+        }
+        let [x, y, z] = mul_vec(self.matrix, [rgb.x, rgb.y, rgb.z]);
+        Vec3::new(x, y, z)
+    }
+}
+
+// CIE XYZ (relative to D65) to linear RGB with the given primaries
+#[derive(Copy, Clone, Debug)]
+pub struct FromXyz(Mat3);
+
+impl FromXyz {
+    #[must_use]
+    pub fn new(to: Primaries) -> Self {
+        let c = to.chromaticities();
+        Self(mul(inverse(rgb_to_xyz(c)), adapt(Primaries::D65, c[3])))
+    }
+    #[must_use]
+    pub fn apply(&self, xyz: Vec3) -> Vec3 {
+        let [r, g, b] = mul_vec(self.0, [xyz.x, xyz.y, xyz.z]);
+        Vec3::new(r, g, b)
+    }
+}
+
+#[must_use]
+fn mul(a: Mat3, b: Mat3) -> Mat3 {
+    std::array::from_fn(|i| std::array::from_fn(|j| (0..3).map(|k| a[i][k] * b[k][j]).sum()))
+}
+
+#[must_use]
+pub fn mul_vec(m: Mat3, v: [f32; 3]) -> [f32; 3] {
+    m.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+#[must_use]
+pub fn inverse(m: Mat3) -> Mat3 {
+    let cofactor = |i: usize, j: usize| {
+        let (r0, r1) = ((i + 1) % 3, (i + 2) % 3);
+        let (c0, c1) = ((j + 1) % 3, (j + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let det: f32 = (0..3).map(|j| m[0][j] * cofactor(0, j)).sum();
+    // transpose of the cofactors
+    std::array::from_fn(|i| std::array::from_fn(|j| cofactor(j, i) / det))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn primaries() {
+        // white stays white and converting there and back is the identity
+        let to_aces = ColorTransform::new(Primaries::Rec2020, Primaries::AcesCg);
+        let from_aces = ColorTransform::new(Primaries::AcesCg, Primaries::Rec2020);
+        assert!((to_aces.apply(Vec3::ONE) - Vec3::ONE).abs().component_max() < 1e-3);
+        let v = Vec3::new(0.2, 0.7, 0.1);
+        assert!(
+            (from_aces.apply(to_aces.apply(v)) - v)
+                .abs()
+                .component_max()
+                < 1e-5
+        );
+
+        // well known sRGB -> XYZ
+        let m = rgb_to_xyz(Primaries::Srgb.chromaticities());
+        assert!((m[0][0] - 0.4124).abs() < 1e-3 && (m[1][1] - 0.7152).abs() < 1e-3);
+
+        for p in [Primaries::Srgb, Primaries::Rec709, Primaries::AcesCg] {
+            assert!((p.decode(p.encode(0.3)) - 0.3).abs() < 1e-5);
+        }
+        assert_eq!(
+            Primaries::from_chromaticities(Primaries::AcesCg.chromaticities()),
+            Some(Primaries::AcesCg)
+        );
+    }
+}
@@ -1,4 +1,5 @@
-use crate::Vec3;
+use crate::{Mat4, Vec3};
+use std::f32::consts::PI;
 
 pub struct Coordinate {
     pub x: Vec3,
@@ -55,6 +56,60 @@ impl Quaternion {
     pub const fn new(w: f32, x: f32, y: f32, z: f32) -> Self {
         Self { w, x, y, z }
     }
+    // blender XYZ euler angles (X applied first)
+    #[must_use]
+    pub fn from_euler(mut rotation: Vec3, degrees: bool) -> Self {
+        if degrees {
+            rotation *= PI / 180.0;
+        }
+        let rotation = rotation * 0.5;
+
+        let (sx, cx) = rotation.x.sin_cos();
+        let (sy, cy) = rotation.y.sin_cos();
+        let (sz, cz) = rotation.z.sin_cos();
+
+        Self::new(
+            cx * cy * cz + sx * sy * sz,
+            sx * cy * cz - cx * sy * sz,
+            cx * sy * cz + sx * cy * sz,
+            cx * cy * sz - sx * sy * cz,
+        )
+    }
+    // spherical interpolation between unit quaternions along the shortest path
+    #[must_use]
+    pub fn slerp(a: Self, b: Self, t: f32) -> Self {
+        let mut cos_theta = a.dot(b);
+        // q & -q are the same rotation
+        let b = if cos_theta < 0.0 {
+            cos_theta = -cos_theta;
+            Self::new(-b.w, -b.x, -b.y, -b.z)
+        } else {
+            b
+        };
+        // nearly parallel, fall back to normalised linear interpolation
+        let (wa, wb) = if cos_theta > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let theta = cos_theta.acos();
+            let sin_theta = theta.sin();
+            (
+                ((1.0 - t) * theta).sin() / sin_theta,
+                (t * theta).sin() / sin_theta,
+            )
+        };
+        let q = Self::new(
+            wa * a.w + wb * b.w,
+            wa * a.x + wb * b.x,
+            wa * a.y + wb * b.y,
+            wa * a.z + wb * b.z,
+        );
+        let mag = q.dot(q).sqrt();
+        Self::new(q.w / mag, q.x / mag, q.y / mag, q.z / mag)
+    }
+    #[must_use]
+    pub const fn dot(&self, other: Self) -> f32 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
     pub const fn hamilton(&self, other: Self) -> Self {
         Self::new(
             self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
@@ -69,6 +124,41 @@ impl Quaternion {
     pub const fn conj(&self) -> Self {
         Self::new(self.w, -self.x, -self.y, -self.z)
     }
+    // rotates v by a unit quaternion
+    #[must_use]
+    pub const fn rotate(&self, v: Vec3) -> Vec3 {
+        self.hamilton(Quaternion::new(0.0, v.x, v.y, v.z))
+            .hamilton(self.conj())
+            .xyz()
+    }
+}
+
+// rotation matrix of a unit quaternion
+impl From<Quaternion> for Mat4 {
+    fn from(q: Quaternion) -> Self {
+        let Quaternion { w, x, y, z } = q;
+        Mat4::new([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
 }
 
 impl From<Vec3> for Quaternion {
@@ -111,6 +201,104 @@ mod tests {
         assert!((coord.global_to_local(rando_coord) - Vec3::Z).mag_sq() < ETA);
     }
 
+    #[test]
+    fn from_euler() {
+        // the construction previously inlined in Cam::new_rot & Rot::quaternion
+        let inlined = |rotation: Vec3| {
+            let rotation = rotation * (PI / 180.0) * 0.5;
+            let (sx, cx) = rotation.x.sin_cos();
+            let (sy, cy) = rotation.y.sin_cos();
+            let (sz, cz) = rotation.z.sin_cos();
+            Quaternion::new(
+                cx * cy * cz + sx * sy * sz,
+                sx * cy * cz - cx * sy * sz,
+                cx * sy * cz + sx * cy * sz,
+                cx * cy * sz - sx * sy * cz,
+            )
+        };
+        for rotation in [
+            Vec3::ZERO,
+            Vec3::new(90.0, 0.0, 0.0),
+            Vec3::new(0.0, -45.0, 180.0),
+            Vec3::new(98.27, 0.0, 96.0),
+            Vec3::new(-30.0, 60.0, 270.0),
+        ] {
+            let q = Quaternion::from_euler(rotation, true);
+            let expected = inlined(rotation);
+            assert!((q.dot(expected) - 1.0).abs() < ETA, "{rotation}");
+            let radians = Quaternion::from_euler(rotation * (PI / 180.0), false);
+            assert!((radians.dot(expected) - 1.0).abs() < ETA, "{rotation}");
+        }
+        // X is applied first
+        let q = Quaternion::from_euler(Vec3::new(90.0, 0.0, 90.0), true);
+        assert!((q.rotate(Vec3::Y) - Vec3::Z).mag() < 1e-5);
+        assert!((q.rotate(Vec3::X) - Vec3::Y).mag() < 1e-5);
+    }
+
+    #[test]
+    fn slerp() {
+        let a = Quaternion::from_euler(Vec3::new(0.0, 0.0, 10.0), true);
+        let b = Quaternion::from_euler(Vec3::new(0.0, 0.0, 130.0), true);
+        let close = |p: Quaternion, q: Quaternion| (p.dot(q).abs() - 1.0).abs() < 1e-5;
+
+        assert!(close(Quaternion::slerp(a, b, 0.0), a));
+        assert!(close(Quaternion::slerp(a, b, 1.0), b));
+        // constant angular velocity about the shared axis
+        let mid = Quaternion::from_euler(Vec3::new(0.0, 0.0, 40.0), true);
+        assert!(close(Quaternion::slerp(a, b, 0.25), mid));
+        // takes the shortest path when the quaternions are in opposite hemispheres
+        let neg_b = Quaternion::new(-b.w, -b.x, -b.y, -b.z);
+        assert!(close(Quaternion::slerp(a, neg_b, 0.25), mid));
+        // nearly identical rotations stay normalised
+        let c = Quaternion::from_euler(Vec3::new(0.0, 0.0, 10.001), true);
+        let q = Quaternion::slerp(a, c, 0.5);
+        assert!((q.dot(q) - 1.0).abs() < ETA);
+    }
+
+    #[test]
+    fn matrix() {
+        let close = |a: Vec3, b: Vec3| (a - b).mag() < 1e-5;
+        let (t, s) = (Vec3::new(1.0, -2.0, 0.5), Vec3::new(2.0, 0.5, 3.0));
+        for rotation in [
+            Vec3::ZERO,
+            Vec3::new(90.0, 0.0, 0.0),
+            Vec3::new(98.27, 0.0, 96.0),
+            Vec3::new(-30.0, 60.0, 270.0),
+        ] {
+            let q = Quaternion::from_euler(rotation, true);
+            let m = Mat4::from_trs(t, q.into(), s);
+            let normals = m.normal_matrix().unwrap();
+            for _ in 0..10 {
+                // the per vertex transforms of the gltf loader
+                let v = random_unit_vector();
+                assert!(close(m.mul_point(v), q.rotate(v.hadamard(s)) + t));
+                assert!(close(m.mul_dir(v), q.rotate(v.hadamard(s))));
+                assert!(close(
+                    normals.mul_dir(v).normalised(),
+                    q.rotate(v / s).normalised()
+                ));
+            }
+        }
+
+        // parent * child matches propagating the translation, rotation & scale
+        // separately (exact for uniform parent scales)
+        let (pq, pt, ps) = (
+            Quaternion::from_euler(Vec3::new(10.0, 20.0, 30.0), true),
+            Vec3::new(0.0, 3.0, -1.0),
+            Vec3::splat(2.0),
+        );
+        let cq = Quaternion::from_euler(Vec3::new(-45.0, 0.0, 15.0), true);
+        let parent = Mat4::from_trs(pt, pq.into(), ps);
+        let child = parent * Mat4::from_trs(t, cq.into(), s);
+        let (ct, cq, cs) = (
+            pq.rotate(t.hadamard(ps)) + pt,
+            pq.hamilton(cq),
+            ps.hadamard(s),
+        );
+        let v = random_unit_vector();
+        assert!(close(child.mul_point(v), cq.rotate(v.hadamard(cs)) + ct));
+    }
+
     #[test]
     fn nop() {
         let rando_vec = random_unit_vector();
@@ -0,0 +1,151 @@
+use crate::prelude::*;
+use rand_pcg::Pcg64Mcg;
+
+// edge avoiding à-trous wavelet filter (Dammertz et al. 2010), a 5x5 B3 spline kernel
+// is applied with increasing gaps between taps and taps are weighted down across
+// edges in the first hit albedo & normal
+
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+// squared distance between guide values at which a tap's weight falls to 1/e
+const NORMAL_SIGMA: f32 = 0.1;
+const ALBEDO_SIGMA: f32 = 0.01;
+
+pub struct Guides {
+    pub albedo: Vec<Vec3>,
+    pub normal: Vec<Vec3>,
+    // distance along the camera ray (not used by the filter, saved with the AOVs)
+    pub depth: Vec<f32>,
+}
+
+impl Guides {
+    // albedo, normal & depth of the first hit through the centre of each pixel (of the tile
+    // if any), misses get the environment as albedo, a zero normal and infinite depth
+    #[must_use]
+    pub fn new(width: usize, height: usize, tile: Option<Tile>) -> Self {
+        let (cam, mats, envmap) = unsafe {
+            (
+                CAM.get().as_ref_unchecked(),
+                MATERIALS.get().as_ref_unchecked(),
+                ENVMAP.get().as_ref_unchecked(),
+            )
+        };
+        let mut rng = Pcg64Mcg::new(0);
+        let mut depth = Vec::with_capacity(width * height);
+        let (albedo, normal) = (0..(width * height) as u64)
+            .map(|i| {
+                let ray = cam.get_centre_ray(tile.map_or(i, |t| t.frame_pixel(i)));
+                let sect = get_intersection(&ray, &mut rng);
+                if sect.is_none() {
+                    depth.push(f32::INFINITY);
+                    (envmap.sample_dir(ray.dir).saturate(), Vec3::ZERO)
+                } else {
+                    depth.push(sect.t);
+                    (mats[sect.mat].albedo(sect.uv), sect.nor)
+                }
+            })
+            .unzip();
+        Self {
+            albedo,
+            normal,
+            depth,
+        }
+    }
+    #[must_use]
+    fn weight(&self, p: usize, q: usize) -> f32 {
+        let n = (self.normal[p] - self.normal[q]).mag_sq();
+        let a = (self.albedo[p] - self.albedo[q]).mag_sq();
+        (-n / NORMAL_SIGMA - a / ALBEDO_SIGMA).exp()
+    }
+}
+
+// iteration i uses a gap of 2^i pixels between taps so the filter covers a
+// (4 * 2^iterations + 1) pixel wide footprint
+#[must_use]
+pub fn atrous(
+    image: &[Vec3],
+    guides: &Guides,
+    width: usize,
+    height: usize,
+    iterations: u32,
+) -> Vec<Vec3> {
+    let mut image = image.to_vec();
+    for i in 0..iterations {
+        let step = 1 << i;
+        image = (0..width * height)
+            .map(|p| {
+                let (x, y) = ((p % width) as isize, (p / width) as isize);
+                let (mut sum, mut weight_sum) = (Vec3::ZERO, 0.0);
+                for (ky, hy) in KERNEL.iter().enumerate() {
+                    let qy = y + (ky as isize - 2) * step;
+                    if qy < 0 || qy >= height as isize {
+                        continue;
+                    }
+                    for (kx, hx) in KERNEL.iter().enumerate() {
+                        let qx = x + (kx as isize - 2) * step;
+                        if qx < 0 || qx >= width as isize {
+                            continue;
+                        }
+                        let q = qy as usize * width + qx as usize;
+                        let w = hx * hy * guides.weight(p, q);
+                        sum += w * image[q];
+                        weight_sum += w;
+                    }
+                }
+                // the centre tap always has a positive weight
+                sum / weight_sum
+            })
+            .collect();
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn atrous_edges() {
+        // left half faces +Z with a dark albedo, right half faces +X with a bright one
+        let (width, height) = (32, 16);
+        let left = |p: usize| p % width < width / 2;
+        let guides = Guides {
+            albedo: (0..width * height)
+                .map(|p| Vec3::splat(if left(p) { 0.2 } else { 0.8 }))
+                .collect(),
+            normal: (0..width * height)
+                .map(|p| if left(p) { Vec3::Z } else { Vec3::X })
+                .collect(),
+            depth: vec![1.0; width * height],
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let noisy: Vec<_> = guides
+            .albedo
+            .iter()
+            .map(|&a| a + Vec3::splat(0.4 * (rng.gen() - 0.5)))
+            .collect();
+
+        let filtered = atrous(&noisy, &guides, width, height, 4);
+
+        let error = |image: &[Vec3]| {
+            image
+                .iter()
+                .zip(&guides.albedo)
+                .map(|(v, a)| (*v - *a).mag_sq())
+                .sum::<f32>()
+                / image.len() as f32
+        };
+        assert!(
+            error(&filtered) < 0.1 * error(&noisy),
+            "{} {}",
+            error(&filtered),
+            error(&noisy)
+        );
+        // the columns either side of the edge don't bleed into each other
+        for y in 0..height {
+            let p = y * width + width / 2;
+            assert!((filtered[p - 1].x - 0.2).abs() < 0.05);
+            assert!((filtered[p].x - 0.8).abs() < 0.05);
+        }
+    }
+}
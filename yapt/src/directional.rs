@@ -0,0 +1,107 @@
+use crate::prelude::*;
+
+// infinitely distant light (e.g. the sun) seen as a disk of uniform radiance within
+// angular_radius of dir, it is sampled by NEE and hit by rays escaping the scene
+#[derive(Debug)]
+pub struct DirectionalLight {
+    // towards the light
+    pub dir: Vec3,
+    pub radiance: Vec3,
+    pub angular_radius: f32,
+}
+
+impl DirectionalLight {
+    #[must_use]
+    pub fn new(dir: Vec3, radiance: Vec3, angular_radius: f32) -> Self {
+        Self {
+            dir: dir.normalised(),
+            radiance,
+            angular_radius,
+        }
+    }
+    // radiance giving irradiance at normal incidence
+    #[must_use]
+    pub fn from_irradiance(dir: Vec3, irradiance: Vec3, angular_radius: f32) -> Self {
+        Self::new(
+            dir,
+            irradiance / solid_angle(angular_radius),
+            angular_radius,
+        )
+    }
+    #[must_use]
+    fn contains(&self, dir: Vec3) -> bool {
+        dir.dot(self.dir) >= self.angular_radius.cos()
+    }
+    // radiance arriving from dir (pointing away from the scene)
+    #[must_use]
+    pub fn le(&self, dir: Vec3) -> Vec3 {
+        if self.contains(dir) {
+            self.radiance
+        } else {
+            Vec3::ZERO
+        }
+    }
+    // (dir, solid angle pdf) uniform over the cone
+    #[must_use]
+    pub fn sample_light(&self, rng: &mut impl MinRng) -> (Vec3, f32) {
+        let cos_theta = 1.0 - rng.gen() * one_minus_cos(self.angular_radius);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let (sin_phi, cos_phi) = (TAU * rng.gen()).sin_cos();
+        let local = Vec3::from_spherical(sin_theta, cos_theta, sin_phi, cos_phi);
+        let dir = Coordinate::new_from_z(self.dir).local_to_global(local);
+        (dir, 1.0 / solid_angle(self.angular_radius))
+    }
+    #[must_use]
+    pub fn pdf(&self, dir: Vec3) -> f32 {
+        if self.contains(dir) {
+            1.0 / solid_angle(self.angular_radius)
+        } else {
+            0.0
+        }
+    }
+    // estimated power reaching a scene bounded by a sphere of the given radius
+    // (see EnvMap::power)
+    #[must_use]
+    pub fn power(&self, scene_radius: f32) -> f32 {
+        let irradiance = crate::luminance(self.radiance) * solid_angle(self.angular_radius);
+        PI * scene_radius.powi(2) * irradiance
+    }
+}
+
+// 1 - cos(angle) without cancellation
+#[must_use]
+fn one_minus_cos(angle: f32) -> f32 {
+    2.0 * (0.5 * angle).sin().powi(2)
+}
+
+#[must_use]
+fn solid_angle(angular_radius: f32) -> f32 {
+    TAU * one_minus_cos(angular_radius)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn directional() {
+        let dir = Vec3::new(0.2, -0.3, 0.9).normalised();
+        let sun = DirectionalLight::from_irradiance(dir, Vec3::splat(3.0), 0.05);
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // integrating the radiance over the cone gives back the irradiance
+        let n = 10_000;
+        let mut irradiance = Vec3::ZERO;
+        for _ in 0..n {
+            let (wi, pdf) = sun.sample_light(&mut rng);
+            assert!(wi.dot(dir) >= 0.05f32.cos() - 1e-6);
+            assert_eq!(pdf, sun.pdf(wi));
+            irradiance += sun.le(wi) / pdf;
+        }
+        assert!((irradiance / n as f32 - Vec3::splat(3.0)).mag() < 1e-2);
+
+        assert_eq!(sun.le(-dir), Vec3::ZERO);
+        assert_eq!(sun.pdf(Vec3::Z), 0.0);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::prelude::*;
 use rand::Rng;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -51,8 +52,21 @@ impl Distribution1D {
         self.cdf.iter().position(|v| v >= &threshold).unwrap() - 1
     }
     pub fn sample(&self, rng: &mut impl Rng) -> usize {
-        let num = rng.gen();
-
+        self.find(rng.gen())
+    }
+    // bin containing u and u remapped to [0, 1) within that bin
+    #[must_use]
+    pub fn sample_remapped(&self, u: f32) -> (usize, f32) {
+        let i = self.find(u);
+        let remapped = if self.pdf[i] > 0.0 {
+            (u - self.cdf[i]) / self.pdf[i]
+        } else {
+            0.5
+        };
+        (i, remapped.clamp(0.0, 1.0 - f32::EPSILON))
+    }
+    #[must_use]
+    fn find(&self, num: f32) -> usize {
         let pred = |i| self.cdf[i] <= num;
 
         {
@@ -73,3 +87,85 @@ impl Distribution1D {
         }
     }
 }
+
+// piecewise constant distribution over the unit square from a row major grid of values
+// rows are picked from the marginal then columns from that row's conditional
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution2D {
+    pub marginal: Distribution1D,
+    pub conditional: Vec<Distribution1D>,
+}
+
+impl Distribution2D {
+    #[must_use]
+    pub fn new(values: &[f32], width: usize) -> Self {
+        assert!(
+            width != 0 && values.len().is_multiple_of(width),
+            "Non rectangular grid passed to Distribution2D::new!"
+        );
+        let conditional: Vec<_> = values
+            .chunks_exact(width)
+            .map(Distribution1D::new)
+            .collect();
+        let row_ints: Vec<_> = conditional.iter().map(|c| c.func_int).collect();
+        Self {
+            marginal: Distribution1D::new(&row_ints),
+            conditional,
+        }
+    }
+    #[must_use]
+    fn dim(&self) -> [usize; 2] {
+        [self.conditional[0].pdf.len(), self.conditional.len()]
+    }
+    // point in the unit square & its pdf (w.r.t. area)
+    #[must_use]
+    pub fn sample(&self, rng: &mut impl MinRng) -> (Vec2, f32) {
+        let (y, v) = self.marginal.sample_remapped(rng.gen());
+        let (x, u) = self.conditional[y].sample_remapped(rng.gen());
+        let [w, h] = self.dim();
+        let point = Vec2::new((x as f32 + u) / w as f32, (y as f32 + v) / h as f32);
+        (point, self.pdf(point))
+    }
+    #[must_use]
+    pub fn pdf(&self, point: Vec2) -> f32 {
+        let [w, h] = self.dim();
+        let x = ((point.x * w as f32) as usize).min(w - 1);
+        let y = ((point.y * h as f32) as usize).min(h - 1);
+        self.marginal.pdf[y] * self.conditional[y].pdf[x] * (w * h) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distribution_2d() {
+        let mut rng = rand::thread_rng();
+        #[rustfmt::skip]
+        let values = [
+            0.0, 1.0, 2.0,
+            3.0, 0.0, 6.0,
+        ];
+        let dist = Distribution2D::new(&values, 3);
+
+        // the pdf is proportional to the values & integrates to 1
+        let pdf = |x: f32, y: f32| dist.pdf(Vec2::new(x, y));
+        assert_eq!(pdf(0.1, 0.1), 0.0);
+        assert!((pdf(0.5, 0.1) - 0.5).abs() < 1e-6);
+        assert!((pdf(0.9, 0.9) - 3.0).abs() < 1e-6);
+
+        let n = 100_000;
+        let mut counts = [0; 6];
+        for _ in 0..n {
+            let (p, pdf) = dist.sample(&mut rng);
+            assert!((0.0..1.0).contains(&p.x) && (0.0..1.0).contains(&p.y));
+            assert_eq!(pdf, dist.pdf(p));
+            counts[(p.y * 2.0) as usize * 3 + (p.x * 3.0) as usize] += 1;
+        }
+        for (count, value) in counts.iter().zip(values) {
+            let expected = value / 12.0;
+            assert!((*count as f32 / n as f32 - expected).abs() < 0.01);
+        }
+    }
+}
@@ -1,30 +1,219 @@
-use crate::prelude::*;
+use crate::{distributions::Distribution2D, prelude::*};
 
 pub enum EnvMap {
     Solid(Vec3),
-    Image(TextureData),
+    // blend from bottom (straight down) to top (straight up) by height (Z)
+    Gradient { top: Vec3, bottom: Vec3 },
+    // with a distribution over the image proportional to the luminance of each
+    // pixel times the solid angle it covers for importance sampling, and the rotation
+    // of the image (None without one so lookups are exactly as before)
+    Image(TextureData, Distribution2D, Option<Quaternion>),
+    // procedural daylight with a sun (see Sky)
+    Sky(crate::sky::Sky),
 }
 
 impl EnvMap {
     pub const DEFAULT: Self = EnvMap::Solid(Vec3::ZERO);
 
+    #[must_use]
+    pub fn image(image: TextureData) -> Self {
+        let distribution = image.distribution();
+        Self::Image(image, distribution, None)
+    }
+    // spins images anticlockwise (looking down) about Z, other environments are unchanged
+    #[must_use]
+    pub fn with_rotation(mut self, degrees: f32) -> Self {
+        if let Self::Image(_, _, ref mut rotation) = self {
+            *rotation = (degrees != 0.0)
+                .then(|| Quaternion::from_euler(Vec3::new(0.0, 0.0, degrees), true));
+        }
+        self
+    }
+    // world direction to the image's own
+    #[must_use]
+    fn to_image(&self, dir: Vec3) -> Vec3 {
+        match self {
+            Self::Image(_, _, Some(rotation)) => rotation.conj().rotate(dir),
+            _ => dir,
+        }
+    }
+    // "solid:colour" or "gradient:top:bottom" where colours are r,g,b or a single grey
+    // value, None for anything else (an image path or "sky")
+    #[must_use]
+    pub fn procedural(spec: &str) -> Option<Result<Self, String>> {
+        let (kind, colours) = spec.split_once(':')?;
+        if kind != "solid" && kind != "gradient" {
+            return None;
+        }
+        let colour = |c: &str| {
+            let values = c
+                .split(',')
+                .map(|v| v.trim().parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .ok()?;
+            let v = match values[..] {
+                [v] => Vec3::splat(v),
+                [r, g, b] => Vec3::new(r, g, b),
+                _ => return None,
+            };
+            (v.component_min() >= 0.0).then_some(v)
+        };
+        let colours: Option<Vec<Vec3>> = colours.split(':').map(colour).collect();
+        Some(match (kind, colours.as_deref()) {
+            ("solid", Some(&[v])) => Ok(Self::Solid(v)),
+            ("gradient", Some(&[top, bottom])) => Ok(Self::Gradient { top, bottom }),
+            _ => Err(format!(
+                "Invalid environment {spec}, expected solid:colour or gradient:top:bottom with non negative r,g,b or grey colours"
+            )),
+        })
+    }
+
     #[must_use]
     pub fn sample(&self, uv: Vec2) -> Vec3 {
         match self {
             Self::Solid(v) => *v,
-            Self::Image(v) => v.sample(uv),
+            Self::Gradient { top, bottom } => gradient(*top, *bottom, (PI * uv.x).cos()),
+            Self::Image(v, ..) => v.sample(uv),
+            Self::Sky(sky) => sky.radiance(Vec3::from_spherical(
+                (PI * uv.x).sin(),
+                (PI * uv.x).cos(),
+                (TAU * uv.y - PI).sin(),
+                (TAU * uv.y - PI).cos(),
+            )),
         }
     }
     #[must_use]
     pub fn sample_dir(&self, dir: Vec3) -> Vec3 {
+        match self {
+            Self::Sky(sky) => return sky.radiance(dir),
+            Self::Gradient { top, bottom } => return gradient(*top, *bottom, dir.z),
+            _ => {}
+        }
+        let dir = self.to_image(dir);
         let theta = dir.z.acos() / PI;
         let phi = (dir.y.atan2(dir.x) + PI) / TAU;
         self.sample(Vec2::new(theta, phi))
     }
+    // sample a direction for NEE returning (dir, solid angle pdf)
+    // images are importance sampled, solid colours & gradients (or black images) uniform
+    // over the sphere
+    #[must_use]
+    pub fn sample_light(&self, rng: &mut impl MinRng) -> (Vec3, f32) {
+        if let Self::Sky(sky) = self {
+            return sky.sample_light(rng);
+        }
+        if let Self::Image(_, distribution, rotation) = self {
+            if distribution.marginal.func_int > 0.0 {
+                // (phi, theta) like TextureData::sample
+                let (point, pdf) = distribution.sample(rng);
+                let (sin_theta, cos_theta) = (PI * point.y).sin_cos();
+                let (sin_phi, cos_phi) = (TAU * point.x - PI).sin_cos();
+                let dir = Vec3::from_spherical(sin_theta, cos_theta, sin_phi, cos_phi);
+                // rotations preserve solid angle so don't change the pdf
+                let dir = rotation.map_or(dir, |r| r.rotate(dir));
+                return (dir, image_pdf(pdf, sin_theta));
+            }
+        }
+        let cos_theta = rng.gen_range(-1.0..1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let (sin_phi, cos_phi) = (TAU * rng.gen()).sin_cos();
+        let dir = Vec3::from_spherical(sin_theta, cos_theta, sin_phi, cos_phi);
+        (dir, self.pdf(dir))
+    }
+    // solid angle pdf of sample_light
+    #[must_use]
+    pub fn pdf(&self, dir: Vec3) -> f32 {
+        match self {
+            Self::Image(_, distribution, _) if distribution.marginal.func_int > 0.0 => {
+                let dir = self.to_image(dir);
+                let theta = dir.z.clamp(-1.0, 1.0).acos() / PI;
+                let phi = (dir.y.atan2(dir.x) + PI) / TAU;
+                let sin_theta = (1.0 - dir.z * dir.z).max(0.0).sqrt();
+                image_pdf(distribution.pdf(Vec2::new(phi, theta)), sin_theta)
+            }
+            Self::Sky(sky) => sky.pdf(dir),
+            _ => 0.25 * FRAC_1_PI,
+        }
+    }
+    // estimated power reaching a scene bounded by a sphere of the given radius
+    #[must_use]
+    pub fn power(&self, scene_radius: f32) -> f32 {
+        let avg = match self {
+            Self::Solid(v) => crate::luminance(*v),
+            // linear in height which is uniformly distributed over the sphere
+            Self::Gradient { top, bottom } => crate::luminance(0.5 * (*top + *bottom)),
+            Self::Image(v, ..) => v.average_luminance(),
+            Self::Sky(sky) => sky.average_luminance(),
+        };
+        4.0 * PI * PI * scene_radius.powi(2) * avg
+    }
+}
+
+// colour of a gradient at height z of a direction
+#[must_use]
+fn gradient(top: Vec3, bottom: Vec3, z: f32) -> Vec3 {
+    let t = (0.5 * (z + 1.0)).clamp(0.0, 1.0);
+    (1.0 - t) * bottom + t * top
+}
+
+// solid angle pdf from the pdf over the (phi, theta) unit square, which covers 2pi^2
+// and dw = sin_theta dphi dtheta
+#[must_use]
+fn image_pdf(pdf: f32, sin_theta: f32) -> f32 {
+    if sin_theta <= 0.0 {
+        return 0.0;
+    }
+    pdf / (2.0 * PI * PI * sin_theta)
+}
+
+// image on a finite rectangle at a fixed distance behind the subject, unlike the
+// environment map it has parallax when the camera moves, only camera rays see it
+pub struct Backplate {
+    corner: Vec3,
+    right: Vec3,
+    up: Vec3,
+    image: TextureData,
+}
+
+impl Backplate {
+    // fills the camera's view at distance from the camera
+    #[must_use]
+    pub fn new(cam: &Cam, distance: f32, image: TextureData) -> Self {
+        let centre = cam.lower_left + 0.5 * (cam.right + cam.up) - cam.origin;
+        let scale = distance / centre.mag();
+        Self {
+            corner: cam.origin + scale * (cam.lower_left - cam.origin),
+            right: scale * cam.right,
+            up: scale * cam.up,
+            image,
+        }
+    }
+    #[must_use]
+    pub fn hit(&self, ray: &Ray) -> Option<Vec3> {
+        let normal = self.right.cross(self.up);
+        let t = (self.corner - ray.origin).dot(normal) / ray.dir.dot(normal);
+        if t.is_nan() || t <= 0.0 {
+            return None;
+        }
+        let offset = ray.origin + t * ray.dir - self.corner;
+        let u = offset.dot(self.right) / self.right.mag_sq();
+        let v = offset.dot(self.up) / self.up.mag_sq();
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return None;
+        }
+        // image rows go from top to bottom
+        Some(self.image.sample(Vec2::new(1.0 - v, u)))
+    }
+}
+
+// radiance (in RGB) of a ray leaving the scene
+#[must_use]
+pub fn background(ray: &Ray, camera_ray: bool) -> Vec3 {
+    World::global().background(ray, camera_ray)
 }
 
 pub struct TextureData {
-    dim: [usize; 2],
+    pub dim: [usize; 2],
     pub data: Vec<Vec3>,
 }
 
@@ -60,6 +249,35 @@ impl TextureData {
 
         Ok(Self { dim, data })
     }
+    // average over the sphere (rows are weighted by the solid angle they cover)
+    #[must_use]
+    pub fn average_luminance(&self) -> f32 {
+        let (mut sum, mut weight) = (0.0, 0.0);
+        for (y, row) in self.data.chunks_exact(self.dim[0]).enumerate() {
+            let sin_theta = (PI * (y as f32 + 0.5) / self.dim[1] as f32).sin();
+            sum += sin_theta * row.iter().map(|v| crate::luminance(*v)).sum::<f32>();
+            weight += sin_theta * self.dim[0] as f32;
+        }
+        if weight == 0.0 {
+            return 0.0;
+        }
+        sum / weight
+    }
+    // over the cells covered by sample, pixels are looked up with (dim - 1) so the
+    // last row & column are only hit at the edge and get no cell
+    #[must_use]
+    fn distribution(&self) -> Distribution2D {
+        let [w, h] = self.dim;
+        let (cols, rows) = ((w - 1).max(1), (h - 1).max(1));
+        let mut values = Vec::with_capacity(cols * rows);
+        for y in 0..rows {
+            let sin_theta = (PI * (y as f32 + 0.5) / rows as f32).sin();
+            for x in 0..cols {
+                values.push(sin_theta * crate::luminance(self.data[x + y * w]).max(0.0));
+            }
+        }
+        Distribution2D::new(&values, cols)
+    }
     #[must_use]
     pub fn sample(&self, uv: Vec2) -> Vec3 {
         // since it's (theta, phi)
@@ -70,3 +288,168 @@ impl TextureData {
         self.data[index]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envmap_importance_sampling() {
+        let mut rng = rand::thread_rng();
+        // dim sky with a bright spot
+        let (w, h) = (9, 6);
+        let mut data: Vec<_> = (0..w * h)
+            .map(|i| Vec3::splat(0.1 * (i % 4) as f32))
+            .collect();
+        data[3 + 2 * w] = Vec3::splat(50.0);
+        let envmap = EnvMap::image(TextureData { dim: [w, h], data });
+
+        // exact integral over the sphere, each cell is piecewise constant
+        let (cols, rows) = (w - 1, h - 1);
+        let mut integral = 0.0;
+        for y in 0..rows {
+            let band =
+                (PI * y as f32 / rows as f32).cos() - (PI * (y + 1) as f32 / rows as f32).cos();
+            for x in 0..cols {
+                let uv = Vec2::new(
+                    (y as f32 + 0.5) / rows as f32,
+                    (x as f32 + 0.5) / cols as f32,
+                );
+                integral += crate::luminance(envmap.sample(uv)) * band * TAU / cols as f32;
+            }
+        }
+
+        let n = 100_000;
+        let mut estimate = 0.0;
+        let (mut bright, mut mismatched) = (0, 0);
+        for _ in 0..n {
+            let (dir, pdf) = envmap.sample_light(&mut rng);
+            assert!((dir.mag() - 1.0).abs() < 1e-4 && pdf > 0.0);
+            // directions on a cell boundary can round into the neighbouring cell
+            mismatched += usize::from((pdf - envmap.pdf(dir)).abs() > 1e-3 * pdf);
+            let le = crate::luminance(envmap.sample_dir(dir));
+            estimate += le / pdf / n as f32;
+            bright += usize::from(le > 10.0);
+        }
+        assert!(
+            (estimate / integral - 1.0).abs() < 0.01,
+            "{estimate} {integral}"
+        );
+        // the spot covers less than 2% of the sphere
+        assert!(bright > n / 4);
+        assert!(mismatched < n / 1000, "{mismatched}");
+
+        // solid colours stay uniform
+        let solid = EnvMap::Solid(Vec3::ONE);
+        let (dir, pdf) = solid.sample_light(&mut rng);
+        assert_eq!(pdf, 0.25 * FRAC_1_PI);
+        assert_eq!(solid.pdf(dir), pdf);
+    }
+
+    #[test]
+    fn procedural() {
+        let parse = |spec: &str| EnvMap::procedural(spec).map(Result::unwrap);
+        assert!(parse("env.exr").is_none() && parse("sky").is_none());
+        assert!(matches!(parse("solid:0.5"), Some(EnvMap::Solid(v)) if v == Vec3::splat(0.5)));
+        for spec in [
+            "solid:1,2",
+            "solid:-1",
+            "gradient:1",
+            "gradient:1:a",
+            "solid:1:1",
+        ] {
+            assert!(EnvMap::procedural(spec).unwrap().is_err(), "{spec}");
+        }
+
+        let (top, bottom) = (Vec3::new(0.2, 0.4, 1.0), Vec3::splat(0.1));
+        let envmap = parse("gradient:0.2,0.4,1:0.1").unwrap();
+        assert_eq!(envmap.sample_dir(Vec3::Z), top);
+        assert_eq!(envmap.sample_dir(-Vec3::Z), bottom);
+        assert_eq!(envmap.sample_dir(Vec3::X), 0.5 * (top + bottom));
+        assert!((envmap.sample(Vec2::new(0.0, 0.3)) - top).mag() < 1e-6);
+
+        // uniformly sampled so the average matches what power uses
+        let mut rng = rand::thread_rng();
+        let n = 100_000;
+        let mut average = 0.0;
+        for _ in 0..n {
+            let (dir, pdf) = envmap.sample_light(&mut rng);
+            assert_eq!(pdf, envmap.pdf(dir));
+            average += crate::luminance(envmap.sample_dir(dir)) / n as f32;
+        }
+        let expected = envmap.power(1.0) / (4.0 * PI * PI);
+        assert!(
+            (average / expected - 1.0).abs() < 0.01,
+            "{average} {expected}"
+        );
+    }
+
+    #[test]
+    fn rotation() {
+        let mut rng = rand::thread_rng();
+        let (w, h) = (9, 6);
+        let data: Vec<_> = (0..w * h)
+            .map(|i| Vec3::new(i as f32, (i % 4) as f32, 1.0))
+            .collect();
+        let image = || TextureData {
+            dim: [w, h],
+            data: data.clone(),
+        };
+        let envmap = EnvMap::image(image());
+        assert!(matches!(
+            EnvMap::image(image()).with_rotation(0.0),
+            EnvMap::Image(_, _, None)
+        ));
+        let rotated = EnvMap::image(image()).with_rotation(90.0);
+
+        // +X in the image is +Y once rotated by 90 degrees (away from cell boundaries)
+        let dir = Vec3::new(0.9, 0.1, 0.3).normalised();
+        let turned = Vec3::new(-dir.y, dir.x, dir.z);
+        assert_eq!(rotated.sample_dir(turned), envmap.sample_dir(dir));
+        assert!((rotated.pdf(turned) - envmap.pdf(dir)).abs() < 1e-4 * envmap.pdf(dir));
+
+        // sampled directions are rotated with their pdfs unchanged (up to directions on
+        // cell boundaries rounding into the neighbouring cell)
+        let n = 10_000;
+        let mut mismatched = 0;
+        for _ in 0..n {
+            let (dir, pdf) = rotated.sample_light(&mut rng);
+            assert!((dir.mag() - 1.0).abs() < 1e-4);
+            mismatched += usize::from((rotated.pdf(dir) - pdf).abs() > 1e-3 * pdf);
+        }
+        assert!(mismatched < n / 100, "{mismatched}");
+    }
+
+    #[test]
+    fn backplate_parallax() {
+        // left half black, right half white
+        let image = TextureData {
+            dim: [10, 1],
+            data: [[Vec3::ZERO; 5], [Vec3::ONE; 5]].concat(),
+        };
+        let envmap = EnvMap::image(TextureData {
+            dim: [2, 1],
+            data: vec![Vec3::ZERO, Vec3::ONE],
+        });
+        let rs = RenderSettings::default();
+        let cam = Cam::new(Vec3::ZERO, -Vec3::Z, Vec3::Y, 60.0, 1.0, 0.0, &rs);
+        let backplate = Backplate::new(&cam, 10.0, image);
+
+        // the view is filled with the left half on the left
+        let ray = |x: f32| Ray::new(Vec3::new(x, 0.0, 0.0), Vec3::new(-0.1, 0.0, -1.0));
+        assert_eq!(backplate.hit(&ray(0.0)), Some(Vec3::ZERO));
+        assert_eq!(
+            backplate.hit(&Ray::new(Vec3::ZERO, Vec3::new(0.1, 0.0, -1.0))),
+            Some(Vec3::ONE)
+        );
+        assert_eq!(backplate.hit(&Ray::new(Vec3::ZERO, Vec3::Z)), None);
+
+        // moving the camera sideways moves the same direction across the backplate
+        // while the environment map only depends on the direction
+        assert_ne!(backplate.hit(&ray(0.0)), backplate.hit(&ray(3.0)));
+        assert_eq!(
+            envmap.sample_dir(ray(0.0).dir),
+            envmap.sample_dir(ray(3.0).dir)
+        );
+    }
+}
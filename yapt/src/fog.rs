@@ -0,0 +1,324 @@
+use crate::prelude::*;
+use bvh::aabb::Aabb;
+
+// homogeneous medium, coefficients are per unit distance and the same for every channel
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Medium {
+    pub scattering: f32,
+    pub absorption: f32,
+    // Henyey-Greenstein asymmetry, > 0 scatters forward & < 0 backward
+    pub g: f32,
+}
+
+impl Medium {
+    #[must_use]
+    pub fn sigma_t(&self) -> f32 {
+        self.scattering + self.absorption
+    }
+    // throughput of scattering at a distance sampled proportional to transmittance
+    #[must_use]
+    pub fn albedo(&self) -> f32 {
+        self.scattering / self.sigma_t()
+    }
+}
+
+// fog filling the scene (global) with boxes (regions) of different media, where regions
+// overlap the last one given is used
+#[derive(Debug)]
+pub struct Fog {
+    global: Medium,
+    regions: Vec<(Aabb, Medium)>,
+}
+
+impl Fog {
+    // None if no medium has any extinction so renders are unchanged without fog
+    #[must_use]
+    pub fn new(global: Medium, regions: Vec<(Aabb, Medium)>) -> Option<Self> {
+        let extinction = std::iter::once(&global)
+            .chain(regions.iter().map(|(_, m)| m))
+            .any(|m| m.sigma_t() > 0.0);
+        extinction.then_some(Self { global, regions })
+    }
+    #[must_use]
+    fn medium_at(&self, pos: Vec3) -> Medium {
+        self.regions
+            .iter()
+            .rev()
+            .find(|(b, _)| (0..3).all(|i| b.min[i] <= pos[i] && pos[i] <= b.max[i]))
+            .map_or(self.global, |&(_, m)| m)
+    }
+    // pieces of the ray in [0, t_max] with a constant medium (that has extinction)
+    #[must_use]
+    fn segments(&self, ray: &Ray, t_max: f32) -> Vec<(f32, f32, Medium)> {
+        let mut ts = vec![0.0, t_max];
+        for (b, _) in &self.regions {
+            let t1 = (b.min - ray.origin).hadamard(ray.inv_dir);
+            let t2 = (b.max - ray.origin).hadamard(ray.inv_dir);
+            let near = t1.min_by_component(t2).component_max();
+            let far = t1.max_by_component(t2).component_min();
+            ts.extend([near, far].into_iter().filter(|&t| 0.0 < t && t < t_max));
+        }
+        ts.sort_by(f32::total_cmp);
+        ts.windows(2)
+            .filter(|t| t[0] < t[1])
+            .map(|t| {
+                // the middle of a ray that leaves the regions is at infinity
+                let mid = if t[1].is_finite() {
+                    0.5 * (t[0] + t[1])
+                } else {
+                    t[0] + 1.0
+                };
+                (t[0], t[1], self.medium_at(ray.origin + mid * ray.dir))
+            })
+            .filter(|(_, _, m)| m.sigma_t() > 0.0)
+            .collect()
+    }
+    // samples a distance before t_max proportional to transmittance, returns it and the
+    // medium there or None if the ray reaches t_max (with probability of transmittance)
+    #[must_use]
+    pub fn sample_distance(
+        &self,
+        ray: &Ray,
+        t_max: f32,
+        rng: &mut impl MinRng,
+    ) -> Option<(f32, Medium)> {
+        let mut depth = -(1.0 - rng.gen()).ln();
+        for (near, far, medium) in self.segments(ray, t_max) {
+            let t = near + depth / medium.sigma_t();
+            if t < far {
+                return Some((t, medium));
+            }
+            depth -= (far - near) * medium.sigma_t();
+        }
+        None
+    }
+    // between the ray origin and t_max
+    #[must_use]
+    pub fn transmittance(&self, ray: &Ray, t_max: f32) -> f32 {
+        let depth: f32 = self
+            .segments(ray, t_max)
+            .iter()
+            .map(|(near, far, m)| (far - near) * m.sigma_t())
+            .sum();
+        (-depth).exp()
+    }
+}
+
+// Henyey-Greenstein phase function of the angle between the incoming & outgoing
+// directions of travel (wo & wi), also the pdf of sample_phase
+#[must_use]
+pub fn phase(g: f32, wo: Vec3, wi: Vec3) -> f32 {
+    let denom = 1.0 + g * g - 2.0 * g * wo.dot(wi);
+    0.25 * FRAC_1_PI * (1.0 - g * g) / (denom * denom.sqrt())
+}
+
+#[must_use]
+pub fn sample_phase(g: f32, wo: Vec3, rng: &mut impl MinRng) -> Vec3 {
+    let (u1, u2) = (rng.gen(), rng.gen());
+    let cos_theta = if g.abs() < 1e-3 {
+        1.0 - 2.0 * u1
+    } else {
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u1);
+        ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+    };
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = TAU * u2;
+    Coordinate::new_from_z(wo).local_to_global(Vec3::new(
+        sin_theta * phi.cos(),
+        sin_theta * phi.sin(),
+        cos_theta,
+    ))
+}
+
+// the fog described by --fog-sigma & --fog-g with --fog-override entries of the form
+// min_x,min_y,min_z,max_x,max_y,max_z:key=value (keys scattering, absorption & g) for
+// media inside boxes, entries with the same box are the same region
+pub fn parse_fog(render_settings: &RenderSettings) -> Result<Option<Fog>, String> {
+    let check = |m: Medium, what: &str| {
+        if m.scattering < 0.0 || m.absorption < 0.0 || !(-1.0 < m.g && m.g < 1.0) {
+            return Err(format!(
+                "Invalid {what}, coefficients must be non negative & g must be in (-1, 1)"
+            ));
+        }
+        Ok(m)
+    };
+    let global = check(
+        match render_settings.fog_sigma.as_deref() {
+            Some(&[scattering, absorption]) => Medium {
+                scattering,
+                absorption,
+                g: render_settings.fog_g,
+            },
+            _ => Medium {
+                g: render_settings.fog_g,
+                ..Default::default()
+            },
+        },
+        "fog",
+    )?;
+
+    let mut regions: Vec<(Aabb, Medium)> = vec![];
+    for entry in &render_settings.fog_override {
+        let Some((bounds, (key, value))) = entry
+            .split_once(':')
+            .and_then(|(bounds, kv)| Some((bounds, kv.split_once('=')?)))
+        else {
+            return Err(format!(
+                "Invalid fog override {entry}, expected min_x,min_y,min_z,max_x,max_y,max_z:key=value"
+            ));
+        };
+        let (Ok(b), Ok(value)) = (
+            bounds
+                .split(',')
+                .map(str::parse::<f32>)
+                .collect::<Result<Vec<_>, _>>(),
+            value.parse::<f32>(),
+        ) else {
+            return Err(format!("Invalid fog override {entry}"));
+        };
+        let [x0, y0, z0, x1, y1, z1] = b[..] else {
+            return Err(format!(
+                "Invalid fog override bounds {bounds}, expected 6 values"
+            ));
+        };
+        if x0 > x1 || y0 > y1 || z0 > z1 {
+            return Err(format!(
+                "Invalid fog override bounds {bounds}, min must not exceed max"
+            ));
+        }
+        let aabb = Aabb::new(Vec3::new(x0, y0, z0), Vec3::new(x1, y1, z1));
+        let idx = match regions.iter().position(|(b, _)| *b == aabb) {
+            Some(idx) => idx,
+            None => {
+                regions.push((aabb, global));
+                regions.len() - 1
+            }
+        };
+        let medium = &mut regions[idx].1;
+        match key {
+            "scattering" => medium.scattering = value,
+            "absorption" => medium.absorption = value,
+            "g" => medium.g = value,
+            _ => {
+                return Err(format!(
+                    "Unknown fog override {key}, expected scattering, absorption or g"
+                ))
+            }
+        }
+    }
+    for (_, medium) in &regions {
+        check(*medium, "fog override")?;
+    }
+
+    Ok(Fog::new(global, regions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    const SAMPLES: usize = 200_000;
+
+    #[test]
+    fn phase_sampling() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let wo = Vec3::new(0.3, -0.5, 0.8).normalised();
+        for g in [0.0, 0.7, -0.4] {
+            // the phase function is normalised & sampled proportionally, so the mean
+            // cosine of sampled directions is g
+            let mut integral = 0.0;
+            let mut mean_cos = 0.0;
+            for _ in 0..SAMPLES {
+                let uniform = Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 2.0 - Vec3::ONE;
+                if uniform.mag_sq() <= 1.0 && uniform.mag_sq() > 0.0 {
+                    integral += phase(g, wo, uniform.normalised()) * 4.0 * PI;
+                }
+                let wi = sample_phase(g, wo, &mut rng);
+                assert!((wi.mag() - 1.0).abs() < 1e-4);
+                mean_cos += wi.dot(wo);
+            }
+            let accepted = SAMPLES as f32 * PI / 6.0;
+            assert!((integral / accepted - 1.0).abs() < 0.03, "{g} {integral}");
+            assert!(
+                (mean_cos / SAMPLES as f32 - g).abs() < 0.01,
+                "{g} {mean_cos}"
+            );
+        }
+    }
+
+    #[test]
+    fn regions() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let dense = Medium {
+            scattering: 0.5,
+            absorption: 0.5,
+            g: 0.0,
+        };
+        assert!(Fog::new(Medium::default(), vec![]).is_none());
+        // fog only between x = 2 & x = 4
+        let aabb = Aabb::new(Vec3::new(2.0, -1.0, -1.0), Vec3::new(4.0, 1.0, 1.0));
+        let fog = Fog::new(Medium::default(), vec![(aabb, dense)]).unwrap();
+        let ray = Ray::new(Vec3::ZERO, Vec3::X);
+
+        assert_eq!(fog.transmittance(&ray, 1.5), 1.0);
+        assert!((fog.transmittance(&ray, 3.0) - (-1f32).exp()).abs() < 1e-5);
+        assert!((fog.transmittance(&ray, f32::INFINITY) - (-2f32).exp()).abs() < 1e-5);
+
+        let mut escaped = 0;
+        for _ in 0..SAMPLES {
+            match fog.sample_distance(&ray, f32::INFINITY, &mut rng) {
+                Some((t, medium)) => {
+                    assert!((2.0..4.0).contains(&t));
+                    assert_eq!(medium, dense);
+                }
+                None => escaped += 1,
+            }
+        }
+        let escaped = escaped as f32 / SAMPLES as f32;
+        assert!((escaped - (-2f32).exp()).abs() < 0.005, "{escaped}");
+    }
+
+    #[test]
+    fn parse() {
+        use clap::Parser;
+        let parse = |args: &[&str]| {
+            parse_fog(&RenderSettings::parse_from(
+                std::iter::once("yapt").chain(args.iter().copied()),
+            ))
+        };
+        assert!(matches!(parse(&[]), Ok(None)));
+
+        let fog = parse(&[
+            "--fog-override",
+            "2,-1,-1,4,1,1:scattering=0.5",
+            "--fog-override",
+            "2,-1,-1,4,1,1:g=0.3",
+        ])
+        .unwrap()
+        .unwrap();
+        let aabb = Aabb::new(Vec3::new(2.0, -1.0, -1.0), Vec3::new(4.0, 1.0, 1.0));
+        let medium = Medium {
+            scattering: 0.5,
+            absorption: 0.0,
+            g: 0.3,
+        };
+        assert_eq!(fog.regions, vec![(aabb, medium)]);
+
+        for entry in [
+            "2,-1,-1,4,1,1",
+            "2,-1,-1,4,1:g=0.3",
+            "4,-1,-1,2,1,1:g=0.3",
+            "2,-1,-1,4,1,1:density=1",
+            "2,-1,-1,4,1,1:g=1",
+            "2,-1,-1,4,1,1:scattering=-1",
+        ] {
+            assert!(parse(&["--fog-override", entry]).is_err(), "{entry}");
+        }
+        let negative = RenderSettings {
+            fog_sigma: Some(vec![-1.0, 0.0]),
+            ..Default::default()
+        };
+        assert!(parse_fog(&negative).is_err());
+    }
+}
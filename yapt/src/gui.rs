@@ -1,7 +1,16 @@
 use crate::prelude::*;
+use crate::tonemap::{tonemap, Tonemap};
 use crate::App;
 use rayon::prelude::*;
 
+const SWATCH_SIZE: usize = 64;
+const SWATCH_SAMPLES: u32 = 16;
+const PREVIEW_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+// fly camera speeds in world units per second & degrees per dragged pixel
+const FLY_SPEED: f32 = 1.0;
+const FLY_FAST_MULT: f32 = 10.0;
+const LOOK_SPEED: f32 = 0.2;
+
 impl eframe::App for App {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         let rs = &mut self.render_settings;
@@ -13,7 +22,7 @@ impl eframe::App for App {
         // -----------------------------------------------
         while let Ok(update) = self.update_recv.try_recv() {
             match update {
-                Update::Calculation(splats, workload_id, ray_count)
+                Update::Calculation(mut splats, workload_id, ray_count)
                     if workload_id == self.workload_id =>
                 {
                     self.work_duration += self.work_start.elapsed();
@@ -21,28 +30,21 @@ impl eframe::App for App {
                     self.splats_done += splats.len() as u64;
 
                     // add splats to image
-                    for splat in splats {
-                        let uv = splat.uv;
-                        let idx = {
-                            assert!(uv[0] <= 1.0 && uv[1] <= 1.0);
-
-                            let x = (uv[0] * u32::from(rs.width) as f32) as usize;
-                            let y = (uv[1] * u32::from(rs.height) as f32) as usize;
-
-                            (y * u32::from(rs.width) as usize + x).min(
-                                u32::from(rs.width) as usize * u32::from(rs.height) as usize - 1,
-                            )
-                        };
+                    for splat in splats.drain(..) {
+                        let idx = splat.pixel(rs.width.get(), rs.height.get());
 
                         self.canvas[idx] += splat.rgb;
+                        if let Some(half) = &mut self.half_buffers {
+                            half.add(idx, splat.rgb, splat.odd);
+                        }
                         self.updated = true;
                     }
+                    self.splat_pool.recycle(splats);
                     self.work_rays += ray_count;
 
                     // work queue finished
-                    if self.splats_done
-                        == u32::from(rs.width) as u64 * u32::from(rs.height) as u64 * rs.samples
-                    {
+                    let pixels = self.region.map_or(self.canvas.len() as u64, |r| r.pixels());
+                    if self.splats_done == self.accumulated_splats + pixels * rs.samples {
                         log::info!(
                             "Reached end of workload: Mrays: {:.2} - Rays shot: {} - elapsed: {:.1} - samples: {}",
                             (self.work_rays as f64 / self.work_duration.as_secs_f64())
@@ -53,11 +55,16 @@ impl eframe::App for App {
                         );
                     }
                 }
-                Update::Calculation(_, workload_id, _) => {
-                    log::trace!("Got splats from previous workload {workload_id}!")
+                Update::Calculation(splats, workload_id, _) => {
+                    log::trace!("Got splats from previous workload {workload_id}!");
+                    self.splat_pool.recycle(splats);
                 }
                 Update::PssmltBootstrapDone => log::info!("PSSMLT bootstrap done!"),
                 Update::NoState => log::info!("No state found!"),
+                Update::Abort(e) => {
+                    log::error!("{e}");
+                    std::process::exit(1);
+                }
             }
         }
 
@@ -65,25 +72,28 @@ impl eframe::App for App {
         // Present framebufferto GUI @ 2Hz if there has been an update
         // This is limited to 2Hz as there is a non trivial amount of overhead
         // -----------------------------------------------
-        if self.updated && self.last_update.elapsed() > std::time::Duration::from_millis(500) {
+        if self.updated && self.last_update.elapsed() >= PREVIEW_INTERVAL {
             // update texture
             let mult = ((u32::from(rs.width) * u32::from(rs.height)) as f64
                 / self.splats_done as f64) as f32;
-            let buf = self
-                .canvas
+            let (op, exposure) = (rs.tonemap, rs.exposure);
+            let canvas = crate::output::composite(
+                &self.canvas,
+                self.region,
+                &self.backdrop,
+                self.splats_done,
+            );
+            let buf = canvas
                 .par_iter()
                 .map(|rgb| {
                     // scale based on samples
                     let rgb = *rgb * mult;
 
-                    // gamma correction
-                    let r = rgb.x.powf(1.0 / 2.2);
-                    let g = rgb.y.powf(1.0 / 2.2);
-                    let b = rgb.z.powf(1.0 / 2.2);
+                    let rgb = tonemap(rgb, exposure, op);
 
-                    let r = (r * 255.0) as u8;
-                    let g = (g * 255.0) as u8;
-                    let b = (b * 255.0) as u8;
+                    let r = (rgb.x * 255.0) as u8;
+                    let g = (rgb.y * 255.0) as u8;
+                    let b = (rgb.z * 255.0) as u8;
 
                     egui::Color32::from_rgb(r, g, b)
                 })
@@ -98,51 +108,53 @@ impl eframe::App for App {
             self.updated = false;
             self.last_update = std::time::Instant::now();
         }
-        let old_samples = rs.samples;
-        let cam = unsafe { CAM.get().as_mut_unchecked() };
-        if ctx.input(|i| i.key_released(egui::Key::W)) {
-            cam.origin += Vec3::Y * 0.01;
-            cam.lower_left += Vec3::Y * 0.01;
-            self.next_workload();
-            self.work_start = std::time::Instant::now();
-            self.work_duration = std::time::Duration::ZERO;
-            self.render_settings.samples = old_samples;
-            self.work_req
-                .send(ComputeChange::WorkSamples(old_samples, self.workload_id))
-                .unwrap();
-        } else if ctx.input(|i| i.key_released(egui::Key::S)) {
-            cam.origin -= Vec3::Y * 0.01;
-            cam.lower_left -= Vec3::Y * 0.01;
-            self.next_workload();
-            self.work_start = std::time::Instant::now();
-            self.work_duration = std::time::Duration::ZERO;
-            self.render_settings.samples = old_samples;
-            self.work_req
-                .send(ComputeChange::WorkSamples(old_samples, self.workload_id))
-                .unwrap();
-        }
-
         let rs = &mut self.render_settings;
         let (ctx, tex_handle) = self.egui_state.as_ref().unwrap();
 
         // -----------------------------------------------
         // Draw GUI
         // -----------------------------------------------
+        let mut clear_region = false;
         egui::TopBottomPanel::top("menu").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
-                ui.menu_button("File", |ui| ui.button("Export Camera"));
+                ui.menu_button("File", |ui| {
+                    if ui.button("Export Camera").clicked() {
+                        export_camera(&rs.filename, self.camera);
+                        ui.close_menu();
+                    }
+                });
                 if ui.button("Add 100 samples").clicked() {
                     if rs.samples == 0 {
                         self.work_start = std::time::Instant::now();
                     }
                     rs.samples += 100;
                     self.work_req
-                        .send(ComputeChange::WorkSamples(100, self.workload_id))
+                        .send(ComputeChange::work(rs.pssmlt, 100, self.workload_id))
                         .unwrap();
                 }
+                if self.region.is_some() && ui.button("Clear region").clicked() {
+                    clear_region = true;
+                }
                 if ui.button("Show render settings").clicked() {
                     self.display_settings = true;
                 }
+                if ui.button("Show materials").clicked() {
+                    self.display_materials = true;
+                }
+                // only changes how the canvas is presented so no re-render is needed
+                let (old_tonemap, old_exposure) = (rs.tonemap, rs.exposure);
+                egui::ComboBox::from_label("Preview")
+                    .selected_text(rs.tonemap.to_string())
+                    .show_ui(ui, |ui| {
+                        for op in Tonemap::ALL {
+                            ui.selectable_value(&mut rs.tonemap, op, op.to_string());
+                        }
+                    });
+                ui.add(egui::Slider::new(&mut rs.exposure, -8.0..=8.0).text("Exposure"));
+                if rs.tonemap != old_tonemap || rs.exposure != old_exposure {
+                    self.updated = true;
+                    self.last_update = std::time::Instant::now() - PREVIEW_INTERVAL;
+                }
                 ui.label(format!(
                     "Mrays: {:.2} - Rays shot: {} - elapsed: {:.1}",
                     (self.work_rays as f64 / self.work_duration.as_secs_f64()) / 1000000 as f64,
@@ -151,11 +163,53 @@ impl eframe::App for App {
                 ));
             });
         });
-        egui::CentralPanel::default().show(ctx, |ui| {
-            let size = tex_handle.size_vec2();
-            let sized_tex = egui::load::SizedTexture::new(tex_handle, size);
-            ui.add(egui::Image::new(sized_tex).shrink_to_fit().max_size(size));
-        });
+        let (drag, selection) = egui::CentralPanel::default()
+            .show(ctx, |ui| {
+                let size = tex_handle.size_vec2();
+                let sized_tex = egui::load::SizedTexture::new(tex_handle, size);
+                let image = egui::Image::new(sized_tex)
+                    .shrink_to_fit()
+                    .max_size(size)
+                    .sense(egui::Sense::drag());
+                let response = ui.add(image);
+
+                // ctrl + drag selects a region to render, otherwise dragging looks around
+                let (ctrl, origin, pos) = ui.input(|i| {
+                    (
+                        i.modifiers.ctrl,
+                        i.pointer.press_origin(),
+                        i.pointer.latest_pos(),
+                    )
+                });
+                if !ctrl {
+                    return (response.drag_delta(), None);
+                }
+                let (Some(origin), Some(pos)) = (origin, pos) else {
+                    return (egui::Vec2::ZERO, None);
+                };
+                let rect = egui::Rect::from_two_pos(origin, pos).intersect(response.rect);
+                if response.dragged() {
+                    ui.painter().rect_stroke(
+                        rect,
+                        0.0,
+                        egui::Stroke::new(1.0_f32, egui::Color32::WHITE),
+                    );
+                }
+                if !response.drag_stopped() {
+                    return (egui::Vec2::ZERO, None);
+                }
+                // image pixels covered by the selection
+                let pixel = |p: egui::Pos2| {
+                    let uv = (p - response.rect.min) / response.rect.size();
+                    [
+                        (uv.x * size.x).round() as u32,
+                        (uv.y * size.y).round() as u32,
+                    ]
+                };
+                let ([x0, y0], [x1, y1]) = (pixel(rect.min), pixel(rect.max));
+                (egui::Vec2::ZERO, Some([x0, y0, x1, y1]))
+            })
+            .inner;
         egui::Window::new("Render Settings")
             .open(&mut self.display_settings)
             .show(ctx, |ui| {
@@ -168,6 +222,150 @@ impl eframe::App for App {
                 ));
                 ui.label(format!("output filename: {}", rs.filename));
                 ui.label(format!("use PSSMLT: {}", rs.pssmlt));
+                ui.label(format!("seed: {}", rs.seed));
+            });
+
+        // swatches are only rendered once when the window is first opened
+        if self.display_materials && self.material_swatches.is_empty() {
+            self.material_swatches = material_swatches(ctx);
+        }
+        egui::Window::new("Materials")
+            .open(&mut self.display_materials)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for (name, tex_handle) in &self.material_swatches {
+                        ui.horizontal(|ui| {
+                            let size = tex_handle.size_vec2();
+                            let sized_tex = egui::load::SizedTexture::new(tex_handle, size);
+                            ui.add(egui::Image::new(sized_tex));
+                            ui.label(name);
+                        });
+                    }
+                });
             });
+
+        let (width, height) = (rs.width.get(), rs.height.get());
+        let ctx = ctx.clone();
+        self.fly(&ctx, drag);
+
+        if let Some(rect) = selection {
+            match Tile::from_rect(rect, width, height) {
+                Ok(region) => {
+                    self.set_region(Some(region));
+                    self.restart();
+                }
+                Err(e) => log::warn!("{e}"),
+            }
+        } else if clear_region {
+            self.set_region(None);
+            self.restart();
+        }
+    }
+}
+
+impl App {
+    // WASD/QE move along the camera's axes (hold shift to go faster) and dragging the
+    // render looks around, any movement restarts the render
+    fn fly(&mut self, ctx: &egui::Context, drag: egui::Vec2) {
+        let (dir, dt, fast) = ctx.input(|i| {
+            let key = |k| if i.key_down(k) { 1.0 } else { 0.0 };
+            let dir = Vec3::new(
+                key(egui::Key::D) - key(egui::Key::A),
+                key(egui::Key::E) - key(egui::Key::Q),
+                key(egui::Key::W) - key(egui::Key::S),
+            );
+            (dir, i.stable_dt.min(0.1), i.modifiers.shift)
+        });
+        let dir = if ctx.wants_keyboard_input() {
+            Vec3::ZERO
+        } else {
+            dir
+        };
+        if dir == Vec3::ZERO && drag == egui::Vec2::ZERO {
+            return;
+        }
+
+        let cam = unsafe { CAM.get().as_mut_unchecked() };
+        let [right, up, forward] = cam.basis();
+
+        let speed = FLY_SPEED * dt * if fast { FLY_FAST_MULT } else { 1.0 };
+        let origin = cam.origin + speed * (dir.x * right + dir.y * up + dir.z * forward);
+
+        // yaw about up then pitch about right (dragging right/up turns right/up)
+        let rotate = |axis: Vec3, degrees: f32, v: Vec3| {
+            let (s, c) = (0.5 * degrees.to_radians()).sin_cos();
+            Quaternion::new(c, s * axis.x, s * axis.y, s * axis.z).rotate(v)
+        };
+        let (yaw, pitch) = (-drag.x * LOOK_SPEED, -drag.y * LOOK_SPEED);
+        let (forward, right) = (rotate(up, yaw, forward), rotate(up, yaw, right));
+        let (forward, up) = (rotate(right, pitch, forward), rotate(right, pitch, up));
+
+        *cam = cam.reoriented(origin, forward, up, &self.render_settings);
+        self.restart();
+        ctx.request_repaint();
+    }
+    // restart the render with the same number of samples
+    fn restart(&mut self) {
+        let old_samples = self.render_settings.samples;
+        self.next_workload();
+        self.work_start = std::time::Instant::now();
+        self.work_duration = std::time::Duration::ZERO;
+        self.render_settings.samples = old_samples;
+        self.work_req
+            .send(ComputeChange::work(
+                self.render_settings.pssmlt,
+                old_samples,
+                self.workload_id,
+            ))
+            .unwrap();
+    }
+}
+
+// writes the live camera next to the output as {stem}_camera.json
+fn export_camera(filename: &str, camera: Option<usize>) {
+    let cam = unsafe { CAM.get().as_ref_unchecked() };
+    let stem = match filename.rsplit_once('.') {
+        Some((stem, _)) => stem,
+        None if filename.is_empty() => "render",
+        None => filename,
+    };
+    let path = format!("{stem}_camera.json");
+    let json = serde_json::to_string_pretty(&cam.export_json(camera.unwrap_or(0))).unwrap();
+    match std::fs::write(&path, json) {
+        Ok(()) => log::info!("Exported camera to {path}"),
+        Err(e) => log::error!("Failed to export camera to {path}\n{e}"),
+    }
+}
+
+fn material_swatches(ctx: &egui::Context) -> Vec<(String, egui::TextureHandle)> {
+    let mats = unsafe { MATERIALS.get().as_ref_unchecked() };
+
+    let mut names = vec![Vec::new(); mats.len()];
+    if let Some(mat_names) = MATERIAL_NAMES.lock().unwrap().get() {
+        for (name, &idx) in mat_names {
+            names[idx].push(name.clone());
+        }
     }
+
+    let mut rng = rand::thread_rng();
+    names
+        .into_iter()
+        .enumerate()
+        .map(|(i, mut names)| {
+            names.sort();
+            let name = if names.is_empty() {
+                i.to_string()
+            } else {
+                names.join(", ")
+            };
+            let rgba = crate::swatch::render_swatch(i, SWATCH_SIZE, SWATCH_SAMPLES, &mut rng);
+            let image = egui::ColorImage::from_rgba_unmultiplied([SWATCH_SIZE, SWATCH_SIZE], &rgba);
+            let tex_handle = ctx.load_texture(
+                format!("swatch {i}"),
+                image,
+                egui::TextureOptions::default(),
+            );
+            (name, tex_handle)
+        })
+        .collect()
 }
@@ -0,0 +1,115 @@
+use crate::prelude::*;
+use std::fmt::Write;
+
+// settings that don't change the rendered image
+const IGNORED_SETTINGS: [&str; 3] = ["filename", "headless", "num_threads"];
+
+// FNV-1a (64 bit), unlike std's hashers this is stable between builds
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+}
+
+impl Write for Fnv1a {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for b in s.bytes() {
+            self.0 = (self.0 ^ b as u64).wrapping_mul(Self::PRIME);
+        }
+        Ok(())
+    }
+}
+
+// hash of everything that determines the rendered image: the loaded scene data
+// (geometry, materials, textures, sidedness, normal maps, environment map) and the
+// resolved settings, must be called after the scene has been loaded
+#[must_use]
+pub fn scene_hash(render_settings: &RenderSettings) -> u64 {
+    let mut h = Fnv1a(Fnv1a::OFFSET);
+    unsafe {
+        write!(h, "{:?}", VERTICES.get().as_ref_unchecked()).unwrap();
+        write!(h, "{:?}", NORMALS.get().as_ref_unchecked()).unwrap();
+        write!(h, "{:?}", TANGENTS.get().as_ref_unchecked()).unwrap();
+        write!(h, "{:?}", UVS.get().as_ref_unchecked()).unwrap();
+        write!(h, "{:?}", TRIANGLES.get().as_ref_unchecked()).unwrap();
+        write!(h, "{:?}", MESHES.get().as_ref_unchecked()).unwrap();
+        write!(h, "{:?}", INSTANCES.get().as_ref_unchecked()).unwrap();
+        write!(h, "{:?}", MATERIALS.get().as_ref_unchecked()).unwrap();
+        write!(h, "{:?}", TEXTURES.get().as_ref_unchecked()).unwrap();
+        write!(h, "{:?}", SINGLE_SIDED.get().as_ref_unchecked()).unwrap();
+        write!(h, "{:?}", NORMAL_MAPS.get().as_ref_unchecked()).unwrap();
+        match ENVMAP.get().as_ref_unchecked() {
+            EnvMap::Solid(v) => write!(h, "{v:?}").unwrap(),
+            EnvMap::Gradient { top, bottom } => write!(h, "{top:?} {bottom:?}").unwrap(),
+            EnvMap::Image(t, _, rotation) => write!(h, "{:?} {rotation:?}", t.data).unwrap(),
+            EnvMap::Sky(sky) => write!(h, "{:?} {}", sky.sun_dir, sky.turbidity).unwrap(),
+        }
+    }
+    write_settings(&mut h, render_settings);
+    h.0
+}
+
+fn write_settings(h: &mut Fnv1a, render_settings: &RenderSettings) {
+    let mut settings = serde_json::to_value(render_settings).unwrap();
+    let map = settings.as_object_mut().unwrap();
+    for key in IGNORED_SETTINGS {
+        map.remove(key);
+    }
+    write_json(h, &settings);
+}
+
+// JSON objects are written with sorted keys so key order doesn't matter
+fn write_json(h: &mut Fnv1a, value: &serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut keys: Vec<_> = map.keys().collect();
+            keys.sort();
+            for key in keys {
+                write!(h, "{key:?}:").unwrap();
+                write_json(h, &map[key]);
+                write!(h, ",").unwrap();
+            }
+        }
+        v => write!(h, "{v};").unwrap(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn hash(rs: &RenderSettings) -> u64 {
+        let mut h = Fnv1a(Fnv1a::OFFSET);
+        write_settings(&mut h, rs);
+        h.0
+    }
+
+    #[test]
+    fn settings_hash() {
+        let base = RenderSettings::parse_from(["yapt", "--seed", "3"]);
+        let same = RenderSettings::parse_from(["yapt", "-o", "other.exr", "--seed", "3"]);
+        let seed = RenderSettings::parse_from(["yapt", "--seed", "4"]);
+        let roughness =
+            RenderSettings::parse_from(["yapt", "--seed", "3", "--roughness-min", "0.01"]);
+
+        assert_eq!(hash(&base), hash(&same));
+        assert_ne!(hash(&base), hash(&seed));
+        assert_ne!(hash(&base), hash(&roughness));
+
+        let json = |s: &str| {
+            let mut h = Fnv1a(Fnv1a::OFFSET);
+            write_json(&mut h, &serde_json::from_str(s).unwrap());
+            h.0
+        };
+        assert_eq!(
+            json(r#"{"a": 1, "b": [2.5]}"#),
+            json(r#"{"b": [2.5], "a": 1}"#)
+        );
+        assert_ne!(
+            json(r#"{"a": 1, "b": [2.5]}"#),
+            json(r#"{"a": 1, "b": [2.6]}"#)
+        );
+    }
+}
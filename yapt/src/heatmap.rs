@@ -0,0 +1,139 @@
+use crate::prelude::*;
+use rayon::prelude::*;
+
+// values above this percentile are clamped to the top of the colour map
+const PERCENTILE: f32 = 0.99;
+
+#[derive(clap::ValueEnum, Copy, Clone, Default)]
+pub enum HeatmapScale {
+    Linear,
+    Log,
+    #[default]
+    Percentile,
+}
+
+impl fmt::Display for HeatmapScale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Linear => "linear",
+            Self::Log => "log",
+            Self::Percentile => "percentile",
+        };
+        write!(f, "{s}")
+    }
+}
+
+// BVH traversal steps of each pixel's centre ray mapped through the heatmap colour map,
+// including the TLAS and the meshes of the instances the ray enters
+#[must_use]
+pub fn bvh_heatmap(width: u32, height: u32, scale: HeatmapScale) -> Vec<Vec3> {
+    let cam = unsafe { CAM.get().as_ref_unchecked() };
+    let world = crate::integrator::World::global();
+
+    let steps: Vec<usize> = (0..width as u64 * height as u64)
+        .into_par_iter()
+        .map(|i| {
+            let ray = cam.get_centre_ray(i);
+            let instanced: usize = world
+                .tlas
+                .traverse(&ray)
+                .into_iter()
+                .flatten()
+                .map(|i| {
+                    let instance = &world.instances[i];
+                    instance.traverse_steps(&world.meshes[instance.mesh], &ray)
+                })
+                .sum();
+            world.bvh.traverse_steps(&ray) + world.tlas.traverse_steps(&ray) + instanced
+        })
+        .collect();
+
+    normalise(&steps, scale)
+        .into_iter()
+        .map(crate::heatmap)
+        .collect()
+}
+
+// map step counts to [0, 1]
+#[must_use]
+pub fn normalise(steps: &[usize], scale: HeatmapScale) -> Vec<f32> {
+    let max = match scale {
+        HeatmapScale::Linear | HeatmapScale::Log => steps.iter().copied().max().unwrap_or(0),
+        HeatmapScale::Percentile if steps.is_empty() => 0,
+        HeatmapScale::Percentile => {
+            let mut steps = steps.to_vec();
+            let idx = ((steps.len() - 1) as f32 * PERCENTILE) as usize;
+            *steps.select_nth_unstable(idx).1
+        }
+    }
+    .max(1) as f32;
+
+    steps
+        .iter()
+        .map(|&s| match scale {
+            HeatmapScale::Log => (s as f32).ln_1p() / max.ln_1p(),
+            HeatmapScale::Linear | HeatmapScale::Percentile => (s as f32 / max).min(1.0),
+        })
+        .collect()
+}
+
+// samples (splats) of each pixel mapped through the heatmap colour map
+#[must_use]
+pub fn spp_heatmap(samples: &[u32], scale: HeatmapScale) -> Vec<Vec3> {
+    let samples: Vec<usize> = samples.iter().map(|&n| n as usize).collect();
+    normalise(&samples, scale)
+        .into_iter()
+        .map(crate::heatmap)
+        .collect()
+}
+
+pub fn save_heatmap(heatmap: &[Vec3], width: u32, height: u32, path: &str) {
+    let buf: Vec<u8> = heatmap
+        .iter()
+        .map(|rgb| rgb.saturate())
+        .flat_map(|rgb| [rgb.x, rgb.y, rgb.z])
+        .map(|v| (v * 255.0) as u8)
+        .collect();
+
+    match image::save_buffer(path, &buf, width, height, image::ColorType::Rgb8) {
+        Ok(()) => log::info!("Saved heatmap to {path}"),
+        Err(e) => log::error!("Failed to save heatmap to {path}\n{e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outlier() {
+        // typical pixels between 10 and 30 steps with a single pathological pixel
+        let mut steps: Vec<usize> = (0..1000).map(|i| 10 + i % 21).collect();
+        steps[500] = 100_000;
+
+        let spread = |scale| {
+            let t = normalise(&steps, scale);
+            let typical = t.iter().enumerate().filter(|(i, _)| *i != 500);
+            let max = typical.clone().map(|(_, v)| *v).fold(0.0, f32::max);
+            let min = typical.map(|(_, v)| *v).fold(1.0, f32::min);
+            assert!(t.iter().all(|v| (0.0..=1.0).contains(v)));
+            max - min
+        };
+
+        assert!(spread(HeatmapScale::Linear) < 0.01);
+        assert!(spread(HeatmapScale::Log) > 0.05);
+        assert!(spread(HeatmapScale::Percentile) > 0.5);
+    }
+
+    #[test]
+    fn spp() {
+        // a region of 4 spp in an otherwise unrendered frame
+        let samples = [0, 4, 4, 0];
+        let heatmap = spp_heatmap(&samples, HeatmapScale::Linear);
+        assert_eq!(
+            heatmap,
+            [0.0, 1.0, 1.0, 0.0].map(crate::heatmap),
+            "unrendered pixels are the bottom of the colour map"
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use crate::prelude::*;
+use bvh::aabb::{Aabb, Aabound};
+
+// id of hits on instances, they can't be lights so they're never SAMPLABLE
+pub const INSTANCE_ID: usize = usize::MAX;
+
+// geometry of a mesh shared by all of its instances, in the mesh's own space
+#[derive(Debug)]
+pub struct Blas {
+    pub prims: Vec<Primitive>,
+    pub bvh: Bvh,
+}
+
+impl Blas {
+    #[must_use]
+    pub fn new(mut prims: Vec<Primitive>) -> Self {
+        let bvh = Bvh::new(&mut prims);
+        Self { prims, bvh }
+    }
+}
+
+// a copy of a Blas (index into MESHES) placed in the scene by transform
+#[derive(Debug)]
+pub struct Instance {
+    pub mesh: usize,
+    transform: Mat4,
+    inverse: Mat4,
+    normal_matrix: Mat4,
+    bounds: Aabb,
+}
+
+impl Instance {
+    // None for empty meshes & singular (flattening) transforms
+    #[must_use]
+    pub fn new(mesh: usize, blas: &Blas, transform: Mat4) -> Option<Self> {
+        let inverse = transform.inverse()?;
+        let local = blas.bvh.bounds()?;
+        let bounds = (0..8)
+            .map(|i| {
+                let pick = |bit: usize, min: f32, max: f32| if i & bit == 0 { min } else { max };
+                transform.mul_point(Vec3::new(
+                    pick(1, local.min.x, local.max.x),
+                    pick(2, local.min.y, local.max.y),
+                    pick(4, local.min.z, local.max.z),
+                ))
+            })
+            .map(|c| Aabb::new(c, c))
+            .reduce(Aabb::merge)
+            .unwrap();
+        Some(Self {
+            mesh,
+            transform,
+            inverse,
+            normal_matrix: inverse.transpose(),
+            bounds,
+        })
+    }
+    // the ray in the mesh's space and the world space length of a unit distance along it
+    #[must_use]
+    fn local_ray(&self, ray: &Ray) -> (Ray, f32) {
+        let local = Ray::new(
+            self.inverse.mul_point(ray.origin),
+            self.inverse.mul_dir(ray.dir),
+        );
+        let scale = self.transform.mul_dir(local.dir).mag();
+        (local, scale)
+    }
+    // closest hit (in world space) within clip
+    #[must_use]
+    pub fn intersect(
+        &self,
+        blas: &Blas,
+        ray: &Ray,
+        clip: ClipRange,
+        rng: &mut impl MinRng,
+    ) -> Intersection {
+        let (local, scale) = self.local_ray(ray);
+        let clip = ClipRange {
+            near: clip.near / scale,
+            far: clip.far / scale,
+        };
+        let mut sect = closest_hit(&blas.prims, &blas.bvh, &local, clip, rng);
+        if sect.is_none() {
+            return sect;
+        }
+        sect.t *= scale;
+        sect.pos = self.transform.mul_point(sect.pos);
+        sect.nor = self.normal_matrix.mul_dir(sect.nor).normalised();
+        sect.id = INSTANCE_ID;
+        sect
+    }
+    // BVH traversal steps of the ray through the mesh (see Bvh::traverse_steps)
+    #[must_use]
+    pub fn traverse_steps(&self, blas: &Blas, ray: &Ray) -> usize {
+        blas.bvh.traverse_steps(&self.local_ray(ray).0)
+    }
+    // whether anything is hit closer than max_t (see occluded)
+    #[must_use]
+    pub fn occluded(&self, blas: &Blas, ray: &Ray, max_t: f32, rng: &mut impl MinRng) -> bool {
+        let (local, scale) = self.local_ray(ray);
+        blas.bvh.traverse(&local).into_iter().flatten().any(|i| {
+            blas.prims[i]
+                .intersect_t(&local, rng)
+                .is_some_and(|t| crate::T_MIN < t * scale && t * scale < max_t)
+        })
+    }
+}
+
+impl Aabound for Instance {
+    fn aabb(&self) -> Aabb {
+        self.bounds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn instance() {
+        crate::scene::test_scene();
+        let mut rng = StdRng::seed_from_u64(0);
+        let blas = Blas::new(vec![Primitive::Sphere(Sphere::new(Vec3::ZERO, 1.0, 0))]);
+        let rotation = Quaternion::from_euler(Vec3::new(30.0, 45.0, 60.0), true);
+        let transform = Mat4::from_trs(Vec3::new(5.0, 1.0, 0.0), rotation.into(), Vec3::splat(2.0));
+        let instance = Instance::new(0, &blas, transform).unwrap();
+        // the same sphere built in world space
+        let world = Sphere::new(Vec3::new(5.0, 1.0, 0.0), 2.0, 0);
+
+        for (origin, dir) in [
+            (Vec3::new(5.0, 1.0, 10.0), -Vec3::Z),
+            (Vec3::new(0.0, 0.0, 0.0), Vec3::new(5.0, 1.5, 0.5)),
+            (Vec3::new(5.5, 1.0, 0.5), Vec3::new(0.3, -1.0, 0.2)),
+        ] {
+            let ray = Ray::new(origin, dir);
+            let sect = instance.intersect(&blas, &ray, ClipRange::NONE, &mut rng);
+            let expected = world.intersect(&ray, &mut rng);
+            assert!(
+                (sect.t - expected.t).abs() < 1e-4,
+                "{} {}",
+                sect.t,
+                expected.t
+            );
+            assert!((sect.pos - expected.pos).mag() < 1e-4);
+            assert!((sect.nor - expected.nor).mag() < 1e-4);
+            assert_eq!((sect.out, sect.id), (expected.out, INSTANCE_ID));
+
+            assert!(instance.occluded(&blas, &ray, sect.t + 1e-3, &mut rng));
+            assert!(!instance.occluded(&blas, &ray, sect.t - 1e-3, &mut rng));
+            // clipping works in world space distances
+            let clip = ClipRange {
+                near: 0.0,
+                far: sect.t - 1e-3,
+            };
+            assert!(instance.intersect(&blas, &ray, clip, &mut rng).is_none());
+        }
+
+        let ray = Ray::new(Vec3::new(0.0, 10.0, 0.0), Vec3::Y);
+        assert!(instance
+            .intersect(&blas, &ray, ClipRange::NONE, &mut rng)
+            .is_none());
+        assert!(Instance::new(0, &blas, Mat4::from_scale(Vec3::new(1.0, 0.0, 1.0))).is_none());
+    }
+}
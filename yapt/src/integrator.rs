@@ -1,42 +1,276 @@
 use crate::prelude::*;
 
-const MAX_DEPTH: u64 = 50;
-const RUSSIAN_ROULETTE_THRESHOLD: u64 = 3;
+pub const MAX_DEPTH: u64 = 50;
+const MAX_SPECULAR_DEPTH: u64 = 256;
+pub const RUSSIAN_ROULETTE_THRESHOLD: u64 = 3;
+
+// non delta bounces before a path is terminated & after which russian roulette starts
+// (see --max-depth & --rr-depth) along with the --clamp of contributions
+#[derive(Copy, Clone, Debug)]
+pub struct PathLimits {
+    pub max_depth: u64,
+    pub rr_depth: u64,
+    // maximum luminance of a contribution not directly seen by the camera (--clamp)
+    pub clamp: f32,
+    // maximum radiance / pdf of a single NEE light sample (--nee-max-radiance)
+    pub nee_max_radiance: f32,
+}
+
+impl Default for PathLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: MAX_DEPTH,
+            rr_depth: RUSSIAN_ROULETTE_THRESHOLD,
+            clamp: f32::INFINITY,
+            nee_max_radiance: f32::INFINITY,
+        }
+    }
+}
+
+// bounces off delta materials don't lose energy so are counted separately with a
+// higher limit and excluded from russian roulette, this stops long specular chains
+// from being truncated by max_depth
+#[derive(Default)]
+struct PathDepth {
+    depth: u64,
+    specular: u64,
+    limits: PathLimits,
+}
+
+impl PathDepth {
+    #[must_use]
+    fn new(limits: PathLimits) -> Self {
+        Self {
+            limits,
+            ..Default::default()
+        }
+    }
+    // records a bounce, returns false if the path should be terminated
+    #[must_use]
+    fn bounce(&mut self, delta: bool) -> bool {
+        if delta {
+            self.specular += 1;
+            self.specular < MAX_SPECULAR_DEPTH
+        } else {
+            self.depth += 1;
+            self.depth < self.limits.max_depth
+        }
+    }
+    #[must_use]
+    fn russian_roulette(&self) -> bool {
+        self.depth > self.limits.rr_depth
+    }
+    #[must_use]
+    fn is_camera_ray(&self) -> bool {
+        self.depth + self.specular == 0
+    }
+}
+
+// what paths are traced against, the scene globals (see World::global) or a standalone
+// scene such as a material swatch (see swatch.rs), materials are always MATERIALS
+#[derive(Clone, Copy)]
+pub struct World<'a> {
+    pub prims: &'a [Primitive],
+    pub bvh: &'a Bvh,
+    pub meshes: &'a [Blas],
+    pub instances: &'a [Instance],
+    pub tlas: &'a Bvh,
+    pub sampler: &'a crate::light_sampler::LightSampler,
+    pub envmap: &'a EnvMap,
+    pub env_prob: f32,
+    pub sun: &'a Option<DirectionalLight>,
+    pub sun_prob: f32,
+    pub links: &'a crate::light_link::LightLinks,
+    pub fog: &'a Option<crate::fog::Fog>,
+    pub backplate: &'a Option<Backplate>,
+}
+
+impl World<'static> {
+    #[must_use]
+    pub fn global() -> Self {
+        unsafe {
+            Self {
+                prims: TRIANGLES.get().as_ref_unchecked(),
+                bvh: BVH.get().as_ref_unchecked(),
+                meshes: MESHES.get().as_ref_unchecked(),
+                instances: INSTANCES.get().as_ref_unchecked(),
+                tlas: TLAS.get().as_ref_unchecked(),
+                sampler: LIGHT_SAMPLER.get().as_ref_unchecked(),
+                envmap: ENVMAP.get().as_ref_unchecked(),
+                env_prob: *ENV_SAMPLING_PROB.get(),
+                sun: SUN.get().as_ref_unchecked(),
+                sun_prob: *SUN_SAMPLING_PROB.get(),
+                links: LIGHT_LINKS.get().as_ref_unchecked(),
+                fog: crate::FOG.get().as_ref_unchecked(),
+                backplate: BACKPLATE.get().as_ref_unchecked(),
+            }
+        }
+    }
+}
+
+impl World<'_> {
+    // closest intersection with near <= t <= far, anything outside the range is ignored
+    // (so surfaces behind a clipped surface are still visible)
+    #[must_use]
+    pub fn intersect(&self, ray: &Ray, clip: ClipRange, rng: &mut impl MinRng) -> Intersection {
+        let mut sect = closest_hit(self.prims, self.bvh, ray, clip, rng);
+        for i in self.tlas.traverse(ray).into_iter().flatten() {
+            let instance = &self.instances[i];
+            sect.min(instance.intersect(&self.meshes[instance.mesh], ray, clip, rng));
+        }
+        sect
+    }
+    // intersection with primitive idx if nothing else is in the way
+    #[must_use]
+    pub fn intersect_idx(&self, ray: &Ray, idx: usize, rng: &mut impl MinRng) -> Intersection {
+        let sect = self.prims[idx].intersect(ray, rng);
+        if sect.is_none() || self.occluded(ray, sect.t, Some(idx), rng) {
+            return Intersection::NONE;
+        }
+        sect
+    }
+    // whether anything (other than primitive exclude) is hit closer than max_t, stops at
+    // the first hit found and only finds the distance to it (any-hit for shadow rays)
+    #[must_use]
+    pub fn occluded(
+        &self,
+        ray: &Ray,
+        max_t: f32,
+        exclude: Option<usize>,
+        rng: &mut impl MinRng,
+    ) -> bool {
+        self.bvh.traverse(ray).into_iter().flatten().any(|i| {
+            Some(i) != exclude
+                && self.prims[i]
+                    .intersect_t(ray, rng)
+                    .is_some_and(|t| crate::T_MIN < t && t < max_t)
+        }) || self.tlas.traverse(ray).into_iter().flatten().any(|i| {
+            let instance = &self.instances[i];
+            instance.occluded(&self.meshes[instance.mesh], ray, max_t, rng)
+        })
+    }
+    // radiance of rays escaping the scene, camera rays see the backplate in front of
+    // the environment
+    #[must_use]
+    pub fn background(&self, ray: &Ray, camera_ray: bool) -> Vec3 {
+        if camera_ray {
+            if let Some(rgb) = self.backplate.as_ref().and_then(|b| b.hit(ray)) {
+                return rgb;
+            }
+        }
+        let sun = self.sun.as_ref().map_or(Vec3::ZERO, |sun| sun.le(ray.dir));
+        self.envmap.sample_dir(ray.dir) + sun
+    }
+}
 
 pub struct Naive {}
 
 impl Naive {
     #[must_use]
-    pub fn rgb(mut ray: Ray, rng: &mut impl MinRng) -> (Vec3, u64) {
+    pub fn rgb(
+        ray: Ray,
+        rng: &mut impl MinRng,
+        isolate: Option<usize>,
+        primary: Option<Intersection>,
+        limits: PathLimits,
+        lambda: &Lambda,
+    ) -> (Vec3, u64) {
+        Self::rgb_in(&World::global(), ray, rng, isolate, primary, limits, lambda)
+    }
+    // rgb of a path through world instead of the scene globals
+    #[must_use]
+    pub fn rgb_in(
+        world: &World,
+        mut ray: Ray,
+        rng: &mut impl MinRng,
+        isolate: Option<usize>,
+        mut primary: Option<Intersection>,
+        limits: PathLimits,
+        lambda: &Lambda,
+    ) -> (Vec3, u64) {
         let mats = unsafe { MATERIALS.get().as_ref_unchecked() };
-        let envmap = unsafe { ENVMAP.get().as_ref_unchecked() };
+        let (links, fog) = (world.links, world.fog);
         let (mut tp, mut rgb) = (Vec3::ONE, Vec3::ZERO);
 
-        let mut depth = 0;
+        let mut depth = PathDepth::new(limits);
+        let mut rays = 0;
+        // material of the previous surface (for light linking)
+        let mut prev_mat = None;
 
-        while depth < MAX_DEPTH {
-            depth += 1;
+        loop {
+            rays += 1;
 
-            let sect = get_intersection(&ray, rng);
+            let sect = primary
+                .take()
+                .unwrap_or_else(|| world.intersect(&ray, ClipRange::NONE, rng));
 
-            if sect.is_none() {
-                rgb += tp * envmap.sample_dir(ray.dir);
+            if depth.is_camera_ray() && is_masked(&sect, isolate) {
                 break;
             }
 
-            let mat = &mats[sect.mat];
+            let delta = if let Some((t, medium)) = fog
+                .as_ref()
+                .and_then(|fog| fog.sample_distance(&ray, sect.t_max(), rng))
+            {
+                // ----
+                // fog scattering
+                // ----
+                tp *= medium.albedo();
+                let pos = ray.origin + t * ray.dir;
+                ray = Ray::new(pos, crate::fog::sample_phase(medium.g, ray.dir, rng));
+                // the fog is lit by every light
+                prev_mat = None;
+                false
+            } else {
+                // ----
+                // surface interaction
+                // ----
+                if sect.is_none() {
+                    let le = tp * lambda.illuminant(world.background(&ray, depth.is_camera_ray()));
+                    rgb += if depth.is_camera_ray() {
+                        le
+                    } else {
+                        clamp_luminance(le, limits.clamp)
+                    };
+                    break;
+                }
 
-            let wo = ray.dir;
+                let mat = &mats[sect.mat];
 
-            rgb += mat.le(sect.pos, ray.dir) * tp;
+                if let Mat::Holdout = mat {
+                    if depth.is_camera_ray() {
+                        rgb += tp * lambda.illuminant(world.background(&ray, true));
+                    }
+                    break;
+                }
 
-            if mat.scatter(&sect, &mut ray, rng) {
-                break;
-            }
+                tp *= mat.interior_absorption(&sect, lambda);
+
+                let wo = ray.dir;
+
+                if prev_mat.is_none_or(|prev| links.is_linked(prev, sect.mat)) {
+                    let le = lambda.illuminant(mat.le(sect.pos, ray.dir, sect.uv, sect.out)) * tp;
+                    rgb += if depth.is_camera_ray() {
+                        le
+                    } else {
+                        clamp_luminance(le, limits.clamp)
+                    };
+                }
+
+                if mat.scatter(&sect, &mut ray, rng) {
+                    break;
+                }
+                prev_mat = Some(sect.mat);
+
+                tp *= mat.eval(&sect, wo, ray.dir, lambda);
 
-            tp *= mat.eval(&sect, wo, ray.dir);
+                mat.is_delta(sect.uv)
+            };
 
-            if depth > RUSSIAN_ROULETTE_THRESHOLD {
+            if !depth.bounce(delta) {
+                break;
+            }
+            if !delta && depth.russian_roulette() {
                 let p = tp.component_max();
                 if rng.gen() > p {
                     break;
@@ -44,11 +278,83 @@ impl Naive {
                 tp /= p;
             }
         }
-        if rgb.contains_nan() {
-            log::warn!("NAN encountered!");
-            return (Vec3::ZERO, 0);
+        (rgb, rays)
+    }
+}
+
+// where a path scatters from, a surface or a point in the fog (with the phase
+// function's asymmetry)
+#[derive(Clone)]
+enum Vertex<'a> {
+    Surface(Intersection, &'a Mat),
+    Medium(Vec3, f32),
+}
+
+impl Vertex<'_> {
+    #[must_use]
+    fn is_delta(&self) -> bool {
+        match self {
+            Self::Surface(sect, mat) => mat.is_delta(sect.uv),
+            Self::Medium(..) => false,
+        }
+    }
+    #[must_use]
+    fn samples_lights(&self) -> bool {
+        match self {
+            Self::Surface(sect, mat) => mat.samples_lights(sect.uv),
+            Self::Medium(..) => true,
+        }
+    }
+    // intersection lights are sampled from (see Tri::sample_ray)
+    #[must_use]
+    fn sect(&self) -> Intersection {
+        match self {
+            Self::Surface(sect, _) => sect.clone(),
+            Self::Medium(pos, _) => Intersection {
+                pos: *pos,
+                ..Intersection::NONE
+            },
+        }
+    }
+    // the fog is lit by every light
+    #[must_use]
+    fn is_linked(&self, links: &crate::light_link::LightLinks, mat: usize) -> bool {
+        match self {
+            Self::Surface(sect, _) => links.is_linked(sect.mat, mat),
+            Self::Medium(..) => true,
+        }
+    }
+    #[must_use]
+    fn spdf(&self, wo: Vec3, wi: Vec3) -> f32 {
+        match self {
+            Self::Surface(sect, mat) => mat.spdf(sect, wo, wi),
+            Self::Medium(_, g) => crate::fog::phase(*g, wo, wi),
+        }
+    }
+    #[must_use]
+    fn bxdf_cos(&self, wo: Vec3, wi: Vec3, lambda: &Lambda) -> Vec3 {
+        match self {
+            Self::Surface(sect, mat) => mat.bxdf_cos(sect, wo, wi, lambda),
+            Self::Medium(_, g) => Vec3::splat(crate::fog::phase(*g, wo, wi)),
+        }
+    }
+    // scatters ray returning the throughput
+    #[must_use]
+    fn scatter(&self, ray: &mut Ray, rng: &mut impl MinRng, lambda: &Lambda) -> Vec3 {
+        let wo = ray.dir;
+        match self {
+            Self::Surface(sect, mat) => {
+                if mat.scatter(sect, ray, rng) {
+                    unreachable!()
+                }
+                mat.eval(sect, wo, ray.dir, lambda)
+            }
+            // phase function sampling is exact
+            Self::Medium(pos, g) => {
+                *ray = Ray::new(*pos, crate::fog::sample_phase(*g, wo, rng));
+                Vec3::ONE
+            }
         }
-        (rgb, depth)
     }
 }
 
@@ -56,110 +362,294 @@ pub struct NEEMIS {}
 
 impl NEEMIS {
     #[must_use]
-    pub fn rgb(mut ray: Ray, rng: &mut impl MinRng, samplable: &[usize]) -> (Vec3, u64) {
+    pub fn rgb(
+        ray: Ray,
+        rng: &mut impl MinRng,
+        samplable: &[usize],
+        isolate: Option<usize>,
+        primary: Option<Intersection>,
+        limits: PathLimits,
+        lambda: &Lambda,
+    ) -> (Vec3, u64) {
+        Self::rgb_in(
+            &World::global(),
+            ray,
+            rng,
+            samplable,
+            isolate,
+            primary,
+            limits,
+            lambda,
+        )
+    }
+    // rgb of a path through world instead of the scene globals
+    #[allow(clippy::too_many_arguments)]
+    #[must_use]
+    pub fn rgb_in(
+        world: &World,
+        mut ray: Ray,
+        rng: &mut impl MinRng,
+        samplable: &[usize],
+        isolate: Option<usize>,
+        primary: Option<Intersection>,
+        limits: PathLimits,
+        lambda: &Lambda,
+    ) -> (Vec3, u64) {
         let mats = unsafe { MATERIALS.get().as_ref_unchecked() };
-        let envmap = unsafe { ENVMAP.get().as_ref_unchecked() };
-        let tris = unsafe { TRIANGLES.get().as_ref_unchecked() };
-        let samplables = unsafe { SAMPLABLE.get().as_ref_unchecked() };
+        let World {
+            prims: tris,
+            sampler,
+            envmap,
+            env_prob,
+            sun,
+            sun_prob,
+            links,
+            fog,
+            ..
+        } = *world;
 
-        if samplable.is_empty() {
-            return Naive::rgb(ray, rng);
+        if samplable.is_empty() && env_prob == 0.0 && sun_prob == 0.0 {
+            return Naive::rgb_in(world, ray, rng, isolate, primary, limits, lambda);
         }
-        let inverse_samplable = 1.0 / samplable.len() as f32;
+        // probability of picking triangle lights over the environment map & sun
+        let tri_prob = 1.0 - env_prob - sun_prob;
+        // fog transmittance of shadow rays
+        let transmittance = |ray: &Ray, t_max: f32| {
+            fog.as_ref()
+                .map_or(1.0, |fog| fog.transmittance(ray, t_max))
+        };
 
         let mut tp = Vec3::ONE;
+        let mut rgb = Vec3::ZERO;
 
         let mut ray_count = 1;
 
         // ----
         // find first intersection (MIS + NEE doesn't apply to camera rays)
         // ----
-        let mut sect = get_intersection(&ray, rng);
+        let sect = primary.unwrap_or_else(|| world.intersect(&ray, ClipRange::NONE, rng));
 
-        if sect.is_none() {
-            return (envmap.sample_dir(ray.dir), ray_count);
+        if is_masked(&sect, isolate) {
+            return (Vec3::ZERO, ray_count);
         }
 
-        let mut mat = &mats[sect.mat];
+        let mut vertex = if let Some((t, medium)) = fog
+            .as_ref()
+            .and_then(|fog| fog.sample_distance(&ray, sect.t_max(), rng))
+        {
+            tp *= medium.albedo();
+            Vertex::Medium(ray.origin + t * ray.dir, medium.g)
+        } else {
+            if sect.is_none() {
+                return (lambda.illuminant(world.background(&ray, true)), ray_count);
+            }
 
-        let mut rgb = mat.le(sect.pos, ray.dir);
+            let mat = &mats[sect.mat];
 
-        if let Mat::Light(_) = mat {
-            return (rgb, 1);
-        }
+            if let Mat::Holdout = mat {
+                return (lambda.illuminant(world.background(&ray, true)), ray_count);
+            }
+
+            tp *= mat.interior_absorption(&sect, lambda);
+
+            rgb = lambda.illuminant(mat.le(sect.pos, ray.dir, sect.uv, sect.out));
+
+            if let Mat::Light(_) = mat {
+                return (rgb, 1);
+            }
+            Vertex::Surface(sect, mat)
+        };
 
         let mut wo = ray.dir;
+        let mut depth = PathDepth::new(limits);
 
-        for depth in 1..MAX_DEPTH {
+        loop {
             // ----
             // Light sampling
             // ----
-            // pick light
-            let light_idx = rng.gen_range(0.0..(samplable.len() as f32)) as usize;
-            let light_idx = samplables[light_idx];
-            let light = &tris[light_idx];
+            let origin = vertex.sect();
+            // pick between the environment map, the sun and triangle lights (one-sample MIS)
+            let u = rng.gen();
+            if env_prob > 0.0 && u < env_prob {
+                // sample ray
+                let (env_dir, env_pdf) = envmap.sample_light(rng);
+                let env_ray = Ray::new(origin.shadow_origin(), env_dir);
 
-            // sample ray
-            let (light_ray, light_le) = light.sample_ray(&sect, rng);
+                // check for obstructions
+                ray_count += 1;
+                if vertex.samples_lights() && !world.occluded(&env_ray, f32::INFINITY, None, rng) {
+                    let light_pdf = env_pdf * env_prob;
 
-            // check for obstructions
-            ray_count += 1;
-            let light_sect = intersect_idx(&light_ray, light_idx, rng);
-            if !light_sect.is_none() && !mat.is_delta(sect.uv) {
-                let light_pdf = light.pdf(&light_sect, &light_ray) * inverse_samplable;
+                    // add light contribution if path is reachable by bsdf
+                    let light_bsdf_pdf = vertex.spdf(wo, env_dir);
+                    if light_bsdf_pdf != 0.0 && light_pdf != 0.0 {
+                        rgb += clamp_luminance(
+                            tp * power_heuristic(light_pdf, light_bsdf_pdf)
+                                * vertex.bxdf_cos(wo, env_dir, lambda)
+                                * transmittance(&env_ray, f32::INFINITY)
+                                * clamp_radiance(
+                                    lambda.illuminant(envmap.sample_dir(env_dir)) / light_pdf,
+                                    limits.nee_max_radiance,
+                                ),
+                            limits.clamp,
+                        );
+                    }
+                }
+            } else if let Some(sun) = sun
+                .as_ref()
+                .filter(|_| u < env_prob + sun_prob || samplable.is_empty())
+            {
+                // sample ray
+                let (sun_dir, sun_pdf) = sun.sample_light(rng);
+                let sun_ray = Ray::new(origin.shadow_origin(), sun_dir);
 
-                // add light contribution if path is reachable by bsdf
-                let light_bsdf_pdf = mat.spdf(&sect, wo, light_ray.dir);
-                if light_bsdf_pdf != 0.0 && light_pdf != 0.0 {
-                    rgb += tp
-                        * power_heuristic(light_pdf, light_bsdf_pdf)
-                        * mat.bxdf_cos(&sect, wo, light_ray.dir)
-                        * light_le
-                        / light_pdf;
+                // check for obstructions
+                ray_count += 1;
+                if vertex.samples_lights() && !world.occluded(&sun_ray, f32::INFINITY, None, rng) {
+                    let light_pdf = sun_pdf * sun_prob;
+
+                    // add light contribution if path is reachable by bsdf
+                    let light_bsdf_pdf = vertex.spdf(wo, sun_dir);
+                    if light_bsdf_pdf != 0.0 && light_pdf != 0.0 {
+                        rgb += clamp_luminance(
+                            tp * power_heuristic(light_pdf, light_bsdf_pdf)
+                                * vertex.bxdf_cos(wo, sun_dir, lambda)
+                                * transmittance(&sun_ray, f32::INFINITY)
+                                * clamp_radiance(
+                                    lambda.illuminant(sun.le(sun_dir)) / light_pdf,
+                                    limits.nee_max_radiance,
+                                ),
+                            limits.clamp,
+                        );
+                    }
+                }
+            } else {
+                // pick light
+                let (light_idx, pick_prob) = sampler.sample(samplable, rng.gen());
+                let light = &tris[light_idx];
+
+                // sample ray
+                let (light_ray, light_le) = light.sample_ray(&origin, rng);
+
+                // check for obstructions
+                ray_count += 1;
+                let light_sect = world.intersect_idx(&light_ray, light_idx, rng);
+                if !light_sect.is_none()
+                    && vertex.samples_lights()
+                    && vertex.is_linked(links, light.mat())
+                {
+                    let light_pdf = light.pdf(&light_sect, &light_ray) * tri_prob * pick_prob;
+
+                    // add light contribution if path is reachable by bsdf
+                    let light_bsdf_pdf = vertex.spdf(wo, light_ray.dir);
+                    if light_bsdf_pdf != 0.0 && light_pdf != 0.0 {
+                        rgb += clamp_luminance(
+                            tp * power_heuristic(light_pdf, light_bsdf_pdf)
+                                * vertex.bxdf_cos(wo, light_ray.dir, lambda)
+                                * transmittance(&light_ray, light_sect.t)
+                                * clamp_radiance(
+                                    lambda.illuminant(light_le) / light_pdf,
+                                    limits.nee_max_radiance,
+                                ),
+                            limits.clamp,
+                        );
+                    }
                 }
             }
 
             // ----
             // BSDF sampling
             // ----
-            if mat.scatter(&sect, &mut ray, rng) {
-                unreachable!()
-            }
-
-            tp *= mat.eval(&sect, wo, ray.dir);
+            tp *= vertex.scatter(&mut ray, rng, lambda);
+            let delta = vertex.is_delta();
 
             ray_count += 1;
-            let new_sect = get_intersection(&ray, rng);
+            let new_sect = world.intersect(&ray, ClipRange::NONE, rng);
+
+            // the path scatters in the fog before reaching new_sect, the transmittance
+            // cancels with the probability of getting to the medium vertex (or surface)
+            // so isn't part of the MIS weights
+            if let Some((t, medium)) = fog
+                .as_ref()
+                .and_then(|fog| fog.sample_distance(&ray, new_sect.t_max(), rng))
+            {
+                tp *= medium.albedo();
+                vertex = Vertex::Medium(ray.origin + t * ray.dir, medium.g);
+                wo = ray.dir;
+
+                if !depth.bounce(delta) {
+                    break;
+                }
+                if !delta && depth.russian_roulette() {
+                    let p = tp.component_max();
+                    if rng.gen() > p {
+                        break;
+                    }
+                    tp /= p;
+                }
+                continue;
+            }
+
             if new_sect.is_none() {
-                rgb += tp * envmap.sample_dir(ray.dir);
+                // hit environment map calculate weight if it is samplable
+                let env_le = lambda.illuminant(envmap.sample_dir(ray.dir));
+                let le = if env_prob > 0.0 && vertex.samples_lights() {
+                    let bsdf_pdf = vertex.spdf(wo, ray.dir);
+                    let bsdf_env_pdf = envmap.pdf(ray.dir) * env_prob;
+                    tp * power_heuristic(bsdf_pdf, bsdf_env_pdf) * env_le
+                } else {
+                    tp * env_le
+                };
+                rgb += clamp_luminance(le, limits.clamp);
+                if let Some(sun) = sun {
+                    let sun_le = lambda.illuminant(sun.le(ray.dir));
+                    let le = if sun_prob > 0.0 && vertex.samples_lights() {
+                        let bsdf_pdf = vertex.spdf(wo, ray.dir);
+                        let bsdf_sun_pdf = sun.pdf(ray.dir) * sun_prob;
+                        tp * power_heuristic(bsdf_pdf, bsdf_sun_pdf) * sun_le
+                    } else {
+                        tp * sun_le
+                    };
+                    rgb += clamp_luminance(le, limits.clamp);
+                }
                 break;
             }
 
             let new_mat = &mats[new_sect.mat];
+            tp *= new_mat.interior_absorption(&new_sect, lambda);
+            let new_le =
+                lambda.illuminant(new_mat.le(new_sect.pos, ray.dir, new_sect.uv, new_sect.out));
 
             // hit samplable calculate weight
-            if samplable.contains(&new_sect.id) && !mat.is_delta(sect.uv) {
-                let bsdf_pdf = mat.spdf(&sect, wo, ray.dir);
-                let bsdf_light_pdf = tris[new_sect.id].pdf(&new_sect, &ray) * inverse_samplable;
-                rgb += tp
-                    * power_heuristic(bsdf_pdf, bsdf_light_pdf)
-                    * new_mat.le(new_sect.pos, ray.dir);
+            if !vertex.is_linked(links, new_sect.mat) {
+                // light isn't linked to the previous surface so contributes nothing
+            } else if samplable.contains(&new_sect.id) && vertex.samples_lights() {
+                let bsdf_pdf = vertex.spdf(wo, ray.dir);
+                let bsdf_light_pdf = tris[new_sect.id].pdf(&new_sect, &ray)
+                    * tri_prob
+                    * sampler.pmf(samplable, new_sect.id);
+                rgb += clamp_luminance(
+                    tp * power_heuristic(bsdf_pdf, bsdf_light_pdf) * new_le,
+                    limits.clamp,
+                );
             } else {
-                rgb += tp * new_mat.le(new_sect.pos, ray.dir);
+                rgb += clamp_luminance(tp * new_le, limits.clamp);
             }
 
-            if let Mat::Light(_) = new_mat {
+            if let Mat::Light(_) | Mat::Holdout = new_mat {
                 break;
             }
 
-            sect = new_sect;
-            mat = new_mat;
+            vertex = Vertex::Surface(new_sect, new_mat);
             wo = ray.dir;
 
             // ----
             // Russian Roulette early exit
             // ----
-            if depth > RUSSIAN_ROULETTE_THRESHOLD {
+            if !depth.bounce(delta) {
+                break;
+            }
+            if !delta && depth.russian_roulette() {
                 let p = tp.component_max();
                 if rng.gen() > p {
                     break;
@@ -168,54 +658,886 @@ impl NEEMIS {
             }
         }
 
-        if rgb.contains_nan() {
-            log::warn!("NAN encountered!");
-            return (Vec3::ZERO, 0);
+        (rgb, ray_count)
+    }
+}
+// path tracer through the heterogeneous medium (VOLUME) using delta tracking for
+// free flight sampling and ratio tracking for the transmittance of shadow rays
+// note: triangle lights are only sampled through NEE (no MIS) and the environment
+// map only through escaping rays
+#[cfg(feature = "volumes")]
+pub struct Volumetric {}
+
+#[cfg(feature = "volumes")]
+impl Volumetric {
+    #[must_use]
+    pub fn rgb(
+        mut ray: Ray,
+        rng: &mut impl MinRng,
+        samplable: &[usize],
+        isolate: Option<usize>,
+        mut primary: Option<Intersection>,
+        limits: PathLimits,
+        lambda: &Lambda,
+    ) -> (Vec3, u64) {
+        let mats = unsafe { MATERIALS.get().as_ref_unchecked() };
+        let Some(volume) = (unsafe { crate::VOLUME.get().as_ref_unchecked() }) else {
+            return NEEMIS::rgb(ray, rng, samplable, isolate, primary, limits, lambda);
+        };
+        let links = unsafe { LIGHT_LINKS.get().as_ref_unchecked() };
+        let (mut tp, mut rgb) = (Vec3::ONE, Vec3::ZERO);
+
+        let mut ray_count = 0;
+        // whether emission hit by the current ray wasn't already accounted for by NEE
+        let mut count_emission = true;
+        // material of the previous surface (for light linking)
+        let mut prev_mat = None;
+
+        let mut depth = PathDepth::new(limits);
+
+        loop {
+            ray_count += 1;
+            let sect = primary
+                .take()
+                .unwrap_or_else(|| get_intersection(&ray, rng));
+
+            if depth.is_camera_ray() && is_masked(&sect, isolate) {
+                break;
+            }
+
+            let delta = if let Some(t) = volume.sample_distance(&ray, sect.t_max(), rng) {
+                // ----
+                // medium scattering (isotropic phase function)
+                // ----
+                tp *= lambda.reflectance(volume.albedo);
+                let pos = ray.origin + t * ray.dir;
+
+                if let Some((_, le)) = sample_lights(pos, None, samplable, volume, rng) {
+                    ray_count += 1;
+                    rgb += tp
+                        * clamp_radiance(lambda.illuminant(le), limits.nee_max_radiance)
+                        * 0.25
+                        * FRAC_1_PI;
+                }
+
+                let cos_theta = rng.gen_range(-1.0..1.0);
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let (sin_phi, cos_phi) = (TAU * rng.gen()).sin_cos();
+                ray = Ray::new(
+                    pos,
+                    Vec3::from_spherical(sin_theta, cos_theta, sin_phi, cos_phi),
+                );
+                count_emission = samplable.is_empty();
+                prev_mat = None;
+                false
+            } else {
+                // ----
+                // surface interaction
+                // ----
+                if sect.is_none() {
+                    rgb += tp * lambda.illuminant(background(&ray, depth.is_camera_ray()));
+                    break;
+                }
+
+                let mat = &mats[sect.mat];
+
+                if let Mat::Holdout = mat {
+                    if depth.is_camera_ray() {
+                        rgb += tp * lambda.illuminant(background(&ray, true));
+                    }
+                    break;
+                }
+
+                tp *= mat.interior_absorption(&sect, lambda);
+
+                if count_emission && prev_mat.is_none_or(|prev| links.is_linked(prev, sect.mat)) {
+                    rgb += tp * lambda.illuminant(mat.le(sect.pos, ray.dir, sect.uv, sect.out));
+                }
+
+                if let Mat::Light(_) = mat {
+                    break;
+                }
+
+                let wo = ray.dir;
+                let delta = mat.is_delta(sect.uv);
+                let nee = mat.samples_lights(sect.uv);
+
+                if nee {
+                    if let Some((light_ray, le)) =
+                        sample_lights(sect.shadow_origin(), Some(sect.mat), samplable, volume, rng)
+                    {
+                        ray_count += 1;
+                        rgb += tp
+                            * mat.bxdf_cos(&sect, wo, light_ray.dir, lambda)
+                            * clamp_radiance(lambda.illuminant(le), limits.nee_max_radiance);
+                    }
+                }
+
+                if mat.scatter(&sect, &mut ray, rng) {
+                    break;
+                }
+                tp *= mat.eval(&sect, wo, ray.dir, lambda);
+                count_emission = !nee || samplable.is_empty();
+                prev_mat = Some(sect.mat);
+                delta
+            };
+
+            if !depth.bounce(delta) {
+                break;
+            }
+            if !delta && depth.russian_roulette() {
+                let p = tp.component_max();
+                if rng.gen() > p {
+                    break;
+                }
+                tp /= p;
+            }
         }
 
         (rgb, ray_count)
     }
 }
+// sample a point on a random triangle light returning the ray towards it and the
+// incoming radiance (attenuated by the medium) divided by the pdf
+// mat is the material at pos (None in the medium) used for light linking
+#[cfg(feature = "volumes")]
 #[must_use]
-fn get_intersection(ray: &Ray, rng: &mut impl MinRng) -> Intersection {
+fn sample_lights(
+    pos: Vec3,
+    mat: Option<usize>,
+    samplable: &[usize],
+    volume: &crate::volume::Volume,
+    rng: &mut impl MinRng,
+) -> Option<(Ray, Vec3)> {
+    if samplable.is_empty() {
+        return None;
+    }
     let tris = unsafe { TRIANGLES.get().as_ref_unchecked() };
-    let bvh = unsafe { BVH.get().as_ref_unchecked() };
+
+    let sampler = unsafe { LIGHT_SAMPLER.get().as_ref_unchecked() };
+    let (light_idx, pick_prob) = sampler.sample(samplable, rng.gen());
+    let light = &tris[light_idx];
+
+    let links = unsafe { LIGHT_LINKS.get().as_ref_unchecked() };
+    if mat.is_some_and(|mat| !links.is_linked(mat, light.mat())) {
+        return None;
+    }
+
+    let origin = Intersection {
+        pos,
+        ..Intersection::NONE
+    };
+    let (light_ray, le) = light.sample_ray(&origin, rng);
+
+    let light_sect = intersect_idx(&light_ray, light_idx, rng);
+    if light_sect.is_none() {
+        return None;
+    }
+    let pdf = light.pdf(&light_sect, &light_ray) * pick_prob;
+    if pdf == 0.0 {
+        return None;
+    }
+    let tr = volume.transmittance(&light_ray, light_sect.t, rng);
+
+    Some((light_ray, le * tr / pdf))
+}
+// when isolating a mesh, camera rays that don't hit it act like a holdout
+// (the rest of the scene still occludes and shadows but contributes nothing directly)
+#[inline]
+#[must_use]
+fn is_masked(sect: &Intersection, isolate: Option<usize>) -> bool {
+    isolate.is_some_and(|mesh| sect.is_none() || sect.mesh != Some(mesh))
+}
+#[must_use]
+pub fn get_intersection(ray: &Ray, rng: &mut impl MinRng) -> Intersection {
+    get_intersection_clipped(ray, ClipRange::NONE, rng)
+}
+// closest intersection within clip (see World::intersect)
+#[must_use]
+pub fn get_intersection_clipped(ray: &Ray, clip: ClipRange, rng: &mut impl MinRng) -> Intersection {
+    World::global().intersect(ray, clip, rng)
+}
+// closest intersection with the primitives of a BVH within clip, id is the index into
+// prims
+#[must_use]
+pub fn closest_hit(
+    prims: &[Primitive],
+    bvh: &Bvh,
+    ray: &Ray,
+    clip: ClipRange,
+    rng: &mut impl MinRng,
+) -> Intersection {
     let mut sect = Intersection::NONE;
     for range in bvh.traverse(ray) {
         for i in range {
-            let mut tri_sect = tris[i].intersect(ray, rng);
-            tri_sect.id = i;
-            sect.min(tri_sect);
+            let mut prim_sect = prims[i].intersect(ray, rng);
+            if !clip.contains(prim_sect.t) {
+                continue;
+            }
+            prim_sect.id = i;
+            sect.min(prim_sect);
         }
     }
     sect
 }
-#[must_use]
-pub fn intersect_idx(ray: &Ray, idx: usize, rng: &mut impl MinRng) -> Intersection {
-    let tris = unsafe { TRIANGLES.get().as_ref_unchecked() };
-    let bvh = unsafe { BVH.get().as_ref_unchecked() };
-    let sect = tris[idx].intersect(ray, rng);
-    if sect.is_none() {
-        return sect;
+
+// near & far clip planes (distances along the camera ray)
+#[derive(Copy, Clone, Debug)]
+pub struct ClipRange {
+    pub near: f32,
+    pub far: f32,
+}
+
+impl ClipRange {
+    pub const NONE: Self = Self {
+        near: crate::T_MIN,
+        far: f32::INFINITY,
+    };
+
+    #[must_use]
+    pub fn contains(&self, t: f32) -> bool {
+        t >= self.near && t <= self.far
+    }
+    // near -> 0, far -> 1, raw distance if there is no far plane
+    #[must_use]
+    pub fn depth(&self, t: f32) -> f32 {
+        if self.far.is_infinite() {
+            return t;
+        }
+        ((t - self.near) / (self.far - self.near)).clamp(0.0, 1.0)
     }
+}
 
-    for range in bvh.traverse(ray) {
-        for i in range {
-            if i == idx {
-                continue;
-            }
-            let t = tris[i].intersect(ray, rng).t;
-            if t > 0.0 && t < sect.t {
-                return Intersection::NONE;
-            }
+// denoising albedo of the first hit (see Mat::albedo), misses use the environment
+pub struct Albedo {}
+
+impl Albedo {
+    #[must_use]
+    pub fn rgb(ray: Ray, rng: &mut impl MinRng, primary: Option<Intersection>) -> (Vec3, u64) {
+        let mats = unsafe { MATERIALS.get().as_ref_unchecked() };
+        let sect = primary.unwrap_or_else(|| get_intersection(&ray, rng));
+        if sect.is_none() || matches!(mats[sect.mat], Mat::Holdout) {
+            return (background(&ray, true).saturate(), 1);
         }
+        (mats[sect.mat].albedo(sect.uv), 1)
     }
-    sect
 }
 
+// first hit distance normalised by the clip range, misses are at the far plane
+// (or 0 if there is no far plane)
+pub struct Depth {}
+
+impl Depth {
+    #[must_use]
+    pub fn rgb(
+        ray: Ray,
+        rng: &mut impl MinRng,
+        clip: ClipRange,
+        primary: Option<Intersection>,
+    ) -> (Vec3, u64) {
+        let sect = primary.unwrap_or_else(|| get_intersection(&ray, rng));
+        let depth = if !sect.is_none() {
+            clip.depth(sect.t)
+        } else if clip.far.is_infinite() {
+            0.0
+        } else {
+            1.0
+        };
+        (Vec3::splat(depth), 1)
+    }
+}
+// white if nothing is hit within radius of the first hit (cosine weighted), materials
+// and lights are ignored, misses are unoccluded
+pub struct AO {}
+
+impl AO {
+    #[must_use]
+    pub fn rgb(
+        ray: Ray,
+        rng: &mut impl MinRng,
+        radius: f32,
+        primary: Option<Intersection>,
+    ) -> (Vec3, u64) {
+        let sect = primary.unwrap_or_else(|| get_intersection(&ray, rng));
+        if sect.is_none() {
+            return (Vec3::ONE, 1);
+        }
+        let ray = Ray::new(sect.bounce_origin(), Matte::sample(sect.nor, rng));
+        let range = ClipRange {
+            far: radius,
+            ..ClipRange::NONE
+        };
+        if get_intersection_clipped(&ray, range, rng).is_none() {
+            (Vec3::ONE, 2)
+        } else {
+            (Vec3::ZERO, 2)
+        }
+    }
+}
+// intersection with primitive idx if nothing else is in the way
+#[must_use]
+pub fn intersect_idx(ray: &Ray, idx: usize, rng: &mut impl MinRng) -> Intersection {
+    World::global().intersect_idx(ray, idx, rng)
+}
+// whether anything (other than primitive exclude) is hit closer than max_t (see
+// World::occluded)
+#[must_use]
+pub fn occluded(ray: &Ray, max_t: f32, exclude: Option<usize>, rng: &mut impl MinRng) -> bool {
+    World::global().occluded(ray, max_t, exclude, rng)
+}
+
+// scale radiance down so no component exceeds max (keeping the hue)
+// used to cap NEE samples, this is biased (energy is lost) but removes fireflies
+// from lights with a tiny pdf such as small distant lights
+#[inline]
+#[must_use]
+pub fn clamp_radiance(rgb: Vec3, max: f32) -> Vec3 {
+    let m = rgb.component_max();
+    if m > max {
+        rgb * (max / m)
+    } else {
+        rgb
+    }
+}
+// scale radiance down so its luminance doesn't exceed max (keeping the hue)
+// used by --clamp on everything but what the camera sees directly, this is biased
+// (energy is lost) but removes fireflies from rare high throughput paths
+#[inline]
+#[must_use]
+pub fn clamp_luminance(rgb: Vec3, max: f32) -> Vec3 {
+    let lum = crate::luminance(rgb);
+    if lum > max {
+        rgb * (max / lum)
+    } else {
+        rgb
+    }
+}
+// MIS weights are scalar as the pdfs don't depend on the channel, coloured lights are
+// accounted for by how lights are picked (see --mis-per-channel)
 #[inline]
 #[must_use]
 pub fn power_heuristic(pdf_a: f32, pdf_b: f32) -> f32 {
     let a_sq = pdf_a.powi(2);
     a_sq / (a_sq + pdf_b.powi(2))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // mean & variance of n samples of f
+    fn mean_var(mut f: impl FnMut() -> Vec3, n: usize) -> (Vec3, Vec3) {
+        let samples: Vec<Vec3> = (0..n).map(|_| f()).collect();
+        let mean = samples.iter().fold(Vec3::ZERO, |a, &b| a + b) / n as f32;
+        let var = samples
+            .iter()
+            .fold(Vec3::ZERO, |a, &b| a + (b - mean) * (b - mean))
+            / n as f32;
+        (mean, var)
+    }
+
+    #[test]
+    fn specular_depth() {
+        // hall of mirrors: diffuse -> many delta bounces -> diffuse
+        let mut depth = PathDepth::default();
+        assert!(depth.bounce(false));
+        for _ in 0..(2 * MAX_DEPTH) {
+            assert!(depth.bounce(true));
+        }
+        assert!(!depth.russian_roulette());
+        assert!(depth.bounce(false));
+
+        // delta bounces are still bounded
+        let mut depth = PathDepth::default();
+        let bounces = (0..).take_while(|_| depth.bounce(true)).count() as u64;
+        assert_eq!(bounces + 1, MAX_SPECULAR_DEPTH);
+
+        // flat limit for everything else
+        let mut depth = PathDepth::default();
+        let bounces = (0..).take_while(|_| depth.bounce(false)).count() as u64;
+        assert_eq!(bounces + 1, MAX_DEPTH);
+        assert!(depth.russian_roulette());
+    }
+
+    #[test]
+    fn path_limits() {
+        let limits = PathLimits {
+            max_depth: 5,
+            rr_depth: 1,
+            ..Default::default()
+        };
+        let mut depth = PathDepth::new(limits);
+        assert!(depth.bounce(false) && !depth.russian_roulette());
+        assert!(depth.bounce(false) && depth.russian_roulette());
+        let bounces = (0..).take_while(|_| depth.bounce(false)).count() as u64;
+        assert_eq!(bounces + 3, limits.max_depth);
+
+        // a single bounce can't reach a light from a diffuse surface
+        crate::scene::test_scene();
+        let mut rng = rand::thread_rng();
+        let ray = Ray::new(Vec3::new(0.0, 3.0, -1.0), -Vec3::Z);
+        let limits = PathLimits {
+            max_depth: 1,
+            ..Default::default()
+        };
+        for _ in 0..100 {
+            let (rgb, rays) = Naive::rgb(ray.clone(), &mut rng, None, None, limits, &Lambda::Rgb);
+            assert_eq!((rgb, rays), (Vec3::ZERO, 1));
+        }
+    }
+
+    #[test]
+    fn nee_radiance_cap() {
+        // small distant light: tiny solid angle so huge le / pdf for rare samples
+        let le = Vec3::new(1000.0, 800.0, 600.0);
+        let area = 1e-4;
+        let samples: Vec<Vec3> = [50.0f32, 2.0, 0.05]
+            .map(|dist| {
+                let pdf = dist.powi(2) / area;
+                clamp_radiance(le / pdf, 10.0)
+            })
+            .into();
+
+        assert!(samples.iter().all(|v| v.component_max() <= 10.0));
+        // well sampled contributions are untouched
+        assert_eq!(samples[0], le / (2500.0 / area));
+        assert_eq!(samples[1], le / (4.0 / area));
+        // outlier keeps its hue
+        let v = samples[2];
+        assert!((v.y / v.x - 0.8).abs() < 1e-5 && (v.z / v.x - 0.6).abs() < 1e-5);
+        assert_eq!(clamp_radiance(le, f32::INFINITY), le);
+    }
+
+    #[test]
+    fn luminance_clamp() {
+        use rand_pcg::Pcg64Mcg;
+        crate::scene::test_scene();
+        let samplable = unsafe { SAMPLABLE.get().as_ref_unchecked() };
+        let v = Vec3::new(4.0, 2.0, 1.0);
+        let clamped = clamp_luminance(v, 0.5);
+        assert!((crate::luminance(clamped) - 0.5).abs() < 1e-5);
+        assert!((clamped.y / clamped.x - 0.5).abs() < 1e-5);
+        assert_eq!(clamp_luminance(v, f32::INFINITY), v);
+
+        // unset the clamp changes nothing
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.3, 0.1, -1.0));
+        let nee = |clamp, seed| {
+            let mut rng = Pcg64Mcg::new(seed);
+            NEEMIS::rgb(
+                ray.clone(),
+                &mut rng,
+                samplable,
+                None,
+                None,
+                PathLimits {
+                    clamp,
+                    ..Default::default()
+                },
+                &Lambda::Rgb,
+            )
+            .0
+        };
+        let naive = |clamp, seed| {
+            let mut rng = Pcg64Mcg::new(seed);
+            Naive::rgb(
+                ray.clone(),
+                &mut rng,
+                None,
+                None,
+                PathLimits {
+                    clamp,
+                    ..Default::default()
+                },
+                &Lambda::Rgb,
+            )
+            .0
+        };
+        for seed in 0..1000 {
+            assert_eq!(nee(f32::INFINITY, seed), nee(1e30, seed));
+            assert_eq!(naive(f32::INFINITY, seed), naive(1e30, seed));
+        }
+
+        // every contribution is clamped but lights seen directly are not
+        let bound = 1e-3 * 2.0 * (MAX_DEPTH + MAX_SPECULAR_DEPTH) as f32;
+        for seed in 0..1000 {
+            assert!(crate::luminance(nee(1e-3, seed)) <= bound);
+            assert!(crate::luminance(naive(1e-3, seed)) <= bound);
+        }
+        let light = Ray::new(Vec3::new(-1.5, 0.0, -1.0), -Vec3::Z);
+        let mut rng = Pcg64Mcg::new(0);
+        let limits = PathLimits {
+            clamp: 1e-3,
+            ..Default::default()
+        };
+        let direct = NEEMIS::rgb(
+            light.clone(),
+            &mut rng,
+            samplable,
+            None,
+            None,
+            limits,
+            &Lambda::Rgb,
+        );
+        assert_eq!(direct.0, Vec3::new(20.0, 0.0, 0.0));
+        assert_eq!(
+            Naive::rgb(light, &mut rng, None, None, limits, &Lambda::Rgb).0,
+            Vec3::new(20.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn clip_range() {
+        crate::scene::test_scene();
+        let mut rng = rand::thread_rng();
+        // quads at t = 2 and t = 5
+        let ray = Ray::new(Vec3::ZERO, -Vec3::Z);
+        let clip = |near, far| ClipRange { near, far };
+
+        let sect = get_intersection(&ray, &mut rng);
+        assert!((sect.t - 2.0).abs() < 1e-5);
+        assert!(get_intersection_clipped(&ray, clip(0.0, 1.5), &mut rng).is_none());
+        let sect = get_intersection_clipped(&ray, clip(3.0, 10.0), &mut rng);
+        assert!((sect.t - 5.0).abs() < 1e-5);
+
+        let depth = |clip: ClipRange| {
+            Depth::rgb(ray.clone(), &mut rand::thread_rng(), clip, None)
+                .0
+                .x
+        };
+        assert!(depth(clip(2.0, 6.0)).abs() < 1e-5);
+        assert!((depth(clip(0.0, 2.0)) - 1.0).abs() < 1e-5);
+        assert!((depth(clip(1.0, 3.0)) - 0.5).abs() < 1e-5);
+        assert_eq!(depth(clip(6.0, 8.0)), 0.0);
+        let sect = get_intersection_clipped(&ray, clip(6.0, 8.0), &mut rng);
+        assert_eq!(
+            Depth::rgb(ray.clone(), &mut rng, clip(6.0, 8.0), Some(sect))
+                .0
+                .x,
+            1.0
+        );
+        assert!((depth(ClipRange::NONE) - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn any_hit() {
+        crate::scene::test_scene();
+        let mut rng = rand::thread_rng();
+        // quads at t = 2 and t = 5 (away from their diagonals)
+        let ray = Ray::new(Vec3::new(0.2, 0.1, 0.0), -Vec3::Z);
+        assert!(occluded(&ray, 3.0, None, &mut rng));
+        assert!(!occluded(&ray, 1.5, None, &mut rng));
+        let sect = get_intersection(&ray, &mut rng);
+        assert!(!occluded(&ray, 3.0, Some(sect.id), &mut rng));
+        assert!(occluded(&ray, 6.0, Some(sect.id), &mut rng));
+
+        // the back of the single sided quad doesn't occlude
+        let front = Ray::new(Vec3::new(20.2, 0.1, 0.0), -Vec3::Z);
+        let back = Ray::new(Vec3::new(20.2, 0.1, -3.0), Vec3::Z);
+        assert!(occluded(&front, 3.0, None, &mut rng));
+        assert!(!occluded(&back, 3.0, None, &mut rng));
+
+        // the light quad at (1.5, 0, -1.5) is in front of the quad at z = -2
+        let ray = Ray::new(Vec3::new(1.7, 0.1, 0.0), -Vec3::Z);
+        let light = get_intersection(&ray, &mut rng);
+        assert!(!intersect_idx(&ray, light.id, &mut rng).is_none());
+        let behind = get_intersection(&Ray::new(Vec3::new(1.7, 0.1, -1.75), -Vec3::Z), &mut rng);
+        assert!(intersect_idx(&ray, behind.id, &mut rng).is_none());
+    }
+
+    #[test]
+    fn ao() {
+        crate::scene::test_scene();
+        let mut rng = rand::thread_rng();
+        // hits the z = -2 quad 0.5 below the 1x1 light quad at (1.5, 0, -1.5)
+        let ray = Ray::new(Vec3::new(1.5, 0.0, -1.8), -Vec3::Z);
+        let mut mean = |radius| {
+            (0..10_000)
+                .map(|_| AO::rgb(ray.clone(), &mut rng, radius, None).0.x)
+                .sum::<f32>()
+                / 10_000.0
+        };
+        assert_eq!(mean(0.4), 1.0);
+        let occluded = 1.0 - mean(f32::INFINITY);
+        assert!(occluded > 0.2 && occluded < 0.8, "{occluded}");
+        let miss = Ray::new(Vec3::ZERO, Vec3::Z);
+        assert_eq!(AO::rgb(miss, &mut rng, f32::INFINITY, None).0, Vec3::ONE);
+    }
+
+    #[test]
+    fn naive_nee_agree() {
+        use rand_pcg::Pcg64Mcg;
+        crate::scene::test_scene();
+        let samplable = unsafe { SAMPLABLE.get().as_ref_unchecked() };
+        const SAMPLES: usize = 50_000;
+
+        // (mean, variance of the mean)
+        let estimate = |f: &mut dyn FnMut() -> Vec3| {
+            let (mean, var) = mean_var(f, SAMPLES);
+            (mean, var / SAMPLES as f32)
+        };
+
+        // between the lights, under the red light, hitting the blue light and the far quad
+        // then beside the single & two sided green lights and through the fog (FOG) to the
+        // quad beside the last green light
+        let rays = [
+            (Vec3::ZERO, -Vec3::Z),
+            (Vec3::ZERO, Vec3::new(-0.75, 0.0, -1.0)),
+            (Vec3::ZERO, Vec3::new(1.1, 0.1, -1.5)),
+            (Vec3::ZERO, Vec3::new(0.0, 0.5, -1.0)),
+            (Vec3::new(41.0, 0.0, -1.0), -Vec3::Z),
+            (Vec3::new(61.0, 0.0, -1.0), -Vec3::Z),
+            (Vec3::new(101.0, 0.0, 0.0), -Vec3::Z),
+        ];
+        for (origin, dir) in rays {
+            let ray = Ray::new(origin, dir);
+            let mut rng = Pcg64Mcg::new(3);
+            let (naive, naive_var) = estimate(&mut || {
+                Naive::rgb(
+                    ray.clone(),
+                    &mut rng,
+                    None,
+                    None,
+                    PathLimits::default(),
+                    &Lambda::Rgb,
+                )
+                .0
+            });
+            let (nee, nee_var) = estimate(&mut || {
+                NEEMIS::rgb(
+                    ray.clone(),
+                    &mut rng,
+                    samplable,
+                    None,
+                    None,
+                    PathLimits::default(),
+                    &Lambda::Rgb,
+                )
+                .0
+            });
+
+            // within 5 standard errors
+            let tolerance = 5.0 * (naive_var + nee_var).powf(0.5) + Vec3::splat(1e-4);
+            let diff = (naive - nee).abs();
+            assert!(
+                diff.x < tolerance.x && diff.y < tolerance.y && diff.z < tolerance.z,
+                "{dir}: naive {naive} nee {nee} tolerance {tolerance}"
+            );
+        }
+    }
+
+    #[test]
+    fn two_sided_light() {
+        use rand_pcg::Pcg64Mcg;
+        crate::scene::test_scene();
+        let samplable = unsafe { SAMPLABLE.get().as_ref_unchecked() };
+        let mut rng = Pcg64Mcg::new(0);
+        // green reaching the quad beside (and behind) a green light centred on x
+        let mut green = |x: f32| {
+            let ray = Ray::new(Vec3::new(x + 1.0, 0.0, -1.0), -Vec3::Z);
+            (0..10_000)
+                .map(|_| {
+                    NEEMIS::rgb(
+                        ray.clone(),
+                        &mut rng,
+                        samplable,
+                        None,
+                        None,
+                        PathLimits::default(),
+                        &Lambda::Rgb,
+                    )
+                    .0
+                    .y
+                })
+                .sum::<f32>()
+                / 10_000.0
+        };
+        // the back of the single sided light is dark (and culled) so the quad only reflects
+        // the grey environment, making the light two sided lights the quad
+        let (dark, lit) = (green(40.0), green(60.0));
+        assert!((dark - 0.25).abs() < 0.02, "{dark}");
+        assert!(lit > dark + 0.2, "{dark} {lit}");
+    }
+
+    #[test]
+    fn invisible_occluder() {
+        use rand_pcg::Pcg64Mcg;
+        crate::scene::test_scene();
+        let samplable = unsafe { SAMPLABLE.get().as_ref_unchecked() };
+        const SAMPLES: usize = 50_000;
+        let mut rng = Pcg64Mcg::new(0);
+        // (mean, variance of the mean) of the green reaching the quad beside the two sided
+        // green light centred on x
+        let mut green = |x: f32| {
+            let ray = Ray::new(Vec3::new(x + 1.0, 0.0, -1.0), -Vec3::Z);
+            let (mean, var) = mean_var(
+                || {
+                    NEEMIS::rgb(
+                        ray.clone(),
+                        &mut rng,
+                        samplable,
+                        None,
+                        None,
+                        PathLimits::default(),
+                        &Lambda::Rgb,
+                    )
+                    .0
+                },
+                SAMPLES,
+            );
+            (mean.y, var.y / SAMPLES as f32)
+        };
+        // the invisible quad between the light and the quad at x = 80 (that the camera ray
+        // also passes through) doesn't shadow it, within 5 standard errors
+        let (open, occluded) = (green(60.0), green(80.0));
+        assert!(open.0 > 0.45, "{open:?}");
+        let tolerance = 5.0 * (open.1 + occluded.1).sqrt();
+        assert!(
+            (occluded.0 - open.0).abs() < tolerance,
+            "{open:?} {occluded:?}"
+        );
+    }
+
+    #[test]
+    fn excluded_light() {
+        use rand_pcg::Pcg64Mcg;
+        crate::scene::test_scene();
+        let samplable = unsafe { SAMPLABLE.get().as_ref_unchecked() };
+        const SAMPLES: usize = 50_000;
+        let mut rng = Pcg64Mcg::new(0);
+        // (mean, variance of the mean) of the red minus green & blue minus green at the
+        // quad under the ray, the grey environment adds the same to every channel
+        let mut excess = |origin: Vec3| {
+            let ray = Ray::new(origin, -Vec3::Z);
+            let (mean, var) = mean_var(
+                || {
+                    let rgb = NEEMIS::rgb(
+                        ray.clone(),
+                        &mut rng,
+                        samplable,
+                        None,
+                        None,
+                        PathLimits::default(),
+                        &Lambda::Rgb,
+                    )
+                    .0;
+                    Vec3::new(rgb.x - rgb.y, rgb.z - rgb.y, 0.0)
+                },
+                SAMPLES,
+            );
+            (mean, var / SAMPLES as f32)
+        };
+
+        // the red light excluded from the unlinked quad adds no red, within 5 standard
+        // errors, while the blue light beside it still lights it
+        let (unlinked, var) = excess(Vec3::new(120.0, 0.0, -1.0));
+        assert!(unlinked.x.abs() < 5.0 * var.x.sqrt(), "{unlinked} {var}");
+        assert!(unlinked.y > 0.1, "{unlinked}");
+        // and the red light still lights the quad between the other pair of lights
+        let (linked, _) = excess(Vec3::new(0.0, 0.0, -1.0));
+        assert!(linked.x > 0.1 && linked.y > 0.1, "{linked}");
+    }
+
+    #[test]
+    fn holdout() {
+        use rand_pcg::Pcg64Mcg;
+        crate::scene::test_scene();
+        let samplable = unsafe { SAMPLABLE.get().as_ref_unchecked() };
+        let mut rng = Pcg64Mcg::new(0);
+        let mut naive = |ray: &Ray| {
+            Naive::rgb(
+                ray.clone(),
+                &mut rng,
+                None,
+                None,
+                PathLimits::default(),
+                &Lambda::Rgb,
+            )
+            .0
+        };
+
+        // the holdout shows the background instead of the quad behind it
+        let ray = Ray::new(Vec3::new(-20.0, 0.0, 0.0), -Vec3::Z);
+        assert_eq!(naive(&ray), background(&ray, true));
+        let mut rng = Pcg64Mcg::new(1);
+        let nee = NEEMIS::rgb(
+            ray.clone(),
+            &mut rng,
+            samplable,
+            None,
+            None,
+            PathLimits::default(),
+            &Lambda::Rgb,
+        )
+        .0;
+        assert_eq!(nee, background(&ray, true));
+        // but the quad is still shaded beside it
+        let beside = Ray::new(Vec3::new(-21.0, 0.0, 0.0), -Vec3::Z);
+        assert_ne!(naive(&beside), background(&beside, true));
+
+        // and shadowed by it, points on the quad under the holdout and away from it seen
+        // from an angle (not through the holdout)
+        let mean = |target: Vec3, naive: &mut dyn FnMut(&Ray) -> Vec3| {
+            let origin = target + Vec3::new(3.0, 0.0, 3.0);
+            let ray = Ray::new(origin, target - origin);
+            mean_var(|| naive(&ray), 4000).0.x
+        };
+        let under = mean(Vec3::new(-20.0, 0.0, -2.0), &mut naive);
+        let away = mean(Vec3::new(-23.0, 0.0, -2.0), &mut naive);
+        assert!(under < 0.9 * away, "{under} {away}");
+    }
+
+    #[test]
+    fn env_light_variance() {
+        use rand_pcg::Pcg64Mcg;
+        crate::scene::test_scene();
+        let samplable = unsafe { SAMPLABLE.get().as_ref_unchecked() };
+        // dim sky with a bright spot overhead (that BSDF sampling rarely finds) lighting the
+        // floor between the red and blue lights
+        let (w, h) = (33, 17);
+        let mut data = vec![Vec3::splat(0.2); w * h];
+        data[5 + 3 * w] = Vec3::splat(20.0);
+        let envmap = crate::envmap::EnvMap::image(crate::envmap::TextureData { dim: [w, h], data });
+        const SAMPLES: usize = 50_000;
+
+        // (mean, variance) of the luminance picking the environment map with probability p
+        let estimate = |p: f32| {
+            let mut rng = Pcg64Mcg::new(1);
+            let world = World {
+                envmap: &envmap,
+                env_prob: p,
+                ..World::global()
+            };
+            let (mean, var) = mean_var(
+                || {
+                    let rgb = NEEMIS::rgb_in(
+                        &world,
+                        Ray::new(Vec3::ZERO, -Vec3::Z),
+                        &mut rng,
+                        samplable,
+                        None,
+                        None,
+                        PathLimits::default(),
+                        &Lambda::Rgb,
+                    )
+                    .0;
+                    Vec3::splat(crate::luminance(rgb))
+                },
+                SAMPLES,
+            );
+            (mean.x, var.x)
+        };
+        let (lights, env, combined) = (estimate(0.0), estimate(1.0), estimate(0.5));
+
+        // the same image from every strategy but the combined one is the least noisy
+        for (mean, _) in [lights, env] {
+            assert!(
+                (mean / combined.0 - 1.0).abs() < 0.05,
+                "{mean} {combined:?}"
+            );
+        }
+        assert!(
+            combined.1 < lights.1 && combined.1 < env.1,
+            "{lights:?} {env:?} {combined:?}"
+        );
+    }
+}
@@ -0,0 +1,112 @@
+// per material bitset of which (light) materials are allowed to directly light it
+// an empty table links every light to every material
+pub struct LightLinks {
+    words: usize,
+    bits: Vec<u64>,
+}
+
+impl LightLinks {
+    pub const NONE: Self = Self {
+        words: 0,
+        bits: vec![],
+    };
+
+    #[must_use]
+    fn new(num_mats: usize) -> Self {
+        let words = num_mats.div_ceil(64);
+        Self {
+            words,
+            bits: vec![u64::MAX; words * num_mats],
+        }
+    }
+    // entries are of the form object=light,light where both are material names
+    // include lists restrict an object to only the listed lights, exclude lists
+    // remove the listed lights from an object
+    pub fn from_lists(
+        include: &[String],
+        exclude: &[String],
+        num_mats: usize,
+        material_idx: impl Fn(&str) -> Option<usize>,
+    ) -> Result<Self, String> {
+        if include.is_empty() && exclude.is_empty() {
+            return Ok(Self::NONE);
+        }
+        let mut links = Self::new(num_mats);
+
+        let parse = |entry: &str| -> Result<(usize, Vec<usize>), String> {
+            let (object, lights) = entry.split_once('=').ok_or(format!(
+                "Invalid light link {entry}, expected object=light,..."
+            ))?;
+            let idx = |name: &str| {
+                material_idx(name)
+                    .ok_or(format!("Cannot link material {name} as it does not exist!"))
+            };
+            let lights = lights
+                .split(',')
+                .filter(|v| !v.is_empty())
+                .map(idx)
+                .collect::<Result<_, _>>()?;
+            Ok((idx(object)?, lights))
+        };
+
+        for entry in include {
+            let (object, lights) = parse(entry)?;
+            let start = object * links.words;
+            links.bits[start..start + links.words].fill(0);
+            for light in lights {
+                links.set(object, light, true);
+            }
+        }
+        for entry in exclude {
+            let (object, lights) = parse(entry)?;
+            for light in lights {
+                links.set(object, light, false);
+            }
+        }
+
+        Ok(links)
+    }
+    fn set(&mut self, mat: usize, light: usize, linked: bool) {
+        let word = &mut self.bits[mat * self.words + light / 64];
+        if linked {
+            *word |= 1 << (light % 64);
+        } else {
+            *word &= !(1 << (light % 64));
+        }
+    }
+    #[must_use]
+    pub fn is_linked(&self, mat: usize, light: usize) -> bool {
+        if self.bits.is_empty() {
+            return true;
+        }
+        (self.bits[mat * self.words + light / 64] >> (light % 64)) & 1 == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn light_linking() {
+        // 0 & 1 are objects, 2 & 3 are lights (70 materials to span multiple words)
+        let names = ["floor", "statue", "key", "rim"];
+        let idx = |name: &str| names.iter().position(|&v| v == name).map(|i| i * 23);
+        let (floor, statue, key, rim) = (0, 23, 46, 69);
+
+        let links = LightLinks::from_lists(&[], &["statue=key".to_owned()], 70, idx).unwrap();
+        assert!(!links.is_linked(statue, key));
+        assert!(links.is_linked(statue, rim));
+        assert!(links.is_linked(floor, key));
+
+        let links = LightLinks::from_lists(&["floor=rim".to_owned()], &[], 70, idx).unwrap();
+        assert!(!links.is_linked(floor, key));
+        assert!(links.is_linked(floor, rim));
+        assert!(links.is_linked(statue, key));
+
+        assert!(LightLinks::from_lists(&["floor=sun".to_owned()], &[], 70, idx).is_err());
+        assert!(LightLinks::from_lists(&[], &[], 70, idx)
+            .unwrap()
+            .is_linked(statue, key));
+    }
+}
@@ -0,0 +1,148 @@
+use crate::distributions::Distribution1D;
+use utility::Vec3;
+
+// picks a triangle light out of SAMPLABLE in proportion to its emitted power
+// an empty table picks uniformly which also covers scenes where every light is as bright
+pub struct LightSampler {
+    distribution: Option<Distribution1D>,
+    // per primitive, probability of being picked (only valid for samplable primitives)
+    pmf: Vec<f32>,
+}
+
+impl LightSampler {
+    pub const UNIFORM: Self = Self {
+        distribution: None,
+        pmf: vec![],
+    };
+
+    #[must_use]
+    pub fn new(samplable: &[usize], num_prims: usize, power: impl Fn(usize) -> f32) -> Self {
+        let powers: Vec<f32> = samplable.iter().map(|&i| power(i).max(0.0)).collect();
+
+        let total: f32 = powers.iter().sum();
+        if powers.windows(2).all(|w| w[0] == w[1]) || !total.is_normal() {
+            return Self::UNIFORM;
+        }
+
+        let distribution = Distribution1D::new(&powers);
+        let mut pmf = vec![0.0; num_prims];
+        for (&i, &p) in samplable.iter().zip(&distribution.pdf) {
+            pmf[i] = p;
+        }
+        Self {
+            distribution: Some(distribution),
+            pmf,
+        }
+    }
+    // primitive index & probability of picking it, samplable must be non empty
+    #[must_use]
+    pub fn sample(&self, samplable: &[usize], u: f32) -> (usize, f32) {
+        let Some(distribution) = &self.distribution else {
+            let i = ((u * samplable.len() as f32) as usize).min(samplable.len() - 1);
+            return (samplable[i], 1.0 / samplable.len() as f32);
+        };
+        let (i, _) = distribution.sample_remapped(u);
+        (samplable[i], distribution.pdf[i])
+    }
+    // probability of picking a primitive that is known to be samplable
+    #[must_use]
+    pub fn pmf(&self, samplable: &[usize], idx: usize) -> f32 {
+        if self.distribution.is_none() {
+            return 1.0 / samplable.len() as f32;
+        }
+        self.pmf[idx]
+    }
+}
+
+// weights for picking lights in proportion to their share of each colour channel's total
+// power averaged over the channels any light emits in (instead of their luminance), so
+// lights with saturated colours aren't undersampled in the channels they light
+#[must_use]
+pub fn per_channel_power(powers: &[Vec3]) -> Vec<f32> {
+    let total = powers
+        .iter()
+        .fold(Vec3::ZERO, |a, &b| a + b.max_by_component(Vec3::ZERO));
+    let channels = [total.x, total.y, total.z];
+    let lit = channels.iter().filter(|&&t| t > 0.0).count().max(1) as f32;
+    powers
+        .iter()
+        .map(|p| {
+            [p.x, p.y, p.z]
+                .iter()
+                .zip(channels)
+                .filter(|(_, t)| *t > 0.0)
+                .map(|(p, t)| p.max(0.0) / t)
+                .sum::<f32>()
+                / lit
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn power_proportional() {
+        let samplable = [1, 3, 4];
+        let power = |i: usize| [0.0, 1.0, 0.0, 3.0, 4.0][i];
+        let sampler = LightSampler::new(&samplable, 5, power);
+
+        for (&i, expected) in samplable.iter().zip([0.125, 0.375, 0.5]) {
+            assert!((sampler.pmf(&samplable, i) - expected).abs() < 1e-6);
+        }
+
+        let n = 100_000;
+        let mut counts = [0usize; 5];
+        for k in 0..n {
+            let (i, prob) = sampler.sample(&samplable, (k as f32 + 0.5) / n as f32);
+            assert_eq!(prob, sampler.pmf(&samplable, i));
+            counts[i] += 1;
+        }
+        for &i in &samplable {
+            let freq = counts[i] as f32 / n as f32;
+            assert!((freq - sampler.pmf(&samplable, i)).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn equal_power_is_uniform() {
+        let samplable = [0, 2, 5, 7];
+        let sampler = LightSampler::new(&samplable, 8, |_| 2.0);
+        assert!(sampler.distribution.is_none());
+        assert_eq!(sampler.pmf(&samplable, 5), 0.25);
+        assert_eq!(sampler.sample(&samplable, 0.6), (5, 0.25));
+
+        let dark = LightSampler::new(&samplable, 8, |_| 0.0);
+        assert!(dark.distribution.is_none());
+    }
+
+    #[test]
+    fn per_channel() {
+        // a pure red & a pure blue light of equal power, blue contributes little to
+        // luminance so is rarely picked by it
+        let powers = [Vec3::new(10.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 10.0)];
+        let samplable = [0, 1];
+        let luminance = LightSampler::new(&samplable, 2, |i| crate::luminance(powers[i]));
+        let weights = per_channel_power(&powers);
+        assert_eq!(weights, vec![0.5, 0.5]);
+        let channel = LightSampler::new(&samplable, 2, |i| weights[i]);
+
+        // per channel variance of estimating the total power from a single picked light
+        let variance = |sampler: &LightSampler| {
+            samplable.iter().fold(Vec3::ZERO, |a, &i| {
+                a + powers[i] * powers[i] / sampler.pmf(&samplable, i)
+            }) - Vec3::splat(100.0) * Vec3::new(1.0, 0.0, 1.0)
+        };
+        let (lum_var, channel_var) = (variance(&luminance), variance(&channel));
+        // the blue light no longer dominates the noise
+        assert!(channel_var.z < 0.5 * lum_var.z, "{lum_var} {channel_var}");
+        assert!(channel_var.component_max() < lum_var.component_max());
+        assert!(channel_var.x + channel_var.z < lum_var.x + lum_var.z);
+
+        // a white light keeps its share next to a coloured one
+        let mixed = per_channel_power(&[Vec3::splat(1.0), Vec3::new(2.0, 0.0, 0.0)]);
+        assert!((mixed[0] - (1.0 / 3.0 + 1.0 + 1.0) / 3.0).abs() < 1e-6);
+        assert!((mixed[0] + mixed[1] - 1.0).abs() < 1e-6);
+    }
+}
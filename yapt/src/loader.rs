@@ -23,6 +23,148 @@ pub unsafe fn add_texture<T: Into<String>>(name: T, texture: Texture) {
     tex_names.insert(name.into(), index);
 }
 
+/// # Safety
+/// Writes to the global `TRIANGLES` static so must be called before any render thread starts
+/// (and before the BVH is built).
+pub unsafe fn add_sphere(centre: Vec3, radius: f32, mat: &str) {
+    let Some(mat) = material_idx(mat) else {
+        log::error!("Cannot add sphere with material {mat} as it does not exist!");
+        std::process::exit(1);
+    };
+    let tris = unsafe { TRIANGLES.get().as_mut_unchecked() };
+    tris.push(Primitive::Sphere(Sphere::new(centre, radius, mat)));
+}
+// x,y,z,radius,material
+pub fn parse_sphere(spec: &str) -> Result<Sphere, String> {
+    let invalid = || format!("Invalid sphere {spec}, expected x,y,z,radius,material");
+    let [x, y, z, radius, mat] = spec.split(',').map(str::trim).collect::<Vec<_>>()[..] else {
+        return Err(invalid());
+    };
+    let num = |v: &str| v.parse::<f32>().map_err(|_| invalid());
+    let (x, y, z, radius) = (num(x)?, num(y)?, num(z)?, num(radius)?);
+    if radius <= 0.0 || !radius.is_finite() {
+        return Err(format!("Sphere {spec} must have a positive radius"));
+    }
+    let mat = material_idx(mat)
+        .ok_or_else(|| format!("Cannot add sphere with material {mat} as it does not exist!"))?;
+    Ok(Sphere::new(Vec3::new(x, y, z), radius, mat))
+}
+
+// rotation of a glTF mesh about the origin of its node (--mesh-rotation)
+#[derive(Debug, Default, Clone, Copy)]
+pub enum Rot {
+    #[default]
+    Identity,
+    // w,x,y,z (normalised when parsed)
+    Quat(Quaternion),
+    // degrees, blender XYZ euler (same as Cam::new_rot)
+    Euler(Vec3),
+}
+
+impl Rot {
+    #[must_use]
+    pub fn quaternion(&self) -> Quaternion {
+        match *self {
+            Self::Identity => Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            Self::Quat(q) => q,
+            Self::Euler(rotation) => Quaternion::from_euler(rotation, true),
+        }
+    }
+}
+
+// x,y,z (euler degrees) or w,x,y,z (quaternion)
+pub fn parse_rot(spec: &str) -> Result<Rot, String> {
+    let invalid = || format!("Invalid rotation {spec}, expected x,y,z (degrees) or w,x,y,z");
+    let values = spec
+        .split(',')
+        .map(|v| v.trim().parse::<f32>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if values.iter().any(|v| !v.is_finite()) {
+        return Err(invalid());
+    }
+    match values[..] {
+        [x, y, z] => Ok(Rot::Euler(Vec3::new(x, y, z))),
+        [w, x, y, z] => {
+            let mag = (w * w + x * x + y * y + z * z).sqrt();
+            if mag == 0.0 {
+                return Err(format!("Rotation {spec} must be a non zero quaternion"));
+            }
+            Ok(Rot::Quat(Quaternion::new(
+                w / mag,
+                x / mag,
+                y / mag,
+                z / mag,
+            )))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+// transform of a mesh's vertices given its node's transform & --mesh-rotation rotation
+#[must_use]
+fn mesh_transform(node_transform: Mat4, rotation: Option<Quaternion>) -> Mat4 {
+    match rotation {
+        Some(rotation) => node_transform * Mat4::from_trs(Vec3::ZERO, rotation.into(), Vec3::ONE),
+        None => node_transform,
+    }
+}
+
+// mesh=rotation (see parse_rot) entries keyed by glTF mesh name
+pub fn parse_mesh_rotations(specs: &[String]) -> Result<HashMap<String, Quaternion>, String> {
+    specs
+        .iter()
+        .map(|spec| {
+            let (mesh, rot) = spec
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid mesh rotation {spec}, expected mesh=rotation"))?;
+            Ok((mesh.to_owned(), parse_rot(rot)?.quaternion()))
+        })
+        .collect()
+}
+
+// s (uniform) or x,y,z, non uniform scales transform normals by the inverse scale
+pub fn parse_scale(spec: &str) -> Result<Vec3, String> {
+    let invalid = || format!("Invalid scale {spec}, expected s or x,y,z (non zero)");
+    let values = spec
+        .split(',')
+        .map(|v| v.trim().parse::<f32>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if values.iter().any(|v| !v.is_normal()) {
+        return Err(invalid());
+    }
+    match values[..] {
+        [s] => Ok(Vec3::splat(s)),
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(invalid()),
+    }
+}
+
+pub fn texture_idx(name: &str) -> Option<usize> {
+    let mut lock = TEXTURE_NAMES.lock().unwrap();
+    let tex_names = lock.get_mut_or_init(HashMap::new);
+    tex_names.get(name).copied()
+}
+
+pub fn material_idx(name: &str) -> Option<usize> {
+    let mut lock = MATERIAL_NAMES.lock().unwrap();
+    let mat_names = lock.get_mut_or_init(HashMap::new);
+    mat_names.get(name).copied()
+}
+
+// index of a mesh name, meshes sharing a name share an index
+pub fn add_mesh_name<T: Into<String>>(name: T) -> usize {
+    let mut lock = MESH_NAMES.lock().unwrap();
+    let mesh_names = lock.get_mut_or_init(HashMap::new);
+    let index = mesh_names.len();
+    *mesh_names.entry(name.into()).or_insert(index)
+}
+
+pub fn mesh_idx(name: &str) -> Option<usize> {
+    let mut lock = MESH_NAMES.lock().unwrap();
+    let mesh_names = lock.get_mut_or_init(HashMap::new);
+    mesh_names.get(name).copied()
+}
+
 pub fn create_model_map<T: Into<String>>(map: Vec<(T, T)>) -> HashMap<String, String> {
     let mut lock = MATERIAL_NAMES.lock().unwrap();
     let mat_names = lock.get_mut_or_init(HashMap::new);
@@ -53,7 +195,10 @@ pub unsafe fn load_gltf(
     let tris = unsafe { TRIANGLES.get().as_mut_unchecked() };
     let verts = unsafe { VERTICES.get().as_mut_unchecked() };
     let norms = unsafe { NORMALS.get().as_mut_unchecked() };
+    let tangents = unsafe { TANGENTS.get().as_mut_unchecked() };
     let uvs = unsafe { UVS.get().as_mut_unchecked() };
+    let single_sided = unsafe { SINGLE_SIDED.get().as_mut_unchecked() };
+    let normal_maps = unsafe { NORMAL_MAPS.get().as_mut_unchecked() };
     let mut lock = MATERIAL_NAMES.lock().unwrap();
     let mat_names = lock.get_mut_or_init(HashMap::new);
     let mut lock_tex = TEXTURE_NAMES.lock().unwrap();
@@ -73,11 +218,14 @@ pub unsafe fn load_gltf(
         std::process::exit(0);
     };
 
+    // cameras only use the translation & rotation, meshes use the full transform
+    // (which also handles non uniform scales of rotated children)
     struct NodeCollection<'a> {
         nodes: Vec<Node<'a>>,
         translation: Vec3,
         rotation: Quaternion,
         scale: Vec3,
+        transform: Mat4,
     }
 
     impl<'a> NodeCollection<'a> {
@@ -86,21 +234,59 @@ pub unsafe fn load_gltf(
             translation: Vec3,
             rotation: Quaternion,
             scale: Vec3,
+            transform: Mat4,
         ) -> Self {
             Self {
                 nodes,
                 translation,
                 rotation,
                 scale,
+                transform,
             }
         }
     }
 
+    let mesh_rotations = match parse_mesh_rotations(&render_settings.mesh_rotation) {
+        Ok(rotations) => rotations,
+        Err(e) => {
+            log::error!("{e}");
+            std::process::exit(0);
+        }
+    };
+
+    let mesh_scale = match render_settings.mesh_scale.as_deref().map(parse_scale) {
+        None => Vec3::ONE,
+        Some(Ok(scale)) => scale,
+        Some(Err(e)) => {
+            log::error!("{e}");
+            std::process::exit(0);
+        }
+    };
+
+    // meshes referenced by several nodes are loaded once in their own space and
+    // instanced, except for lights as NEE can only sample TRIANGLES
+    let mut mesh_refs: HashMap<usize, usize> = HashMap::new();
+    for mesh in doc.nodes().filter_map(|node| node.mesh()) {
+        *mesh_refs.entry(mesh.index()).or_default() += 1;
+    }
+    let instanced = |mesh: &gltf::Mesh| {
+        !render_settings.no_instancing
+            && mesh_refs[&mesh.index()] > 1
+            && mesh
+                .primitives()
+                .all(|p| p.material().emissive_factor() == [0.0; 3])
+    };
+    // gltf mesh index -> index into MESHES
+    let mut blas_ids: HashMap<usize, usize> = HashMap::new();
+    let meshes = unsafe { MESHES.get().as_mut_unchecked() };
+    let instances = unsafe { INSTANCES.get().as_mut_unchecked() };
+
     let mut node_queue = vec![NodeCollection::new(
         scene.nodes().collect(),
         offset,
         Quaternion::new(1.0, 0.0, 0.0, 0.0),
-        Vec3::splat(scale),
+        mesh_scale * scale,
+        Mat4::from_trs(offset, Mat4::IDENTITY, mesh_scale * scale),
     )];
 
     while let Some(NodeCollection {
@@ -108,59 +294,143 @@ pub unsafe fn load_gltf(
         translation,
         rotation,
         scale,
+        transform,
     }) = node_queue.pop()
     {
         while let Some(node) = nodes.pop() {
-            let (local_translation, local_rotation, local_scale) = node.transform().decomposed();
+            let (node_translation, node_rotation, node_scale) = node.transform().decomposed();
+            let (node_translation, node_scale): (Vec3, Vec3) =
+                (node_translation.into(), node_scale.into());
+            let node_rotation = Quaternion::new(
+                node_rotation[3],
+                node_rotation[0],
+                node_rotation[1],
+                node_rotation[2],
+            );
+            let local_transform =
+                transform * Mat4::from_trs(node_translation, node_rotation.into(), node_scale);
 
-            let local_translation: Vec3 = local_translation.into();
-            let local_translation = local_translation + translation;
-
-            let local_rotation = rotation.hamilton(Quaternion::new(
-                local_rotation[3],
-                local_rotation[0],
-                local_rotation[1],
-                local_rotation[2],
-            ));
-
-            let local_scale = scale.hadamard(local_scale.into());
+            // a node's translation is in the space of its parent
+            let local_translation = rotation.rotate(node_translation.hadamard(scale)) + translation;
+            let local_rotation = rotation.hamilton(node_rotation);
+            let local_scale = scale.hadamard(node_scale);
 
             // load camera if it exists
             if let Some(cam) = node.camera() {
-                if let gltf::camera::Projection::Perspective(perp) = cam.projection() {
-                    let hfov = (perp.yfov()
-                        * (render_settings.width.get() as f32
-                            / render_settings.height.get() as f32))
-                        .to_degrees();
-                    log::info!(
-                        "Loaded cam {} @ {} with fov {}",
-                        cams.len(),
-                        local_translation,
-                        hfov,
-                    );
-                    cams.push(Cam::new_quat(
-                        local_translation,
-                        local_rotation,
-                        hfov,
-                        render_settings,
-                    ));
+                match cam.projection() {
+                    gltf::camera::Projection::Perspective(perp) => {
+                        let hfov = (perp.yfov() * render_settings.aspect_ratio()).to_degrees();
+                        log::info!(
+                            "Loaded cam {} @ {} with fov {}",
+                            cams.len(),
+                            local_translation,
+                            hfov,
+                        );
+                        cams.push(Cam::new_quat(
+                            local_translation,
+                            local_rotation,
+                            hfov,
+                            render_settings,
+                        ));
+                    }
+                    // the height of the image follows from the render's aspect ratio
+                    gltf::camera::Projection::Orthographic(ortho) => {
+                        let (xmag, ymag) = (ortho.xmag(), ortho.ymag());
+                        if (xmag / ymag - render_settings.aspect_ratio()).abs() > 1e-3 {
+                            log::warn!(
+                                "Orthographic cam {} has a different aspect ratio to the render",
+                                cams.len()
+                            );
+                        }
+                        log::info!(
+                            "Loaded orthographic cam {} @ {} with width {}",
+                            cams.len(),
+                            local_translation,
+                            2.0 * xmag,
+                        );
+                        let mut cam =
+                            Cam::new_quat(local_translation, local_rotation, 90.0, render_settings);
+                        cam.projection = Projection::Orthographic;
+                        cam.ortho_width = 2.0 * xmag;
+                        cams.push(cam);
+                    }
                 }
             }
 
-            // load mesh if it exists
-            if let Some(mesh) = node.mesh() {
+            // load mesh if it exists, rotated in its node's space (cameras aren't)
+            // instances with a flattening (zero scale) transform are invisible & skipped
+            let local_transform = mesh_transform(
+                local_transform,
+                node.mesh()
+                    .and_then(|mesh| mesh_rotations.get(mesh.name()?))
+                    .copied(),
+            );
+            let instanced = node.mesh().is_some_and(|mesh| instanced(&mesh));
+            let blas_id = node
+                .mesh()
+                .filter(|_| instanced)
+                .and_then(|mesh| blas_ids.get(&mesh.index()).copied());
+            if let Some(id) = blas_id {
+                instances.extend(Instance::new(id, &meshes[id], local_transform));
+            } else if let Some(mesh) = node.mesh() {
+                let mesh_id = mesh.name().map(add_mesh_name);
+                let (transform, normal_matrix) = if instanced {
+                    (Mat4::IDENTITY, Mat4::IDENTITY)
+                } else {
+                    // flattened (zero scale) meshes keep their normals
+                    let normal_matrix = local_transform.normal_matrix().unwrap_or_default();
+                    (local_transform, normal_matrix)
+                };
+                let mut local_prims = Vec::new();
+                let out = if instanced {
+                    &mut local_prims
+                } else {
+                    &mut *tris
+                };
                 for primitive in mesh.primitives() {
                     let mat = primitive.material();
 
                     // 0 reserved for default
                     let fallback_name: String = mat.index().map(|v| v + 1).unwrap_or(0).to_string();
                     let mat_name = mat.name().map(|s| s.to_owned()).unwrap_or(fallback_name);
+                    let projection = uv_projection(&mat_name, render_settings);
 
                     let idx = if !mat_names.contains_key(&mat_name) {
                         let idx = mats.len();
                         mats.push(
-                            mat_to_mat(&bufs, &mat, mat_name.clone(), texs, tex_names).unwrap(),
+                            mat_to_mat(
+                                &bufs,
+                                &mat,
+                                mat_name.clone(),
+                                texs,
+                                tex_names,
+                                render_settings,
+                            )
+                            .unwrap(),
                         );
+                        // materials defined by the scene are always double sided
+                        if culls_back(mat.double_sided(), &mats[idx]) {
+                            single_sided.resize(idx + 1, false);
+                            single_sided[idx] = true;
+                        }
+                        if let Some(normal_map) = mat.normal_texture() {
+                            let scale = parse_mat_override(&mat_name, render_settings, |n| {
+                                tex_names.get(n).copied()
+                            })
+                            .normal_scale
+                            .unwrap_or(normal_map.scale());
+                            if !render_settings.disable_normal_maps && scale != 0.0 {
+                                let tex_idx = load_texture(
+                                    &bufs,
+                                    &normal_map.texture(),
+                                    format!("{mat_name}_normal"),
+                                    texs,
+                                    tex_names,
+                                );
+                                normal_maps.resize(idx + 1, None);
+                                normal_maps[idx] = Some((tex_idx, scale));
+                            }
+                        }
                         mat_names.insert(mat_name, idx);
                         idx
                     } else {
@@ -170,19 +440,20 @@ pub unsafe fn load_gltf(
                     let reader = primitive.reader(|buffer| Some(&bufs[buffer.index()]));
 
                     match primitive.mode() {
-                        gltf::mesh::Mode::Triangles => {
+                        mode @ (gltf::mesh::Mode::Triangles
+                        | gltf::mesh::Mode::TriangleFan
+                        | gltf::mesh::Mode::TriangleStrip) => {
                             let vert_offset = verts.len();
                             let norm_offset = norms.len();
                             let uv_offset = uvs.len();
 
-                            let apply_transform = |v: Vec3| -> Vec3 {
-                                let v = v.hadamard(local_scale);
-                                local_rotation
-                                    .hamilton(v.into())
-                                    .hamilton(local_rotation.conj())
-                                    .xyz()
-                                    + local_translation
-                            };
+                            let apply_transform = |v: Vec3| -> Vec3 { transform.mul_point(v) };
+                            // directions aren't translated and normals use the inverse
+                            // transpose to stay perpendicular to the surface
+                            let transform_normal =
+                                |v: Vec3| -> Vec3 { normal_matrix.mul_dir(v).normalised() };
+                            let transform_tangent =
+                                |v: Vec3| -> Vec3 { transform.mul_dir(v).normalised() };
 
                             let new_verticies: Vec<Vec3> = reader
                                 .read_positions()
@@ -195,50 +466,67 @@ pub unsafe fn load_gltf(
                                 .read_normals()
                                 .unwrap()
                                 .map(|v| v.into())
-                                .map(apply_transform)
+                                .map(transform_normal)
                                 .collect();
 
-                            let new_uvs: Vec<Vec2> = reader
-                                .read_tex_coords(0)
-                                .unwrap()
-                                .into_f32()
-                                .map(|v| v.into())
-                                .collect();
+                            let new_tangents: Vec<(Vec3, f32)> = match reader.read_tangents() {
+                                Some(tangents) => tangents
+                                    .map(|[x, y, z, w]| (transform_tangent(Vec3::new(x, y, z)), w))
+                                    .collect(),
+                                None => vec![(Vec3::ZERO, 1.0); new_normals.len()],
+                            };
+
+                            let new_uvs: Vec<Vec2> = match reader.read_tex_coords(0) {
+                                Some(uvs) => uvs.into_f32().map(|v| v.into()).collect(),
+                                None => {
+                                    log::warn!(
+                                        "Mesh {} has no UVs, using {projection} projection",
+                                        mesh.name().unwrap_or("unnamed")
+                                    );
+                                    project_uvs(&new_verticies, &new_normals, projection)
+                                }
+                            };
+
+                            // non indexed primitives use every vertex in order
+                            let indices: Vec<usize> = match reader.read_indices() {
+                                Some(indices) => indices.into_u32().map(|v| v as usize).collect(),
+                                None => (0..new_verticies.len()).collect(),
+                            };
 
                             verts.extend(new_verticies);
                             norms.extend(new_normals);
+                            tangents.extend(new_tangents);
                             uvs.extend(new_uvs);
 
-                            let new_tris: Vec<_> = reader
-                                .read_indices()
-                                .unwrap()
-                                .into_u32()
-                                .map(|v| v as usize)
-                                .collect::<Vec<_>>()
-                                .chunks_exact(3)
-                                .map(|chunk| {
-                                    let a = chunk[0];
-                                    let b = chunk[1];
-                                    let c = chunk[2];
-                                    Tri::new(
-                                        [a + vert_offset, b + vert_offset, c + vert_offset],
-                                        [a + norm_offset, b + norm_offset, c + norm_offset],
-                                        [a + uv_offset, b + uv_offset, c + uv_offset],
-                                        idx,
+                            let new_tris: Vec<_> = triangle_indices(mode, &indices)
+                                .into_iter()
+                                .map(|[a, b, c]| {
+                                    Primitive::Tri(
+                                        Tri::new(
+                                            [a + vert_offset, b + vert_offset, c + vert_offset],
+                                            [a + norm_offset, b + norm_offset, c + norm_offset],
+                                            [a + uv_offset, b + uv_offset, c + uv_offset],
+                                            idx,
+                                        )
+                                        .with_mesh(mesh_id),
                                     )
                                 })
                                 .collect();
 
-                            tris.extend(new_tris);
+                            out.extend(new_tris);
                         }
-                        gltf::mesh::Mode::TriangleFan => todo!(),
-                        gltf::mesh::Mode::TriangleStrip => todo!(),
                         mode => {
                             log::error!("Unsupported primitive type: {mode:?}");
                             std::process::exit(0);
                         }
                     }
                 }
+                if instanced {
+                    let id = meshes.len();
+                    meshes.push(Blas::new(local_prims));
+                    blas_ids.insert(mesh.index(), id);
+                    instances.extend(Instance::new(id, &meshes[id], local_transform));
+                }
             }
 
             let child_nodes: Vec<_> = node.children().collect();
@@ -247,60 +535,556 @@ pub unsafe fn load_gltf(
                 local_translation,
                 local_rotation,
                 local_scale,
+                local_transform,
             ));
         }
     }
 
     log::info!("Loaded: {} triangles", tris.len());
+    if !instances.is_empty() {
+        log::info!(
+            "Loaded: {} instances of {} meshes",
+            instances.len(),
+            meshes.len()
+        );
+    }
     log::info!("Loaded: {} materials", mats.len());
     log::info!("Loaded: {} textures", texs.len());
     log::info!("Loaded: {} verts", verts.len());
     log::info!("Loaded: {} norms", norms.len());
 
+    unsafe { CAMERAS.get().as_mut_unchecked() }.extend(cams.iter().cloned());
     cams
 }
 
+// fallback for meshes without texture coordinates
+#[derive(clap::ValueEnum, Copy, Clone, Default, Debug, PartialEq)]
+pub enum UvProjection {
+    // every vertex at (0, 0) (a single texel)
+    Zero,
+    // onto the plane of the mesh's two largest bounding box axes
+    #[default]
+    Planar,
+    // onto the plane most facing each vertex normal
+    Box,
+}
+
+impl fmt::Display for UvProjection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Zero => "zero",
+            Self::Planar => "planar",
+            Self::Box => "box",
+        };
+        write!(f, "{s}")
+    }
+}
+
+// projection for a material, entries in --uv-fallback-for are of the form material=projection
+fn uv_projection(mat_name: &str, render_settings: &RenderSettings) -> UvProjection {
+    for entry in &render_settings.uv_fallback_for {
+        let Some((name, projection)) = entry.split_once('=') else {
+            log::error!("Invalid UV fallback {entry}, expected material=projection");
+            std::process::exit(0);
+        };
+        if name != mat_name {
+            continue;
+        }
+        match <UvProjection as clap::ValueEnum>::from_str(projection, true) {
+            Ok(v) => return v,
+            Err(e) => {
+                log::error!("Invalid UV projection {projection} for {name}\n{e}");
+                std::process::exit(0);
+            }
+        }
+    }
+    render_settings.uv_fallback
+}
+
+// ior of glass unless overridden
+const DEFAULT_IOR: f32 = 1.5;
+
+// material types that replace an imported material (--mat-override material:type=...)
+#[derive(clap::ValueEnum, Copy, Clone, Debug, PartialEq)]
+pub enum MatType {
+    // smooth glass with an interior (see SmoothDielectric)
+    Dielectric,
+    // a sheet of glass without an interior (see ThinDielectric)
+    #[value(name = "thindielectric")]
+    ThinDielectric,
+    // rough glass with its ior in a texture, ior_tex or one named <material>_ior (see
+    // RoughDielectric)
+    #[value(name = "roughdielectric")]
+    RoughDielectric,
+}
+
+// overrides of an imported material's scalar factors
+#[derive(Copy, Clone, Default, Debug, PartialEq)]
+pub struct MatOverride {
+    pub roughness_factor: Option<f32>,
+    pub metallic_factor: Option<f32>,
+    // stretches the roughness of metals along the surface's tangent, in [-1, 1]
+    pub anisotropy: Option<f32>,
+    // turns the material into glass with this index of refraction
+    pub ior: Option<f32>,
+    // turns the material into rough glass with its ior read from this texture
+    pub ior_tex: Option<usize>,
+    // per unit distance transmittance of the glass interior (see SmoothDielectric)
+    pub transmittance: Option<Vec3>,
+    // replaces the material, glass defaults to DEFAULT_IOR
+    pub mat_type: Option<MatType>,
+    // use the principled material (defaulting to --principled)
+    pub principled: Option<bool>,
+    // dielectric specular of the principled material (see Principled)
+    pub specular: Option<f32>,
+    // strength of the normal map, 0 disables it
+    pub normal_scale: Option<f32>,
+    // makes a light emit from both sides of a single sided surface
+    pub two_sided: bool,
+}
+
+impl MatOverride {
+    // the material for a base colour texture, without the principled material the
+    // metallic factor is only used when overridden and picks between a dielectric (matte)
+    // and a metal (glossy) surface, an ior makes it glass unless another type is given
+    #[must_use]
+    pub fn apply(
+        &self,
+        roughness_factor: f32,
+        metallic_factor: f32,
+        idx: usize,
+        render_settings: &RenderSettings,
+    ) -> Mat {
+        let ior = self.ior.unwrap_or(DEFAULT_IOR);
+        let implied = match (self.ior_tex, self.ior) {
+            (Some(_), _) => Some(MatType::RoughDielectric),
+            (None, Some(_)) => Some(MatType::Dielectric),
+            (None, None) => None,
+        };
+        match self.mat_type.or(implied) {
+            Some(MatType::Dielectric) => {
+                return Mat::Refractive(SmoothDielectric::new(
+                    ior,
+                    self.transmittance.unwrap_or(Vec3::ONE),
+                ));
+            }
+            Some(MatType::ThinDielectric) => return Mat::ThinDielectric(ThinDielectric::new(ior)),
+            Some(MatType::RoughDielectric) | None => (),
+        }
+        let ggx = Ggx::new_clamped(
+            self.roughness_factor.unwrap_or(roughness_factor),
+            idx,
+            render_settings.roughness_min,
+            render_settings.roughness_max,
+        )
+        .anisotropic(self.anisotropy.unwrap_or(0.0))
+        .with_multiscatter(render_settings.ggx_multiscatter);
+        if self.mat_type.or(implied) == Some(MatType::RoughDielectric) {
+            // without ior_tex the ior texture is added by the loader (see with_ior_texture)
+            let ior = self.ior_tex.unwrap_or(crate::NO_TEXTURE);
+            return Mat::RoughDielectric(RoughDielectric::new(ggx, ior));
+        }
+        if self.principled.unwrap_or(render_settings.principled) {
+            return Mat::Principled(Principled::new(
+                idx,
+                self.metallic_factor.unwrap_or(metallic_factor),
+                self.specular.unwrap_or(0.5),
+                ggx,
+            ));
+        }
+        match self.metallic_factor {
+            Some(m) if m < 0.5 => Mat::Matte(Matte::new(idx)),
+            _ => Mat::Glossy(ggx),
+        }
+    }
+}
+
+// overrides for a material, entries in --mat-override are of the form material:key=value
+// with keys roughness_factor, metallic_factor, anisotropy, ior, ior_tex (a texture name
+// looked up with tex_idx), transmittance (either r,g,b or a single grey value), type
+// (dielectric, thindielectric or roughdielectric), principled (0 or 1), specular,
+// normal_scale and two_sided (0 or 1)
+fn parse_mat_override(
+    mat_name: &str,
+    render_settings: &RenderSettings,
+    tex_idx: impl Fn(&str) -> Option<usize>,
+) -> MatOverride {
+    let mut mat_override = MatOverride::default();
+    for entry in &render_settings.mat_override {
+        let Some((name, (key, value))) = entry
+            .rsplit_once(':')
+            .and_then(|(name, kv)| Some((name, kv.split_once('=')?)))
+        else {
+            log::error!("Invalid material override {entry}, expected material:key=value");
+            std::process::exit(0);
+        };
+        if name != mat_name {
+            continue;
+        }
+        if key == "type" {
+            match <MatType as clap::ValueEnum>::from_str(value, true) {
+                Ok(v) => mat_override.mat_type = Some(v),
+                Err(e) => {
+                    log::error!("Invalid material type {value} for {name}\n{e}");
+                    std::process::exit(0);
+                }
+            }
+            continue;
+        }
+        if key == "ior_tex" {
+            let Some(idx) = tex_idx(value) else {
+                log::error!(
+                    "Cannot use texture {value} as the ior of {name} as it does not exist!"
+                );
+                std::process::exit(0);
+            };
+            mat_override.ior_tex = Some(idx);
+            continue;
+        }
+        let invalid = || -> ! {
+            log::error!("Invalid value {value} for {key} of {name}");
+            std::process::exit(0);
+        };
+        let Ok(values) = value
+            .split(',')
+            .map(str::parse::<f32>)
+            .collect::<Result<Vec<_>, _>>()
+        else {
+            invalid()
+        };
+        let scalar = || match values[..] {
+            [v] => v,
+            _ => invalid(),
+        };
+        match key {
+            "roughness_factor" => mat_override.roughness_factor = Some(scalar()),
+            "metallic_factor" => mat_override.metallic_factor = Some(scalar()),
+            "anisotropy" => {
+                let value = scalar();
+                if !(-1.0..=1.0).contains(&value) {
+                    log::error!("Invalid anisotropy {value} for {name}, expected [-1, 1]");
+                    std::process::exit(0);
+                }
+                mat_override.anisotropy = Some(value);
+            }
+            "ior" => {
+                let value = scalar();
+                if value <= 0.0 {
+                    invalid();
+                }
+                mat_override.ior = Some(value);
+            }
+            "transmittance" => {
+                let v = match values[..] {
+                    [v] => Vec3::splat(v),
+                    [r, g, b] => Vec3::new(r, g, b),
+                    _ => invalid(),
+                };
+                if v.component_min() < 0.0 {
+                    invalid();
+                }
+                mat_override.transmittance = Some(v);
+            }
+            "principled" => mat_override.principled = Some(scalar() != 0.0),
+            "specular" => mat_override.specular = Some(scalar()),
+            "normal_scale" => mat_override.normal_scale = Some(scalar()),
+            "two_sided" => mat_override.two_sided = scalar() != 0.0,
+            _ => {
+                log::error!(
+                    "Unknown material override {key}, expected roughness_factor, metallic_factor, anisotropy, ior, ior_tex, transmittance, type, principled, specular, normal_scale or two_sided"
+                );
+                std::process::exit(0);
+            }
+        }
+    }
+    mat_override
+}
+
+// world space projection scaled so the largest bounding box axis spans [0, 1]
+#[must_use]
+pub fn project_uvs(verts: &[Vec3], norms: &[Vec3], projection: UvProjection) -> Vec<Vec2> {
+    let Some(&first) = verts.first() else {
+        return Vec::new();
+    };
+    let (min, max) = verts.iter().fold((first, first), |(min, max), &v| {
+        (min.min_by_component(v), max.max_by_component(v))
+    });
+    let extent = max - min;
+    let scale = 1.0 / extent.component_max().max(f32::EPSILON);
+
+    // axes spanning the plane perpendicular to axis
+    let plane = |axis: usize| match axis {
+        0 => (1, 2),
+        1 => (0, 2),
+        _ => (0, 1),
+    };
+    let smallest = |v: Vec3| {
+        if v.x <= v.y && v.x <= v.z {
+            0
+        } else if v.y <= v.z {
+            1
+        } else {
+            2
+        }
+    };
+    let planar = plane(smallest(extent));
+
+    verts
+        .iter()
+        .zip(norms)
+        .map(|(&v, &n)| {
+            let (u, w) = match projection {
+                UvProjection::Zero => return Vec2::ZERO,
+                UvProjection::Planar => planar,
+                // largest normal component
+                UvProjection::Box => plane(smallest(-n.abs())),
+            };
+            let local = (v - min) * scale;
+            Vec2::new(local[u], local[w])
+        })
+        .collect()
+}
+
+// vertex indices of each triangle of a triangle list, strip or fan keeping the winding
+// of the first triangle (see the glTF spec 3.7.2.1)
+#[must_use]
+fn triangle_indices(mode: gltf::mesh::Mode, indices: &[usize]) -> Vec<[usize; 3]> {
+    let n = indices.len().saturating_sub(2);
+    match mode {
+        gltf::mesh::Mode::TriangleStrip => (0..n)
+            .map(|i| {
+                // every other triangle is flipped to keep the winding
+                if i % 2 == 0 {
+                    [indices[i], indices[i + 1], indices[i + 2]]
+                } else {
+                    [indices[i], indices[i + 2], indices[i + 1]]
+                }
+            })
+            .collect(),
+        gltf::mesh::Mode::TriangleFan => (0..n)
+            .map(|i| [indices[0], indices[i + 1], indices[i + 2]])
+            .collect(),
+        _ => indices
+            .chunks_exact(3)
+            .map(|c| [c[0], c[1], c[2]])
+            .collect(),
+    }
+}
+
+// index of an embedded image texture, loading it if it hasn't been already (textures
+// without a name are named fallback_name)
+fn load_texture(
+    bufs: &[gltf::buffer::Data],
+    tex: &gltf::Texture,
+    fallback_name: String,
+    texs: &mut Vec<Texture>,
+    tex_names: &mut HashMap<String, usize>,
+) -> usize {
+    let source = tex.source().source();
+    let gltf::image::Source::View { view, .. } = source else {
+        panic!()
+    };
+    let buff = &bufs[view.buffer().index()];
+    let tex_name = tex.name().map(|v| v.to_owned()).unwrap_or(fallback_name);
+
+    if let Some(&idx) = tex_names.get(&tex_name) {
+        return idx;
+    }
+    let start = view.offset();
+    let end = start + view.length();
+    let tex_data = &buff[start..end];
+    let sampler = tex.sampler();
+    let wrap = |mode| match mode {
+        gltf::texture::WrappingMode::Repeat => WrapMode::Repeat,
+        gltf::texture::WrappingMode::ClampToEdge => WrapMode::Clamp,
+        gltf::texture::WrappingMode::MirroredRepeat => WrapMode::Mirror,
+    };
+    let mut tex = decode_image(tex_data).unwrap();
+    if let Texture::Image(ref mut img) = tex {
+        if sampler.mag_filter() == Some(gltf::texture::MagFilter::Nearest) {
+            img.filter = TextureFilter::Nearest;
+        }
+        img.wrap = [wrap(sampler.wrap_s()), wrap(sampler.wrap_t())];
+    }
+    let idx = texs.len();
+    texs.push(tex);
+    tex_names.insert(tex_name, idx);
+    idx
+}
+
+// image (PNG, EXR, ...) in memory to an image texture (keeping alpha for cutouts)
+fn decode_image(data: &[u8]) -> Result<Texture, image::ImageError> {
+    let image = image::load_from_memory(data)?.to_rgba32f();
+    let dim = image.dimensions();
+    let image = image.into_vec();
+    Ok(Texture::Image(Image::from_rgbaf32(
+        dim.0 as usize,
+        dim.1 as usize,
+        image,
+    )))
+}
+
+// texture=source replacing a loaded texture, source is an image path (relative to the
+// working directory like scene paths) or a base64 data URI (data:image/png;base64,...)
+pub fn parse_tex_override(spec: &str) -> Result<(usize, Texture), String> {
+    let Some((name, source)) = spec.split_once('=') else {
+        return Err(format!(
+            "Invalid texture override {spec}, expected texture=source"
+        ));
+    };
+    let Some(idx) = texture_idx(name) else {
+        return Err(format!(
+            "Cannot override texture {name} as it does not exist!"
+        ));
+    };
+    if let Some(tex) = Texture::parse_procedural(source) {
+        return tex.map(|tex| (idx, tex));
+    }
+    let data = match source.strip_prefix("data:") {
+        Some(uri) => {
+            let Some((_, data)) = uri.split_once(";base64,") else {
+                return Err(format!(
+                    "Texture override {name} has a data URI that isn't base64"
+                ));
+            };
+            base64::decode(data).map_err(|e| format!("Invalid base64 for texture {name}: {e}"))?
+        }
+        None => std::fs::read(source)
+            .map_err(|e| format!("Failed to read texture {name} @ {source}\n{e}"))?,
+    };
+    let tex = decode_image(&data).map_err(|e| format!("Failed to decode texture {name}\n{e}"))?;
+    Ok((idx, tex))
+}
+
+// whether the back of an imported surface can't be hit, glTF materials are single sided
+// unless marked double sided but two sided lights are visible (and emit) from behind
+#[must_use]
+fn culls_back(double_sided: bool, mat: &Mat) -> bool {
+    !double_sided && !matches!(mat, Mat::Light(l) if l.is_two_sided())
+}
+
+// points rough glass without an ior_tex at the texture <material>_ior, a constant of the
+// (overridden) ior unless it has already been loaded so it can be replaced by
+// --tex-override
+fn with_ior_texture(
+    mut mat: Mat,
+    mat_name: &str,
+    ior: f32,
+    texs: &mut Vec<Texture>,
+    tex_names: &mut HashMap<String, usize>,
+) -> Mat {
+    if let Mat::RoughDielectric(ref mut m) = mat {
+        if m.ior != crate::NO_TEXTURE {
+            return mat;
+        }
+        let tex_name = format!("{mat_name}_ior");
+        m.ior = *tex_names.entry(tex_name).or_insert_with(|| {
+            texs.push(Texture::Solid(Vec3::splat(ior)));
+            texs.len() - 1
+        });
+    }
+    mat
+}
+
+// adds the clear coat & sheen of a glTF material (KHR_materials_clearcoat &
+// KHR_materials_sheen) and its dielectric specular (KHR_materials_specular, unless
+// overridden) to the principled material, only the factors are used (textures are
+// ignored) and the specular colour is averaged as the principled specular isn't tinted
+fn with_gltf_lobes(
+    mat: Mat,
+    gltf_mat: &gltf::Material,
+    mat_override: &MatOverride,
+    render_settings: &RenderSettings,
+) -> Mat {
+    let mut m = match mat {
+        Mat::Principled(m) => m,
+        mat => return mat,
+    };
+    let name = gltf_mat.name().unwrap_or("unnamed");
+    let ext = |ext: &str| {
+        let value = gltf_mat.extension_value(ext)?;
+        if let Some(texture) = value
+            .as_object()
+            .and_then(|o| o.keys().find(|k| k.ends_with("Texture")))
+        {
+            log::warn!("{ext} {texture} of {name} isn't supported, using its factors");
+        }
+        Some(value)
+    };
+    let factor = |value: &gltf::json::Value, key: &str, default: f32| {
+        value
+            .get(key)
+            .and_then(|v| v.as_f64())
+            .map_or(default, |v| v as f32)
+    };
+    let colour = |value: &gltf::json::Value, key: &str, default: f32| match value
+        .get(key)
+        .and_then(|v| v.as_array())
+        .map(|a| {
+            a.iter()
+                .map(|v| v.as_f64().unwrap_or(default as f64) as f32)
+                .collect::<Vec<_>>()
+        }) {
+        Some(v) if v.len() == 3 => Vec3::new(v[0], v[1], v[2]),
+        _ => Vec3::splat(default),
+    };
+
+    if let Some(value) = ext("KHR_materials_clearcoat") {
+        let ggx = Ggx::new_clamped(
+            factor(value, "clearcoatRoughnessFactor", 0.0),
+            0,
+            render_settings.roughness_min,
+            render_settings.roughness_max,
+        )
+        .with_multiscatter(render_settings.ggx_multiscatter);
+        m = m.with_clearcoat(factor(value, "clearcoatFactor", 0.0), ggx);
+    }
+    if let Some(value) = ext("KHR_materials_sheen") {
+        m = m.with_sheen(
+            colour(value, "sheenColorFactor", 0.0),
+            factor(value, "sheenRoughnessFactor", 0.0),
+        );
+    }
+    if let Some(value) = ext("KHR_materials_specular").filter(|_| mat_override.specular.is_none()) {
+        let tint = colour(value, "specularColorFactor", 1.0);
+        // the default of 1 is the principled default of 0.5 (4% reflectance)
+        m.specular = 0.5 * factor(value, "specularFactor", 1.0) * (tint.x + tint.y + tint.z) / 3.0;
+    }
+    Mat::Principled(m)
+}
+
 fn mat_to_mat(
     bufs: &[gltf::buffer::Data],
     gltf_mat: &gltf::Material,
     mat_name: String,
     texs: &mut Vec<Texture>,
     tex_names: &mut HashMap<String, usize>,
+    render_settings: &RenderSettings,
 ) -> Option<Mat> {
+    let mat_override =
+        parse_mat_override(&mat_name, render_settings, |n| tex_names.get(n).copied());
+
+    // emissive materials are lights, scaled across the surface by the emissive texture
+    let emissive = Vec3::from(gltf_mat.emissive_factor());
+    if emissive != Vec3::ZERO {
+        let texture = gltf_mat.emissive_texture().map(|info| {
+            let name = format!("{mat_name}_emissive");
+            load_texture(bufs, &info.texture(), name, texs, tex_names)
+        });
+        return Some(Mat::Light(
+            Light::new(emissive)
+                .with_texture(texture)
+                .with_two_sided(mat_override.two_sided || gltf_mat.double_sided()),
+        ));
+    }
+
     let roughness = gltf_mat.pbr_metallic_roughness();
-    match roughness.base_color_texture() {
-        Some(info) => {
-            let tex = info.texture();
-            let source = tex.source().source();
-            let gltf::image::Source::View { view, .. } = source else {
-                panic!()
-            };
-            let buff = &bufs[view.buffer().index()];
-            let tex_name = tex.name().map(|v| v.to_owned()).unwrap_or(mat_name);
-
-            let idx = if !tex_names.contains_key(&tex_name) {
-                let start = view.offset();
-                let end = start + view.length();
-                let tex_data = &buff[start..end];
-                let image = image::load_from_memory(tex_data).unwrap();
-                let image = image.to_rgba32f();
-                let dim = image.dimensions();
-                let image = image.into_vec();
-                let tex =
-                    Texture::Image(Image::from_rgbaf32(dim.0 as usize, dim.1 as usize, image));
-                let idx = texs.len();
-                texs.push(tex);
-                tex_names.insert(tex_name, idx);
-                idx
-            } else {
-                *tex_names.get(&tex_name).unwrap()
-            };
-            return Some(Mat::Glossy(Ggx::new(roughness.roughness_factor(), idx)));
-        }
+    let idx = match roughness.base_color_texture() {
+        Some(info) => load_texture(bufs, &info.texture(), mat_name.clone(), texs, tex_names),
         None => {
             let base_col = roughness.base_color_factor();
-            let tex_name = mat_name;
-            let idx = if !tex_names.contains_key(&tex_name) {
+            let tex_name = mat_name.clone();
+            if !tex_names.contains_key(&tex_name) {
                 let tex = Texture::Solid(Vec3::new(base_col[0], base_col[1], base_col[2]));
                 let idx = texs.len();
                 texs.push(tex);
@@ -308,9 +1092,331 @@ fn mat_to_mat(
                 idx
             } else {
                 *tex_names.get(&tex_name).unwrap()
-            };
+            }
+        }
+    };
+    let mat = mat_override.apply(
+        roughness.roughness_factor(),
+        roughness.metallic_factor(),
+        idx,
+        render_settings,
+    );
+    let mat = with_gltf_lobes(mat, gltf_mat, &mat_override, render_settings);
+    let ior = mat_override.ior.unwrap_or(DEFAULT_IOR);
+    Some(with_ior_texture(mat, &mat_name, ior, texs, tex_names))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mesh_rotation() {
+        let close = |a: Vec3, b: Vec3| (a - b).mag() < 1e-5;
+        assert!(parse_rot("1,2").is_err() && parse_rot("a,b,c").is_err());
+        assert!(parse_rot("0,0,0,0").is_err());
+        let Ok(Rot::Euler(v)) = parse_rot("90, 0, 45") else {
+            panic!()
+        };
+        assert_eq!(v, Vec3::new(90.0, 0.0, 45.0));
+
+        // blender XYZ euler rotates about X, then Y, then Z (all global axes)
+        let rot = parse_rot("90,0,0").unwrap().quaternion();
+        assert!(close(rot.rotate(Vec3::Y), Vec3::Z));
+        let rot = parse_rot("0,0,90").unwrap().quaternion();
+        assert!(close(rot.rotate(Vec3::X), Vec3::Y));
+        let rot = parse_rot("90,0,90").unwrap().quaternion();
+        assert!(close(rot.rotate(Vec3::Y), Vec3::Z));
+        assert!(close(rot.rotate(Vec3::Z), Vec3::X));
+        let rot = parse_rot("0,90,90").unwrap().quaternion();
+        assert!(close(rot.rotate(Vec3::X), -Vec3::Z));
 
-            return Some(Mat::Glossy(Ggx::new(roughness.roughness_factor(), idx)));
+        // quaternions are normalised, 90 degrees about Z
+        let rot = parse_rot("2,0,0,2").unwrap().quaternion();
+        assert!(close(rot.rotate(Vec3::X), Vec3::Y));
+        assert!(close(Rot::Identity.quaternion().rotate(Vec3::X), Vec3::X));
+
+        let specs = ["chair=0,0,90".to_owned(), "lamp=1,0,0,0".to_owned()];
+        let rotations = parse_mesh_rotations(&specs).unwrap();
+        assert_eq!(rotations.len(), 2);
+        assert!(parse_mesh_rotations(&["0,0,90".to_owned()]).is_err());
+        assert!(parse_mesh_rotations(&["chair=0,0".to_owned()]).is_err());
+
+        // the chair turns about its node's origin, the node's transform is kept
+        let node = Mat4::from_trs(Vec3::new(5.0, 0.0, 0.0), Mat4::IDENTITY, Vec3::splat(2.0));
+        let chair = mesh_transform(node, rotations.get("chair").copied());
+        assert!(close(chair.mul_point(Vec3::X), Vec3::new(5.0, 2.0, 0.0)));
+        assert!(close(
+            chair.mul_point(Vec3::ZERO),
+            node.mul_point(Vec3::ZERO)
+        ));
+        let table = mesh_transform(node, rotations.get("table").copied());
+        assert!(close(table.mul_point(Vec3::X), Vec3::new(7.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn mesh_scale() {
+        assert_eq!(parse_scale("2"), Ok(Vec3::splat(2.0)));
+        assert_eq!(parse_scale("1, 0.5,3"), Ok(Vec3::new(1.0, 0.5, 3.0)));
+        assert_eq!(parse_scale("-1,1,1"), Ok(Vec3::new(-1.0, 1.0, 1.0)));
+        for spec in ["0", "1,2", "1,0,1", "a", "1,inf,1"] {
+            assert!(parse_scale(spec).is_err(), "{spec}");
+        }
+    }
+
+    #[test]
+    fn triangle_strip() {
+        // 2x1 strip of quads in the XY plane with a fan & list of the same vertices
+        let positions: [[f32; 3]; 6] = [
+            [0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [2.0, 0.0, 0.0],
+            [2.0, 1.0, 0.0],
+        ];
+        let mut buffer: Vec<u8> = positions
+            .iter()
+            .flatten()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        buffer.extend((0..6u16).flat_map(|i| i.to_le_bytes()));
+        let gltf = format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "buffers": [{{"byteLength": {}, "uri": "data:application/octet-stream;base64,{}"}}],
+                "bufferViews": [
+                    {{"buffer": 0, "byteOffset": 0, "byteLength": 72}},
+                    {{"buffer": 0, "byteOffset": 72, "byteLength": 12}}
+                ],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": 6, "type": "VEC3",
+                      "min": [0, 0, 0], "max": [2, 1, 0]}},
+                    {{"bufferView": 1, "componentType": 5123, "count": 6, "type": "SCALAR"}}
+                ],
+                "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}, "indices": 1, "mode": 5}}]}}]
+            }}"#,
+            buffer.len(),
+            base64::encode(&buffer)
+        );
+        let (doc, bufs, _) = gltf::import_slice(gltf.as_bytes()).unwrap();
+        let primitive = doc.meshes().next().unwrap().primitives().next().unwrap();
+        assert_eq!(primitive.mode(), gltf::mesh::Mode::TriangleStrip);
+        let reader = primitive.reader(|buffer| Some(&bufs[buffer.index()]));
+        let indices: Vec<usize> = reader
+            .read_indices()
+            .unwrap()
+            .into_u32()
+            .map(|v| v as usize)
+            .collect();
+
+        let strip = triangle_indices(primitive.mode(), &indices);
+        assert_eq!(strip.len(), 4);
+        let fan = triangle_indices(gltf::mesh::Mode::TriangleFan, &[0, 1, 3, 2]);
+        assert_eq!(fan, [[0, 1, 3], [0, 3, 2]]);
+        let list = triangle_indices(gltf::mesh::Mode::Triangles, &indices);
+        assert_eq!(list, [[0, 1, 2], [3, 4, 5]]);
+
+        // every triangle of the strip & fan faces the same way as the first
+        let normal = |[a, b, c]: [usize; 3]| {
+            let [a, b, c] = [a, b, c].map(|i| Vec3::from(positions[i]));
+            (c - a).cross(b - a).normalised()
+        };
+        for tri in strip.into_iter().chain(fan) {
+            assert_eq!(normal(tri), Vec3::Z);
         }
     }
+
+    #[test]
+    fn tex_override() {
+        crate::scene::test_scene();
+        let path = std::env::temp_dir().join("yapt_tex_override.png");
+        let pixels: Vec<u8> = (0..5 * 3 * 4).map(|i| (i * 4) as u8).collect();
+        image::save_buffer(&path, &pixels, 5, 3, image::ColorType::Rgba8).unwrap();
+        let png = std::fs::read(&path).unwrap();
+
+        let dimensions = |spec: &str| match parse_tex_override(spec) {
+            Ok((idx, Texture::Image(img))) => (idx, img.dimensions()),
+            Ok(_) => panic!("{spec} is not an image"),
+            Err(e) => panic!("{e}"),
+        };
+        assert_eq!(dimensions(&format!("red={}", path.display())), (1, (5, 3)));
+        let uri = format!("gold=data:image/png;base64,{}", base64::encode(&png));
+        assert_eq!(dimensions(&uri), (2, (5, 3)));
+
+        assert!(parse_tex_override("missing=a.png").is_err());
+        assert!(parse_tex_override("red=/does/not/exist.png").is_err());
+        assert!(parse_tex_override("red=data:image/png,abc").is_err());
+        assert!(parse_tex_override("red").is_err());
+    }
+
+    #[test]
+    fn missing_uvs() {
+        // 8x8 checker
+        let checker: Vec<f32> = (0..64)
+            .flat_map(|i| {
+                let v = ((i % 8 + i / 8) % 2) as f32;
+                [v, v, v, 1.0]
+            })
+            .collect();
+        let checker =
+            Texture::Image(Image::from_rgbaf32(8, 8, checker).with_filter(TextureFilter::Nearest));
+
+        // UV-less grid on the XZ plane
+        let verts: Vec<Vec3> = (0..100)
+            .map(|i| Vec3::new((i % 10) as f32, 0.0, (i / 10) as f32))
+            .collect();
+        let norms = vec![Vec3::Y; verts.len()];
+
+        let distinct = |projection| {
+            let uvs = project_uvs(&verts, &norms, projection);
+            assert_eq!(uvs.len(), verts.len());
+            let mut cols: Vec<_> = uvs.iter().map(|&uv| checker.uv_value(uv).x as u8).collect();
+            cols.sort();
+            cols.dedup();
+            cols.len()
+        };
+
+        assert_eq!(distinct(UvProjection::Zero), 1);
+        assert_eq!(distinct(UvProjection::Planar), 2);
+        assert_eq!(distinct(UvProjection::Box), 2);
+    }
+
+    #[test]
+    fn mat_override() {
+        use clap::Parser;
+        let rs = RenderSettings::parse_from([
+            "yapt",
+            "--mat-override",
+            "floor:roughness_factor=0.7",
+            "--mat-override",
+            "wall:metallic_factor=0",
+        ]);
+        let floor = parse_mat_override("floor", &rs, texture_idx);
+        assert_eq!(floor.roughness_factor, Some(0.7));
+        assert_eq!(
+            parse_mat_override("other", &rs, texture_idx),
+            MatOverride::default()
+        );
+
+        // replaces the imported factor
+        let Mat::Glossy(ggx) = floor.apply(0.1, 1.0, 0, &rs) else {
+            panic!()
+        };
+        assert_eq!(ggx.roughness(), 0.7);
+        let Mat::Glossy(ggx) = MatOverride::default().apply(0.1, 1.0, 0, &rs) else {
+            panic!()
+        };
+        assert_eq!(ggx.roughness(), 0.1);
+        assert!(matches!(
+            parse_mat_override("wall", &rs, texture_idx).apply(0.1, 1.0, 0, &rs),
+            Mat::Matte(_)
+        ));
+
+        // anisotropy keeps the mean roughness
+        let brushed = MatOverride {
+            anisotropy: Some(0.8),
+            ..Default::default()
+        };
+        let Mat::Glossy(ggx) = brushed.apply(0.3, 1.0, 0, &rs) else {
+            panic!()
+        };
+        assert!((ggx.roughness() - 0.3).abs() < 1e-6);
+
+        let rs = RenderSettings::parse_from([
+            "yapt",
+            "--mat-override",
+            "bottle:ior=1.5",
+            "--mat-override",
+            "bottle:transmittance=0.2,0.8,0.5",
+        ]);
+        let Mat::Refractive(glass) =
+            parse_mat_override("bottle", &rs, texture_idx).apply(0.1, 1.0, 0, &rs)
+        else {
+            panic!()
+        };
+        assert_eq!(glass.ior, 1.5);
+        assert_eq!(glass.transmittance, Vec3::new(0.2, 0.8, 0.5));
+        let rs = RenderSettings::parse_from([
+            "yapt",
+            "--mat-override",
+            "window:type=thindielectric",
+            "--mat-override",
+            "bubble:type=thindielectric",
+            "--mat-override",
+            "bubble:ior=1.3",
+        ]);
+        let Mat::ThinDielectric(window) =
+            parse_mat_override("window", &rs, texture_idx).apply(0.1, 1.0, 0, &rs)
+        else {
+            panic!()
+        };
+        assert_eq!(window.ior, 1.5);
+        let Mat::ThinDielectric(bubble) =
+            parse_mat_override("bubble", &rs, texture_idx).apply(0.1, 1.0, 0, &rs)
+        else {
+            panic!()
+        };
+        assert_eq!(bubble.ior, 1.3);
+
+        // rough glass reads its ior from a texture named after the material
+        let rs =
+            RenderSettings::parse_from(["yapt", "--mat-override", "frosted:type=roughdielectric"]);
+        let frosted = parse_mat_override("frosted", &rs, texture_idx).apply(0.3, 1.0, 0, &rs);
+        let (mut texs, mut tex_names) = (vec![], HashMap::new());
+        let Mat::RoughDielectric(frosted) =
+            with_ior_texture(frosted, "frosted", 1.7, &mut texs, &mut tex_names)
+        else {
+            panic!()
+        };
+        assert!((frosted.ggx.roughness() - 0.3).abs() < 1e-6);
+        assert_eq!(tex_names.get("frosted_ior"), Some(&frosted.ior));
+        assert!(matches!(texs[frosted.ior], Texture::Solid(v) if v == Vec3::splat(1.7)));
+
+        // or from a named texture, which implies rough glass
+        crate::scene::test_scene();
+        let rs =
+            RenderSettings::parse_from(["yapt", "--mat-override", "varnish:ior_tex=ior_gradient"]);
+        let varnish = parse_mat_override("varnish", &rs, texture_idx);
+        assert_eq!(varnish.ior_tex, texture_idx("ior_gradient"));
+        let Mat::RoughDielectric(varnish) = with_ior_texture(
+            varnish.apply(0.3, 1.0, 0, &rs),
+            "varnish",
+            1.7,
+            &mut texs,
+            &mut tex_names,
+        ) else {
+            panic!()
+        };
+        assert_eq!(Some(varnish.ior), texture_idx("ior_gradient"));
+        assert!(!tex_names.contains_key("varnish_ior"));
+
+        // the imported metallic factor is only used by the principled material
+        let rs = RenderSettings::parse_from([
+            "yapt",
+            "--principled",
+            "--mat-override",
+            "cup:specular=0.25",
+            "--mat-override",
+            "floor:principled=0",
+        ]);
+        let Mat::Principled(cup) =
+            parse_mat_override("cup", &rs, texture_idx).apply(0.2, 0.3, 0, &rs)
+        else {
+            panic!()
+        };
+        assert_eq!((cup.metallic, cup.specular), (0.3, 0.25));
+        assert!(matches!(
+            parse_mat_override("floor", &rs, texture_idx).apply(0.2, 0.3, 0, &rs),
+            Mat::Glossy(_)
+        ));
+    }
+
+    #[test]
+    fn two_sided_light() {
+        use clap::Parser;
+        let rs = RenderSettings::parse_from(["yapt", "--mat-override", "lamp:two_sided=1"]);
+        assert!(parse_mat_override("lamp", &rs, texture_idx).two_sided);
+        assert!(!parse_mat_override("other", &rs, texture_idx).two_sided);
+    }
 }
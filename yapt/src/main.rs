@@ -10,28 +10,77 @@ pub const WIDTH: std::num::NonZeroU32 = unsafe { std::num::NonZeroU32::new_unche
 pub const HEIGHT: std::num::NonZeroU32 = unsafe { std::num::NonZeroU32::new_unchecked(1024) };
 const SAMPLES: u64 = 1000;
 pub const NO_TEXTURE: usize = usize::MAX;
+// offsets along the normal for rays leaving a surface, these are separate as too
+// small a shadow offset causes acne while too large a one leaks light at contacts
+pub const BOUNCE_EPSILON: f32 = 0.000001;
+pub const SHADOW_EPSILON: f32 = 0.000001;
+// hits closer than this along a ray are ignored so rays starting on a surface (such as
+// those passing through a ThinDielectric) don't hit it again
+pub const T_MIN: f32 = 0.0001;
 
+pub mod adaptive;
 pub mod camera;
+pub mod color;
 pub mod coord;
+pub mod denoise;
+pub mod directional;
 pub mod distributions;
 pub mod envmap;
+pub mod fog;
 #[cfg(feature = "gui")]
 pub mod gui;
+pub mod hash;
+pub mod heatmap;
+pub mod instance;
 pub mod integrator;
+pub mod light_link;
+pub mod light_sampler;
 pub mod loader;
 pub mod material;
+#[cfg(feature = "oidn")]
+pub mod oidn;
+pub mod output;
+pub mod primitive;
 pub mod pssmlt;
+pub mod sampler;
 pub mod scene;
+pub mod sky;
+pub mod spectral;
+pub mod sphere;
+pub mod swatch;
 pub mod texture;
+pub mod tile;
+pub mod tonemap;
 pub mod triangle;
+#[cfg(feature = "volumes")]
+pub mod volume;
 pub mod work_handler;
 
 pub mod prelude {
     pub use crate::{
-        camera::Cam, coord::*, envmap::*, integrator::*, loader, material::*, pssmlt::MinRng,
-        scene::Scene, texture::*, triangle::Tri, work_handler::*, IntegratorType, Intersection,
-        RenderSettings, Splat, BVH, CAM, ENVMAP, HEIGHT, MATERIALS, MATERIAL_NAMES, NORMALS,
-        SAMPLABLE, TEXTURES, TEXTURE_NAMES, TRIANGLES, UVS, VERTICES, WIDTH,
+        camera::{Cam, EulerOrder, Projection},
+        color::{ColorTransform, Primaries},
+        coord::*,
+        directional::DirectionalLight,
+        envmap::*,
+        instance::{Blas, Instance},
+        integrator::*,
+        loader,
+        material::*,
+        primitive::Primitive,
+        pssmlt::MinRng,
+        scene::Scene,
+        spectral::Lambda,
+        sphere::Sphere,
+        texture::*,
+        tile::Tile,
+        triangle::Tri,
+        work_handler::*,
+        IntegratorType, Intersection, RenderSettings, Splat, BACKPLATE, BVH, CAM, CAMERAS, ENVMAP,
+        ENV_SAMPLING_PROB, HEIGHT, INSTANCES, LIGHT_LINKS, LIGHT_SAMPLER, MATERIALS,
+        MATERIAL_NAMES, MESHES, MESH_NAMES, NORMALS, NORMAL_MAPS, SAMPLABLE, SINGLE_SIDED, SUN,
+        SUN_SAMPLING_PROB, TANGENTS, TEXTURES, TEXTURE_NAMES, TLAS, TRIANGLES, UVS, VERTICES,
+        WIDTH,
     };
     pub use bvh::Bvh;
     pub use derive_new::new;
@@ -43,13 +92,14 @@ pub mod prelude {
         ptr::{addr_of, addr_of_mut},
         sync::Arc,
     };
-    pub use utility::{Ray, Vec2, Vec3};
+    pub use utility::{Mat4, Ray, Vec2, Vec3};
 }
 use std::sync::Mutex;
 
 use prelude::*;
 
 use clap::Parser;
+use heatmap::HeatmapScale;
 
 const CHUNK_SIZE: usize = 4096;
 
@@ -58,18 +108,50 @@ const CHAINS: usize = 100;
 
 pub static VERTICES: SyncUnsafeCell<Vec<Vec3>> = SyncUnsafeCell::new(vec![]);
 pub static NORMALS: SyncUnsafeCell<Vec<Vec3>> = SyncUnsafeCell::new(vec![]);
+// parallel to NORMALS, tangent & bitangent sign (zero tangent = mesh had no tangents)
+pub static TANGENTS: SyncUnsafeCell<Vec<(Vec3, f32)>> = SyncUnsafeCell::new(vec![]);
 pub static UVS: SyncUnsafeCell<Vec<Vec2>> = SyncUnsafeCell::new(vec![]);
 pub static MATERIALS: SyncUnsafeCell<Vec<Mat>> = SyncUnsafeCell::new(vec![]);
 pub static TEXTURES: SyncUnsafeCell<Vec<Texture>> = SyncUnsafeCell::new(vec![]);
-pub static TRIANGLES: SyncUnsafeCell<Vec<Tri>> = SyncUnsafeCell::new(vec![]);
+// all primitives (triangles and spheres) the BVH is built over
+pub static TRIANGLES: SyncUnsafeCell<Vec<Primitive>> = SyncUnsafeCell::new(vec![]);
 pub static SAMPLABLE: SyncUnsafeCell<Vec<usize>> = SyncUnsafeCell::new(vec![]);
+// per material, single sided materials can't be hit from behind (missing = double sided)
+pub static SINGLE_SIDED: SyncUnsafeCell<Vec<bool>> = SyncUnsafeCell::new(vec![]);
+// per material, tangent space normal map texture & scale (missing = no normal map)
+pub static NORMAL_MAPS: SyncUnsafeCell<Vec<Option<(usize, f32)>>> = SyncUnsafeCell::new(vec![]);
+// probability of sampling the environment map rather than a triangle light for NEE
+pub static ENV_SAMPLING_PROB: SyncUnsafeCell<f32> = SyncUnsafeCell::new(0.0);
+pub static SUN: SyncUnsafeCell<Option<directional::DirectionalLight>> = SyncUnsafeCell::new(None);
+// probability of sampling SUN for NEE
+pub static SUN_SAMPLING_PROB: SyncUnsafeCell<f32> = SyncUnsafeCell::new(0.0);
+pub static LIGHT_LINKS: SyncUnsafeCell<light_link::LightLinks> =
+    SyncUnsafeCell::new(light_link::LightLinks::NONE);
+// picks between the SAMPLABLE triangle lights (uniform until built in init)
+pub static LIGHT_SAMPLER: SyncUnsafeCell<light_sampler::LightSampler> =
+    SyncUnsafeCell::new(light_sampler::LightSampler::UNIFORM);
 pub static BVH: SyncUnsafeCell<Bvh> = SyncUnsafeCell::new(Bvh { nodes: vec![] });
+// meshes referenced by several glTF nodes are stored once (in their own space) and
+// placed by instances, TLAS is built over the instances like BVH is over TRIANGLES
+pub static MESHES: SyncUnsafeCell<Vec<Blas>> = SyncUnsafeCell::new(vec![]);
+pub static INSTANCES: SyncUnsafeCell<Vec<Instance>> = SyncUnsafeCell::new(vec![]);
+pub static TLAS: SyncUnsafeCell<Bvh> = SyncUnsafeCell::new(Bvh { nodes: vec![] });
 pub static MATERIAL_NAMES: Mutex<std::cell::OnceCell<HashMap<String, usize>>> =
     Mutex::new(std::cell::OnceCell::new());
 pub static TEXTURE_NAMES: Mutex<std::cell::OnceCell<HashMap<String, usize>>> =
     Mutex::new(std::cell::OnceCell::new());
+// names of loaded meshes, primitives of a named mesh store its index (see Tri::mesh)
+pub static MESH_NAMES: Mutex<std::cell::OnceCell<HashMap<String, usize>>> =
+    Mutex::new(std::cell::OnceCell::new());
 pub static ENVMAP: SyncUnsafeCell<EnvMap> = SyncUnsafeCell::new(EnvMap::DEFAULT);
 pub static CAM: SyncUnsafeCell<Cam> = SyncUnsafeCell::new(crate::camera::PLACEHOLDER);
+// every camera in the loaded scene files (rendered in turn with --all-cameras)
+pub static CAMERAS: SyncUnsafeCell<Vec<Cam>> = SyncUnsafeCell::new(vec![]);
+pub static BACKPLATE: SyncUnsafeCell<Option<Backplate>> = SyncUnsafeCell::new(None);
+#[cfg(feature = "volumes")]
+pub static VOLUME: SyncUnsafeCell<Option<volume::Volume>> = SyncUnsafeCell::new(None);
+// homogeneous fog traced by the naive & NEE + MIS integrators, None without any
+pub static FOG: SyncUnsafeCell<Option<fog::Fog>> = SyncUnsafeCell::new(None);
 
 const MAGIC_VALUE_ONE: f32 = 543543521.0;
 const MAGIC_VALUE_ONE_VEC: Vec3 = Vec3::new(MAGIC_VALUE_ONE, MAGIC_VALUE_ONE, MAGIC_VALUE_ONE);
@@ -81,16 +163,37 @@ pub enum IntegratorType {
     Naive,
     #[default]
     NEE,
+    #[cfg(feature = "volumes")]
+    Volumetric,
+    // distance to the first hit (see --near/--far)
+    Depth,
+    // first hit albedo (for denoising)
+    Albedo,
+    // ambient occlusion of the first hit (see --ao-radius)
+    AO,
 }
 
 pub struct Splat {
     uv: [f32; 2],
     rgb: Vec3,
+    // from an odd sample index (for --half-buffers)
+    odd: bool,
 }
 
 impl Splat {
-    pub fn new(uv: [f32; 2], rgb: Vec3) -> Self {
-        Self { uv, rgb }
+    pub fn new(uv: [f32; 2], rgb: Vec3, odd: bool) -> Self {
+        Self { uv, rgb, odd }
+    }
+    // canvas index of the pixel the splat lands in
+    #[must_use]
+    pub fn pixel(&self, width: u32, height: u32) -> usize {
+        let uv = self.uv;
+        assert!(uv[0] <= 1.0 && uv[1] <= 1.0);
+
+        let x = (uv[0] * width as f32) as usize;
+        let y = (uv[1] * height as f32) as usize;
+
+        (y * width as usize + x).min(width as usize * height as usize - 1)
     }
 }
 
@@ -99,12 +202,17 @@ impl fmt::Display for IntegratorType {
         let s = match self {
             Self::Naive => "naive",
             Self::NEE => "nee",
+            #[cfg(feature = "volumes")]
+            Self::Volumetric => "volumetric",
+            Self::Depth => "depth",
+            Self::Albedo => "albedo",
+            Self::AO => "ao",
         };
         write!(f, "{s}")
     }
 }
 
-#[derive(Debug, new)]
+#[derive(Debug, Clone, new)]
 pub struct Intersection {
     pub t: f32,
     pub uv: Vec2,
@@ -113,6 +221,9 @@ pub struct Intersection {
     pub out: bool,
     pub mat: usize,
     pub id: usize,
+    // named mesh that was hit (see MESH_NAMES)
+    #[new(default)]
+    pub mesh: Option<usize>,
 }
 
 impl Intersection {
@@ -124,6 +235,7 @@ impl Intersection {
         out: false,
         mat: 0,
         id: 0,
+        mesh: None,
     };
 
     #[allow(clippy::float_cmp)]
@@ -131,12 +243,31 @@ impl Intersection {
     pub fn is_none(&self) -> bool {
         self.t == -1.0
     }
+    // distance along the ray to the hit, infinite for misses
+    #[must_use]
+    pub fn t_max(&self) -> f32 {
+        if self.is_none() {
+            f32::INFINITY
+        } else {
+            self.t
+        }
+    }
 
     pub fn min(&mut self, other: Self) {
         if self.is_none() || (other.t < self.t && other.t > 0.0) {
             *self = other;
         }
     }
+    // origin for scattered rays (nor faces the incoming ray)
+    #[must_use]
+    pub fn bounce_origin(&self) -> Vec3 {
+        self.pos + self.nor * BOUNCE_EPSILON
+    }
+    // origin for shadow rays towards lights
+    #[must_use]
+    pub fn shadow_origin(&self) -> Vec3 {
+        self.pos + self.nor * SHADOW_EPSILON
+    }
 }
 
 fn main() {
@@ -144,6 +275,11 @@ fn main() {
 
     let args = RenderSettings::parse();
 
+    if args.print_config {
+        println!("{}", args.to_json());
+        return;
+    }
+
     // GUI mode
     #[cfg(feature = "gui")]
     if !args.headless {
@@ -179,32 +315,36 @@ fn main() {
         None,
         args.clone(),
     );
-    let rs = &mut app.render_settings;
+    if app.render_settings.bvh_heatmap {
+        return;
+    }
     while let Ok(update) = app.update_recv.recv() {
         match update {
-            Update::Calculation(splats, workload_id, ray_count)
+            Update::Calculation(mut splats, workload_id, ray_count)
                 if workload_id == app.workload_id =>
             {
+                let rs = &app.render_settings;
                 app.work_duration += app.work_start.elapsed();
                 app.work_start = std::time::Instant::now();
                 app.splats_done += splats.len() as u64;
 
                 // add splats to image
-                for splat in splats {
-                    let uv = splat.uv;
-                    let idx = {
-                        assert!(uv[0] <= 1.0 && uv[1] <= 1.0);
-
-                        let x = (uv[0] * u32::from(rs.width) as f32) as usize;
-                        let y = (uv[1] * u32::from(rs.height) as f32) as usize;
-
-                        (y * u32::from(rs.width) as usize + x)
-                            .min(u32::from(rs.width) as usize * u32::from(rs.height) as usize - 1)
-                    };
+                for splat in splats.drain(..) {
+                    let idx = splat.pixel(rs.width.get(), rs.height.get());
 
                     app.canvas[idx] += splat.rgb;
+                    if let Some(half) = &mut app.half_buffers {
+                        half.add(idx, splat.rgb, splat.odd);
+                    }
+                    if let Some(adaptive) = &mut app.adaptive {
+                        adaptive.add(idx, splat.rgb);
+                    }
+                    if let Some(spp) = &mut app.spp {
+                        spp[idx] += 1;
+                    }
                     app.updated = true;
                 }
+                app.splat_pool.recycle(splats);
                 app.work_rays += ray_count;
 
                 // update progress
@@ -220,10 +360,65 @@ fn main() {
                     app.last_update = std::time::Instant::now();
                 }
 
-                // work queue cleared
-                if app.splats_done
-                    == u32::from(rs.width) as u64 * u32::from(rs.height) as u64 * rs.samples
+                // save the render state for --resume
+                if rs.checkpoint_interval > 0.0
+                    && app.last_checkpoint.elapsed().as_secs_f32() > rs.checkpoint_interval
                 {
+                    let path = output::checkpoint_path(&app.output_filename());
+                    match output::save_exr(
+                        &app.canvas,
+                        rs.width.get() as usize,
+                        rs.height.get() as usize,
+                        rs.pixel_aspect,
+                        app.splats_done,
+                        &path,
+                        &ColorTransform::new(rs.working_primaries, rs.working_primaries),
+                    ) {
+                        Ok(()) => log::info!("Saved checkpoint to {path}"),
+                        Err(e) => log::error!("Failed to save checkpoint to {path}\n{e}"),
+                    }
+                    app.last_checkpoint = std::time::Instant::now();
+                }
+                if rs.save_interval > 0.0
+                    && app.last_save.elapsed().as_secs_f32() > rs.save_interval
+                {
+                    save_snapshot(&app);
+                    app.last_save = std::time::Instant::now();
+                }
+
+                // adaptive sampling carries on with the unconverged pixels after each pass
+                let finished = match &mut app.adaptive {
+                    None => app.splats_done == app.accumulated_splats + app.pixels() * rs.samples,
+                    Some(adaptive) => {
+                        app.splats_done == adaptive.pass_end()
+                            && match adaptive.next_pass(&app.canvas, rs.samples) {
+                                Some((samples, pixels)) => {
+                                    log::debug!(
+                                        "Adaptive pass of {samples} samples over {} pixels",
+                                        pixels.len()
+                                    );
+                                    app.work_req
+                                        .send(ComputeChange::WorkPixels(
+                                            samples,
+                                            pixels.into(),
+                                            app.workload_id,
+                                        ))
+                                        .unwrap();
+                                    false
+                                }
+                                None => {
+                                    log::info!(
+                                        "Adaptive sampling averaged {:.1} samples per pixel",
+                                        app.splats_done as f64 / app.canvas.len() as f64
+                                    );
+                                    true
+                                }
+                            }
+                    }
+                };
+
+                // work queue cleared
+                if finished {
                     log::info!(
                             "Render finished: Mrays: {:.2} - Rays shot: {} - elapsed: {:.1} - samples: {}",
                             (app.work_rays as f64 / app.work_duration.as_secs_f64())
@@ -232,41 +427,392 @@ fn main() {
                             app.work_duration.as_secs_f64(),
                             rs.samples
                         );
+                    let nan_samples = NAN_SAMPLES.swap(0, std::sync::atomic::Ordering::Relaxed);
+                    if nan_samples > 0 {
+                        log::warn!(
+                            "{nan_samples} samples had NaN or infinite radiance (--on-nan {})",
+                            rs.on_nan
+                        );
+                    }
+                    let (width, height) = (rs.width.get() as usize, rs.height.get() as usize);
+                    let filename = app.output_filename();
+                    let canvas = app.composited();
+                    let transform = ColorTransform::new(rs.working_primaries, rs.output_primaries);
+                    if filename.ends_with(".png") {
+                        match output::save_png(
+                            &canvas,
+                            width,
+                            height,
+                            app.splats_done,
+                            &filename,
+                            &transform,
+                            (rs.tonemap, rs.exposure),
+                        ) {
+                            Ok(()) => log::info!("Saved render to {filename}"),
+                            Err(e) => log::error!("Failed to save render to {filename}\n{e}"),
+                        }
+                        if rs.clip_mask {
+                            let path =
+                                format!("{}_clipped.png", filename.strip_suffix(".png").unwrap());
+                            match output::save_clip_mask(
+                                &canvas,
+                                width,
+                                height,
+                                app.splats_done,
+                                &path,
+                                &transform,
+                                (rs.tonemap, rs.exposure),
+                            ) {
+                                Ok(()) => log::info!("Saved clip mask to {path}"),
+                                Err(e) => log::error!("Failed to save clip mask\n{e}"),
+                            }
+                        }
+                    }
+                    if filename.ends_with(".exr") {
+                        let guides = (rs.aovs || rs.filter_denoise)
+                            .then(|| denoise::Guides::new(width, height, app.tile));
+                        let saved = match guides.as_ref().filter(|_| rs.aovs) {
+                            Some(guides) => output::save_exr_aovs(
+                                &canvas,
+                                (
+                                    guides,
+                                    app.adaptive.as_ref().map(adaptive::Adaptive::samples),
+                                ),
+                                width,
+                                height,
+                                rs.pixel_aspect,
+                                app.splats_done,
+                                &filename,
+                                &transform,
+                            ),
+                            None => output::save_exr(
+                                &canvas,
+                                width,
+                                height,
+                                rs.pixel_aspect,
+                                app.splats_done,
+                                &filename,
+                                &transform,
+                            ),
+                        };
+                        match saved {
+                            Ok(()) => log::info!("Saved render to {filename}"),
+                            Err(e) => log::error!("Failed to save render to {filename}\n{e}"),
+                        }
+                        if let Some(half) = &app.half_buffers {
+                            match half.save(width, height, rs.pixel_aspect, &filename, &transform) {
+                                Ok(()) => log::info!("Saved half buffers"),
+                                Err(e) => log::error!("Failed to save half buffers\n{e}"),
+                            }
+                        }
+                        if let Some(guides) = guides.as_ref().filter(|_| rs.filter_denoise) {
+                            let filtered = denoise::atrous(
+                                &canvas,
+                                guides,
+                                width,
+                                height,
+                                rs.filter_iterations,
+                            );
+                            let path =
+                                format!("{}_denoised.exr", filename.strip_suffix(".exr").unwrap());
+                            match output::save_exr(
+                                &filtered,
+                                width,
+                                height,
+                                rs.pixel_aspect,
+                                app.splats_done,
+                                &path,
+                                &transform,
+                            ) {
+                                Ok(()) => log::info!("Saved filtered render to {path}"),
+                                Err(e) => log::error!("Failed to save filtered render\n{e}"),
+                            }
+                        }
+                        #[cfg(feature = "oidn")]
+                        if rs.oidn {
+                            let path =
+                                format!("{}_denoised.exr", filename.strip_suffix(".exr").unwrap());
+                            match oidn::denoise(
+                                &canvas,
+                                guides.as_ref(),
+                                width,
+                                height,
+                                app.splats_done,
+                            ) {
+                                Ok(denoised) => match output::save_exr(
+                                    &denoised,
+                                    width,
+                                    height,
+                                    rs.pixel_aspect,
+                                    app.splats_done,
+                                    &path,
+                                    &transform,
+                                ) {
+                                    Ok(()) => log::info!("Saved denoised render to {path}"),
+                                    Err(e) => log::error!("Failed to save denoised render\n{e}"),
+                                },
+                                Err(e) => log::error!("Failed to denoise render\n{e}"),
+                            }
+                        }
+                        if let Some(tile) = app.tile {
+                            if let Err(e) = tile.save_metadata(&filename) {
+                                log::error!("Failed to save tile metadata\n{e}");
+                            }
+                        }
+                    }
+                    if let Some(spp) = app.spp.as_ref().filter(|_| !filename.is_empty()) {
+                        let heatmap = heatmap::spp_heatmap(spp, rs.heatmap_scale);
+                        let path = output::spp_heatmap_path(&filename);
+                        heatmap::save_heatmap(&heatmap, width as u32, height as u32, &path);
+                    }
+
+                    // move on to the next camera reusing the work handler
+                    let cameras = unsafe { CAMERAS.get().as_ref_unchecked() };
+                    if let Some(i) = app.camera.filter(|&i| i + 1 < cameras.len()) {
+                        log::info!("Rendering camera {}", i + 1);
+                        app.camera = Some(i + 1);
+                        app.next_workload();
+                        app.start_work();
+                        continue;
+                    }
                     break;
                 }
             }
-            Update::Calculation(_, workload_id, _) => {
-                log::trace!("Got splats from previous workload {workload_id}!")
+            Update::Calculation(splats, workload_id, _) => {
+                log::trace!("Got splats from previous workload {workload_id}!");
+                app.splat_pool.recycle(splats);
             }
             Update::PssmltBootstrapDone => log::info!("PSSMLT bootstrap done!"),
             Update::NoState => log::info!("No state found!"),
+            Update::Abort(e) => {
+                log::error!("{e}");
+                std::process::exit(1);
+            }
         }
     }
 }
 
-#[derive(Parser, Clone)]
+#[derive(Parser, Clone, serde::Serialize)]
 #[command(about, long_about = None, disable_help_flag = true)]
 pub struct RenderSettings {
     #[arg(long, action = clap::ArgAction::HelpLong)]
+    #[serde(skip)]
     pub help: Option<bool>,
+    // print the resolved settings as JSON and exit
+    #[arg(long, default_value_t = false)]
+    #[serde(skip)]
+    pub print_config: bool,
+    // print a hash of the loaded scene and settings and exit
+    #[arg(long, default_value_t = false)]
+    #[serde(skip)]
+    pub print_hash: bool,
     #[arg(short, default_value_t = false)]
     pub bvh_heatmap: bool,
+    #[arg(long, default_value_t = HeatmapScale::default())]
+    #[serde(serialize_with = "serialize_display")]
+    pub heatmap_scale: HeatmapScale,
+    // also save the samples (splats with PSSMLT) of each pixel mapped through the heatmap
+    // colour map (scaled by --heatmap-scale) as {stem}_spp.png (headless only)
+    #[arg(long, default_value_t = false)]
+    pub spp_heatmap: bool,
     #[arg(short, long, default_value_t = crate::WIDTH)]
     pub width: std::num::NonZeroU32,
     #[arg(short, long, default_value_t = crate::HEIGHT)]
     pub height: std::num::NonZeroU32,
+    // width / height of a single pixel (for anamorphic output)
+    #[arg(long, default_value_t = 1.0)]
+    pub pixel_aspect: f32,
     #[arg(short = 'n', long, default_value_t = crate::SAMPLES)]
     pub samples: u64,
+    // adaptive sampling, pixels stop being sampled (in passes of adaptive::PASS_SAMPLES)
+    // once the relative standard error of their mean luminance is below this, --samples
+    // is then the maximum per pixel (headless only)
+    #[arg(long)]
+    pub noise_threshold: Option<f32>,
     #[arg(short='o', long, default_value_t = String::new())]
     pub filename: String,
     #[arg(short, long, default_value_t = IntegratorType::default())]
+    #[serde(serialize_with = "serialize_display")]
     pub integrator: IntegratorType,
     #[arg(short, long, default_value_t = Scene::default())]
+    #[serde(serialize_with = "serialize_display")]
     pub scene: Scene,
+    // override the scene's camera position (x,y,z)
+    #[arg(
+        long,
+        value_delimiter = ',',
+        num_args = 3,
+        allow_negative_numbers = true
+    )]
+    pub cam_origin: Option<Vec<f32>>,
+    // override the scene's camera rotation with euler angles (x,y,z) in degrees
+    // (matching blender), forward = -Z & up = Y before rotating
+    #[arg(
+        long,
+        value_delimiter = ',',
+        num_args = 3,
+        allow_negative_numbers = true
+    )]
+    pub cam_rotation: Option<Vec<f32>>,
+    // treat the --cam-rotation angles as radians
+    #[arg(long, default_value_t = false)]
+    pub cam_radians: bool,
+    #[arg(long, default_value_t = EulerOrder::default())]
+    #[serde(serialize_with = "serialize_display")]
+    pub cam_euler_order: EulerOrder,
+    // override the scene's horizontal field of view (degrees)
+    #[arg(long)]
+    pub cam_fov: Option<f32>,
+    // rotate the camera about its forward axis (degrees)
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub cam_roll: f32,
+    // override the scene's lens diameter (0 is a pinhole camera) and the distance of
+    // the plane in focus
+    #[arg(long)]
+    pub cam_aperture: Option<f32>,
+    #[arg(long)]
+    pub cam_focus_dist: Option<f32>,
+    // override the scene's projection, orthographic cameras are --cam-ortho-width world
+    // units wide (defaulting to the width of the image plane) and equirectangular
+    // cameras capture the full sphere (and ignore the fov and lens)
+    #[arg(long)]
+    #[serde(serialize_with = "serialize_option_display")]
+    pub cam_projection: Option<Projection>,
+    #[arg(long)]
+    pub cam_ortho_width: Option<f32>,
+    // near & far planes, the depth integrator maps near -> 0 and far -> 1
+    #[arg(long, default_value_t = 0.0)]
+    pub near: f32,
+    #[arg(long)]
+    pub far: Option<f32>,
+    // distance within which the AO integrator counts occlusion (unlimited by default)
+    #[arg(long)]
+    pub ao_radius: Option<f32>,
+    // ignore camera ray intersections outside of near & far
+    #[arg(long, default_value_t = false)]
+    pub clip: bool,
     #[arg(short, default_value_t = false)]
     pub pssmlt: bool,
+    // paths traced before PSSMLT to start the chains & normalise the image
+    #[arg(long, default_value_t = 100_000)]
+    pub pssmlt_bootstrap: u64,
+    // reuse 16 camera rays & intersections of each pixel (one without jitter) for all its
+    // samples, faster at high sample counts but anti-aliasing is limited to those 16
+    // positions, cameras with an aperture aren't cached so keep their depth of field
+    #[arg(long, default_value_t = false)]
+    pub cache_primary: bool,
+    // cap on the radiance of a single light sample (biased, reduces fireflies)
+    #[arg(long)]
+    pub nee_max_radiance: Option<f32>,
+    // pick lights for NEE (and weight MIS with that probability) by their share of each
+    // colour channel's power rather than their luminance, reduces colour noise when
+    // lights have very different colours
+    #[arg(long, default_value_t = false)]
+    pub mis_per_channel: bool,
+    // cap on the luminance of each contribution after the first bounce (naive & nee
+    // integrators), off by default as it's biased (energy is lost) but removes fireflies
+    #[arg(long)]
+    pub clamp: Option<f32>,
+    // non specular bounces before a path is terminated
+    #[arg(long, default_value_t = integrator::MAX_DEPTH)]
+    pub max_depth: u64,
+    // non specular bounces after which russian roulette can terminate a path
+    #[arg(long, default_value_t = integrator::RUSSIAN_ROULETTE_THRESHOLD)]
+    pub rr_depth: u64,
+    // what happens to samples with NaN (or infinite) radiance
+    #[arg(long, default_value_t = NanPolicy::default())]
+    #[serde(serialize_with = "serialize_display")]
+    pub on_nan: NanPolicy,
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+    // random numbers used by camera rays, materials and light sampling
+    #[arg(long, default_value_t = sampler::Sampler::default())]
+    #[serde(serialize_with = "serialize_display")]
+    pub sampler: sampler::Sampler,
+    // frame index of an animation, folded into the seed so each frame has independent noise
+    #[arg(long, default_value_t = 0)]
+    pub frame: u64,
+    // use the same seed for every frame (for debugging)
+    #[arg(long, default_value_t = false)]
+    pub lock_seed: bool,
+    // shoot camera rays through pixel centres, aliased but deterministic for pixel
+    // exact comparisons (for debugging)
+    #[arg(long, default_value_t = false)]
+    pub no_jitter: bool,
+    // also save the even & odd samples as separate images along with their
+    // difference as a noise estimate (requires an .exr output)
+    #[arg(long, default_value_t = false)]
+    pub half_buffers: bool,
+    // also save an edge aware (à-trous) filtered image guided by first hit albedo and
+    // normals (requires an .exr output)
+    #[arg(long, default_value_t = false)]
+    pub filter_denoise: bool,
+    // also save first hit albedo, normals & depth (see denoise::Guides) as "albedo",
+    // "normal" & "depth" layers of the render for external denoisers & compositing
+    // (requires an .exr output)
+    #[arg(long, default_value_t = false)]
+    pub aovs: bool,
+    // number of filter passes, each doubling the filter's footprint
+    #[arg(long, default_value_t = 5)]
+    pub filter_iterations: u32,
+    // also save {stem}_denoised.exr denoised by OpenImageDenoise, guided by the --aovs
+    // albedo & normals when saved (requires an .exr output)
+    #[cfg(feature = "oidn")]
+    #[arg(long, default_value_t = false)]
+    pub oidn: bool,
+    // EXR image, "sky" for a procedural sky (see Sky) or a solid colour or vertical
+    // gradient (see EnvMap::procedural)
     #[arg(short, long)]
     pub environment_map: Option<String>,
+    // degrees the environment image is spun anticlockwise (looking down) about Z
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub env_rotation: f32,
+    // degrees above the horizon
+    #[arg(long, default_value_t = 45.0)]
+    pub sun_elevation: f32,
+    // degrees anticlockwise from +X (looking down on the scene)
+    #[arg(long, default_value_t = 0.0)]
+    pub sun_azimuth: f32,
+    // haziness of the sky, 2 is very clear & 10 is hazy
+    #[arg(long, default_value_t = 3.0)]
+    pub turbidity: f32,
+    // irradiance (at normal incidence) of a directional light placed at --sun-elevation &
+    // --sun-azimuth (see DirectionalLight), separate from the sky's own sun
+    #[arg(long, value_delimiter = ',', num_args = 3)]
+    pub sun_light: Option<Vec<f32>>,
+    // angular radius in degrees of the directional light
+    #[arg(long, default_value_t = 0.27)]
+    pub sun_radius: f32,
+    // EXR image shown behind the scene to camera rays on a rectangle filling the initial
+    // view at --backplate-distance (so it has parallax unlike the environment map)
+    #[arg(long)]
+    pub backplate: Option<String>,
+    #[arg(long, default_value_t = 10.0)]
+    pub backplate_distance: f32,
+    // EXR (with .samples sidecar) from a previous render to add new samples to
+    #[arg(long)]
+    pub accumulate_from: Option<String>,
+    // render state of an interrupted render to finish (the .exr saved with
+    // --checkpoint-interval), unlike --accumulate-from the samples already in it count
+    // towards --samples
+    #[arg(long)]
+    pub resume: Option<String>,
+    // seconds between saving the render state to {stem}.checkpoint.exr for --resume
+    // (0 disables, headless only)
+    #[arg(long, default_value_t = 0.0)]
+    pub checkpoint_interval: f32,
+    // seconds between saving the render so far as {stem}_{samples}spp next to the output
+    // (0 disables, headless only)
+    #[arg(long, default_value_t = 0.0)]
+    pub save_interval: f32,
+    // render only tile i of a frame split into n tiles (i/n), the output gets a .tile
+    // sidecar with the tile's position so the tiles can be stitched together
+    #[arg(long)]
+    pub tile: Option<String>,
+    // only render the pixels x0..x1, y0..y1 (x0,y0,x1,y1), the rest of the image is black
+    // (or in the GUI keeps what was there before)
+    #[arg(long, value_delimiter = ',', num_args = 4)]
+    pub region: Option<Vec<u32>>,
     #[arg(long, default_value_t = 0.0)]
     pub u_low: f32,
     #[arg(long, default_value_t = 1.0)]
@@ -277,7 +823,116 @@ pub struct RenderSettings {
     pub v_high: f32,
     #[arg(long)]
     pub num_threads: Option<std::num::NonZeroUsize>,
-    #[cfg(feature = "gui")]
+    // samples per unit of work handed to compute threads (default scales with resolution)
+    #[arg(long)]
+    pub workgroup_size: Option<std::num::NonZeroU64>,
+    // hand compute threads square tiles of this size (one sample each) instead of runs
+    // of pixels, keeping neighbouring rays together for more coherent traversal
+    #[arg(long)]
+    pub work_tile_size: Option<std::num::NonZeroU32>,
+    // only render the contribution of the glTF mesh with this name, saved to its own file
+    // (render.exr -> render_name.exr)
+    #[arg(long)]
+    pub isolate: Option<String>,
+    // material names turned into holdouts (see Mat::Holdout)
+    #[arg(long)]
+    pub holdout: Vec<String>,
+    // texture=source replacing a texture (by name) with an image file, base64 data URI or
+    // procedural texture (checker:r,g,b:r,g,b[:scale] or noise[:scale[:octaves]])
+    #[arg(long)]
+    pub tex_override: Vec<String>,
+    // mesh=rotation rotating a glTF mesh (by name) about its node's origin, the rotation
+    // is x,y,z (degrees, blender XYZ euler) or w,x,y,z
+    #[arg(long)]
+    pub mesh_rotation: Vec<String>,
+    // scale of loaded glTF meshes, s or x,y,z about the origin of the glTF scene
+    #[arg(long)]
+    pub mesh_scale: Option<String>,
+    // extra spheres added to the scene of the form x,y,z,radius,material
+    #[arg(long)]
+    pub sphere: Vec<String>,
+    // object=light,... (material names) restricting the lights that directly light an object
+    #[arg(long)]
+    pub light_include: Vec<String>,
+    // object=light,... (material names) of lights that don't directly light an object
+    #[arg(long)]
+    pub light_exclude: Vec<String>,
+    // projection used to generate UVs for meshes without them
+    #[arg(long, default_value_t = loader::UvProjection::default())]
+    #[serde(serialize_with = "serialize_display")]
+    pub uv_fallback: loader::UvProjection,
+    // per material projection of the form material=projection
+    #[arg(long)]
+    pub uv_fallback_for: Vec<String>,
+    // per material factor overrides of the form material:key=value (see loader::MatOverride)
+    #[arg(long)]
+    pub mat_override: Vec<String>,
+    // also save {stem}_clipped.png marking the pixels clamped in 8 bit output
+    #[arg(long)]
+    pub clip_mask: bool,
+    // colour space of scene colours and of saved images (EXRs record theirs)
+    #[arg(long, default_value_t = Primaries::default())]
+    #[serde(serialize_with = "serialize_display")]
+    pub working_primaries: Primaries,
+    // trace each path at three wavelengths (hero wavelength sampling) with scene colours
+    // upsampled to smooth spectra (see spectral::Spectral), converted back to RGB when
+    // splatted
+    #[arg(long, default_value_t = false)]
+    pub spectral: bool,
+    #[arg(long, default_value_t = Primaries::default())]
+    #[serde(serialize_with = "serialize_display")]
+    pub output_primaries: Primaries,
+    // operator mapping HDR radiance into the GUI preview and 8 bit PNGs (EXRs stay linear)
+    #[arg(long, default_value_t = tonemap::Tonemap::default())]
+    #[serde(serialize_with = "serialize_display")]
+    pub tonemap: tonemap::Tonemap,
+    // in stops, applied before the tonemap operator
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub exposure: f32,
+    #[arg(long, default_value_t = 0.0)]
+    pub roughness_min: f32,
+    #[arg(long, default_value_t = 1.0)]
+    pub roughness_max: f32,
+    // compensate for the energy glossy materials lose to multiple scattering between
+    // microfacets which otherwise darkens rough metals
+    #[arg(long)]
+    pub ggx_multiscatter: bool,
+    // import glTF materials as the principled material (a blend of metal, specular and
+    // diffuse lobes) rather than picking either a matte or a glossy material
+    #[arg(long)]
+    pub principled: bool,
+    // ignore the normal maps of imported materials
+    #[arg(long)]
+    pub disable_normal_maps: bool,
+    // copy the triangles of meshes referenced by several glTF nodes instead of
+    // instancing them (see Instance)
+    #[arg(long)]
+    pub no_instancing: bool,
+    // density grid for a heterogeneous medium (rendered with the volumetric integrator)
+    #[cfg(feature = "volumes")]
+    #[arg(long)]
+    pub volume: Option<String>,
+    #[cfg(feature = "volumes")]
+    #[arg(long, default_value_t = 1.0)]
+    pub volume_density: f32,
+    #[cfg(feature = "volumes")]
+    #[arg(long, default_value_t = 1.0)]
+    pub volume_albedo: f32,
+    // scattering,absorption coefficients of a homogeneous fog filling the scene
+    #[arg(long, value_delimiter = ',', num_args = 2)]
+    pub fog_sigma: Option<Vec<f32>>,
+    // Henyey-Greenstein asymmetry of the fog's phase function in (-1, 1)
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub fog_g: f32,
+    // fog inside boxes of the form min_x,min_y,min_z,max_x,max_y,max_z:key=value
+    // (see fog::parse_fog)
+    #[arg(long, allow_negative_numbers = true)]
+    pub fog_override: Vec<String>,
+    // render every camera in the scene in turn to {stem}_{index}.{ext} (headless only)
+    #[arg(long)]
+    pub all_cameras: bool,
+    // builds without the gui feature are always headless (the flag is still accepted
+    // so the same command lines work with either build)
     #[arg(long)]
     pub headless: bool,
 }
@@ -286,26 +941,141 @@ impl Default for RenderSettings {
     fn default() -> Self {
         Self {
             help: None,
+            print_config: false,
+            print_hash: false,
             bvh_heatmap: false,
+            heatmap_scale: HeatmapScale::default(),
+            spp_heatmap: false,
             width: crate::WIDTH,
             height: crate::HEIGHT,
+            pixel_aspect: 1.0,
             samples: crate::SAMPLES,
+            noise_threshold: None,
             filename: String::new(),
             integrator: IntegratorType::default(),
             scene: Scene::default(),
+            cam_origin: None,
+            cam_rotation: None,
+            cam_radians: false,
+            cam_euler_order: EulerOrder::default(),
+            cam_fov: None,
+            cam_roll: 0.0,
+            cam_aperture: None,
+            cam_focus_dist: None,
+            cam_projection: None,
+            cam_ortho_width: None,
+            near: 0.0,
+            far: None,
+            ao_radius: None,
+            clip: false,
             pssmlt: false,
+            pssmlt_bootstrap: 100_000,
+            cache_primary: false,
+            nee_max_radiance: None,
+            mis_per_channel: false,
+            clamp: None,
+            max_depth: integrator::MAX_DEPTH,
+            rr_depth: integrator::RUSSIAN_ROULETTE_THRESHOLD,
+            on_nan: NanPolicy::default(),
+            seed: 0,
+            sampler: sampler::Sampler::default(),
+            frame: 0,
+            lock_seed: false,
+            no_jitter: false,
+            half_buffers: false,
+            filter_denoise: false,
+            aovs: false,
+            filter_iterations: 5,
+            #[cfg(feature = "oidn")]
+            oidn: false,
             environment_map: None,
+            env_rotation: 0.0,
+            sun_elevation: 45.0,
+            sun_azimuth: 0.0,
+            turbidity: 3.0,
+            sun_light: None,
+            sun_radius: 0.27,
+            backplate: None,
+            backplate_distance: 10.0,
+            accumulate_from: None,
+            resume: None,
+            checkpoint_interval: 0.0,
+            save_interval: 0.0,
+            tile: None,
+            region: None,
             u_low: 0.0,
             u_high: 1.0,
             v_low: 0.0,
             v_high: 1.0,
             num_threads: None,
-            #[cfg(feature = "gui")]
+            workgroup_size: None,
+            work_tile_size: None,
+            isolate: None,
+            holdout: vec![],
+            sphere: vec![],
+            mesh_rotation: Vec::new(),
+            mesh_scale: None,
+            tex_override: vec![],
+            light_include: vec![],
+            light_exclude: vec![],
+            uv_fallback: loader::UvProjection::default(),
+            uv_fallback_for: vec![],
+            mat_override: vec![],
+            clip_mask: false,
+            all_cameras: false,
+            working_primaries: Primaries::default(),
+            spectral: false,
+            output_primaries: Primaries::default(),
+            tonemap: tonemap::Tonemap::default(),
+            exposure: 0.0,
+            roughness_min: 0.0,
+            roughness_max: 1.0,
+            ggx_multiscatter: false,
+            principled: false,
+            disable_normal_maps: false,
+            no_instancing: false,
+            #[cfg(feature = "volumes")]
+            volume: None,
+            #[cfg(feature = "volumes")]
+            volume_density: 1.0,
+            #[cfg(feature = "volumes")]
+            volume_albedo: 1.0,
+            fog_sigma: None,
+            fog_g: 0.0,
+            fog_override: vec![],
             headless: false,
         }
     }
 }
 
+impl RenderSettings {
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+    #[must_use]
+    pub fn is_headless(&self) -> bool {
+        self.headless || !cfg!(feature = "gui")
+    }
+    // towards the sun of the sky and the directional light
+    #[must_use]
+    pub fn sun_dir(&self) -> Vec3 {
+        let (elevation, azimuth) = (
+            self.sun_elevation.to_radians(),
+            self.sun_azimuth.to_radians(),
+        );
+        Vec3::new(
+            elevation.cos() * azimuth.cos(),
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+        )
+    }
+    // aspect ratio of the image as displayed
+    pub fn aspect_ratio(&self) -> f32 {
+        self.pixel_aspect * u32::from(self.width) as f32 / u32::from(self.height) as f32
+    }
+}
+
 pub struct App {
     pub render_settings: RenderSettings,
     // egui state
@@ -314,19 +1084,41 @@ pub struct App {
     // communication
     pub update_recv: std::sync::mpsc::Receiver<Update>,
     pub work_req: std::sync::mpsc::Sender<ComputeChange>,
+    pub splat_pool: SplatPool,
     // state
     pub canvas: Vec<Vec3>,
+    pub half_buffers: Option<output::HalfBuffers>,
+    // per pixel sample counts & variance with --noise-threshold
+    pub adaptive: Option<adaptive::Adaptive>,
+    // splats of each canvas pixel with --spp-heatmap (not those of --accumulate-from)
+    pub spp: Option<Vec<u32>>,
+    // part of the frame being rendered, the canvas only covers the tile
+    pub tile: Option<Tile>,
+    // only these pixels of the frame are being rendered
+    pub region: Option<Tile>,
+    // normalised image shown outside of the region (empty is black)
+    pub backdrop: Vec<Vec3>,
+    // index into CAMERAS being rendered with --all-cameras
+    pub camera: Option<usize>,
     pub splats_done: u64,
+    // splats loaded from --accumulate-from (included in splats_done)
+    pub accumulated_splats: u64,
     pub work_rays: u64,
     // work statistics
     pub work_duration: std::time::Duration,
     pub work_start: std::time::Instant,
     pub last_update: std::time::Instant,
+    pub last_checkpoint: std::time::Instant,
+    pub last_save: std::time::Instant,
     pub updated: bool,
     pub workload_id: u8,
     // gui state
     #[cfg(feature = "gui")]
     pub display_settings: bool,
+    #[cfg(feature = "gui")]
+    pub display_materials: bool,
+    #[cfg(feature = "gui")]
+    pub material_swatches: Vec<(String, egui::TextureHandle)>,
 }
 
 impl App {
@@ -334,7 +1126,7 @@ impl App {
         #[cfg(feature = "gui")] egui_state: Option<(egui::Context, egui::TextureHandle)>,
         render_settings: RenderSettings,
     ) -> Self {
-        let (update_recv, work_req) =
+        let (update_recv, work_req, splat_pool) =
             work_handler::create_work_handler(render_settings.num_threads);
         let mut a = Self {
             #[cfg(feature = "gui")]
@@ -342,39 +1134,142 @@ impl App {
             render_settings,
             update_recv,
             work_req,
+            splat_pool,
             canvas: Vec::new(),
+            half_buffers: None,
+            adaptive: None,
+            spp: None,
+            tile: None,
+            region: None,
+            backdrop: Vec::new(),
+            camera: None,
             splats_done: 0,
+            accumulated_splats: 0,
             work_duration: std::time::Duration::ZERO,
             work_start: std::time::Instant::now(),
             last_update: std::time::Instant::now(),
+            last_checkpoint: std::time::Instant::now(),
+            last_save: std::time::Instant::now(),
             workload_id: 0,
             work_rays: 0,
             updated: false,
             #[cfg(feature = "gui")]
             display_settings: false,
+            #[cfg(feature = "gui")]
+            display_materials: false,
+            #[cfg(feature = "gui")]
+            material_swatches: Vec::new(),
         };
         a.init();
-        if a.render_settings.samples != 0 {
-            a.work_req
-                .send(ComputeChange::WorkSamples(
-                    a.render_settings.samples,
-                    a.workload_id,
-                ))
-                .unwrap();
+        if a.render_settings.samples != 0 && !a.render_settings.bvh_heatmap {
+            a.start_work();
             a.work_start = std::time::Instant::now();
         }
         a
     }
+    // requests the samples of the current workload (the first pass of adaptive sampling)
+    fn start_work(&mut self) {
+        let pixels = self.pixels();
+        let rs = &self.render_settings;
+        let samples = match &mut self.adaptive {
+            Some(adaptive) => adaptive.first_pass(pixels, rs.samples),
+            None => rs.samples,
+        };
+        self.work_req
+            .send(ComputeChange::work(rs.pssmlt, samples, self.workload_id))
+            .unwrap();
+    }
     fn init(&mut self) {
         let rs = &mut self.render_settings;
         assert!(rs.u_low >= 0.0);
         assert!(rs.u_high >= rs.u_low && rs.u_low <= 1.0);
         assert!(rs.v_low >= 0.0);
         assert!(rs.v_high >= rs.v_low && rs.v_low <= 1.0);
+        if rs.pixel_aspect.is_nan() || rs.pixel_aspect <= 0.0 {
+            log::error!("Invalid --pixel-aspect {}, expected > 0", rs.pixel_aspect);
+            std::process::exit(1);
+        }
+        assert!(!rs.pssmlt || rs.pssmlt_bootstrap > 0);
+        assert!(rs.nee_max_radiance.is_none_or(|v| v > 0.0));
+        assert!(rs.clamp.is_none_or(|v| v > 0.0));
+        assert!(rs.max_depth > 0);
+        assert!(rs.roughness_min >= 0.0 && rs.roughness_max >= rs.roughness_min);
+        if !(rs.near >= 0.0 && rs.far.is_none_or(|far| far > rs.near)) {
+            log::error!(
+                "Invalid --near {} / --far {:?}, expected 0 <= near < far",
+                rs.near,
+                rs.far
+            );
+            std::process::exit(1);
+        }
+        assert!(rs.ao_radius.is_none_or(|v| v > 0.0));
+        assert!(rs.cam_aperture.is_none_or(|v| v >= 0.0));
+        assert!(rs.cam_focus_dist.is_none_or(|v| v > 0.0));
+        assert!(rs.cam_ortho_width.is_none_or(|v| v > 0.0));
+        assert!(
+            rs.cam_projection != Some(Projection::Equirectangular)
+                || (rs.u_low, rs.u_high, rs.v_low, rs.v_high) == (0.0, 1.0, 0.0, 1.0),
+            "Equirectangular cameras don't support rendering part of the frame"
+        );
+
+        // resuming is accumulating with the samples already done counted (see below)
+        if let Some(ref path) = rs.resume {
+            if rs.accumulate_from.is_some() {
+                log::error!("--resume can't be used with --accumulate-from!");
+                std::process::exit(1);
+            }
+            rs.accumulate_from = Some(path.clone());
+        }
+        if rs.spectral
+            && matches!(
+                rs.integrator,
+                IntegratorType::Depth | IntegratorType::Albedo | IntegratorType::AO
+            )
+        {
+            log::warn!("--spectral isn't used by the {} integrator", rs.integrator);
+        }
+        if rs.work_tile_size.is_some() && rs.pssmlt {
+            log::warn!("--work-tile-size isn't used by PSSMLT");
+        }
+        if rs.save_interval > 0.0 && !rs.is_headless() {
+            log::warn!("--save-interval is only used by headless renders");
+        }
+        if rs.checkpoint_interval > 0.0 {
+            if !rs.is_headless() {
+                log::warn!("--checkpoint-interval is only used by headless renders");
+            }
+            if rs.all_cameras || rs.region.is_some() || rs.half_buffers {
+                log::error!(
+                    "--checkpoint-interval can't be used with --all-cameras, --region or --half-buffers!"
+                );
+                std::process::exit(1);
+            }
+        }
+
+        if let Some(threshold) = rs.noise_threshold {
+            assert!(threshold > 0.0);
+            if !rs.is_headless() {
+                log::warn!("--noise-threshold is only used by headless renders");
+            }
+            if rs.pssmlt
+                || rs.region.is_some()
+                || rs.half_buffers
+                || rs.accumulate_from.is_some()
+                || rs.checkpoint_interval > 0.0
+            {
+                log::error!(
+                    "--noise-threshold can't be used with --pssmlt, --region, --half-buffers, --accumulate-from, --resume or --checkpoint-interval!"
+                );
+                std::process::exit(1);
+            }
+            if rs.work_tile_size.is_some() {
+                log::warn!("--work-tile-size isn't used by adaptive passes after the first");
+            }
+        }
 
         self.canvas =
             vec![Vec3::ZERO; u32::from(rs.width) as usize * u32::from(rs.height) as usize];
-        let (cam, bvh, tris, mats, samplables, envmap) = unsafe {
+        let (cam, bvh, tris, mats, samplables, envmap, env_prob) = unsafe {
             (
                 CAM.get().as_mut_unchecked(),
                 BVH.get().as_mut_unchecked(),
@@ -382,53 +1277,464 @@ impl App {
                 MATERIALS.get().as_mut_unchecked(),
                 SAMPLABLE.get().as_mut_unchecked(),
                 ENVMAP.get().as_mut_unchecked(),
+                ENV_SAMPLING_PROB.get().as_mut_unchecked(),
             )
         };
 
-        if let Some(ref path) = rs.environment_map {
+        if rs.environment_map.as_deref() == Some("sky") {
+            if !(0.0..=90.0).contains(&rs.sun_elevation) {
+                log::error!(
+                    "Invalid --sun-elevation {}, expected between 0 and 90 degrees",
+                    rs.sun_elevation
+                );
+                std::process::exit(1);
+            }
+            if !(1.7..=10.0).contains(&rs.turbidity) {
+                log::error!(
+                    "Invalid --turbidity {}, expected between 1.7 and 10",
+                    rs.turbidity
+                );
+                std::process::exit(1);
+            }
+            *envmap = EnvMap::Sky(sky::Sky::new(
+                rs.sun_dir(),
+                rs.turbidity,
+                rs.working_primaries,
+            ));
+            log::info!("Using procedural sky");
+        } else if let Some(procedural) = rs.environment_map.as_deref().and_then(EnvMap::procedural)
+        {
+            match procedural {
+                Ok(v) => *envmap = v,
+                Err(e) => {
+                    log::error!("{e}");
+                    std::process::exit(1);
+                }
+            }
+        } else if let Some(ref path) = rs.environment_map {
             if let Ok(image) = TextureData::from_path(path) {
-                *envmap = EnvMap::Image(image);
+                *envmap = EnvMap::image(image).with_rotation(rs.env_rotation);
                 log::info!("Loaded envmap");
             } else {
                 log::warn!("Could not import envmap {path}.");
             }
         }
+        if rs.env_rotation != 0.0 && !matches!(envmap, EnvMap::Image(..)) {
+            log::warn!("--env-rotation is only applied to environment images");
+        }
+
+        if let Some(ref irradiance) = rs.sun_light {
+            let irradiance = Vec3::new(irradiance[0], irradiance[1], irradiance[2]);
+            if irradiance.component_min() < 0.0 || !(rs.sun_radius > 0.0 && rs.sun_radius < 90.0) {
+                log::error!(
+                    "--sun-light needs a non negative irradiance & a --sun-radius in (0, 90)"
+                );
+                std::process::exit(1);
+            }
+            *unsafe { SUN.get().as_mut_unchecked() } = Some(DirectionalLight::from_irradiance(
+                rs.sun_dir(),
+                irradiance,
+                rs.sun_radius.to_radians(),
+            ));
+            log::info!("Using directional light");
+        }
 
-        *cam = unsafe { crate::scene::setup_scene(&rs) };
+        #[cfg(feature = "volumes")]
+        if let Some(ref path) = rs.volume {
+            assert!(rs.volume_density >= 0.0);
+            assert!((0.0..=1.0).contains(&rs.volume_albedo));
+            let volume = unsafe { VOLUME.get().as_mut_unchecked() };
+            match volume::Volume::from_path(path, rs.volume_density, Vec3::splat(rs.volume_albedo))
+            {
+                Ok(v) => {
+                    *volume = Some(v);
+                    log::info!("Loaded volume");
+                }
+                Err(e) => log::warn!("Could not import volume {path}.\n{e}"),
+            }
+        }
+
+        let fog = match fog::parse_fog(rs) {
+            Ok(fog) => fog,
+            Err(e) => {
+                log::error!("{e}");
+                std::process::exit(1);
+            }
+        };
+        if fog.is_some() {
+            log::info!("Using fog");
+            if !matches!(rs.integrator, IntegratorType::Naive | IntegratorType::NEE) {
+                log::warn!("Fog isn't used by the {} integrator", rs.integrator);
+            }
+        }
+        *unsafe { FOG.get().as_mut_unchecked() } = fog;
+
+        *cam = unsafe { crate::scene::setup_scene(rs) }.with_overrides(rs);
+
+        if rs.all_cameras {
+            if !rs.is_headless() {
+                log::error!("--all-cameras can only be used with --headless!");
+                std::process::exit(1);
+            }
+            if rs.accumulate_from.is_some() {
+                log::error!("--all-cameras can't be used with --accumulate-from!");
+                std::process::exit(1);
+            }
+            let cameras = unsafe { CAMERAS.get().as_ref_unchecked() };
+            if cameras.is_empty() {
+                log::error!("--all-cameras needs a scene with cameras!");
+                std::process::exit(1);
+            }
+            log::info!("Rendering {} cameras", cameras.len());
+            self.camera = Some(0);
+        }
+
+        if let Some(ref path) = rs.backplate {
+            assert!(rs.backplate_distance > 0.0);
+            match TextureData::from_path(path) {
+                Ok(image) => {
+                    *unsafe { BACKPLATE.get().as_mut_unchecked() } =
+                        Some(Backplate::new(cam, rs.backplate_distance, image));
+                    log::info!("Loaded backplate");
+                }
+                Err(e) => log::warn!("Could not import backplate {path}.\n{e}"),
+            }
+        }
+
+        // the camera covers the whole frame while everything else uses the tile's size
+        if let Some(ref spec) = rs.tile {
+            if !rs.is_headless() {
+                log::error!("--tile can only be used with --headless!");
+                std::process::exit(1);
+            }
+            if rs.bvh_heatmap {
+                log::error!("--tile can't be used with --bvh-heatmap!");
+                std::process::exit(1);
+            }
+            match Tile::parse(spec, rs.width.get(), rs.height.get()) {
+                Ok(tile) => {
+                    log::info!(
+                        "Rendering {}x{} tile at ({}, {})",
+                        tile.width,
+                        tile.height,
+                        tile.x,
+                        tile.y
+                    );
+                    rs.width = std::num::NonZeroU32::new(tile.width).unwrap();
+                    rs.height = std::num::NonZeroU32::new(tile.height).unwrap();
+                    self.canvas = vec![Vec3::ZERO; tile.width as usize * tile.height as usize];
+                    self.tile = Some(tile);
+                }
+                Err(e) => {
+                    log::error!("{e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        if let Some(ref rect) = rs.region {
+            if rs.tile.is_some() || rs.bvh_heatmap {
+                log::error!("--region can't be used with --tile or --bvh-heatmap!");
+                std::process::exit(1);
+            }
+            if rs.accumulate_from.is_some() || rs.half_buffers {
+                log::error!("--region can't be used with --accumulate-from or --half-buffers!");
+                std::process::exit(1);
+            }
+            match Tile::from_rect(
+                [rect[0], rect[1], rect[2], rect[3]],
+                rs.width.get(),
+                rs.height.get(),
+            ) {
+                Ok(region) => self.region = Some(region),
+                Err(e) => {
+                    log::error!("{e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        for spec in &rs.tex_override {
+            match loader::parse_tex_override(spec) {
+                Ok((idx, tex)) => {
+                    let texs = unsafe { TEXTURES.get().as_mut_unchecked() };
+                    texs[idx] = tex;
+                    let name = spec.split('=').next().unwrap_or_default();
+                    log::info!("Overrode texture {name}");
+                }
+                Err(e) => {
+                    log::error!("{e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        for spec in &rs.sphere {
+            match loader::parse_sphere(spec) {
+                Ok(sphere) => tris.push(Primitive::Sphere(sphere)),
+                Err(e) => {
+                    log::error!("{e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        for name in &rs.holdout {
+            let Some(idx) = loader::material_idx(name) else {
+                log::error!("Cannot hold out material {name} as it does not exist!");
+                std::process::exit(1);
+            };
+            mats[idx] = Mat::Holdout;
+            log::info!("Holding out material {name}");
+        }
+        let start = std::time::Instant::now();
         *bvh = Bvh::new(tris);
+        log::info!("Built BVH in {:.3}s", start.elapsed().as_secs_f32());
+        let tlas = unsafe { TLAS.get().as_mut_unchecked() };
+        *tlas = Bvh::new(unsafe { INSTANCES.get().as_mut_unchecked() });
+
+        let num_textures = unsafe { TEXTURES.get().as_ref_unchecked() }.len();
+        for e in missing_textures(mats, num_textures, material_name) {
+            log::error!("{e}");
+        }
+
+        if rs.print_hash {
+            println!("{:016x}", hash::scene_hash(rs));
+            std::process::exit(0);
+        }
 
         // calculate samplable objects after BVH rearranges TRIANGLES
         for (i, tri) in tris.iter().enumerate() {
-            if let Mat::Light(_) = mats[tri.mat] {
+            if let Mat::Light(_) = mats[tri.mat()] {
                 samplables.push(i);
             }
         }
 
-        let state = State::new(
-            u32::from(rs.width) as usize,
-            u32::from(rs.height) as usize,
-            #[cfg(feature = "gui")]
-            self.egui_state.as_ref().map(|v| v.0.clone()),
-            rs.integrator,
-            0,
-        );
+        let mut pick_weights = vec![0.0; tris.len()];
+        if rs.mis_per_channel {
+            let powers: Vec<Vec3> = samplables
+                .iter()
+                .map(|&i| tris[i].channel_power())
+                .collect();
+            for (&i, w) in samplables
+                .iter()
+                .zip(light_sampler::per_channel_power(&powers))
+            {
+                pick_weights[i] = w;
+            }
+        } else {
+            for &i in samplables.iter() {
+                pick_weights[i] = tris[i].power();
+            }
+        }
+        *unsafe { LIGHT_SAMPLER.get().as_mut_unchecked() } =
+            light_sampler::LightSampler::new(samplables, tris.len(), |i| pick_weights[i]);
+
+        // split light sampling between the environment map, the directional light and
+        // triangle lights proportional to their estimated power
+        let light_power: f32 = samplables.iter().map(|&i| tris[i].power()).sum();
+        let bounds = [bvh.bounds(), tlas.bounds()].into_iter().flatten();
+        let scene_radius = bounds
+            .reduce(bvh::aabb::Aabb::merge)
+            .map_or(0.0, |b| 0.5 * b.extent().mag());
+        let env_power = envmap.power(scene_radius);
+        let sun_power = unsafe { SUN.get().as_ref_unchecked() }
+            .as_ref()
+            .map_or(0.0, |sun| sun.power(scene_radius));
+        let total_power = env_power + sun_power + light_power;
+        let share = |power: f32| {
+            if power > 0.0 {
+                power / total_power
+            } else {
+                0.0
+            }
+        };
+        *env_prob = share(env_power);
+        *unsafe { SUN_SAMPLING_PROB.get().as_mut_unchecked() } = share(sun_power);
+
+        if rs.bvh_heatmap {
+            let heatmap = heatmap::bvh_heatmap(rs.width.get(), rs.height.get(), rs.heatmap_scale);
+            let path = output::bvh_heatmap_path(&rs.filename);
+            if !rs.filename.is_empty() && path != rs.filename {
+                log::warn!("The BVH heatmap is saved as a .png to {path}");
+            }
+            heatmap::save_heatmap(&heatmap, rs.width.get(), rs.height.get(), &path);
+
+            // display as if it were a single sample render (undoing display gamma)
+            self.canvas = heatmap.into_iter().map(|rgb| rgb.powf(2.2)).collect();
+            self.splats_done = self.canvas.len() as u64;
+            self.updated = true;
+        }
+
+        if let Some(ref name) = rs.isolate {
+            if loader::mesh_idx(name).is_none() {
+                log::error!("Cannot isolate mesh {name} as it does not exist!");
+                std::process::exit(1);
+            }
+            log::info!("Isolating mesh {name}");
+        }
+
+        match light_link::LightLinks::from_lists(
+            &rs.light_include,
+            &rs.light_exclude,
+            mats.len(),
+            loader::material_idx,
+        ) {
+            Ok(links) => *unsafe { LIGHT_LINKS.get().as_mut_unchecked() } = links,
+            Err(e) => {
+                log::error!("{e}");
+                std::process::exit(1);
+            }
+        }
+
+        if rs.half_buffers {
+            if rs.accumulate_from.is_some() {
+                log::error!("--half-buffers can't be used with --accumulate-from!");
+                std::process::exit(1);
+            }
+            if !rs.filename.ends_with(".exr") {
+                log::warn!("--half-buffers are only saved with an .exr output");
+            }
+            self.half_buffers = Some(output::HalfBuffers::new(self.canvas.len()));
+        }
+
+        if rs.filter_denoise {
+            if !rs.is_headless() {
+                log::warn!("--filter-denoise is only applied to headless renders");
+            }
+            if !rs.filename.ends_with(".exr") {
+                log::warn!("--filter-denoise is only saved with an .exr output");
+            }
+        }
+        #[cfg(feature = "oidn")]
+        if rs.oidn {
+            if rs.filter_denoise {
+                log::error!(
+                    "--oidn can't be used with --filter-denoise (both save {{stem}}_denoised.exr)!"
+                );
+                std::process::exit(1);
+            }
+            if !rs.is_headless() {
+                log::warn!("--oidn is only applied to headless renders");
+            }
+            if !rs.filename.ends_with(".exr") {
+                log::warn!("--oidn is only saved with an .exr output");
+            }
+        }
+
+        // headless renders are saved by extension, anything else would be silently dropped
+        if rs.is_headless()
+            && !rs.filename.is_empty()
+            && ![".png", ".exr"]
+                .iter()
+                .any(|ext| rs.filename.ends_with(ext))
+        {
+            log::warn!(
+                "{} isn't a .png or .exr so the render won't be saved",
+                rs.filename
+            );
+        }
+
+        if rs.clip_mask && !rs.filename.ends_with(".png") {
+            log::warn!("--clip-mask is only saved with a .png output");
+        }
+
+        if let Some(ref path) = rs.accumulate_from {
+            let (width, height) = (rs.width.get() as usize, rs.height.get() as usize);
+            match output::load_accumulation(width, height, path, rs.working_primaries) {
+                Ok((canvas, splats)) => {
+                    self.canvas = canvas;
+                    self.splats_done = splats;
+                    self.accumulated_splats = splats;
+                    self.updated = true;
+                    if rs.resume.is_some() {
+                        let done = splats / self.canvas.len() as u64;
+                        if done >= rs.samples {
+                            log::error!("{path} already has {done} samples!");
+                            std::process::exit(1);
+                        }
+                        rs.samples -= done;
+                        log::info!("Resuming {path} at {done} samples");
+                    } else {
+                        log::info!("Accumulating on top of {path} ({splats} splats)");
+                    }
+                }
+                Err(e) => {
+                    log::error!("Failed to load {path} to accumulate from\n{e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        if rs.is_headless() {
+            self.adaptive = rs
+                .noise_threshold
+                .map(|t| adaptive::Adaptive::new(self.canvas.len(), t));
+        }
+        if rs.spp_heatmap {
+            if !rs.is_headless() {
+                log::warn!("--spp-heatmap is only saved by headless renders");
+            }
+            if rs.accumulate_from.is_some() {
+                log::warn!("--spp-heatmap only counts the samples added to --accumulate-from");
+            }
+            self.spp = Some(vec![0; self.canvas.len()]);
+        }
+
+        let state = self.state();
 
         self.work_req
-            .send(ComputeChange::UpdateState(state))
+            .send(ComputeChange::UpdateState(Box::new(state)))
             .unwrap();
     }
-    // reset canvas and state and prepare for a new workload
-    pub fn next_workload(&mut self) {
-        let state = State::new(
-            u32::from(self.render_settings.width) as usize,
-            u32::from(self.render_settings.height) as usize,
+    // state for the compute threads based on the current render settings
+    fn state(&self) -> State {
+        State::new(
+            &self.render_settings,
             #[cfg(feature = "gui")]
             self.egui_state.as_ref().map(|v| v.0.clone()),
-            self.render_settings.integrator,
-            0,
-        );
+            // offset seeding so accumulated samples don't repeat those already loaded
+            self.accumulated_splats,
+            self.tile,
+        )
+        .with_camera(self.camera)
+        .with_region(self.region)
+    }
+    // number of pixels rendered per sample
+    #[must_use]
+    pub fn pixels(&self) -> u64 {
+        self.region.map_or(self.canvas.len() as u64, |r| r.pixels())
+    }
+    // see output::composite (& Adaptive::normalised)
+    #[must_use]
+    pub fn composited(&self) -> std::borrow::Cow<'_, [Vec3]> {
+        if let Some(adaptive) = &self.adaptive {
+            return std::borrow::Cow::Owned(adaptive.normalised(&self.canvas, self.splats_done));
+        }
+        output::composite(&self.canvas, self.region, &self.backdrop, self.splats_done)
+    }
+    // restricts rendering to region, what is currently shown outside of it is kept
+    // (takes effect with the next workload)
+    pub fn set_region(&mut self, region: Option<Tile>) {
+        let scale = self.canvas.len() as f32 / self.splats_done.max(1) as f32;
+        self.backdrop = match region {
+            Some(_) => self.composited().iter().map(|&v| v * scale).collect(),
+            None => Vec::new(),
+        };
+        self.region = region;
+    }
+    // output filename of the current render
+    #[must_use]
+    pub fn output_filename(&self) -> String {
+        let filename = camera_filename(&self.render_settings.filename, self.camera);
+        isolate_filename(&filename, self.render_settings.isolate.as_deref())
+    }
+    // reset canvas and state and prepare for a new workload
+    pub fn next_workload(&mut self) {
+        self.accumulated_splats = 0;
+        let state = self.state();
         self.work_req
-            .send(ComputeChange::UpdateState(state))
+            .send(ComputeChange::UpdateState(Box::new(state)))
             .unwrap();
         self.workload_id = self.workload_id.wrapping_add(1);
         self.canvas = vec![
@@ -436,6 +1742,16 @@ impl App {
             u32::from(self.render_settings.width) as usize
                 * u32::from(self.render_settings.height) as usize
         ];
+        self.half_buffers = self
+            .half_buffers
+            .as_ref()
+            .map(|_| output::HalfBuffers::new(self.canvas.len()));
+        self.adaptive = self
+            .render_settings
+            .noise_threshold
+            .filter(|_| self.adaptive.is_some())
+            .map(|t| adaptive::Adaptive::new(self.canvas.len(), t));
+        self.spp = self.spp.as_ref().map(|_| vec![0; self.canvas.len()]);
         self.work_rays = 0;
         self.splats_done = 0;
         self.updated = true;
@@ -444,6 +1760,92 @@ impl App {
     }
 }
 
+// with a camera index the index is appended to the stem (render.exr -> render_1.exr)
+#[must_use]
+pub fn camera_filename(filename: &str, camera: Option<usize>) -> String {
+    let Some(i) = camera else {
+        return filename.to_owned();
+    };
+    with_suffix(filename, &format!("_{i}"))
+}
+
+// renders isolating a mesh don't replace the full render (render.exr -> render_chair.exr)
+#[must_use]
+pub fn isolate_filename(filename: &str, mesh: Option<&str>) -> String {
+    let Some(mesh) = mesh else {
+        return filename.to_owned();
+    };
+    with_suffix(filename, &format!("_{mesh}"))
+}
+
+// intermediate render saved by --save-interval (render.exr -> render_64spp.exr)
+#[must_use]
+pub fn snapshot_filename(filename: &str, samples: u64) -> String {
+    with_suffix(filename, &format!("_{samples}spp"))
+}
+
+// suffix appended to the stem, keeping the extension (render.exr -> render{suffix}.exr)
+#[must_use]
+pub fn with_suffix(filename: &str, suffix: &str) -> String {
+    match filename.rsplit_once('.') {
+        Some((stem, ext)) => format!("{stem}{suffix}.{ext}"),
+        None => format!("{filename}{suffix}"),
+    }
+}
+
+// saves the render so far (normalised by the splats done) without any of the extra
+// outputs of the final render
+fn save_snapshot(app: &App) {
+    let rs = &app.render_settings;
+    let (width, height) = (rs.width.get() as usize, rs.height.get() as usize);
+    let filename = snapshot_filename(&app.output_filename(), app.splats_done / app.pixels());
+    let canvas = app.composited();
+    let transform = ColorTransform::new(rs.working_primaries, rs.output_primaries);
+    let saved = if filename.ends_with(".png") {
+        output::save_png(
+            &canvas,
+            width,
+            height,
+            app.splats_done,
+            &filename,
+            &transform,
+            (rs.tonemap, rs.exposure),
+        )
+    } else if filename.ends_with(".exr") {
+        output::save_exr(
+            &canvas,
+            width,
+            height,
+            rs.pixel_aspect,
+            app.splats_done,
+            &filename,
+            &transform,
+        )
+    } else {
+        return;
+    };
+    match saved {
+        Ok(()) => log::info!("Saved snapshot to {filename}"),
+        Err(e) => log::error!("Failed to save snapshot to {filename}\n{e}"),
+    }
+}
+
+// name of a material for logging (there may be several, any is returned)
+#[must_use]
+pub fn material_name(idx: usize) -> String {
+    let mut lock = MATERIAL_NAMES.lock().unwrap();
+    let mat_names = lock.get_mut_or_init(HashMap::new);
+    mat_names
+        .iter()
+        .find(|(_, &v)| v == idx)
+        .map_or_else(|| format!("#{idx}"), |(k, _)| k.clone())
+}
+
+// REC.2020 relative luminance
+pub fn luminance(rgb: Vec3) -> f32 {
+    0.2627 * rgb.x + 0.6780 * rgb.y + 0.0593 * rgb.z
+}
+
 // REC.2020 -> XYZ.Y (not entirely sure if this is correct)
 fn scalar_contribution(rgb: Vec3) -> f32 {
     (0.144616903586208 * rgb.x + 0.677998071518871 * rgb.y + 0.0280726930490874 * rgb.z).max(0.0001)
@@ -461,6 +1863,24 @@ pub fn heatmap(t: f32) -> Vec3 {
     C0 + (C1 + (C2 + (C3 + (C4 + (C5 + C6 * t) * t) * t) * t) * t) * t
 }
 
+// serialize enums using the same names as the CLI
+fn serialize_display<T: fmt::Display, S: serde::Serializer>(
+    v: &T,
+    s: S,
+) -> Result<S::Ok, S::Error> {
+    s.collect_str(v)
+}
+
+fn serialize_option_display<T: fmt::Display, S: serde::Serializer>(
+    v: &Option<T>,
+    s: S,
+) -> Result<S::Ok, S::Error> {
+    match v {
+        Some(v) => s.collect_str(v),
+        None => s.serialize_none(),
+    }
+}
+
 pub fn create_logger() {
     // ensure default log level when
     // RUST_LOG isn't set is info
@@ -469,3 +1889,60 @@ pub fn create_logger() {
         .parse_default_env()
         .init();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn print_config() {
+        let rs = RenderSettings::parse_from(["yapt", "-i", "naive", "--seed", "7"]);
+        let json: serde_json::Value = serde_json::from_str(&rs.to_json()).unwrap();
+
+        // CLI flags take effect and everything else is the default
+        assert_eq!(json["integrator"], "naive");
+        assert_eq!(json["seed"], 7);
+        assert_eq!(json["samples"], SAMPLES);
+        assert_eq!(json["scene"], Scene::default().to_string());
+        assert!(json.get("help").is_none());
+    }
+
+    #[test]
+    fn camera_filenames() {
+        assert_eq!(camera_filename("render.exr", None), "render.exr");
+        assert_eq!(camera_filename("render.exr", Some(0)), "render_0.exr");
+        assert_eq!(camera_filename("out/a.b.png", Some(1)), "out/a.b_1.png");
+        assert_eq!(camera_filename("render", Some(2)), "render_2");
+        assert_eq!(snapshot_filename("render.exr", 64), "render_64spp.exr");
+        assert_eq!(
+            snapshot_filename(&camera_filename("a.png", Some(1)), 8),
+            "a_1_8spp.png"
+        );
+        assert_eq!(isolate_filename("render.exr", None), "render.exr");
+        assert_eq!(
+            isolate_filename(&camera_filename("a.png", Some(1)), Some("chair")),
+            "a_1_chair.png"
+        );
+        assert_eq!(output::spp_heatmap_path("out/a.b.exr"), "out/a.b_spp.png");
+        assert_eq!(output::spp_heatmap_path("render"), "render_spp.png");
+        assert_eq!(output::bvh_heatmap_path(""), "heatmap.png");
+        assert_eq!(output::bvh_heatmap_path("out/a.b.exr"), "out/a.b.png");
+        assert_eq!(output::bvh_heatmap_path("bvh.png"), "bvh.png");
+    }
+
+    #[test]
+    fn spawn_offsets() {
+        let mut rng = rand::thread_rng();
+        let sect = Intersection::new(1.0, Vec2::ZERO, Vec3::ONE, Vec3::Z, true, 0, 0);
+        assert_eq!(sect.shadow_origin(), Vec3::ONE + SHADOW_EPSILON * Vec3::Z);
+        assert_eq!(sect.bounce_origin(), Vec3::ONE + BOUNCE_EPSILON * Vec3::Z);
+
+        // scattered rays use the bounce offset
+        let mut ray = Ray::new(Vec3::ONE + Vec3::Z, -Vec3::Z);
+        assert!(!Matte::scatter(&mut ray, &sect, &mut rng));
+        assert_eq!(ray.origin, sect.bounce_origin());
+        let mut ray = Ray::new(Vec3::ONE + Vec3::Z, -Vec3::Z);
+        assert!(!Ggx::new(0.5, 0).scatter(&sect, &mut ray, &mut rng));
+        assert_eq!(ray.origin, sect.bounce_origin());
+    }
+}
@@ -0,0 +1,158 @@
+pub use crate::prelude::*;
+
+// perfectly smooth glass, the interior absorbs light following Beer-Lambert's law
+#[derive(Debug)]
+pub struct SmoothDielectric {
+    pub ior: f32,
+    // fraction of light left after travelling a unit distance through the interior,
+    // white is clear glass
+    pub transmittance: Vec3,
+}
+
+impl SmoothDielectric {
+    #[must_use]
+    pub fn new(ior: f32, transmittance: Vec3) -> Self {
+        Self { ior, transmittance }
+    }
+    // reflects or refracts with probability given by the fresnel reflectance so the
+    // throughput is unchanged (see Mat::eval)
+    #[must_use]
+    pub fn scatter(&self, sect: &Intersection, ray: &mut Ray, rng: &mut impl MinRng) -> bool {
+        let wo = -ray.dir;
+        // relative ior of the side behind the surface to the side the ray came from
+        let eta = if sect.out { self.ior } else { 1.0 / self.ior };
+        let f = fresnel_dielectric(wo.dot(sect.nor), eta);
+        *ray = match wo.refract(sect.nor, eta) {
+            Some(wi) if rng.gen() >= f => Ray::new(sect.pos - sect.nor * crate::BOUNCE_EPSILON, wi),
+            _ => Ray::new(sect.bounce_origin(), wo.reflected(sect.nor)),
+        };
+        false
+    }
+    // attenuation along a ray that travelled dist through the interior
+    #[must_use]
+    pub fn absorption(&self, dist: f32, lambda: &Lambda) -> Vec3 {
+        lambda.reflectance(self.transmittance).powf(dist)
+    }
+}
+
+// a sheet of glass with no thickness (soap bubbles, windows), light reflects off or
+// passes straight through without refracting and there is no interior
+#[derive(Debug)]
+pub struct ThinDielectric {
+    pub ior: f32,
+}
+
+impl ThinDielectric {
+    #[must_use]
+    pub fn new(ior: f32) -> Self {
+        Self { ior }
+    }
+    // reflectance of both interfaces including light bouncing between them
+    // (pbrt v4 9.5.2)
+    #[must_use]
+    pub fn reflectance(&self, cos_i: f32) -> f32 {
+        let r = fresnel_dielectric(cos_i, self.ior);
+        if r < 1.0 {
+            r + (1.0 - r).powi(2) * r / (1.0 - r * r)
+        } else {
+            r
+        }
+    }
+    // like SmoothDielectric::scatter but transmitted rays keep their direction, they
+    // continue from the hit itself (T_MIN stops them hitting the sheet again)
+    #[must_use]
+    pub fn scatter(&self, sect: &Intersection, ray: &mut Ray, rng: &mut impl MinRng) -> bool {
+        let wo = -ray.dir;
+        *ray = if rng.gen() < self.reflectance(wo.dot(sect.nor)) {
+            Ray::new(sect.bounce_origin(), wo.reflected(sect.nor))
+        } else {
+            Ray::new(sect.pos, ray.dir)
+        };
+        false
+    }
+}
+
+// rough glass (Walter et al. 2007), microfacet normals are sampled from the visible
+// normals of the GGX distribution and reflected off or refracted through like
+// SmoothDielectric, the ior is the x channel of a texture so it can vary across the
+// surface
+#[derive(Debug, new)]
+pub struct RoughDielectric {
+    // microfacet distribution (its reflectance texture is unused)
+    pub ggx: Ggx,
+    // texture index of the ior (sampled at sect.uv)
+    pub ior: usize,
+}
+
+impl RoughDielectric {
+    #[must_use]
+    pub fn ior(&self, uv: Vec2) -> f32 {
+        texture(self.ior).uv_value(uv).x
+    }
+    // relative ior of the side behind the surface to the side the ray came from
+    #[must_use]
+    fn eta(&self, sect: &Intersection) -> f32 {
+        let ior = self.ior(sect.uv);
+        if sect.out {
+            ior
+        } else {
+            1.0 / ior
+        }
+    }
+    // reflects or refracts through a sampled microfacet with probability given by its
+    // fresnel reflectance (see RoughDielectric::eval)
+    #[must_use]
+    pub fn scatter(&self, sect: &Intersection, ray: &mut Ray, rng: &mut impl MinRng) -> bool {
+        let coord = crate::coord::Coordinate::new_from_z(sect.nor);
+        let wo = coord.global_to_local(-ray.dir);
+        let wm = self.ggx.sample_vndf_local(wo, rng);
+        let eta = self.eta(sect);
+        let f = fresnel_dielectric(wo.dot(wm), eta);
+        *ray = match wo.refract(wm, eta) {
+            Some(wi) if rng.gen() >= f => Ray::new(
+                sect.pos - sect.nor * crate::BOUNCE_EPSILON,
+                coord.local_to_global(wi),
+            ),
+            _ => Ray::new(
+                sect.bounce_origin(),
+                coord.local_to_global(wo.reflected(wm)),
+            ),
+        };
+        false
+    }
+    // throughput of a direction sampled by scatter (local space), the fresnel & the pdf
+    // of picking reflection/refraction cancel out leaving g2 / g1 like Ggx::eval
+    #[must_use]
+    pub fn eval(&self, wo: Vec3, wi: Vec3, sect: &Intersection) -> f32 {
+        let wm = if wi.z > 0.0 {
+            wo + wi
+        } else {
+            wo + wi * self.eta(sect)
+        };
+        if wm.mag_sq() == 0.0 {
+            return 0.0;
+        }
+        // the half vector faces the side the ray came from
+        let wm = if wm.z < 0.0 { -wm } else { wm }.normalised();
+        let g1 = self.ggx.g1_local(wo, wm);
+        if g1 == 0.0 {
+            return 0.0;
+        }
+        self.ggx.g2_local(wo, wi, wm) / g1
+    }
+}
+
+// unpolarised fresnel reflectance for a relative ior eta (behind / in front of the
+// surface) with cos_i the cosine of the incident angle
+#[must_use]
+pub fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (r_parallel.powi(2) + r_perpendicular.powi(2))
+}
@@ -1,10 +1,22 @@
 pub use crate::prelude::*;
+use std::sync::OnceLock;
+
+// resolution of the directional albedo table over cos theta & roughness
+const ALBEDO_RES: usize = 32;
+const ALBEDO_SAMPLES: usize = 1024;
 
 #[derive(Debug)]
 pub struct Ggx {
-    a: f32,
-    a_sq: f32,
+    // roughness along the local x & y (tangent) axes, equal unless anisotropic
+    a_x: f32,
+    a_y: f32,
+    // texture index of the reflectance at normal incidence (sampled at sect.uv so it
+    // can vary spatially and converted by Mat to the path's wavelengths before fresnel
+    // is applied), RoughDielectric has its own ior texture
     pub ior: usize,
+    // add the energy lost to multiple scattering between microfacets back in (see
+    // Ggx::ms_local)
+    pub multiscatter: bool,
 }
 
 impl Ggx {
@@ -15,15 +27,52 @@ impl Ggx {
         // floating point issues such as a = 100
         let a = a.max(0.0001);
         Self {
-            a,
-            a_sq: a.powi(2),
+            a_x: a,
+            a_y: a,
             ior,
+            multiscatter: false,
+        }
+    }
+    #[must_use]
+    pub fn with_multiscatter(self, multiscatter: bool) -> Self {
+        Self {
+            multiscatter,
+            ..self
+        }
+    }
+    // stretches the roughness along the tangent (anisotropy > 0) or bitangent
+    // (anisotropy < 0) keeping a_x * a_y, anisotropy is in [-1, 1] (Burley2012 5.4)
+    #[must_use]
+    pub fn anisotropic(self, anisotropy: f32) -> Self {
+        if anisotropy == 0.0 {
+            return self;
+        }
+        let a = self.roughness();
+        let aspect = (1.0 - 0.9 * anisotropy.clamp(-1.0, 1.0)).sqrt();
+        Self {
+            a_x: (a / aspect).max(0.0001),
+            a_y: (a * aspect).max(0.0001),
+            ..self
         }
     }
+    // clamp roughness into a renderable range, this trades physical accuracy
+    // for less aliasing/noise from near dirac delta lobes
+    #[must_use]
+    pub fn new_clamped(a: f32, ior: usize, min: f32, max: f32) -> Self {
+        Self::new(a.clamp(min, max), ior)
+    }
+    #[must_use]
+    pub fn roughness(&self) -> f32 {
+        (self.a_x * self.a_y).sqrt()
+    }
+    #[must_use]
+    fn is_isotropic(&self) -> bool {
+        self.a_x == self.a_y
+    }
     #[must_use]
     pub fn scatter(&self, sect: &Intersection, ray: &mut Ray, rng: &mut impl MinRng) -> bool {
         // by convention points away from surface hence the -ray.dir (section 2, definition)
-        *ray = Ray::new(sect.pos, self.sample(sect.nor, -ray.dir, rng));
+        *ray = Ray::new(sect.bounce_origin(), self.sample(sect.nor, -ray.dir, rng));
         false
     }
     #[must_use]
@@ -35,35 +84,82 @@ impl Ggx {
         coord.local_to_global(wi).normalised()
     }
     #[must_use]
-    pub fn eval(&self, wo: Vec3, wi: Vec3, uv: Vec2) -> Vec3 {
+    pub fn eval(&self, wo: Vec3, wi: Vec3, f0: Vec3) -> Vec3 {
         let wm = (wo + wi).normalised();
 
         // f * g2 / g1 (Heitz2018GGX 19)
         let g2 = self.g2_local(wo, wi, wm);
-        let f = self.f(wm.dot(wo), uv);
+        let f = schlick(f0, wm.dot(wo));
         let g1 = self.g1_local(wo, wm);
         if g1 == 0.0 {
             return Vec3::ZERO;
         }
-        f * g2 / g1
+        let mut out = f * g2 / g1;
+        // the multiple scattering lobe is sampled along with the single scattering one
+        if self.multiscatter {
+            let pdf = self.pdf(wo, wi);
+            if pdf > 0.0 {
+                out += self.f_ms(f0) * self.ms_local(wo, wi) * wi.z / pdf;
+            }
+        }
+        out
     }
     #[must_use]
-    pub fn bxdf_cos(&self, wo: Vec3, wi: Vec3, uv: Vec2) -> Vec3 {
+    pub fn bxdf_cos(&self, wo: Vec3, wi: Vec3, f0: Vec3) -> Vec3 {
         let wm = (wo + wi).normalised();
-        self.f(wm.dot(wo), uv) * self.ndf_local(wm) * self.g2_local(wo, wi, wm) / (4.0 * wo.z)
+        let mut out =
+            schlick(f0, wm.dot(wo)) * self.ndf_local(wm) * self.g2_local(wo, wi, wm) / (4.0 * wo.z);
+        if self.multiscatter {
+            out += self.f_ms(f0) * self.ms_local(wo, wi) * wi.z.max(0.0);
+        }
+        out
+    }
+    // bxdf_cos with a fresnel of one, integrates to the directional albedo
+    #[must_use]
+    pub fn white_bxdf_cos(&self, wo: Vec3, wi: Vec3) -> f32 {
+        let wm = (wo + wi).normalised();
+        let mut out = self.ndf_local(wm) * self.g2_local(wo, wi, wm) / (4.0 * wo.z);
+        if self.multiscatter {
+            out += self.ms_local(wo, wi) * wi.z.max(0.0);
+        }
+        out
+    }
+    // energy compensation lobe (Kulla & Conty 2017), the energy missing from single
+    // scattering for both directions without fresnel
+    #[must_use]
+    fn ms_local(&self, wo: Vec3, wi: Vec3) -> f32 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let a = self.roughness();
+        let e_avg = average_albedo(a);
+        // smooth enough that no energy is lost
+        if e_avg >= 0.9999 {
+            return 0.0;
+        }
+        (1.0 - directional_albedo(wo.z, a)) * (1.0 - directional_albedo(wi.z, a))
+            / (PI * (1.0 - e_avg))
+    }
+    // fresnel of the multiple scattering lobe from the average of schlick's fresnel
+    // over the hemisphere (f0 + (1 - f0) / 21)
+    #[must_use]
+    fn f_ms(&self, f0: Vec3) -> Vec3 {
+        let f_avg = f0 + (1.0 - f0) / 21.0;
+        let e_avg = average_albedo(self.roughness());
+        f_avg * f_avg * e_avg / (1.0 - f_avg * (1.0 - e_avg))
     }
     // local space (hemisphere on z=0 plane see section 2, definition)
     #[must_use]
     pub fn sample_vndf_local(&self, in_w: Vec3, rng: &mut impl MinRng) -> Vec3 {
         // map episoid to unit hemisphere (section 2, importance sampling 1)
-        let in_w = Vec3::new(self.a * in_w.x, self.a * in_w.y, in_w.z).normalised();
+        let in_w = Vec3::new(self.a_x * in_w.x, self.a_y * in_w.y, in_w.z).normalised();
 
         // intersect unit hemisphere based on new in_w and record point (section 2, important
         // sampling 2)
         let p_hemi = Self::sample_vndf_hemisphere(in_w, rng);
 
         // transform intersection point back (section 2, importance sampling 3)
-        Vec3::new(p_hemi.x * self.a, p_hemi.y * self.a, p_hemi.z).normalised()
+        Vec3::new(p_hemi.x * self.a_x, p_hemi.y * self.a_y, p_hemi.z).normalised()
         // see pbrt v4 9.6.4 for why  * not /
     }
     // (section 3, listing 3)
@@ -103,14 +199,25 @@ impl Ggx {
         if wm.z <= 0.0 {
             return 0.0;
         }
-        let tmp = wm.z.powi(2) * (self.a_sq - 1.0) + 1.0;
-        self.a_sq * FRAC_1_PI / tmp.powi(2)
+        if self.is_isotropic() {
+            let a_sq = self.a_x.powi(2);
+            let tmp = wm.z.powi(2) * (a_sq - 1.0) + 1.0;
+            return a_sq * FRAC_1_PI / tmp.powi(2);
+        }
+        // Heitz2014Microfacet (82)
+        let tmp = (wm.x / self.a_x).powi(2) + (wm.y / self.a_y).powi(2) + wm.z.powi(2);
+        FRAC_1_PI / (self.a_x * self.a_y * tmp.powi(2))
     }
     #[must_use]
     fn lambda(&self, w: Vec3) -> f32 {
         // Heitz2018 (2)
         // fairly certain that w.x^2 + w.y^2 / w.z^2 = tan^2
-        let lambda = self.a_sq * (w.x.powi(2) + w.y.powi(2)) / w.z.powi(2);
+        // with the projected roughness a^2 = (a_x^2 x^2 + a_y^2 y^2) / (x^2 + y^2)
+        let lambda = if self.is_isotropic() {
+            self.a_x.powi(2) * (w.x.powi(2) + w.y.powi(2)) / w.z.powi(2)
+        } else {
+            ((self.a_x * w.x).powi(2) + (self.a_y * w.y).powi(2)) / w.z.powi(2)
+        };
         // approx 1/100 billion change out < 0.0 due to floating point
         let out = 0.5 * ((1.0 + lambda).sqrt() - 1.0).max(0.0);
         out
@@ -125,18 +232,77 @@ impl Ggx {
     }
     // Height correlated G2 (Heitz2014Microfacet 99)
     #[must_use]
-    fn g2_local(&self, wa: Vec3, wb: Vec3, wm: Vec3) -> f32 {
+    pub fn g2_local(&self, wa: Vec3, wb: Vec3, wm: Vec3) -> f32 {
         let mut out = 1.0 / (1.0 + self.lambda(wa) + self.lambda(wb));
         if wa.dot(wm) * wa.z <= 0.0 || wb.dot(wm) * wb.z <= 0.0 {
             out = 0.0;
         }
         out
     }
-    // fresnel
-    #[must_use]
-    fn f(&self, cos_theta: f32, uv: Vec2) -> Vec3 {
-        let texs = unsafe { crate::TEXTURES.get().as_ref_unchecked() };
-        let ior = texs[self.ior].uv_value(uv);
-        ior + (1.0 - ior) * (1.0 - cos_theta).powi(5)
-    }
+}
+
+// schlick's approximation of fresnel from the reflectance at normal incidence
+#[must_use]
+fn schlick(f0: Vec3, cos_theta: f32) -> Vec3 {
+    f0 + (1.0 - f0) * (1.0 - cos_theta).powi(5)
+}
+
+// directional albedo E(cos theta) of a white single scattering lobe tabulated over
+// [roughness][cos theta] with its cosine weighted average over the hemisphere per
+// roughness, estimated with VNDF sampling on first use
+#[allow(clippy::type_complexity)]
+fn albedo_table() -> &'static ([[f32; ALBEDO_RES]; ALBEDO_RES], [f32; ALBEDO_RES]) {
+    static TABLE: OnceLock<([[f32; ALBEDO_RES]; ALBEDO_RES], [f32; ALBEDO_RES])> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut rng = rand_pcg::Pcg64Mcg::new(0);
+        let step = 1.0 / (ALBEDO_RES - 1) as f32;
+        let mut albedo = [[0.0; ALBEDO_RES]; ALBEDO_RES];
+        let mut average = [0.0; ALBEDO_RES];
+        for (i, row) in albedo.iter_mut().enumerate() {
+            let ggx = Ggx::new(i as f32 * step, 0);
+            for (j, e) in row.iter_mut().enumerate() {
+                let mu = (j as f32 * step).max(1e-3);
+                let wo = Vec3::new((1.0 - mu * mu).sqrt(), 0.0, mu);
+                let sum: f32 = (0..ALBEDO_SAMPLES)
+                    .map(|_| {
+                        let wm = ggx.sample_vndf_local(wo, &mut rng);
+                        let wi = wo.reflected(wm);
+                        ggx.g2_local(wo, wi, wm) / ggx.g1_local(wo, wm)
+                    })
+                    .sum();
+                *e = sum / ALBEDO_SAMPLES as f32;
+            }
+            // 2 * int E(mu) mu dmu (trapezoidal rule)
+            average[i] = (1..ALBEDO_RES)
+                .map(|j| {
+                    let (mu0, mu1) = ((j - 1) as f32 * step, j as f32 * step);
+                    (row[j - 1] * mu0 + row[j] * mu1) * step
+                })
+                .sum();
+        }
+        (albedo, average)
+    })
+}
+
+// linear interpolation of values spread evenly over [0, 1]
+#[must_use]
+fn lerp_table(values: &[f32; ALBEDO_RES], x: f32) -> f32 {
+    let x = x.clamp(0.0, 1.0) * (ALBEDO_RES - 1) as f32;
+    let i = (x as usize).min(ALBEDO_RES - 2);
+    let t = x - i as f32;
+    values[i] * (1.0 - t) + values[i + 1] * t
+}
+
+#[must_use]
+fn directional_albedo(cos_theta: f32, a: f32) -> f32 {
+    let (albedo, _) = albedo_table();
+    let x = a.clamp(0.0, 1.0) * (ALBEDO_RES - 1) as f32;
+    let i = (x as usize).min(ALBEDO_RES - 2);
+    let t = x - i as f32;
+    lerp_table(&albedo[i], cos_theta) * (1.0 - t) + lerp_table(&albedo[i + 1], cos_theta) * t
+}
+
+#[must_use]
+fn average_albedo(a: f32) -> f32 {
+    lerp_table(&albedo_table().1, a)
 }
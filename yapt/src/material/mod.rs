@@ -3,45 +3,74 @@ use std::f32::consts::{FRAC_1_PI, TAU};
 use crate::coord::Coordinate;
 use crate::{prelude::*, TEXTURES};
 
+mod dielectric;
 mod ggx;
+mod principled;
 mod testing;
 
+pub use dielectric::{fresnel_dielectric, RoughDielectric, SmoothDielectric, ThinDielectric};
 pub use ggx::Ggx;
+pub use principled::Principled;
 
 #[derive(Debug, new)]
 pub enum Mat {
     Matte(Matte),
     Light(Light),
     Glossy(Ggx),
+    Principled(Principled),
+    Refractive(SmoothDielectric),
+    ThinDielectric(ThinDielectric),
+    RoughDielectric(RoughDielectric),
     Invisible,
+    // occludes & blocks light without being shaded, camera rays see the background
+    // through it (for compositing into a plate)
+    Holdout,
 }
 
 impl Mat {
     #[must_use]
-    pub fn eval(&self, sect: &Intersection, mut wo: Vec3, mut wi: Vec3) -> Vec3 {
-        let texs = unsafe { TEXTURES.get().as_ref_unchecked() };
+    pub fn eval(&self, sect: &Intersection, mut wo: Vec3, mut wi: Vec3, lambda: &Lambda) -> Vec3 {
         wo = -wo;
         if self.requires_local_space() {
             (wo, wi) = Self::to_local_space(sect, wo, wi);
         }
 
+        // colours are converted to the path's wavelengths before fresnel etc is applied
         match self {
             // cos pdf and weakening factor cancel out
-            Self::Matte(m) => texs[m.albedo].uv_value(sect.uv),
+            Self::Matte(m) => reflectance(m.albedo, sect.uv, lambda),
             Self::Light(_) => unreachable!(),
-            Self::Glossy(m) => m.eval(wo, wi, sect.uv),
-            Self::Invisible => Vec3::ONE,
+            Self::Glossy(m) => m.eval(wo, wi, reflectance(m.ior, sect.uv, lambda)),
+            Self::Principled(m) => m.eval(wo, wi, sect.uv, lambda),
+            Self::RoughDielectric(m) => Vec3::splat(m.eval(wo, wi, sect)),
+            // fresnel & the pdf of picking reflection/refraction cancel out, absorption is
+            // applied when the ray leaves the interior (see Mat::interior_absorption)
+            Self::Refractive(_) | Self::ThinDielectric(_) | Self::Invisible => Vec3::ONE,
+            Self::Holdout => Vec3::ZERO,
+        }
+    }
+    // Beer-Lambert attenuation for a ray that hit the back of a surface so travelled
+    // through its interior
+    #[must_use]
+    pub fn interior_absorption(&self, sect: &Intersection, lambda: &Lambda) -> Vec3 {
+        match self {
+            Self::Refractive(m) if !sect.out => m.absorption(sect.t, lambda),
+            _ => Vec3::ONE,
         }
     }
     pub fn scatter(&self, sect: &Intersection, ray: &mut Ray, rng: &mut impl MinRng) -> bool {
         match self {
             Self::Matte(_) => Matte::scatter(ray, sect, rng),
-            Self::Light(_) => true,
+            Self::Light(_) | Self::Holdout => true,
             Self::Invisible => {
                 ray.origin = sect.pos - sect.nor * 0.00001;
                 false
             }
             Self::Glossy(m) => m.scatter(sect, ray, rng),
+            Self::Principled(m) => m.scatter(sect, ray, rng),
+            Self::Refractive(m) => m.scatter(sect, ray, rng),
+            Self::ThinDielectric(m) => m.scatter(sect, ray, rng),
+            Self::RoughDielectric(m) => m.scatter(sect, ray, rng),
         }
     }
     pub fn uv_intersect(&self, uv: Vec2, rng: &mut impl MinRng) -> bool {
@@ -49,21 +78,52 @@ impl Mat {
 
         match self {
             Self::Invisible => false,
-            Self::Glossy(m) => texs[m.ior].does_intersect(uv, rng),
+            // missing textures are opaque
+            Self::Glossy(m) => texs.get(m.ior).is_none_or(|t| t.does_intersect(uv, rng)),
+            Self::Principled(m) => texs.get(m.base).is_none_or(|t| t.does_intersect(uv, rng)),
             _ => true,
         }
     }
     pub fn is_delta(&self, uv: Vec2) -> bool {
+        matches!(
+            self,
+            Self::Invisible | Self::Refractive(_) | Self::ThinDielectric(_)
+        )
+    }
+    // whether light sampling (NEE & the MIS weights) is used at the surface, rough glass
+    // isn't delta but is only sampled by scattering
+    pub fn samples_lights(&self, uv: Vec2) -> bool {
+        !self.is_delta(uv) && !matches!(self, Self::RoughDielectric(_))
+    }
+    // albedo for denoising, the reflectance of the surface independent of lighting
+    #[must_use]
+    pub fn albedo(&self, uv: Vec2) -> Vec3 {
         match self {
-            Self::Invisible => true,
-            _ => false,
+            Self::Matte(m) => texture(m.albedo).uv_value(uv),
+            Self::Light(l) => l.irradiance.saturate(),
+            // reflectance at normal incidence
+            Self::Glossy(m) => texture(m.ior).uv_value(uv),
+            Self::Principled(m) => texture(m.base).uv_value(uv),
+            Self::Refractive(m) => m.transmittance,
+            Self::ThinDielectric(_) | Self::RoughDielectric(_) => Vec3::ONE,
+            Self::Invisible => Vec3::ONE,
+            Self::Holdout => Vec3::ZERO,
         }
     }
+    // emitted radiance in RGB, converted by the integrator (see Lambda::illuminant), front
+    // is whether the front of the surface is seen
     #[must_use]
-    pub fn le(&self, _pos: Vec3, _wo: Vec3) -> Vec3 {
+    pub fn le(&self, _pos: Vec3, _wo: Vec3, uv: Vec2, front: bool) -> Vec3 {
         match self {
-            Self::Matte(_) | Self::Glossy(_) | Self::Invisible => Vec3::ZERO,
-            Self::Light(l) => l.irradiance,
+            Self::Matte(_)
+            | Self::Glossy(_)
+            | Self::Principled(_)
+            | Self::Refractive(_)
+            | Self::ThinDielectric(_)
+            | Self::RoughDielectric(_)
+            | Self::Invisible
+            | Self::Holdout => Vec3::ZERO,
+            Self::Light(l) => l.le(uv, front),
         }
     }
     // scattering pdf
@@ -76,30 +136,67 @@ impl Mat {
         }
         match self {
             Self::Matte(_) => Matte::pdf(wi, sect.nor),
-            Self::Light(_) => 0.0,
+            Self::Light(_) | Self::Holdout => 0.0,
             Self::Glossy(m) => m.pdf(wo, wi),
-            Self::Invisible => unreachable!(),
+            Self::Principled(m) => m.pdf(wo, wi),
+            // invisible surfaces are rejected by uv_intersect so are never shaded
+            // but are delta & transparent if they are
+            Self::Refractive(_)
+            | Self::ThinDielectric(_)
+            | Self::RoughDielectric(_)
+            | Self::Invisible => 0.0,
         }
     }
     #[must_use]
-    pub fn bxdf_cos(&self, sect: &Intersection, mut wo: Vec3, mut wi: Vec3) -> Vec3 {
-        let texs = unsafe { TEXTURES.get().as_ref_unchecked() };
+    pub fn bxdf_cos(
+        &self,
+        sect: &Intersection,
+        mut wo: Vec3,
+        mut wi: Vec3,
+        lambda: &Lambda,
+    ) -> Vec3 {
         wo = -wo;
         if self.requires_local_space() {
             (wo, wi) = Self::to_local_space(sect, wo, wi);
         }
         match self {
             Self::Matte(m) => {
-                texs[m.albedo].uv_value(sect.uv) * wi.dot(sect.nor).max(0.0) * FRAC_1_PI
+                reflectance(m.albedo, sect.uv, lambda) * wi.dot(sect.nor).max(0.0) * FRAC_1_PI
             }
-            Self::Light(_) | Self::Invisible => unreachable!(),
-            Self::Glossy(m) => m.bxdf_cos(wo, wi, sect.uv),
+            Self::Light(_) => unreachable!(),
+            Self::Glossy(m) => m.bxdf_cos(wo, wi, reflectance(m.ior, sect.uv, lambda)),
+            Self::Principled(m) => m.bxdf_cos(wo, wi, sect.uv, lambda),
+            Self::Refractive(_)
+            | Self::ThinDielectric(_)
+            | Self::RoughDielectric(_)
+            | Self::Invisible
+            | Self::Holdout => Vec3::ZERO,
+        }
+    }
+    // indices of the textures this material uses
+    #[must_use]
+    pub fn textures(&self) -> Vec<usize> {
+        match self {
+            Self::Matte(m) => vec![m.albedo],
+            Self::Glossy(m) => vec![m.ior],
+            Self::Principled(m) => vec![m.base],
+            Self::RoughDielectric(m) => vec![m.ior],
+            Self::Light(_)
+            | Self::Refractive(_)
+            | Self::ThinDielectric(_)
+            | Self::Invisible
+            | Self::Holdout => vec![],
         }
     }
     fn requires_local_space(&self) -> bool {
         match self {
-            Self::Matte(_) | Self::Light(_) | Self::Invisible => false,
-            Self::Glossy(_) => true,
+            Self::Matte(_)
+            | Self::Light(_)
+            | Self::Refractive(_)
+            | Self::ThinDielectric(_)
+            | Self::Invisible
+            | Self::Holdout => false,
+            Self::Glossy(_) | Self::Principled(_) | Self::RoughDielectric(_) => true,
         }
     }
     fn to_local_space(sect: &Intersection, wo: Vec3, wi: Vec3) -> (Vec3, Vec3) {
@@ -108,6 +205,12 @@ impl Mat {
     }
 }
 
+// colour of a texture at uv as a reflectance at the path's wavelengths
+#[must_use]
+fn reflectance(tex: usize, uv: Vec2, lambda: &Lambda) -> Vec3 {
+    lambda.reflectance(texture(tex).uv_value(uv))
+}
+
 #[derive(Debug, new)]
 pub struct Matte {
     pub albedo: usize,
@@ -116,7 +219,7 @@ pub struct Matte {
 impl Matte {
     pub fn scatter(ray: &mut Ray, sect: &Intersection, rng: &mut impl MinRng) -> bool {
         let dir = Self::sample(sect.nor, rng);
-        *ray = Ray::new(sect.pos, dir.normalised());
+        *ray = Ray::new(sect.bounce_origin(), dir.normalised());
         false
     }
     #[must_use]
@@ -139,4 +242,41 @@ impl Matte {
 #[derive(Debug, new)]
 pub struct Light {
     irradiance: Vec3,
+    // scales the irradiance across the surface
+    #[new(default)]
+    texture: Option<usize>,
+    // emits from the back of the surface too (and isn't culled from behind)
+    #[new(default)]
+    two_sided: bool,
+}
+
+impl Light {
+    #[must_use]
+    pub fn with_texture(mut self, texture: Option<usize>) -> Self {
+        self.texture = texture;
+        self
+    }
+    #[must_use]
+    pub fn with_two_sided(mut self, two_sided: bool) -> Self {
+        self.two_sided = two_sided;
+        self
+    }
+    #[must_use]
+    pub fn is_textured(&self) -> bool {
+        self.texture.is_some()
+    }
+    #[must_use]
+    pub fn is_two_sided(&self) -> bool {
+        self.two_sided
+    }
+    #[must_use]
+    fn le(&self, uv: Vec2, front: bool) -> Vec3 {
+        if !front && !self.two_sided {
+            return Vec3::ZERO;
+        }
+        match self.texture {
+            Some(tex) => self.irradiance * texture(tex).uv_value(uv),
+            None => self.irradiance,
+        }
+    }
 }
@@ -0,0 +1,207 @@
+use crate::coord::Coordinate;
+pub use crate::prelude::*;
+use std::sync::OnceLock;
+
+// reflectance at normal incidence of the clear coat (ior 1.5)
+const CLEARCOAT_F0: f32 = 0.04;
+// resolution of the sheen albedo table over cos theta & roughness
+const SHEEN_RES: usize = 32;
+// quadrature points per axis when integrating the sheen lobe
+const SHEEN_STEPS: usize = 64;
+
+// glTF's metallic-roughness model, a blend by metallic of a metal (GGX tinted by the base
+// colour) and a dielectric (white GGX specular over a diffuse base colour weighted by
+// the dielectric's fresnel), optionally under a sheen and a clear coat
+#[derive(Debug)]
+pub struct Principled {
+    // texture index of the base colour
+    pub base: usize,
+    pub metallic: f32,
+    // reflectance at normal incidence of the dielectric is 0.08 * specular (so the
+    // default of 0.5 is 4%)
+    pub specular: f32,
+    // shared specular lobe (its ior texture is unused)
+    pub ggx: Ggx,
+    // strength of a white GGX layer over everything else (KHR_materials_clearcoat), the
+    // layer below is darkened by the fresnel of the coat
+    pub clearcoat: f32,
+    pub clearcoat_ggx: Ggx,
+    // colour & roughness of a Charlie sheen lobe (KHR_materials_sheen) for cloth, the
+    // lobes below are scaled by the sheen's albedo to keep energy
+    pub sheen: Vec3,
+    pub sheen_roughness: f32,
+}
+
+impl Principled {
+    #[must_use]
+    pub fn new(base: usize, metallic: f32, specular: f32, ggx: Ggx) -> Self {
+        Self {
+            base,
+            metallic: metallic.clamp(0.0, 1.0),
+            specular,
+            ggx,
+            clearcoat: 0.0,
+            clearcoat_ggx: Ggx::new(0.0, 0),
+            sheen: Vec3::ZERO,
+            sheen_roughness: 0.0,
+        }
+    }
+    #[must_use]
+    pub fn with_clearcoat(mut self, clearcoat: f32, ggx: Ggx) -> Self {
+        self.clearcoat = clearcoat.clamp(0.0, 1.0);
+        self.clearcoat_ggx = ggx;
+        self
+    }
+    #[must_use]
+    pub fn with_sheen(mut self, sheen: Vec3, roughness: f32) -> Self {
+        self.sheen = sheen;
+        self.sheen_roughness = roughness;
+        self
+    }
+    #[must_use]
+    fn dielectric_f0(&self) -> f32 {
+        0.08 * self.specular
+    }
+    // probability of sampling the specular lobe rather than the diffuse one
+    #[must_use]
+    fn specular_prob(&self, wo: Vec3) -> f32 {
+        let fresnel = schlick(self.dielectric_f0(), wo.z.max(0.0));
+        self.metallic + (1.0 - self.metallic) * fresnel
+    }
+    // weight of the clear coat, also the probability of sampling it
+    #[must_use]
+    fn clearcoat_weight(&self, wo: Vec3) -> f32 {
+        self.clearcoat * schlick(CLEARCOAT_F0, wo.z.max(0.0))
+    }
+    #[must_use]
+    pub fn scatter(&self, sect: &Intersection, ray: &mut Ray, rng: &mut impl MinRng) -> bool {
+        let coord = Coordinate::new_from_z(sect.nor);
+        let wo = coord.global_to_local(-ray.dir);
+        let dir = if rng.gen() < self.clearcoat_weight(wo) {
+            self.clearcoat_ggx.sample(sect.nor, -ray.dir, rng)
+        } else if rng.gen() < self.specular_prob(wo) {
+            self.ggx.sample(sect.nor, -ray.dir, rng)
+        } else {
+            // also samples the sheen
+            Matte::sample(sect.nor, rng).normalised()
+        };
+        *ray = Ray::new(sect.bounce_origin(), dir);
+        false
+    }
+    // local space, reflections of sampled microfacets can end up below the surface
+    #[must_use]
+    pub fn pdf(&self, wo: Vec3, wi: Vec3) -> f32 {
+        let p = self.specular_prob(wo);
+        let base = p * self.ggx.pdf(wo, wi) + (1.0 - p) * wi.z.max(0.0) * FRAC_1_PI;
+        let c = self.clearcoat_weight(wo);
+        if c > 0.0 {
+            c * self.clearcoat_ggx.pdf(wo, wi) + (1.0 - c) * base
+        } else {
+            base
+        }
+    }
+    // colours are converted to the path's wavelengths before fresnel is applied
+    #[must_use]
+    pub fn bxdf_cos(&self, wo: Vec3, wi: Vec3, uv: Vec2, lambda: &Lambda) -> Vec3 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Vec3::ZERO;
+        }
+        let base = lambda.reflectance(texture(self.base).uv_value(uv));
+        let cos_theta = (wo + wi).normalised().dot(wo).max(0.0);
+        let specular = self.ggx.white_bxdf_cos(wo, wi);
+
+        let metal = (base + (1.0 - base) * (1.0 - cos_theta).powi(5)) * specular;
+        let fresnel = schlick(self.dielectric_f0(), cos_theta);
+        let dielectric =
+            (1.0 - fresnel) * base * wi.z * FRAC_1_PI + Vec3::splat(fresnel * specular);
+        let mut out = self.metallic * metal + (1.0 - self.metallic) * dielectric;
+
+        // glTF's albedo scaling, the sheen reflects what the lobes below don't receive
+        let sheen_max = self.sheen.component_max();
+        if sheen_max > 0.0 {
+            let albedo = |cos: f32| 1.0 - sheen_max * sheen_albedo(cos, self.sheen_roughness);
+            out = out * albedo(wo.z).min(albedo(wi.z))
+                + lambda.reflectance(self.sheen) * self.sheen_bxdf_cos(wo, wi);
+        }
+        let c = self.clearcoat_weight(wo);
+        if c > 0.0 {
+            out = out * (1.0 - c) + Vec3::splat(c * self.clearcoat_ggx.white_bxdf_cos(wo, wi));
+        }
+        out
+    }
+    #[must_use]
+    pub fn eval(&self, wo: Vec3, wi: Vec3, uv: Vec2, lambda: &Lambda) -> Vec3 {
+        let pdf = self.pdf(wo, wi);
+        if pdf == 0.0 {
+            return Vec3::ZERO;
+        }
+        self.bxdf_cos(wo, wi, uv, lambda) / pdf
+    }
+    // white sheen lobe
+    #[must_use]
+    fn sheen_bxdf_cos(&self, wo: Vec3, wi: Vec3) -> f32 {
+        sheen_lobe(wo, wi, self.sheen_roughness)
+    }
+}
+
+#[must_use]
+fn schlick(f0: f32, cos_theta: f32) -> f32 {
+    f0 + (1.0 - f0) * (1.0 - cos_theta).powi(5)
+}
+
+// Charlie sheen distribution (Estevez & Kulla 2017) with Neubelt & Pettineo's visibility
+// times the cosine, roughness is glTF's perceptual sheen roughness
+#[must_use]
+fn sheen_lobe(wo: Vec3, wi: Vec3, roughness: f32) -> f32 {
+    if wo.z <= 0.0 || wi.z <= 0.0 {
+        return 0.0;
+    }
+    let a = (roughness * roughness).max(1e-3);
+    let cos_h = (wo + wi).normalised().z;
+    let sin2_h = (1.0 - cos_h * cos_h).max(0.0);
+    let d = (2.0 + 1.0 / a) * sin2_h.powf(0.5 / a) / TAU;
+    let v = 1.0 / (4.0 * (wi.z + wo.z - wi.z * wo.z));
+    d * v * wi.z
+}
+
+// directional albedo of a white sheen lobe tabulated over [roughness][cos theta] with
+// the midpoint rule on first use
+fn sheen_table() -> &'static [[f32; SHEEN_RES]; SHEEN_RES] {
+    static TABLE: OnceLock<[[f32; SHEEN_RES]; SHEEN_RES]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let step = 1.0 / (SHEEN_RES - 1) as f32;
+        let mut albedo = [[0.0; SHEEN_RES]; SHEEN_RES];
+        for (i, row) in albedo.iter_mut().enumerate() {
+            for (j, e) in row.iter_mut().enumerate() {
+                let mu = (j as f32 * step).max(1e-3);
+                let wo = Vec3::new((1.0 - mu * mu).sqrt(), 0.0, mu);
+                // integral over the hemisphere in cos theta & phi (dw = dcos dphi)
+                let mut sum = 0.0;
+                for k in 0..SHEEN_STEPS {
+                    let cos = (k as f32 + 0.5) / SHEEN_STEPS as f32;
+                    let sin = (1.0 - cos * cos).sqrt();
+                    for l in 0..SHEEN_STEPS {
+                        let phi = TAU * (l as f32 + 0.5) / SHEEN_STEPS as f32;
+                        let wi = Vec3::new(sin * phi.cos(), sin * phi.sin(), cos);
+                        sum += sheen_lobe(wo, wi, i as f32 * step);
+                    }
+                }
+                *e = sum * TAU / (SHEEN_STEPS * SHEEN_STEPS) as f32;
+            }
+        }
+        albedo
+    })
+}
+
+#[must_use]
+fn sheen_albedo(cos_theta: f32, roughness: f32) -> f32 {
+    let table = sheen_table();
+    let cell = |v: f32| {
+        let v = v.clamp(0.0, 1.0) * (SHEEN_RES - 1) as f32;
+        let i = (v as usize).min(SHEEN_RES - 2);
+        (i, v - i as f32)
+    };
+    let ((i, s), (j, t)) = (cell(roughness), cell(cos_theta));
+    let row = |i: usize| table[i][j] * (1.0 - t) + table[i][j + 1] * t;
+    row(i) * (1.0 - s) + row(i + 1) * s
+}
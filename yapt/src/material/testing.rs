@@ -25,7 +25,7 @@ mod tests {
         let mut rng = thread_rng();
         let wo = -generate_wo(&mut rng, true);
 
-        let mat = Mat::Matte(Matte::new(Vec3::ZERO));
+        let mat = Mat::Matte(Matte::new(0));
 
         test_material("lambertian", mat, wo, &mut rng);
     }
@@ -37,19 +37,288 @@ mod tests {
         let a = rng.gen();
 
         let name = "ggx";
-        let mat = Mat::Glossy(Ggx::new(a, Vec3::ONE));
+        let mat = Mat::Glossy(Ggx::new(a, 0));
 
         log_info("ggx", format!("alpha: {a}"));
 
         test_material(name, mat, wo, &mut rng);
     }
 
+    #[test]
+    pub fn ggx_roughness_clamp() {
+        let mut rng = thread_rng();
+        let wo = -generate_wo(&mut rng, true);
+        let min = 0.05;
+
+        let name = "ggx_roughness_clamp";
+        let clamped = Ggx::new_clamped(1e-6, 0, min, 1.0);
+        let floor = Ggx::new(min, 0);
+
+        // ultra-low roughness should be raised to the floor
+        for _ in 0..100 {
+            let wi = generate_wo(&mut rng, true);
+            assert_eq!(clamped.pdf(-wo, wi), floor.pdf(-wo, wi));
+        }
+
+        log_info(name, format!("min: {min}"));
+
+        test_material(name, Mat::Glossy(clamped), wo, &mut rng);
+    }
+
+    #[test]
+    pub fn principled() {
+        let mut rng = thread_rng();
+        let wo = -generate_wo(&mut rng, true);
+        let (a, metallic) = (rng.gen(), rng.gen());
+
+        let name = "principled";
+        let mat = Mat::Principled(Principled::new(0, metallic, 0.5, Ggx::new(a, 0)));
+
+        log_info(name, format!("alpha: {a} metallic: {metallic}"));
+
+        test_material(name, mat, wo, &mut rng);
+
+        // a fully metallic principled material is the glossy material
+        crate::scene::test_scene();
+        let sect = Intersection::new(1.0, Vec2::ZERO, Vec3::ZERO, Vec3::Z, true, 0, 0);
+        let metal = Mat::Principled(Principled::new(2, 1.0, 0.5, Ggx::new(a, 0)));
+        let glossy = Mat::Glossy(Ggx::new(a, 2));
+        for _ in 0..100 {
+            let wi = generate_wo(&mut rng, true);
+            let (m, g) = (
+                metal.bxdf_cos(&sect, wo, wi, &Lambda::Rgb),
+                glossy.bxdf_cos(&sect, wo, wi, &Lambda::Rgb),
+            );
+            assert!((m - g).abs().component_max() <= 1e-4 * g.component_max().max(1.0));
+        }
+    }
+
+    // the clear coat & sheen are sampled by their pdf and don't create energy
+    #[test]
+    pub fn principled_layers() {
+        let mut rng = thread_rng();
+        let wo = -generate_wo(&mut rng, true);
+        let (a, coat_a, sheen_roughness) = (rng.gen(), rng.gen(), rng.gen());
+
+        let name = "principled_layers";
+        let layered = |metallic: f32| {
+            Principled::new(0, metallic, 0.5, Ggx::new(a, 0).with_multiscatter(true))
+                .with_clearcoat(1.0, Ggx::new(coat_a, 0).with_multiscatter(true))
+                .with_sheen(Vec3::ONE, sheen_roughness)
+        };
+
+        log_info(
+            name,
+            format!("alpha: {a} coat alpha: {coat_a} sheen roughness: {sheen_roughness}"),
+        );
+
+        test_material(name, Mat::Principled(layered(rng.gen())), wo, &mut rng);
+
+        crate::scene::test_scene();
+        for metallic in [0.0, 1.0] {
+            let mat = layered(metallic);
+            let pdf = |wo: Vec3, wi: Vec3| -> f32 {
+                mat.bxdf_cos(wo, wi, Vec2::ZERO, &Lambda::Rgb)
+                    .component_max()
+            };
+            let albedo = integrate_pdf(pdf, -wo, name);
+            log_info(name, format!("metallic: {metallic} albedo: {albedo}"));
+            assert!(albedo < 1.0 + PDF_EPS, "albedo = {albedo}");
+        }
+    }
+
+    // invisible occluders never block rays and contribute no shading
+    #[test]
+    fn invisible() {
+        let mut rng = thread_rng();
+        let mat = Mat::Invisible;
+        let sect = Intersection::new(1.0, Vec2::ZERO, Vec3::ZERO, Vec3::Z, true, 0, 0);
+
+        for _ in 0..100 {
+            let uv = Vec2::new(rng.gen(), rng.gen());
+            assert!(!mat.uv_intersect(uv, &mut rng));
+            assert!(mat.is_delta(uv));
+
+            let wo = -generate_wo(&mut rng, true);
+            let wi = generate_wo(&mut rng, true);
+            assert_eq!(mat.le(sect.pos, wo, sect.uv, sect.out), Vec3::ZERO);
+            assert_eq!(mat.spdf(&sect, wo, wi), 0.0);
+            assert_eq!(mat.bxdf_cos(&sect, wo, wi, &Lambda::Rgb), Vec3::ZERO);
+
+            // passes straight through
+            let mut ray = Ray::new(Vec3::Z, wo);
+            assert!(!mat.scatter(&sect, &mut ray, &mut rng));
+            assert!((ray.dir - wo).mag() < 1e-5);
+            assert_eq!(mat.eval(&sect, wo, ray.dir, &Lambda::Rgb), Vec3::ONE);
+        }
+    }
+
+    #[test]
+    fn smooth_dielectric() {
+        let mut rng = thread_rng();
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-5);
+        // total internal reflection leaving at a grazing angle
+        assert_eq!(fresnel_dielectric(0.1, 1.0 / 1.5), 1.0);
+
+        let glass = Mat::Refractive(SmoothDielectric::new(1.5, Vec3::new(0.5, 1.0, 1.0)));
+        let sect = Intersection::new(1.0, Vec2::ZERO, Vec3::ZERO, Vec3::Z, true, 0, 0);
+        assert!(glass.is_delta(Vec2::ZERO));
+
+        // ~4% reflected at normal incidence, the rest passes straight into the interior
+        let n = 100_000;
+        let mut reflected = 0;
+        for _ in 0..n {
+            let mut ray = Ray::new(Vec3::Z, -Vec3::Z);
+            assert!(!glass.scatter(&sect, &mut ray, &mut rng));
+            assert_eq!(
+                glass.eval(&sect, -Vec3::Z, ray.dir, &Lambda::Rgb),
+                Vec3::ONE
+            );
+            if ray.dir.z > 0.0 {
+                reflected += 1;
+            } else {
+                assert!((ray.dir + Vec3::Z).mag() < 1e-5 && ray.origin.z < 0.0);
+            }
+        }
+        assert!((reflected as f32 / n as f32 - 0.04).abs() < 0.005);
+
+        // absorbed along rays that leave the interior, white is clear glass
+        let inside = Intersection::new(2.0, Vec2::ZERO, Vec3::ZERO, Vec3::Z, false, 0, 0);
+        assert_eq!(glass.interior_absorption(&sect, &Lambda::Rgb), Vec3::ONE);
+        assert_eq!(
+            glass.interior_absorption(&inside, &Lambda::Rgb),
+            Vec3::new(0.25, 1.0, 1.0)
+        );
+        let clear = Mat::Refractive(SmoothDielectric::new(1.5, Vec3::ONE));
+        assert_eq!(clear.interior_absorption(&inside, &Lambda::Rgb), Vec3::ONE);
+    }
+
+    #[test]
+    fn thin_dielectric() {
+        let mut rng = thread_rng();
+        let thin = ThinDielectric::new(1.5);
+        // two interfaces reflect more than one but everything is reflected at grazing angles
+        assert!(thin.reflectance(1.0) > fresnel_dielectric(1.0, 1.5));
+        assert!((thin.reflectance(1.0) - 0.08 / 1.04).abs() < 1e-5);
+        assert!((thin.reflectance(0.0) - 1.0).abs() < 1e-5);
+
+        let mat = Mat::ThinDielectric(thin);
+        let sect = Intersection::new(1.0, Vec2::ZERO, Vec3::ZERO, Vec3::Z, true, 0, 0);
+        let inside = Intersection::new(2.0, Vec2::ZERO, Vec3::ZERO, Vec3::Z, false, 0, 0);
+        assert_eq!(mat.interior_absorption(&inside, &Lambda::Rgb), Vec3::ONE);
+
+        // transmitted rays aren't bent
+        let wo = Vec3::new(1.0, 0.0, -1.0).normalised();
+        for _ in 0..1000 {
+            let mut ray = Ray::new(Vec3::new(-1.0, 0.0, 1.0), wo);
+            assert!(!mat.scatter(&sect, &mut ray, &mut rng));
+            if ray.dir.z < 0.0 {
+                assert!((ray.dir - wo).mag() < 1e-5);
+            } else {
+                assert!((ray.dir - Vec3::new(1.0, 0.0, 1.0).normalised()).mag() < 1e-5);
+            }
+        }
+
+        // treating the floor of the test scene as a sheet, transmitted rays leave from the
+        // hit itself without hitting the sheet again and carry on to the quad below
+        crate::scene::test_scene();
+        let camera_ray = Ray::new(Vec3::new(0.3, 0.2, 0.0), Vec3::new(0.2, 0.1, -1.0));
+        let sect = crate::integrator::get_intersection(&camera_ray, &mut rng);
+        assert!((sect.pos.z + 2.0).abs() < 1e-5);
+        let mut ray = camera_ray.clone();
+        while ray.dir.z > 0.0 || ray.origin == camera_ray.origin {
+            ray = camera_ray.clone();
+            assert!(!mat.scatter(&sect, &mut ray, &mut rng));
+        }
+        assert_eq!(ray.origin, sect.pos);
+        let below = crate::integrator::get_intersection(&ray, &mut rng);
+        assert!((below.pos.z + 5.0).abs() < 1e-4);
+        let straight = camera_ray.origin + 5.0 / camera_ray.dir.z.abs() * camera_ray.dir;
+        assert!((below.pos - straight).mag() < 1e-4);
+    }
+
+    #[test]
+    fn rough_dielectric_ior_texture() {
+        crate::scene::test_scene();
+        let mut rng = thread_rng();
+        let ior = crate::loader::texture_idx("ior_gradient").unwrap();
+        let glass = RoughDielectric::new(Ggx::new(0.0, crate::NO_TEXTURE), ior);
+        let mat = Mat::RoughDielectric(glass);
+        // loses energy so counts as an ordinary bounce, but isn't light sampled
+        assert!(!mat.is_delta(Vec2::ZERO));
+        assert!(!mat.samples_lights(Vec2::ZERO));
+
+        let sin_i = 0.6;
+        let dir = Vec3::new(sin_i, 0.0, -(1.0 - sin_i * sin_i).sqrt());
+        // the ior follows the texture across the surface so does the refraction
+        for (u, ior) in [(0.0, 1.2), (0.5, 1.6), (1.0, 2.0)] {
+            let sect = Intersection::new(1.0, Vec2::new(u, 0.5), Vec3::ZERO, Vec3::Z, true, 0, 0);
+            let mut ray = Ray::new(-dir, dir);
+            while ray.dir.z > 0.0 || ray.dir == dir {
+                ray = Ray::new(-dir, dir);
+                assert!(!mat.scatter(&sect, &mut ray, &mut rng));
+            }
+            // snell's law with the ior at the hit
+            assert!((sin_i - ior * ray.dir.x).abs() < 1e-3, "{u} {}", ray.dir);
+            let weight = mat.eval(&sect, dir, ray.dir, &Lambda::Rgb);
+            assert!(
+                (weight - Vec3::ONE).abs().component_max() < 1e-3,
+                "{weight}"
+            );
+        }
+    }
+
+    #[test]
+    fn albedo() {
+        crate::scene::test_scene();
+        let red = Mat::Matte(Matte::new(1));
+        let mirror = Mat::Glossy(Ggx::new(0.0, 2));
+
+        for uv in [Vec2::ZERO, Vec2::new(0.3, 0.7)] {
+            assert_eq!(red.albedo(uv), Vec3::new(1.0, 0.0, 0.0));
+            assert_eq!(mirror.albedo(uv), Vec3::new(0.9, 0.6, 0.3));
+            assert_eq!(Mat::Invisible.albedo(uv), Vec3::ONE);
+        }
+        let light = Mat::Light(Light::new(Vec3::new(5.0, 0.5, 0.0)));
+        assert_eq!(light.albedo(Vec2::ZERO), Vec3::new(1.0, 0.5, 0.0));
+
+        // textured emission is scaled by the texture at the uv
+        crate::scene::test_scene();
+        let red = crate::loader::texture_idx("red");
+        let tv = Mat::Light(Light::new(Vec3::splat(4.0)).with_texture(red));
+        assert_eq!(
+            tv.le(Vec3::ZERO, Vec3::Z, Vec2::ZERO, true),
+            Vec3::new(4.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn missing_texture() {
+        crate::scene::test_scene();
+        let dangling = Mat::Matte(Matte::new(usize::MAX - 1));
+        let sect = Intersection::new(1.0, Vec2::ZERO, Vec3::ZERO, Vec3::Z, true, 0, 0);
+
+        assert_eq!(dangling.albedo(Vec2::ZERO), Vec3::new(1.0, 0.0, 1.0));
+        assert_eq!(
+            dangling.eval(&sect, -Vec3::Z, Vec3::Z, &Lambda::Rgb),
+            Vec3::new(1.0, 0.0, 1.0)
+        );
+        let dangling_ggx = Mat::Glossy(Ggx::new(0.5, 1000));
+        assert!(dangling_ggx.uv_intersect(Vec2::ZERO, &mut thread_rng()));
+
+        let mats = [Mat::Matte(Matte::new(0)), dangling, dangling_ggx];
+        let names = ["floor", "statue", "rim"];
+        let errors = crate::texture::missing_textures(&mats, 3, |i| names[i].to_owned());
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains("statue") && errors[1].contains("rim"));
+    }
+
     fn log_info(mat: &str, info: String) {
         log::info!("{mat}: {info}");
     }
 
     fn test_material(name: &str, m: Mat, wo: Vec3, rng: &mut impl MinRng) {
-        let sect = &Intersection::new(1.0, Vec3::ZERO, Vec3::Z, true, 0, 0);
+        let sect = &Intersection::new(1.0, Vec2::ZERO, Vec3::ZERO, Vec3::Z, true, 0, 0);
 
         let sample = || -> Vec3 {
             let mut ray = Ray::new(Vec3::ZERO, wo);
@@ -75,22 +344,29 @@ mod tests {
         let wo = generate_wo(&mut rng, true);
         let a = rng.gen();
 
-        let name = "ggx_vndf";
-        let mat = Ggx::new(a, Vec3::ONE);
+        let anisotropy = rng.gen_range(-1.0..1.0);
 
-        log_info("ggx_vndf", format!("alpha: {a}"));
+        for (name, mat) in [
+            ("ggx_vndf", Ggx::new(a, 0)),
+            (
+                "ggx_vndf_anisotropic",
+                Ggx::new(a, 0).anisotropic(anisotropy),
+            ),
+        ] {
+            log_info(name, format!("alpha: {a} anisotropy: {anisotropy}"));
 
-        let sample = || -> Vec3 { mat.sample_vndf_local(wo, &mut rng) };
-        let pdf = |wo: Vec3, wm: Vec3| -> f32 { mat.vndf_local(wm, wo) };
+            let sample = || -> Vec3 { mat.sample_vndf_local(wo, &mut rng) };
+            let pdf = |wo: Vec3, wm: Vec3| -> f32 { mat.vndf_local(wm, wo) };
 
-        log_info(name, format!("wo: {wo}"));
+            log_info(name, format!("wo: {wo}"));
 
-        sample_image(sample, SAMPLES, name);
+            sample_image(sample, SAMPLES, name);
 
-        let sum = integrate_pdf(pdf, wo, name);
+            let sum = integrate_pdf(pdf, wo, name);
 
-        log_info(name, format!("sum: {sum}"));
-        assert!((sum - 1.0).abs() < PDF_EPS, "sum = {sum}");
+            log_info(name, format!("sum: {sum}"));
+            assert!((sum - 1.0).abs() < PDF_EPS, "sum = {sum}");
+        }
     }
 
     #[test]
@@ -100,7 +376,7 @@ mod tests {
         let a = rng.gen();
 
         let name = "ggx_vndf_transformed";
-        let mat = Ggx::new(a, Vec3::ONE);
+        let mat = Ggx::new(a, 0);
 
         log_info("ggx_vndf_transformed", format!("alpha: {a}"));
 
@@ -130,17 +406,24 @@ mod tests {
         let mut rng = thread_rng();
         let a = rng.gen();
 
-        let name = "ggx_ndf_area";
-        let mat = Ggx::new(a, Vec3::ONE);
+        let anisotropy = rng.gen_range(-1.0..1.0);
 
-        let pdf = |_: Vec3, wm: Vec3| -> f32 { mat.ndf_local(wm) * wm.z };
+        for (name, mat) in [
+            ("ggx_ndf_area", Ggx::new(a, 0)),
+            (
+                "ggx_ndf_area_anisotropic",
+                Ggx::new(a, 0).anisotropic(anisotropy),
+            ),
+        ] {
+            let pdf = |_: Vec3, wm: Vec3| -> f32 { mat.ndf_local(wm) * wm.z };
 
-        log_info("ggx_ndf_area", format!("alpha: {a}"));
+            log_info(name, format!("alpha: {a} anisotropy: {anisotropy}"));
 
-        let sum = integrate_pdf(pdf, Vec3::ZERO, name);
+            let sum = integrate_pdf(pdf, Vec3::ZERO, name);
 
-        log_info(name, format!("sum: {sum}"));
-        assert!((sum - 1.0).abs() < PDF_EPS, "sum = {sum}");
+            log_info(name, format!("sum: {sum}"));
+            assert!((sum - 1.0).abs() < PDF_EPS, "sum = {sum}");
+        }
     }
 
     #[test]
@@ -150,20 +433,106 @@ mod tests {
 
         let wo = generate_wo(&mut rng, true);
 
-        let name = "weak_white_furnace";
-        let mat = Ggx::new(a, Vec3::ONE);
+        let anisotropy = rng.gen_range(-1.0..1.0);
 
-        let pdf = |wo: Vec3, wi: Vec3| -> f32 {
-            let wm = (wo + wi).normalised();
-            mat.ndf_local(wm) * mat.g1_local(wo, wm) / (4.0 * wo.z.abs())
+        for (name, mat) in [
+            ("weak_white_furnace", Ggx::new(a, 0)),
+            (
+                "weak_white_furnace_anisotropic",
+                Ggx::new(a, 0).anisotropic(anisotropy),
+            ),
+        ] {
+            let pdf = |wo: Vec3, wi: Vec3| -> f32 {
+                let wm = (wo + wi).normalised();
+                mat.ndf_local(wm) * mat.g1_local(wo, wm) / (4.0 * wo.z.abs())
+            };
+
+            log_info(name, format!("alpha: {a} anisotropy: {anisotropy}"));
+
+            let sum = integrate_pdf(pdf, wo, name);
+
+            log_info(name, format!("sum: {sum}"));
+            assert!((sum - 1.0).abs() < PDF_EPS, "sum = {sum}");
+        }
+    }
+
+    // the energy lost by a rough white lobe is restored by the multiple scattering lobe
+    #[test]
+    fn white_furnace_multiscatter() {
+        let mut rng = thread_rng();
+        let wo = generate_wo(&mut rng, true);
+
+        let name = "white_furnace_multiscatter";
+        let single = Ggx::new(1.0, 0);
+        let multi = Ggx::new(1.0, 0).with_multiscatter(true);
+
+        let albedo = |mat: &Ggx| {
+            let pdf = |wo: Vec3, wi: Vec3| -> f32 { mat.white_bxdf_cos(wo, wi) };
+            integrate_pdf(pdf, wo, name)
         };
+        let (single, multi) = (albedo(&single), albedo(&multi));
 
-        log_info("weak_white_furnace", format!("alpha: {a}"));
+        log_info(name, format!("wo: {wo} single: {single} multi: {multi}"));
+        assert!(single < 0.95, "single = {single}");
+        assert!((multi - 1.0).abs() < 0.02, "multi = {multi}");
+        assert!((multi - 1.0).abs() < (single - 1.0).abs());
+    }
 
-        let sum = integrate_pdf(pdf, wo, name);
+    // the pdf integration doesn't depend on the rng, this checks that scattering driven
+    // by the sobol sampler (a new path per sample) still follows the pdf
+    #[test]
+    fn sobol_sampling() {
+        use crate::sampler::Sobol;
+        const SAMPLES: u32 = 1 << 16;
+        const COARSE_THETA: usize = 8;
+        const COARSE_PHI: usize = 2 * COARSE_THETA;
 
-        log_info(name, format!("sum: {sum}"));
-        assert!((sum - 1.0).abs() < PDF_EPS, "sum = {sum}");
+        let mut rng = thread_rng();
+        let wo = -generate_wo(&mut rng, true);
+        // rough enough for the coarse bins to be integrated accurately
+        let a = 0.2 + 0.8 * rng.gen();
+        let seed = (rng.gen() * u32::MAX as f32) as u32;
+        let sect = &Intersection::new(1.0, Vec2::ZERO, Vec3::ZERO, Vec3::Z, true, 0, 0);
+
+        for (name, m) in [
+            ("lambertian_sobol", Mat::Matte(Matte::new(0))),
+            ("ggx_sobol", Mat::Glossy(Ggx::new(a, 0))),
+        ] {
+            let mut sobol = Sobol::new(0, seed);
+            let mut sampled = vec![0.0; COARSE_THETA * COARSE_PHI];
+            for i in 0..SAMPLES {
+                sobol.start_path(i);
+                let mut ray = Ray::new(Vec3::ZERO, wo);
+                m.scatter(sect, &mut ray, &mut sobol);
+                let theta = (ray.dir.z.clamp(-1.0, 1.0).acos() / PI * COARSE_THETA as f32) as usize;
+                let phi = ray.dir.y.atan2(ray.dir.x).rem_euclid(TAU) / TAU;
+                let phi = (phi * COARSE_PHI as f32) as usize;
+                let idx = theta.min(COARSE_THETA - 1) * COARSE_PHI + phi.min(COARSE_PHI - 1);
+                sampled[idx] += 1.0 / SAMPLES as f64;
+            }
+
+            let pdf = |wi: Vec3| m.spdf(sect, wo, wi) as f64;
+            let max_diff = sampled
+                .iter()
+                .enumerate()
+                .map(|(idx, s)| {
+                    let (phi, theta) = ((idx % COARSE_PHI) as f64, (idx / COARSE_PHI) as f64);
+                    let (dphi, dtheta) = (
+                        TAU as f64 / COARSE_PHI as f64,
+                        PI as f64 / COARSE_THETA as f64,
+                    );
+                    let expected = integrate_solid_angle(
+                        &pdf,
+                        (phi * dphi, (phi + 1.0) * dphi),
+                        (theta * dtheta, (theta + 1.0) * dtheta),
+                    );
+                    (s - expected).abs()
+                })
+                .fold(0.0, f64::max);
+
+            log_info(name, format!("alpha: {a} max bin difference: {max_diff}"));
+            assert!(max_diff < 5e-3, "max_diff = {max_diff}");
+        }
     }
 
     // uniform hemisphere/sphere sampling
@@ -0,0 +1,103 @@
+use crate::prelude::*;
+use std::ffi::{c_char, c_void, CStr, CString};
+
+// minimal bindings to the OpenImageDenoise C API (the library must be installed to
+// link builds with the oidn feature), Vec3 is repr(C) so canvases are shared as is
+
+type Device = *mut c_void;
+type Filter = *mut c_void;
+
+const DEVICE_TYPE_DEFAULT: i32 = 0;
+const FORMAT_FLOAT3: i32 = 3;
+const ERROR_NONE: i32 = 0;
+
+#[link(name = "OpenImageDenoise")]
+extern "C" {
+    fn oidnNewDevice(device_type: i32) -> Device;
+    fn oidnCommitDevice(device: Device);
+    fn oidnGetDeviceError(device: Device, message: *mut *const c_char) -> i32;
+    fn oidnReleaseDevice(device: Device);
+    fn oidnNewFilter(device: Device, filter_type: *const c_char) -> Filter;
+    fn oidnSetSharedFilterImage(
+        filter: Filter,
+        name: *const c_char,
+        ptr: *mut c_void,
+        format: i32,
+        width: usize,
+        height: usize,
+        byte_offset: usize,
+        pixel_byte_stride: usize,
+        row_byte_stride: usize,
+    );
+    fn oidnSetFilterBool(filter: Filter, name: *const c_char, value: bool);
+    fn oidnCommitFilter(filter: Filter);
+    fn oidnExecuteFilter(filter: Filter);
+    fn oidnReleaseFilter(filter: Filter);
+}
+
+unsafe fn device_error(device: Device) -> Result<(), String> {
+    let mut message = std::ptr::null();
+    if oidnGetDeviceError(device, &mut message) == ERROR_NONE {
+        return Ok(());
+    }
+    Err(if message.is_null() {
+        "unknown OIDN error".to_string()
+    } else {
+        CStr::from_ptr(message).to_string_lossy().into_owned()
+    })
+}
+
+// runs the canvas (sum of splats) through OIDN's ray tracing filter guided by the first
+// hit albedo & normals if given, the result is scaled like the canvas
+pub fn denoise(
+    canvas: &[Vec3],
+    guides: Option<&crate::denoise::Guides>,
+    width: usize,
+    height: usize,
+    splats: u64,
+) -> Result<Vec<Vec3>, String> {
+    // OIDN expects radiance for HDR images
+    let mult = (width * height) as f32 / splats.max(1) as f32;
+    let mut color: Vec<Vec3> = canvas.iter().map(|&v| v * mult).collect();
+    let mut output = vec![Vec3::ZERO; canvas.len()];
+    let (mut albedo, mut normal) =
+        guides.map_or((vec![], vec![]), |g| (g.albedo.clone(), g.normal.clone()));
+    let name = |s: &str| CString::new(s).unwrap();
+
+    unsafe {
+        let device = oidnNewDevice(DEVICE_TYPE_DEFAULT);
+        oidnCommitDevice(device);
+        device_error(device).inspect_err(|_| oidnReleaseDevice(device))?;
+
+        let filter = oidnNewFilter(device, name("RT").as_ptr());
+        let image = |n: &str, buffer: &mut [Vec3]| {
+            oidnSetSharedFilterImage(
+                filter,
+                name(n).as_ptr(),
+                buffer.as_mut_ptr().cast(),
+                FORMAT_FLOAT3,
+                width,
+                height,
+                0,
+                0,
+                0,
+            );
+        };
+        image("color", &mut color);
+        image("output", &mut output);
+        if guides.is_some() {
+            image("albedo", &mut albedo);
+            image("normal", &mut normal);
+        }
+        oidnSetFilterBool(filter, name("hdr").as_ptr(), true);
+        oidnCommitFilter(filter);
+        oidnExecuteFilter(filter);
+
+        let result = device_error(device);
+        oidnReleaseFilter(filter);
+        oidnReleaseDevice(device);
+        result?;
+    }
+
+    Ok(output.into_iter().map(|v| v / mult).collect())
+}
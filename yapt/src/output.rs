@@ -0,0 +1,673 @@
+use crate::prelude::*;
+use crate::tonemap::{tone_curve, Tonemap};
+
+// the canvas is the sum of all splats so every pixel has splats / (width * height) samples
+
+// a canvas only rendered within region placed over a (normalised) backdrop, the
+// result is normalised by splats over the whole frame like an unrestricted render
+#[must_use]
+pub fn composite<'a>(
+    canvas: &'a [Vec3],
+    region: Option<Tile>,
+    backdrop: &[Vec3],
+    splats: u64,
+) -> std::borrow::Cow<'a, [Vec3]> {
+    let Some(region) = region else {
+        return std::borrow::Cow::Borrowed(canvas);
+    };
+    let frame = canvas.len() as f32;
+    let backdrop_scale = splats.max(1) as f32 / frame;
+    let mut out: Vec<Vec3> = if backdrop.is_empty() {
+        vec![Vec3::ZERO; canvas.len()]
+    } else {
+        backdrop.iter().map(|&v| v * backdrop_scale).collect()
+    };
+    let region_scale = region.pixels() as f32 / frame;
+    for i in 0..region.pixels() {
+        let idx = region.frame_pixel(i) as usize;
+        out[idx] = canvas[idx] * region_scale;
+    }
+    std::borrow::Cow::Owned(out)
+}
+
+// sidecar storing the number of splats an image was normalised with
+#[must_use]
+fn sidecar_path(path: &str) -> String {
+    format!("{path}.samples")
+}
+
+// where --checkpoint-interval saves the render state of filename (render.png ->
+// render.checkpoint.exr), always an .exr so it can be resumed
+#[must_use]
+pub fn checkpoint_path(filename: &str) -> String {
+    let stem = filename.rsplit_once('.').map_or(filename, |(stem, _)| stem);
+    format!("{stem}.checkpoint.exr")
+}
+
+// where --spp-heatmap saves the samples per pixel of filename (render.exr ->
+// render_spp.png)
+#[must_use]
+pub fn spp_heatmap_path(filename: &str) -> String {
+    std::path::Path::new(&crate::with_suffix(filename, "_spp"))
+        .with_extension("png")
+        .to_string_lossy()
+        .into_owned()
+}
+
+// where --bvh-heatmap saves the heatmap, always a .png as it's 8 bit (render.exr ->
+// render.png)
+#[must_use]
+pub fn bvh_heatmap_path(filename: &str) -> String {
+    if filename.is_empty() {
+        return "heatmap.png".to_owned();
+    }
+    std::path::Path::new(filename)
+        .with_extension("png")
+        .to_string_lossy()
+        .into_owned()
+}
+
+// writes the normalised (linear) canvas in the output space (stored as the image's
+// chromaticities) with its pixel aspect ratio along with the number of splats to a sidecar
+pub fn save_exr(
+    canvas: &[Vec3],
+    width: usize,
+    height: usize,
+    pixel_aspect: f32,
+    splats: u64,
+    path: &str,
+    transform: &ColorTransform,
+) -> Result<(), Box<dyn std::error::Error>> {
+    write_exr(
+        canvas,
+        None,
+        width,
+        height,
+        pixel_aspect,
+        splats,
+        path,
+        transform,
+    )
+}
+
+// save_exr with the guides as extra layers named "albedo" (in the output space),
+// "normal" (world space) & "depth" (a single Z channel, infinite for misses) after the
+// beauty layer, along with the samples per pixel of adaptive renders as "samples" (Y)
+#[allow(clippy::too_many_arguments)]
+pub fn save_exr_aovs(
+    canvas: &[Vec3],
+    aovs: (&crate::denoise::Guides, Option<&[u32]>),
+    width: usize,
+    height: usize,
+    pixel_aspect: f32,
+    splats: u64,
+    path: &str,
+    transform: &ColorTransform,
+) -> Result<(), Box<dyn std::error::Error>> {
+    write_exr(
+        canvas,
+        Some(aovs),
+        width,
+        height,
+        pixel_aspect,
+        splats,
+        path,
+        transform,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn write_exr(
+    canvas: &[Vec3],
+    aovs: Option<(&crate::denoise::Guides, Option<&[u32]>)>,
+    width: usize,
+    height: usize,
+    pixel_aspect: f32,
+    splats: u64,
+    path: &str,
+    transform: &ColorTransform,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use exr::{math::Vec2, meta::attribute::Chromaticities, prelude::*};
+    let layer = |name: Option<&str>, rgb: &mut dyn Iterator<Item = Vec3>| {
+        let (mut r, mut g, mut b) = (Vec::new(), Vec::new(), Vec::new());
+        for v in rgb {
+            r.push(v.x);
+            g.push(v.y);
+            b.push(v.z);
+        }
+        let channels = AnyChannels::sort(SmallVec::from_vec(vec![
+            AnyChannel::new("R", FlatSamples::F32(r)),
+            AnyChannel::new("G", FlatSamples::F32(g)),
+            AnyChannel::new("B", FlatSamples::F32(b)),
+        ]));
+        Layer::new(
+            (width, height),
+            name.map_or_else(LayerAttributes::default, LayerAttributes::named),
+            Encoding::FAST_LOSSLESS,
+            channels,
+        )
+    };
+
+    let mult = (width * height) as f32 / splats.max(1) as f32;
+    // layers of multi layer files must be named
+    let mut layers = vec![layer(
+        aovs.map(|_| "beauty"),
+        &mut canvas.iter().map(|&v| transform.apply(v * mult)),
+    )];
+    let single = |name: &str, channel: &str, v: Vec<f32>| {
+        Layer::new(
+            (width, height),
+            LayerAttributes::named(name),
+            Encoding::FAST_LOSSLESS,
+            AnyChannels::sort(SmallVec::from_vec(vec![AnyChannel::new(
+                channel,
+                FlatSamples::F32(v),
+            )])),
+        )
+    };
+    if let Some((guides, samples)) = aovs {
+        layers.push(layer(
+            Some("albedo"),
+            &mut guides.albedo.iter().map(|&v| transform.apply(v)),
+        ));
+        layers.push(layer(Some("normal"), &mut guides.normal.iter().copied()));
+        layers.push(single("depth", "Z", guides.depth.clone()));
+        if let Some(samples) = samples {
+            layers.push(single(
+                "samples",
+                "Y",
+                samples.iter().map(|&n| n as f32).collect(),
+            ));
+        }
+    }
+
+    let mut attributes = ImageAttributes::new(IntegerBounds::from_dimensions((width, height)));
+    attributes.pixel_aspect = pixel_aspect;
+    let [red, green, blue, white] = transform.to.chromaticities().map(|[x, y]| Vec2(x, y));
+    attributes.chromaticities = Some(Chromaticities {
+        red,
+        green,
+        blue,
+        white,
+    });
+    Image::from_layers(attributes, layers)
+        .write()
+        .to_file(path)?;
+    std::fs::write(sidecar_path(path), splats.to_string())?;
+    Ok(())
+}
+
+// normalised linear value to 8 bit in the output space encoded with its transfer
+// function, values are clamped to [0, 1] and whether any channel was above 1 is returned
+#[must_use]
+// tonemap is the operator & exposure (in stops), linear skips the transfer function
+pub fn to_ldr(rgb: Vec3, transform: &ColorTransform, tonemap: (Tonemap, f32)) -> ([u8; 3], bool) {
+    let (op, exposure) = tonemap;
+    let rgb = tone_curve(transform.apply(rgb), exposure, op);
+    let clipped = rgb.component_max() > 1.0;
+    let rgb = rgb.saturate();
+    let encode = |v: f32| {
+        let v = if op == Tonemap::Linear {
+            v
+        } else {
+            transform.to.encode(v)
+        };
+        (v * 255.0).round() as u8
+    };
+    ([rgb.x, rgb.y, rgb.z].map(encode), clipped)
+}
+
+pub fn save_png(
+    canvas: &[Vec3],
+    width: usize,
+    height: usize,
+    splats: u64,
+    path: &str,
+    transform: &ColorTransform,
+    tonemap: (Tonemap, f32),
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mult = (width * height) as f32 / splats.max(1) as f32;
+    let buf: Vec<u8> = canvas
+        .iter()
+        .flat_map(|&rgb| to_ldr(rgb * mult, transform, tonemap).0)
+        .collect();
+    image::save_buffer(
+        path,
+        &buf,
+        width as u32,
+        height as u32,
+        image::ColorType::Rgb8,
+    )?;
+    Ok(())
+}
+
+// greyscale mask of the pixels clamped by save_png (white where clipped)
+pub fn save_clip_mask(
+    canvas: &[Vec3],
+    width: usize,
+    height: usize,
+    splats: u64,
+    path: &str,
+    transform: &ColorTransform,
+    tonemap: (Tonemap, f32),
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mult = (width * height) as f32 / splats.max(1) as f32;
+    let buf: Vec<u8> = canvas
+        .iter()
+        .map(|&rgb| {
+            if to_ldr(rgb * mult, transform, tonemap).1 {
+                255
+            } else {
+                0
+            }
+        })
+        .collect();
+    image::save_buffer(
+        path,
+        &buf,
+        width as u32,
+        height as u32,
+        image::ColorType::L8,
+    )?;
+    Ok(())
+}
+
+// inverse of save_exr, returns the canvas (as the sum of splats in the working space)
+// and the number of splats, images without chromaticities are assumed to be in the
+// working space
+pub fn load_accumulation(
+    width: usize,
+    height: usize,
+    path: &str,
+    working: Primaries,
+) -> Result<(Vec<Vec3>, u64), Box<dyn std::error::Error>> {
+    use exr::prelude::*;
+    let splats: u64 = std::fs::read_to_string(sidecar_path(path))?
+        .trim()
+        .parse()?;
+
+    let image = read_first_rgba_layer_from_file(
+        path,
+        |resolution, _| (resolution.width(), vec![Vec3::ZERO; resolution.area()]),
+        |(w, canvas), position, (r, g, b, _): (f32, f32, f32, f32)| {
+            canvas[position.y() * *w + position.x()] = Vec3::new(r, g, b)
+        },
+    )?;
+
+    let size = image.layer_data.size;
+    if (size.width(), size.height()) != (width, height) {
+        return Err(format!(
+            "{path} is {}x{} but the render is {width}x{height}",
+            size.width(),
+            size.height()
+        )
+        .into());
+    }
+
+    let primaries = match image.attributes.chromaticities {
+        Some(c) => Primaries::from_chromaticities(
+            [c.red, c.green, c.blue, c.white].map(|v| [v.x(), v.y()]),
+        )
+        .ok_or(format!("{path} has unsupported chromaticities"))?,
+        None => working,
+    };
+    let transform = ColorTransform::new(primaries, working);
+
+    let mult = splats as f32 / (width * height) as f32;
+    let canvas = image
+        .layer_data
+        .channel_data
+        .pixels
+        .1
+        .into_iter()
+        .map(|rgb| transform.apply(rgb) * mult)
+        .collect();
+    Ok((canvas, splats))
+}
+
+// even and odd samples accumulated separately (as sums like the canvas), these are
+// two independent half sample count renders so their difference estimates the noise
+pub struct HalfBuffers {
+    canvas: [Vec<Vec3>; 2],
+    splats: [u64; 2],
+}
+
+impl HalfBuffers {
+    #[must_use]
+    pub fn new(pixels: usize) -> Self {
+        Self {
+            canvas: [vec![Vec3::ZERO; pixels], vec![Vec3::ZERO; pixels]],
+            splats: [0; 2],
+        }
+    }
+    pub fn add(&mut self, idx: usize, rgb: Vec3, odd: bool) {
+        self.canvas[odd as usize][idx] += rgb;
+        self.splats[odd as usize] += 1;
+    }
+    // (even, odd) each normalised by its own number of samples
+    #[must_use]
+    pub fn normalised(&self) -> [Vec<Vec3>; 2] {
+        [0, 1].map(|i| {
+            let mult = self.canvas[i].len() as f32 / self.splats[i].max(1) as f32;
+            self.canvas[i].iter().map(|&v| v * mult).collect()
+        })
+    }
+    // per pixel absolute difference between the even & odd images
+    #[must_use]
+    pub fn noise(&self) -> Vec<Vec3> {
+        let [even, odd] = self.normalised();
+        even.into_iter()
+            .zip(odd)
+            .map(|(a, b)| (a - b).abs())
+            .collect()
+    }
+    // saves {stem}_even.exr, {stem}_odd.exr and {stem}_noise.exr next to path
+    pub fn save(
+        &self,
+        width: usize,
+        height: usize,
+        pixel_aspect: f32,
+        path: &str,
+        transform: &ColorTransform,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let stem = path.strip_suffix(".exr").unwrap_or(path);
+        let pixels = (width * height) as u64;
+        save_exr(
+            &self.canvas[0],
+            width,
+            height,
+            pixel_aspect,
+            self.splats[0],
+            &format!("{stem}_even.exr"),
+            transform,
+        )?;
+        save_exr(
+            &self.canvas[1],
+            width,
+            height,
+            pixel_aspect,
+            self.splats[1],
+            &format!("{stem}_odd.exr"),
+            transform,
+        )?;
+        save_exr(
+            &self.noise(),
+            width,
+            height,
+            pixel_aspect,
+            pixels,
+            &format!("{stem}_noise.exr"),
+            transform,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn accumulate() {
+        let (width, height, spp) = (8, 4, 50);
+        let pixels = width * height;
+        let mut rng = StdRng::seed_from_u64(0);
+        let samples: Vec<Vec3> = (0..2 * spp * pixels)
+            .map(|_| Vec3::new(rng.gen(), 4.0 * rng.gen(), rng.gen().powi(4)))
+            .collect();
+
+        let render = |samples: &[Vec3]| {
+            let mut canvas = vec![Vec3::ZERO; pixels];
+            for (i, s) in samples.iter().enumerate() {
+                canvas[i % pixels] += *s;
+            }
+            canvas
+        };
+        let (first, second) = samples.split_at(spp * pixels);
+
+        let path = std::env::temp_dir().join("yapt_accumulate_test.exr");
+        let path = path.to_str().unwrap();
+        let rec2020 = ColorTransform::new(Primaries::Rec2020, Primaries::Rec2020);
+        save_exr(
+            &render(first),
+            width,
+            height,
+            1.0,
+            first.len() as u64,
+            path,
+            &rec2020,
+        )
+        .unwrap();
+
+        let (mut canvas, splats) =
+            load_accumulation(width, height, path, Primaries::Rec2020).unwrap();
+        for (c, s) in canvas.iter_mut().zip(render(second)) {
+            *c += s;
+        }
+        let splats = splats + second.len() as u64;
+        assert_eq!(splats, samples.len() as u64);
+
+        let full = render(&samples);
+        for (a, b) in canvas.iter().zip(full) {
+            assert!((*a - b).mag() < 1e-4 * b.mag().max(1.0), "{a} != {b}");
+        }
+        assert!(load_accumulation(width + 1, height, path, Primaries::Rec2020).is_err());
+    }
+
+    #[test]
+    fn half_buffers() {
+        let pixels = 64;
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut half = HalfBuffers::new(pixels);
+        let mut full = vec![Vec3::ZERO; pixels];
+        let mut splats = 0;
+
+        let mut noise = vec![];
+        for spp in [16, 256, 4096] {
+            while splats < spp * pixels {
+                let (idx, sample) = (splats % pixels, splats / pixels);
+                // noisy estimate of a per pixel gradient
+                let rgb = Vec3::splat(idx as f32 / pixels as f32 + rng.gen() - 0.5);
+                full[idx] += rgb;
+                half.add(idx, rgb, sample % 2 == 1);
+                splats += 1;
+            }
+
+            // equal sample counts so the halves average to the full render
+            let [even, odd] = half.normalised();
+            for i in 0..pixels {
+                let mean = full[i] / spp as f32;
+                assert!((0.5 * (even[i] + odd[i]) - mean).mag() < 1e-4);
+            }
+            noise.push(half.noise().iter().map(|v| v.x).sum::<f32>() / pixels as f32);
+        }
+        assert!(
+            noise[0] > 2.0 * noise[1] && noise[1] > 2.0 * noise[2],
+            "{noise:?}"
+        );
+    }
+
+    #[test]
+    fn png_tonemap() {
+        let transform = ColorTransform::new(Primaries::Srgb, Primaries::Srgb);
+        let v = Vec3::splat(0.5);
+        let old = (Primaries::Srgb.encode(0.5) * 255.0).round() as u8;
+        assert_eq!(
+            to_ldr(v, &transform, (Tonemap::Gamma, 0.0)),
+            ([old; 3], false)
+        );
+        assert_eq!(
+            to_ldr(2.0 * v, &transform, (Tonemap::Gamma, -1.0)),
+            to_ldr(v, &transform, (Tonemap::Gamma, 0.0))
+        );
+        assert_eq!(to_ldr(v, &transform, (Tonemap::Linear, 0.0)).0, [128; 3]);
+
+        // compressing operators keep highlights from clipping
+        for op in [Tonemap::Reinhard, Tonemap::Aces] {
+            let (ldr, clipped) = to_ldr(Vec3::splat(4.0), &transform, (op, 0.0));
+            assert!(!clipped && ldr[0] < 255, "{op}");
+        }
+    }
+
+    #[test]
+    fn composite_region() {
+        let (v, b) = (Vec3::new(0.5, 1.0, 2.0), Vec3::splat(0.25));
+        let region = Tile::from_rect([1, 0, 3, 1], 4, 1).unwrap();
+        // 3 samples of v in each region pixel, stale values elsewhere
+        let canvas = [Vec3::splat(9.0), 3.0 * v, 3.0 * v, Vec3::splat(9.0)];
+        let splats = region.pixels() * 3;
+
+        let normalised = |c: &[Vec3]| -> Vec<Vec3> {
+            let mult = c.len() as f32 / splats as f32;
+            c.iter().map(|&c| c * mult).collect()
+        };
+        let out = normalised(&composite(&canvas, Some(region), &[b; 4], splats));
+        let expected = [b, v, v, b];
+        for (a, e) in out.iter().zip(expected) {
+            assert!((*a - e).mag() < 1e-6, "{out:?}");
+        }
+
+        let black = composite(&canvas, Some(region), &[], splats);
+        assert_eq!((black[0], black[3]), (Vec3::ZERO, Vec3::ZERO));
+        assert_eq!(&*composite(&canvas, None, &[], splats), &canvas);
+    }
+
+    #[test]
+    fn clip_mask() {
+        let transform = ColorTransform::new(Primaries::Srgb, Primaries::Srgb);
+        let tm = (Tonemap::Gamma, 0.0);
+        assert_eq!(to_ldr(Vec3::splat(2.0), &transform, tm), ([255; 3], true));
+        assert_eq!(to_ldr(Vec3::splat(1.0), &transform, tm), ([255; 3], false));
+        assert!(to_ldr(Vec3::new(0.1, 1.5, 0.1), &transform, tm).1);
+
+        let canvas = [Vec3::splat(2.0), Vec3::splat(0.5)];
+        let dir = std::env::temp_dir();
+        let (png, mask) = (dir.join("yapt_clip.png"), dir.join("yapt_clip_mask.png"));
+        let (png, mask) = (png.to_str().unwrap(), mask.to_str().unwrap());
+        save_png(&canvas, 2, 1, 2, png, &transform, tm).unwrap();
+        save_clip_mask(&canvas, 2, 1, 2, mask, &transform, tm).unwrap();
+
+        let png = image::open(png).unwrap().to_rgb8();
+        assert_eq!(png.get_pixel(0, 0).0, [255; 3]);
+        assert!(png.get_pixel(1, 0).0[0] < 255);
+        let mask = image::open(mask).unwrap().to_luma8();
+        assert_eq!(
+            (mask.get_pixel(0, 0).0, mask.get_pixel(1, 0).0),
+            ([255], [0])
+        );
+    }
+
+    #[test]
+    fn output_primaries() {
+        use exr::prelude::*;
+        // pure sRGB red in the working space written as sRGB
+        let transform = ColorTransform::new(Primaries::Rec2020, Primaries::Srgb);
+        let red = ColorTransform::new(Primaries::Srgb, Primaries::Rec2020).apply(Vec3::X);
+        let dir = std::env::temp_dir();
+        let (png, exr) = (
+            dir.join("yapt_primaries.png"),
+            dir.join("yapt_primaries.exr"),
+        );
+        let (png, exr) = (png.to_str().unwrap(), exr.to_str().unwrap());
+        save_png(&[red], 1, 1, 1, png, &transform, (Tonemap::Gamma, 0.0)).unwrap();
+        save_exr(&[red], 1, 1, 1.0, 1, exr, &transform).unwrap();
+
+        let chromaticity = |rgb: [f32; 3], c: [[f32; 2]; 4]| {
+            let m = crate::color::rgb_to_xyz(c);
+            let [x, y, z] = m.map(|row| row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2]);
+            [x / (x + y + z), y / (x + y + z)]
+        };
+
+        let png = image::open(png).unwrap().to_rgb8();
+        let png = png
+            .get_pixel(0, 0)
+            .0
+            .map(|v| Primaries::Srgb.decode(v as f32 / 255.0));
+        let png = chromaticity(png, Primaries::Srgb.chromaticities());
+
+        let exr = read_first_rgba_layer_from_file(
+            exr,
+            |_, _| [0.0; 3],
+            |pixel, _, (r, g, b, _): (f32, f32, f32, f32)| *pixel = [r, g, b],
+        )
+        .unwrap();
+        let c = exr.attributes.chromaticities.unwrap();
+        let c = [c.red, c.green, c.blue, c.white].map(|v| [v.x(), v.y()]);
+        let exr = chromaticity(exr.layer_data.channel_data.pixels, c);
+
+        for (a, b) in png.iter().zip(exr) {
+            assert!((a - b).abs() < 1e-3, "{png:?} {exr:?}");
+        }
+        assert!((png[0] - 0.64).abs() < 2e-3 && (png[1] - 0.33).abs() < 2e-3);
+    }
+
+    #[test]
+    fn aov_layers() {
+        use exr::prelude::*;
+        let (width, height) = (2, 1);
+        let canvas = [Vec3::new(2.0, 4.0, 6.0), Vec3::ONE];
+        let guides = crate::denoise::Guides {
+            albedo: vec![Vec3::new(0.1, 0.2, 0.3), Vec3::splat(0.5)],
+            normal: vec![Vec3::Z, -Vec3::X],
+            depth: vec![2.5, f32::INFINITY],
+        };
+        let path = std::env::temp_dir().join("yapt_aovs.exr");
+        let path = path.to_str().unwrap();
+        let rec2020 = ColorTransform::new(Primaries::Rec2020, Primaries::Rec2020);
+        save_exr_aovs(
+            &canvas,
+            (&guides, Some(&[3, 1])),
+            width,
+            height,
+            1.0,
+            4,
+            path,
+            &rec2020,
+        )
+        .unwrap();
+
+        let image = read_all_flat_layers_from_file(path).unwrap();
+        let channel = |layer: &str, channel: &str| -> Vec<f32> {
+            let layer = image
+                .layer_data
+                .iter()
+                .find(|l| l.attributes.layer_name == Some(layer.into()))
+                .unwrap();
+            let channel = layer
+                .channel_data
+                .list
+                .iter()
+                .find(|c| c.name == *channel)
+                .unwrap();
+            channel.sample_data.values_as_f32().collect()
+        };
+        let layer = |name: &str| -> Vec<Vec3> {
+            let [r, g, b] = ["R", "G", "B"].map(|c| channel(name, c));
+            (0..width).map(|i| Vec3::new(r[i], g[i], b[i])).collect()
+        };
+        // 2 splats per pixel
+        assert_eq!(layer("beauty"), canvas.map(|v| v * 0.5));
+        assert_eq!(layer("albedo"), guides.albedo);
+        assert_eq!(layer("normal"), guides.normal);
+        assert_eq!(channel("depth", "Z"), guides.depth);
+        assert_eq!(channel("samples", "Y"), [3.0, 1.0]);
+
+        // the beauty layer is still read back for accumulation
+        let (loaded, splats) = load_accumulation(width, height, path, Primaries::Rec2020).unwrap();
+        assert_eq!((loaded, splats), (canvas.to_vec(), 4));
+    }
+
+    #[test]
+    fn exr_pixel_aspect() {
+        use exr::prelude::*;
+        let path = std::env::temp_dir().join("yapt_pixel_aspect.exr");
+        let path = path.to_str().unwrap();
+        let rec2020 = ColorTransform::new(Primaries::Rec2020, Primaries::Rec2020);
+        save_exr(&[Vec3::ONE; 2], 2, 1, 2.0, 2, path, &rec2020).unwrap();
+
+        // pixels twice as wide as they're tall (--pixel-aspect 2)
+        let image = read_all_flat_layers_from_file(path).unwrap();
+        assert_eq!(image.attributes.pixel_aspect, 2.0);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::prelude::*;
+use bvh::aabb::{Aabb, Aabound};
+
+// everything the BVH can hold, dispatches to the shape
+#[derive(Debug, PartialEq)]
+pub enum Primitive {
+    Tri(Tri),
+    Sphere(Sphere),
+}
+
+impl Aabound for Primitive {
+    fn aabb(&self) -> Aabb {
+        match self {
+            Self::Tri(t) => t.aabb(),
+            Self::Sphere(s) => s.aabb(),
+        }
+    }
+}
+
+impl Primitive {
+    #[must_use]
+    pub fn mat(&self) -> usize {
+        match self {
+            Self::Tri(t) => t.mat,
+            Self::Sphere(s) => s.mat,
+        }
+    }
+    #[must_use]
+    pub fn intersect(&self, ray: &Ray, rng: &mut impl MinRng) -> Intersection {
+        match self {
+            Self::Tri(t) => t.intersect(ray, rng),
+            Self::Sphere(s) => s.intersect(ray, rng),
+        }
+    }
+    // distance to the hit, for occlusion tests (see Tri::intersect_t)
+    #[must_use]
+    pub fn intersect_t(&self, ray: &Ray, rng: &mut impl MinRng) -> Option<f32> {
+        match self {
+            Self::Tri(t) => t.intersect_t(ray, rng),
+            Self::Sphere(s) => {
+                let sect = s.intersect(ray, rng);
+                (!sect.is_none()).then_some(sect.t)
+            }
+        }
+    }
+    #[must_use]
+    pub fn sample_ray(&self, sect: &Intersection, rng: &mut impl MinRng) -> (Ray, Vec3) {
+        match self {
+            Self::Tri(t) => t.sample_ray(sect, rng),
+            Self::Sphere(s) => s.sample_ray(sect, rng),
+        }
+    }
+    #[must_use]
+    pub fn pdf(&self, sect: &Intersection, ray: &Ray) -> f32 {
+        match self {
+            Self::Tri(t) => t.pdf(sect, ray),
+            Self::Sphere(s) => s.pdf(sect, ray),
+        }
+    }
+    #[must_use]
+    pub fn area(&self) -> f32 {
+        match self {
+            Self::Tri(t) => t.area(),
+            Self::Sphere(s) => s.area(),
+        }
+    }
+    // emitted power (scalar) if this primitive is a light
+    #[must_use]
+    pub fn power(&self) -> f32 {
+        crate::luminance(self.channel_power())
+    }
+    // emitted power of each colour channel if this primitive is a light, textured lights
+    // use their mean emission over the surface
+    #[must_use]
+    pub fn channel_power(&self) -> Vec3 {
+        let mats = unsafe { MATERIALS.get().as_ref_unchecked() };
+        let le = match (&mats[self.mat()], self) {
+            (Mat::Light(l), Self::Tri(t)) if l.is_textured() => t.mean_le(),
+            (Mat::Light(l), Self::Sphere(s)) if l.is_textured() => s.mean_le(),
+            (mat, _) => mat.le(Vec3::ZERO, Vec3::ZERO, Vec2::ZERO, true),
+        };
+        PI * self.area() * le
+    }
+}
+
+// mean of f over a stratified grid of points in the unit square
+#[must_use]
+pub fn mean_over_square(f: impl Fn([f32; 2]) -> Vec3) -> Vec3 {
+    const N: usize = 8;
+    let mut sum = Vec3::ZERO;
+    for i in 0..N * N {
+        let u = [(i % N) as f32 + 0.5, (i / N) as f32 + 0.5].map(|v| v / N as f32);
+        sum += f(u);
+    }
+    sum / (N * N) as f32
+}
@@ -89,16 +89,29 @@ impl<R: Rng> PssState<R> {
             self.last_large_idx = self.iteration;
         }
     }
+    // only samples modified this iteration are restored, the backups of the
+    // others belong to an earlier (accepted) iteration
     pub fn reject(&mut self) {
-        self.iteration -= 1;
         for sample in &mut self.state {
-            sample.restore();
+            if sample.modified_idx == self.iteration {
+                sample.restore();
+            }
         }
+        self.iteration -= 1;
+    }
+    // mutations from here on use rng, the current state is kept
+    pub fn reseed(&mut self, rng: R) {
+        self.rng = rng;
     }
     pub fn ensure_ready(&mut self) {
         if self.state_idx >= self.state.len() {
             assert_eq!(self.state_idx, self.state.len());
-            self.state.push(Sample::default());
+            // a new sample starts uniformly random, small mutations would
+            // otherwise keep it near 0
+            self.state.push(Sample {
+                value: self.rng.gen(),
+                ..Default::default()
+            });
         }
 
         let sample = &mut self.state[self.state_idx];
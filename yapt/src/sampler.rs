@@ -0,0 +1,185 @@
+use crate::prelude::*;
+use std::{ops::Range, sync::OnceLock};
+
+#[derive(clap::ValueEnum, Copy, Clone, Default, Debug, PartialEq)]
+pub enum Sampler {
+    // independent uniform numbers from Pcg64Mcg
+    #[default]
+    Random,
+    // scrambled low discrepancy sequence (see Sobol)
+    Sobol,
+}
+
+impl fmt::Display for Sampler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Random => "random",
+            Self::Sobol => "sobol",
+        };
+        write!(f, "{s}")
+    }
+}
+
+// 4D Sobol padded to any number of dimensions with owen scrambling & index shuffling
+// see Burley 2020 https://jcgt.org/published/0009/04/01/paper.pdf
+// every group of 4 dimensions draws from the sequence with its own shuffled index so
+// groups are decorrelated, while each dimension is still stratified over samples
+// index is the sample of the pixel and seed identifies the pixel, the dimension
+// counter advances with every number drawn so a new path must use start_path
+pub struct Sobol {
+    index: u32,
+    seed: u32,
+    dimension: u32,
+}
+
+impl Sobol {
+    #[must_use]
+    pub fn new(index: u32, seed: u32) -> Self {
+        Self {
+            index,
+            seed,
+            dimension: 0,
+        }
+    }
+    pub fn start_path(&mut self, index: u32) {
+        self.index = index;
+        self.dimension = 0;
+    }
+    #[must_use]
+    fn next_u32(&mut self) -> u32 {
+        let (group, dim) = (self.dimension / 4, self.dimension % 4);
+        self.dimension += 1;
+
+        let group_seed = hash(self.seed ^ hash(group));
+        let index = nested_uniform_scramble(self.index, group_seed);
+        nested_uniform_scramble(sobol(index, dim as usize), hash(group_seed ^ hash(dim + 1)))
+    }
+}
+
+impl MinRng for Sobol {
+    fn gen(&mut self) -> f32 {
+        // top 24 bits so the result can't round up to 1
+        (self.next_u32() >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+    }
+    fn gen_range(&mut self, range: Range<f32>) -> f32 {
+        (range.end - range.start) * self.gen() + range.start
+    }
+}
+
+// direction numbers of the first 4 dimensions (Joe & Kuo)
+#[must_use]
+fn directions() -> &'static [[u32; 32]; 4] {
+    static DIRECTIONS: OnceLock<[[u32; 32]; 4]> = OnceLock::new();
+    DIRECTIONS.get_or_init(|| {
+        let mut v = [[0; 32]; 4];
+        // the first dimension is van der corput
+        for (k, v) in v[0].iter_mut().enumerate() {
+            *v = 1 << (31 - k);
+        }
+        // (degree, coefficients, initial m) of each primitive polynomial
+        let polys: [(usize, u32, &[u32]); 3] = [(1, 0, &[1]), (2, 1, &[1, 3]), (3, 1, &[1, 3, 1])];
+        for (v, (s, a, m)) in v[1..].iter_mut().zip(polys) {
+            for k in 0..32 {
+                v[k] = if k < s {
+                    m[k] << (31 - k)
+                } else {
+                    let mut x = v[k - s] ^ (v[k - s] >> s);
+                    for l in 1..s {
+                        if (a >> (s - 1 - l)) & 1 == 1 {
+                            x ^= v[k - l];
+                        }
+                    }
+                    x
+                };
+            }
+        }
+        v
+    })
+}
+
+#[must_use]
+fn sobol(index: u32, dim: usize) -> u32 {
+    let v = &directions()[dim];
+    (0..32)
+        .filter(|bit| (index >> bit) & 1 == 1)
+        .fold(0, |x, bit| x ^ v[bit as usize])
+}
+
+#[must_use]
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+// base 2 owen scramble, each bit is flipped depending on the bits above it
+#[must_use]
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+// lowbias32 by Chris Wellons
+#[must_use]
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sobol_stratification() {
+        // unscrambled values are the well known sequence
+        assert_eq!(
+            (0..4).map(|i| sobol(i, 1)).collect::<Vec<_>>(),
+            [0, 1 << 31, 3 << 30, 1 << 30]
+        );
+
+        // the first 2 dimensions of every group of the first 2^k samples have one
+        // sample in each elementary interval, other dimensions are stratified alone
+        const K: u32 = 8;
+        for seed in [0, 7, 12345] {
+            let samples: Vec<Vec<f32>> = (0..1 << K)
+                .map(|i| {
+                    let mut rng = Sobol::new(i, seed);
+                    (0..8).map(|_| rng.gen()).collect()
+                })
+                .collect();
+            for (a, b) in [(0, 1), (4, 5)] {
+                for x_bits in 0..=K {
+                    let mut cells = vec![false; 1 << K];
+                    for s in &samples {
+                        let x = (s[a] * (1 << x_bits) as f32) as usize;
+                        let y = (s[b] * (1 << (K - x_bits)) as f32) as usize;
+                        let cell = &mut cells[(y << x_bits) | x];
+                        assert!(!*cell, "seed {seed} dims ({a}, {b}) {x_bits} bits");
+                        *cell = true;
+                    }
+                }
+            }
+            for d in 0..8 {
+                let mut strata = vec![false; 1 << K];
+                for s in &samples {
+                    strata[(s[d] * (1 << K) as f32) as usize] = true;
+                }
+                assert!(strata.iter().all(|&s| s), "seed {seed} dim {d}");
+            }
+        }
+
+        // start_path resets the dimension counter
+        let mut rng = Sobol::new(3, 1);
+        let first = rng.gen();
+        let _ = rng.gen();
+        rng.start_path(3);
+        assert_eq!(rng.gen(), first);
+    }
+}
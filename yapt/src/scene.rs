@@ -58,16 +58,65 @@ unsafe fn scene_car(_render_settings: &RenderSettings) -> Cam {
     unimplemented!();
 }
 
+// matte sphere on a (very large sphere) ground lit by a spherical light
 unsafe fn scene_sphere(render_settings: &RenderSettings) -> Cam {
-    todo!();
+    loader::add_texture("__default", Texture::Solid(Vec3::splat(0.5)));
+    loader::add_material(vec!["rest"], Mat::Matte(Matte::new(0)));
+    loader::add_material(vec!["light"], Mat::Light(Light::new(Vec3::splat(10.0))));
+    loader::add_sphere(Vec3::ZERO, 1.0, "rest");
+    loader::add_sphere(Vec3::new(0.0, 0.0, -1001.0), 1000.0, "rest");
+    loader::add_sphere(Vec3::new(2.0, -2.0, 4.0), 1.0, "light");
+    Cam::new(
+        Vec3::new(0.0, -6.0, 1.0),
+        Vec3::ZERO,
+        Vec3::Z,
+        45.0,
+        1.0,
+        0.0,
+        render_settings,
+    )
 }
 
+// matte (left) and glass (right) spheres side by side
 unsafe fn scene_sphere_left_right(render_settings: &RenderSettings) -> Cam {
-    todo!()
+    loader::add_texture("__default", Texture::Solid(Vec3::splat(0.5)));
+    loader::add_material(vec!["rest"], Mat::Matte(Matte::new(0)));
+    loader::add_material(
+        vec!["glass"],
+        Mat::Refractive(SmoothDielectric::new(1.5, Vec3::ONE)),
+    );
+    loader::add_material(vec!["light"], Mat::Light(Light::new(Vec3::splat(10.0))));
+    loader::add_sphere(Vec3::new(-1.1, 0.0, 0.0), 1.0, "rest");
+    loader::add_sphere(Vec3::new(1.1, 0.0, 0.0), 1.0, "glass");
+    loader::add_sphere(Vec3::new(0.0, 0.0, -1001.0), 1000.0, "rest");
+    loader::add_sphere(Vec3::new(0.0, -2.0, 4.0), 1.0, "light");
+    Cam::new(
+        Vec3::new(0.0, -7.0, 1.0),
+        Vec3::ZERO,
+        Vec3::Z,
+        45.0,
+        1.0,
+        0.0,
+        render_settings,
+    )
 }
 
+// white sphere in a uniform white environment, an energy conserving material without
+// any absorption should disappear
 unsafe fn scene_furnace_test(render_settings: &RenderSettings) -> Cam {
-    todo!()
+    loader::add_texture("__default", Texture::Solid(Vec3::ONE));
+    loader::add_material(vec!["rest"], Mat::Matte(Matte::new(0)));
+    loader::add_sphere(Vec3::ZERO, 1.0, "rest");
+    *ENVMAP.get().as_mut_unchecked() = EnvMap::Solid(Vec3::ONE);
+    Cam::new(
+        Vec3::new(0.0, -4.0, 0.0),
+        Vec3::ZERO,
+        Vec3::Z,
+        45.0,
+        1.0,
+        0.0,
+        render_settings,
+    )
 }
 
 unsafe fn scene_room(render_settings: &RenderSettings) -> Cam {
@@ -75,7 +124,8 @@ unsafe fn scene_room(render_settings: &RenderSettings) -> Cam {
     loader::add_material(vec!["rest"], Mat::Matte(Matte::new(0)));
     loader::add_material(
         vec!["light", "grey_and_white_room:lambert2SG_light"],
-        Mat::Light(Light::new(Vec3::ONE * 5.0)),
+        // like every material defined by the scene it's double sided
+        Mat::Light(Light::new(Vec3::ONE * 5.0).with_two_sided(true)),
     );
     loader::load_gltf("res/room.glb", 1.0, Vec3::ZERO, render_settings);
     Cam::new_rot(
@@ -92,7 +142,7 @@ unsafe fn scene_sponza(render_settings: &RenderSettings) -> Cam {
     loader::add_material(vec!["rest"], Mat::Matte(Matte::new(0)));
     loader::add_material(
         vec!["light", "Material.001"],
-        Mat::Light(Light::new(Vec3::ONE * 5.0)),
+        Mat::Light(Light::new(Vec3::ONE * 5.0).with_two_sided(true)),
     );
     let cams = loader::load_gltf("res/sponza.glb", 1.0, Vec3::ZERO, render_settings);
     cams.into_iter().nth(0).unwrap_or_else(|| {
@@ -119,3 +169,152 @@ unsafe fn scene_sponza_ivy(render_settings: &RenderSettings) -> Cam {
         )
     })
 }
+
+// small scene for tests that need the global scene state, it is built once and
+// shared between tests so it must not be modified
+// two 10x10 matte quads facing +Z centred on the Z axis at z = -2 and z = -5
+// and a single sided one centred on (20, 0, -2), plus 1x1 red and blue lights (of
+// equal power) centred on (-1.5, 0, -1.5) and (1.5, 0, -1.5), a 1x1 holdout centred on
+// (-20, 0, -1) in front of another 10x10 matte quad at (-20, 0, -2), the red and blue
+// lights are two sided
+// facing away from 10x10 matte quads at (40, 0, -2) and (60, 0, -2) are 1x1 green lights
+// at (40, 0, -1.5) (single sided, so the quad under it is dark) and (60, 0, -1.5) (two
+// sided)
+// a copy of the two sided green light and its quad at x = 80 has an invisible 10x10 quad
+// between them at (80, 0, -1.75), the same light and matte quad are at (100, 0, -1.5) and (100, 0, -2) in a
+// box of fog from (98, -2, -2) to (102, 2, -1), the only fog in the scene
+// copies of the red and blue lights are either side of a 10x10 matte quad at (120, 0, -2)
+// with the unlinked material, which the red lights are excluded from lighting
+// the z = -2 quad on the Z axis and the red and blue lights are meshes named floor,
+// red_light and blue_light
+// textures: 0 = grey, 1 = red, 2 = gold (f0), 3 = ior from 1.2 to 2 along u
+// also lit by a uniform grey environment (sampled by NEE 30% of the time), the camera is 16x16 at (1, 0.5, 0) looking down -Z
+// and CAMERAS has three 16x16 cameras looking down -Z, one at the origin, one at (-20, 0, 0)
+// and one at the origin with a 0.5 aperture
+#[cfg(test)]
+pub fn test_scene() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| unsafe {
+        loader::add_texture("__default", Texture::Solid(Vec3::splat(0.5)));
+        loader::add_texture("red", Texture::Solid(Vec3::new(1.0, 0.0, 0.0)));
+        loader::add_texture("gold", Texture::Solid(Vec3::new(0.9, 0.6, 0.3)));
+        // ior increasing from 1.2 to 2 along u
+        loader::add_texture(
+            "ior_gradient",
+            Texture::Image(
+                Image::from_rgbaf32(2, 1, vec![1.2, 1.2, 1.2, 1.0, 2.0, 2.0, 2.0, 1.0])
+                    .with_wrap([WrapMode::Clamp; 2]),
+            ),
+        );
+        loader::add_material(vec!["rest"], Mat::Matte(Matte::new(0)));
+        loader::add_material(vec!["single_sided"], Mat::Matte(Matte::new(0)));
+        loader::add_material(
+            vec!["red_light"],
+            Mat::Light(Light::new(Vec3::new(20.0, 0.0, 0.0)).with_two_sided(true)),
+        );
+        loader::add_material(
+            vec!["blue_light"],
+            Mat::Light(Light::new(Vec3::new(0.0, 0.0, 20.0)).with_two_sided(true)),
+        );
+        loader::add_material(vec!["holdout"], Mat::Holdout);
+        loader::add_material(
+            vec!["back_light"],
+            Mat::Light(Light::new(Vec3::new(0.0, 20.0, 0.0))),
+        );
+        loader::add_material(
+            vec!["two_sided_light"],
+            Mat::Light(Light::new(Vec3::new(0.0, 20.0, 0.0)).with_two_sided(true)),
+        );
+        loader::add_material(vec!["invisible"], Mat::Invisible);
+        loader::add_material(vec!["unlinked"], Mat::Matte(Matte::new(0)));
+        *SINGLE_SIDED.get().as_mut_unchecked() =
+            vec![false, true, false, false, false, true, false, false, false];
+        let links = crate::light_link::LightLinks::from_lists(
+            &[],
+            &["unlinked=red_light".to_owned()],
+            MATERIALS.get().as_ref_unchecked().len(),
+            loader::material_idx,
+        );
+        *crate::LIGHT_LINKS.get().as_mut_unchecked() = links.unwrap();
+
+        let (verts, norms, uvs, tris) = (
+            VERTICES.get().as_mut_unchecked(),
+            NORMALS.get().as_mut_unchecked(),
+            UVS.get().as_mut_unchecked(),
+            TRIANGLES.get().as_mut_unchecked(),
+        );
+        let quads = [
+            (-2.0 * Vec3::Z, 10.0, 0, Some("floor")),
+            (-5.0 * Vec3::Z, 10.0, 0, None),
+            (Vec3::new(20.0, 0.0, -2.0), 10.0, 1, None),
+            (Vec3::new(-1.5, 0.0, -1.5), 1.0, 2, Some("red_light")),
+            (Vec3::new(1.5, 0.0, -1.5), 1.0, 3, Some("blue_light")),
+            (Vec3::new(-20.0, 0.0, -1.0), 1.0, 4, None),
+            (Vec3::new(-20.0, 0.0, -2.0), 10.0, 0, None),
+            (Vec3::new(40.0, 0.0, -1.5), 1.0, 5, None),
+            (Vec3::new(40.0, 0.0, -2.0), 10.0, 0, None),
+            (Vec3::new(60.0, 0.0, -1.5), 1.0, 6, None),
+            (Vec3::new(60.0, 0.0, -2.0), 10.0, 0, None),
+            (Vec3::new(80.0, 0.0, -1.5), 1.0, 6, None),
+            (Vec3::new(80.0, 0.0, -1.75), 10.0, 7, None),
+            (Vec3::new(80.0, 0.0, -2.0), 10.0, 0, None),
+            (Vec3::new(100.0, 0.0, -1.5), 1.0, 6, None),
+            (Vec3::new(100.0, 0.0, -2.0), 10.0, 0, None),
+            (Vec3::new(118.5, 0.0, -1.5), 1.0, 2, None),
+            (Vec3::new(121.5, 0.0, -1.5), 1.0, 3, None),
+            (Vec3::new(120.0, 0.0, -2.0), 10.0, 8, None),
+        ];
+        for (centre, size, mat, name) in quads {
+            let o = verts.len();
+            verts.extend(
+                [(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)]
+                    .map(|(x, y)| centre + size * Vec3::new(x, y, 0.0)),
+            );
+            norms.extend([Vec3::Z; 4]);
+            uvs.extend([Vec2::ZERO; 4]);
+            for [a, b, c] in [[0, 1, 2], [0, 2, 3]] {
+                let idx = [o + a, o + b, o + c];
+                let mesh = name.map(loader::add_mesh_name);
+                tris.push(Primitive::Tri(Tri::new(idx, idx, idx, mat).with_mesh(mesh)));
+            }
+        }
+        *BVH.get().as_mut_unchecked() = Bvh::new(tris);
+        // after the BVH rearranges TRIANGLES
+        let samplables = SAMPLABLE.get().as_mut_unchecked();
+        for (i, tri) in tris.iter().enumerate() {
+            if let Mat::Light(_) = MATERIALS.get().as_ref_unchecked()[tri.mat()] {
+                samplables.push(i);
+            }
+        }
+        *ENVMAP.get().as_mut_unchecked() = EnvMap::Solid(Vec3::splat(0.5));
+        *ENV_SAMPLING_PROB.get().as_mut_unchecked() = 0.3;
+        let fog = crate::fog::Medium {
+            scattering: 0.5,
+            absorption: 0.2,
+            g: 0.3,
+        };
+        let region = bvh::aabb::Aabb::new(Vec3::new(98.0, -2.0, -2.0), Vec3::new(102.0, 2.0, -1.0));
+        *crate::FOG.get().as_mut_unchecked() =
+            crate::fog::Fog::new(crate::fog::Medium::default(), vec![(region, fog)]);
+
+        let rs = RenderSettings {
+            width: std::num::NonZeroU32::new(16).unwrap(),
+            height: std::num::NonZeroU32::new(16).unwrap(),
+            ..Default::default()
+        };
+        *CAM.get().as_mut_unchecked() = Cam::new(
+            Vec3::new(1.0, 0.5, 0.0),
+            Vec3::new(1.0, 0.5, -1.0),
+            Vec3::Y,
+            60.0,
+            1.0,
+            0.0,
+            &rs,
+        );
+        let cam = |origin| Cam::new(origin, origin - Vec3::Z, Vec3::Y, 60.0, 1.0, 0.0, &rs);
+        let mut lens = cam(Vec3::ZERO);
+        lens.aperture = 0.5;
+        *CAMERAS.get().as_mut_unchecked() =
+            vec![cam(Vec3::ZERO), cam(Vec3::new(-20.0, 0.0, 0.0)), lens];
+    });
+}
@@ -0,0 +1,230 @@
+use crate::{color::FromXyz, prelude::*};
+
+// angular radius of the sun
+const SUN_RADIUS: f32 = 0.004_65;
+// irradiance from the sun (at normal incidence) outside of the atmosphere
+const SUN_IRRADIANCE: f32 = 20.0;
+// the model is in kcd/m^2, this puts the zenith at around 1
+const SKY_SCALE: f32 = 1.0 / 30.0;
+// probability of sample_light picking the sun rather than the whole sphere
+const SUN_PROB: f32 = 0.5;
+
+// Preetham, Shirley & Smits 1999 analytic daylight https://doi.org/10.1145/311535.311545
+// with the sun as a small disk attenuated by rayleigh & aerosol scattering
+// directions below the horizon see the horizon
+#[derive(Debug)]
+pub struct Sky {
+    pub sun_dir: Vec3,
+    pub turbidity: f32,
+    // perez coefficients (A to E) of Y, x & y
+    perez: [[f32; 5]; 3],
+    // Y, x & y at the zenith divided by the perez function there
+    zenith: [f32; 3],
+    sun: Vec3,
+    from_xyz: FromXyz,
+}
+
+impl Sky {
+    // sun_dir is towards the sun, turbidity is the haziness of the atmosphere
+    #[must_use]
+    pub fn new(sun_dir: Vec3, turbidity: f32, primaries: Primaries) -> Self {
+        let t = turbidity;
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let sun_dir = sun_dir.normalised();
+        let theta_s = sun_dir.z.clamp(0.0, 1.0).acos();
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let chromaticity = |m: [[f32; 4]; 3]| {
+            let theta = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+            let row = |r: [f32; 4]| r.iter().zip(theta).map(|(a, b)| a * b).sum::<f32>();
+            t * t * row(m[0]) + t * row(m[1]) + row(m[2])
+        };
+        let zenith_x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_yc = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        let zenith = [zenith_y, zenith_x, zenith_yc];
+        let zenith = std::array::from_fn(|i| zenith[i] / perez_fn(perez[i], 1.0, theta_s));
+
+        // relative air mass (Kasten & Young 1989) and optical depths at ~680, 550 and
+        // 440nm, aerosols follow Angstrom's formula with beta from the turbidity
+        let air_mass =
+            1.0 / (sun_dir.z.max(0.0) + 0.50572 * (96.07995 - theta_s.to_degrees()).powf(-1.6364));
+        let beta = 0.04608 * t - 0.04586;
+        let depth = [0.68f32, 0.55, 0.44].map(|l| 0.008735 * l.powf(-4.08) + beta * l.powf(-1.3));
+        let transmittance = Vec3::new(depth[0], depth[1], depth[2]) * -air_mass;
+        let transmittance = Vec3::new(
+            transmittance.x.exp(),
+            transmittance.y.exp(),
+            transmittance.z.exp(),
+        );
+
+        Self {
+            sun_dir,
+            turbidity,
+            perez,
+            zenith,
+            sun: transmittance * SUN_IRRADIANCE / sun_solid_angle(),
+            from_xyz: FromXyz::new(primaries),
+        }
+    }
+    #[must_use]
+    fn in_sun(&self, dir: Vec3) -> bool {
+        dir.dot(self.sun_dir) >= SUN_RADIUS.cos()
+    }
+    #[must_use]
+    pub fn radiance(&self, dir: Vec3) -> Vec3 {
+        let sky = self.sky(dir);
+        if self.in_sun(dir) {
+            sky + self.sun
+        } else {
+            sky
+        }
+    }
+    #[must_use]
+    fn sky(&self, dir: Vec3) -> Vec3 {
+        let cos_theta = dir.z.max(0.0);
+        let gamma = dir.dot(self.sun_dir).clamp(-1.0, 1.0).acos();
+        let [y, x, yc] =
+            std::array::from_fn(|i| self.zenith[i] * perez_fn(self.perez[i], cos_theta, gamma));
+        let y = y * SKY_SCALE;
+        let xyz = Vec3::new(x / yc * y, y, (1.0 - x - yc) / yc * y);
+        self.from_xyz.apply(xyz).max_by_component(Vec3::ZERO)
+    }
+    // (dir, solid angle pdf) either within the sun or uniform over the sphere
+    #[must_use]
+    pub fn sample_light(&self, rng: &mut impl MinRng) -> (Vec3, f32) {
+        let dir = if rng.gen() < SUN_PROB {
+            let cos_theta = 1.0 - rng.gen() * one_minus_cos_sun();
+            let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let (sin_phi, cos_phi) = (TAU * rng.gen()).sin_cos();
+            let local = Vec3::from_spherical(sin_theta, cos_theta, sin_phi, cos_phi);
+            Coordinate::new_from_z(self.sun_dir).local_to_global(local)
+        } else {
+            let cos_theta = rng.gen_range(-1.0..1.0);
+            let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let (sin_phi, cos_phi) = (TAU * rng.gen()).sin_cos();
+            Vec3::from_spherical(sin_theta, cos_theta, sin_phi, cos_phi)
+        };
+        (dir, self.pdf(dir))
+    }
+    #[must_use]
+    pub fn pdf(&self, dir: Vec3) -> f32 {
+        let uniform = (1.0 - SUN_PROB) * 0.25 * FRAC_1_PI;
+        if self.in_sun(dir) {
+            uniform + SUN_PROB / sun_solid_angle()
+        } else {
+            uniform
+        }
+    }
+    // average over the sphere
+    #[must_use]
+    pub fn average_luminance(&self) -> f32 {
+        const THETA: usize = 64;
+        let mut sum = 0.0;
+        for i in 0..THETA {
+            let (sin_theta, cos_theta) = (PI * (i as f32 + 0.5) / THETA as f32).sin_cos();
+            for j in 0..2 * THETA {
+                let (sin_phi, cos_phi) = (PI * (j as f32 + 0.5) / THETA as f32).sin_cos();
+                let dir = Vec3::from_spherical(sin_theta, cos_theta, sin_phi, cos_phi);
+                sum += crate::luminance(self.sky(dir)) * sin_theta;
+            }
+        }
+        let d_omega = PI / THETA as f32 * PI / THETA as f32;
+        (sum * d_omega + crate::luminance(self.sun) * sun_solid_angle()) * 0.25 * FRAC_1_PI
+    }
+}
+
+// F(theta, gamma) with theta the angle from the zenith & gamma from the sun
+#[must_use]
+fn perez_fn([a, b, c, d, e]: [f32; 5], cos_theta: f32, gamma: f32) -> f32 {
+    // exp(b / cos_theta) -> 0 at the horizon as b < 0
+    let horizon = if cos_theta > 0.0 {
+        (b / cos_theta).exp()
+    } else {
+        0.0
+    };
+    (1.0 + a * horizon) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+// 1 - cos(SUN_RADIUS) without cancellation
+#[must_use]
+fn one_minus_cos_sun() -> f32 {
+    2.0 * (0.5 * SUN_RADIUS).sin().powi(2)
+}
+
+#[must_use]
+fn sun_solid_angle() -> f32 {
+    TAU * one_minus_cos_sun()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sky() {
+        let mut rng = rand::thread_rng();
+        let sun_dir = Vec3::new(0.3, 0.4, 0.6).normalised();
+        let sky = Sky::new(sun_dir, 3.0, Primaries::Rec2020);
+
+        // Y at the zenith is the model's zenith luminance & the sky is brighter near
+        // the sun than away from it, the sun is far brighter than the sky
+        let theta_s = sun_dir.z.acos();
+        let chi = (4.0 / 9.0 - 3.0 / 120.0) * (PI - 2.0 * theta_s);
+        let zenith = ((4.0453 * 3.0 - 4.9710) * chi.tan() - 0.2155 * 3.0 + 2.4192) * SKY_SCALE;
+        let lum = |dir: Vec3| crate::luminance(sky.radiance(dir.normalised()));
+        assert!((lum(Vec3::Z) / zenith - 1.0).abs() < 0.01);
+        assert!(lum(Vec3::new(0.3, 0.4, 0.5)) > lum(Vec3::new(-0.3, -0.4, 0.5)));
+        assert!(lum(sun_dir) > 1000.0 * lum(Vec3::Z));
+        // the sun is redder than the sky
+        let (sun, blue) = (sky.sun, sky.radiance(Vec3::Z));
+        assert!(sun.x / sun.z > blue.x / blue.z);
+
+        // the sampled estimate of the average luminance (including the sun) matches
+        let n = 200_000;
+        let mut estimate = 0.0;
+        let mut sun_samples = 0;
+        for _ in 0..n {
+            let (dir, pdf) = sky.sample_light(&mut rng);
+            assert_eq!(pdf, sky.pdf(dir));
+            estimate += lum(dir) / pdf / n as f32;
+            sun_samples += usize::from(sky.in_sun(dir));
+        }
+        let average = sky.average_luminance();
+        assert!(
+            (estimate * 0.25 * FRAC_1_PI / average - 1.0).abs() < 0.02,
+            "{estimate} {average}"
+        );
+        assert!((sun_samples as f32 / n as f32 - SUN_PROB).abs() < 0.01);
+    }
+}
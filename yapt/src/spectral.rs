@@ -0,0 +1,357 @@
+use crate::color::{inverse, mul_vec, FromXyz, Primaries};
+use crate::prelude::*;
+use std::sync::Mutex;
+
+// range of wavelengths (nm) sampled
+const LAMBDA_MIN: f32 = 380.0;
+const LAMBDA_RANGE: f32 = 350.0;
+// resolution of the sigmoid coefficient table along each axis
+const TABLE_RES: usize = 32;
+// wavelength step (nm) spectra are integrated with while fitting the table
+const FIT_STEP: f32 = 5.0;
+const FIT_ITERATIONS: usize = 15;
+// saturated colours a sigmoid can only approach (those on the spectral locus like
+// Rec2020's primaries, which come out slightly desaturated) have their coefficients capped
+const MAX_COEFFICIENT: f32 = 200.0;
+
+// coefficient tables are fit once per working space (see Spectral::new)
+static TABLES: Mutex<Vec<(Primaries, &'static CoefficientTable)>> = Mutex::new(Vec::new());
+
+// CIE 1931 2° colour matching functions (multi lobe fit from Wyman et al. 2013)
+#[must_use]
+pub fn cie_xyz(lambda: f32) -> Vec3 {
+    let g = |mu: f32, s1: f32, s2: f32| {
+        let t = (lambda - mu) / if lambda < mu { s1 } else { s2 };
+        (-0.5 * t * t).exp()
+    };
+    Vec3::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+// value at lambda of the spectrum a sigmoid of a quadratic in wavelength (Jakob & Hanika
+// 2019), smooth and always in [0, 1] so reflectances stay physical
+#[must_use]
+fn sigmoid_spectrum(c: [f32; 3], lambda: f32) -> f32 {
+    let x = (lambda - LAMBDA_MIN) / LAMBDA_RANGE;
+    let y = (c[0] * x + c[1]) * x + c[2];
+    0.5 + 0.5 * y / (1.0 + y * y).sqrt()
+}
+
+// sigmoid coefficients of colours in [0, 1], indexed by the largest channel, its value
+// (z) and the other two channels relative to it (x, y) like pbrt's RGBToSpectrumTable
+struct CoefficientTable {
+    // values of z, denser near black & white where the sigmoid changes fastest
+    z: [f32; TABLE_RES],
+    // [largest channel][z][y][x]
+    coefficients: Vec<[f32; 3]>,
+}
+
+impl CoefficientTable {
+    // cmf is each wavelength with its (RGB) weight, summing to one per channel
+    #[must_use]
+    fn fit(cmf: &[(f32, Vec3)]) -> Self {
+        let smoothstep = |x: f32| x * x * (3.0 - 2.0 * x);
+        let step = 1.0 / (TABLE_RES - 1) as f32;
+        let z = std::array::from_fn(|k| smoothstep(smoothstep(k as f32 * step)));
+        let mut coefficients = vec![[0.0; 3]; 3 * TABLE_RES.pow(3)];
+        for largest in 0..3 {
+            for j in 0..TABLE_RES {
+                for i in 0..TABLE_RES {
+                    let (x, y) = (i as f32 * step, j as f32 * step);
+                    let target = |k: usize| {
+                        let mut rgb = [0.0; 3];
+                        rgb[largest] = z[k];
+                        rgb[(largest + 1) % 3] = x * z[k];
+                        rgb[(largest + 2) % 3] = y * z[k];
+                        Vec3::new(rgb[0], rgb[1], rgb[2])
+                    };
+                    // neighbouring colours have similar coefficients so each fit starts
+                    // from the last, working out from a mid grey
+                    let start = TABLE_RES / 5;
+                    let mut c = [0.0; 3];
+                    for k in (start..TABLE_RES).chain((0..start).rev()) {
+                        if k + 1 == start {
+                            c = coefficients[Self::index(largest, start, j, i)];
+                        }
+                        c = Self::solve(cmf, target(k), c);
+                        coefficients[Self::index(largest, k, j, i)] = c;
+                    }
+                }
+            }
+        }
+        Self { z, coefficients }
+    }
+    #[must_use]
+    fn index(largest: usize, k: usize, j: usize, i: usize) -> usize {
+        ((largest * TABLE_RES + k) * TABLE_RES + j) * TABLE_RES + i
+    }
+    // gauss-newton iterations from c towards the sigmoid spectrum with the colour target
+    #[must_use]
+    fn solve(cmf: &[(f32, Vec3)], target: Vec3, mut c: [f32; 3]) -> [f32; 3] {
+        for _ in 0..FIT_ITERATIONS {
+            // colour of the spectrum & its derivatives with respect to each coefficient
+            let (mut rgb, mut jacobian) = (Vec3::ZERO, [Vec3::ZERO; 3]);
+            for &(lambda, weight) in cmf {
+                let x = (lambda - LAMBDA_MIN) / LAMBDA_RANGE;
+                let y = (c[0] * x + c[1]) * x + c[2];
+                let t = 1.0 + y * y;
+                rgb += weight * (0.5 + 0.5 * y / t.sqrt());
+                let ds = weight * 0.5 / (t * t.sqrt());
+                jacobian[0] += ds * x * x;
+                jacobian[1] += ds * x;
+                jacobian[2] += ds;
+            }
+            let r = rgb - target;
+            if r.abs().component_max() < 1e-6 {
+                break;
+            }
+            let j = [
+                jacobian.map(|d| d.x),
+                jacobian.map(|d| d.y),
+                jacobian.map(|d| d.z),
+            ];
+            let delta = mul_vec(inverse(j), [r.x, r.y, r.z]);
+            if !delta.iter().all(|d| d.is_finite()) {
+                break;
+            }
+            for (c, d) in c.iter_mut().zip(delta) {
+                *c -= d;
+            }
+            let max = c.iter().fold(0.0f32, |m, c| m.max(c.abs()));
+            if max > MAX_COEFFICIENT {
+                c = c.map(|c| c * MAX_COEFFICIENT / max);
+            }
+        }
+        c
+    }
+    // trilinear interpolation of the coefficients of a colour in [0, 1]
+    #[must_use]
+    fn coefficients(&self, rgb: Vec3) -> [f32; 3] {
+        let rgb = [rgb.x, rgb.y, rgb.z].map(|v| v.clamp(0.0, 1.0));
+        let largest = if rgb[0] >= rgb[1] && rgb[0] >= rgb[2] {
+            0
+        } else if rgb[1] >= rgb[2] {
+            1
+        } else {
+            2
+        };
+        let z = rgb[largest];
+        let (x, y) = (rgb[(largest + 1) % 3] / z, rgb[(largest + 2) % 3] / z);
+
+        let cell = |v: f32| {
+            let v = v * (TABLE_RES - 1) as f32;
+            let i = (v as usize).min(TABLE_RES - 2);
+            (i, v - i as f32)
+        };
+        let ((i, tx), (j, ty)) = (cell(x), cell(y));
+        let k = self
+            .z
+            .partition_point(|&v| v <= z)
+            .saturating_sub(1)
+            .min(TABLE_RES - 2);
+        let tz = (z - self.z[k]) / (self.z[k + 1] - self.z[k]);
+
+        let mut c = [0.0; 3];
+        for (dk, wz) in [(0, 1.0 - tz), (1, tz)] {
+            for (dj, wy) in [(0, 1.0 - ty), (1, ty)] {
+                for (di, wx) in [(0, 1.0 - tx), (1, tx)] {
+                    let corner = self.coefficients[Self::index(largest, k + dk, j + dj, i + di)];
+                    for (c, v) in c.iter_mut().zip(corner) {
+                        *c += wz * wy * wx * v;
+                    }
+                }
+            }
+        }
+        c
+    }
+}
+
+// conversion between RGB in the working space and spectra
+#[derive(Copy, Clone)]
+pub struct Spectral {
+    from_xyz: FromXyz,
+    // per channel scale of the colour matching functions (in RGB) so a constant spectrum
+    // of one is (1, 1, 1)
+    norm: Vec3,
+    table: &'static CoefficientTable,
+}
+
+impl Spectral {
+    #[must_use]
+    pub fn new(working: Primaries) -> Self {
+        let from_xyz = FromXyz::new(working);
+        let cmf = |lambda| from_xyz.apply(cie_xyz(lambda));
+        // midpoint rule with 1nm steps
+        let norm = Vec3::ONE
+            / (0..LAMBDA_RANGE as u32)
+                .map(|i| cmf(LAMBDA_MIN + i as f32 + 0.5))
+                .fold(Vec3::ZERO, |a, b| a + b);
+
+        let mut tables = TABLES.lock().unwrap();
+        let table = match tables.iter().find(|(p, _)| *p == working) {
+            Some((_, table)) => *table,
+            None => {
+                let lambdas: Vec<f32> = (0..(LAMBDA_RANGE / FIT_STEP) as u32)
+                    .map(|i| LAMBDA_MIN + (i as f32 + 0.5) * FIT_STEP)
+                    .collect();
+                let sum = lambdas
+                    .iter()
+                    .map(|&l| cmf(l))
+                    .fold(Vec3::ZERO, |a, b| a + b);
+                let weights: Vec<_> = lambdas.iter().map(|&l| (l, cmf(l) / sum)).collect();
+                let table = &*Box::leak(Box::new(CoefficientTable::fit(&weights)));
+                tables.push((working, table));
+                table
+            }
+        };
+        Self {
+            from_xyz,
+            norm,
+            table,
+        }
+    }
+    // hero wavelength sampling, u picks the first and the others are evenly spaced
+    #[must_use]
+    pub fn sample(&self, u: f32) -> Wavelengths {
+        let lambda: [f32; 3] =
+            std::array::from_fn(|i| LAMBDA_MIN + (u + i as f32 / 3.0).fract() * LAMBDA_RANGE);
+        // monte carlo estimate of the integral against the colour matching functions
+        let to_rgb =
+            lambda.map(|l| self.from_xyz.apply(cie_xyz(l)) * self.norm * LAMBDA_RANGE / 3.0);
+        Wavelengths {
+            lambda,
+            to_rgb,
+            table: self.table,
+        }
+    }
+}
+
+// wavelengths carried by a path, each channel of the path's Vec3s is the value at
+// one of them
+#[derive(Copy, Clone)]
+pub struct Wavelengths {
+    pub lambda: [f32; 3],
+    to_rgb: [Vec3; 3],
+    table: &'static CoefficientTable,
+}
+
+impl Wavelengths {
+    // smooth spectrum of a reflectance in [0, 1] at these wavelengths
+    #[must_use]
+    pub fn reflectance(&self, rgb: Vec3) -> Vec3 {
+        if rgb.component_max() <= 0.0 {
+            return Vec3::ZERO;
+        }
+        let c = self.table.coefficients(rgb);
+        let [x, y, z] = self.lambda.map(|l| sigmoid_spectrum(c, l));
+        Vec3::new(x, y, z)
+    }
+    // path radiance at the wavelengths back to RGB
+    #[must_use]
+    pub fn to_rgb(&self, v: Vec3) -> Vec3 {
+        self.to_rgb[0] * v.x + self.to_rgb[1] * v.y + self.to_rgb[2] * v.z
+    }
+}
+
+// what the channels of a path's Vec3s are, passed along the path so colours (textures,
+// lights etc) entering it are converted to match
+#[derive(Copy, Clone, Default)]
+pub enum Lambda {
+    // RGB in the working space
+    #[default]
+    Rgb,
+    Spectral(Wavelengths),
+}
+
+impl Lambda {
+    // reflectance (albedo, transmittance etc) entering a path, values over one are
+    // treated like an illuminant
+    #[must_use]
+    pub fn reflectance(&self, rgb: Vec3) -> Vec3 {
+        match self {
+            Self::Spectral(w) if rgb.component_max() <= 1.0 => w.reflectance(rgb),
+            Self::Spectral(_) => self.illuminant(rgb),
+            Self::Rgb => rgb,
+        }
+    }
+    // radiance entering a path, unbounded so the colour is scaled into the table (this
+    // is linear in the brightness of rgb so can be applied before or after a pdf)
+    #[must_use]
+    pub fn illuminant(&self, rgb: Vec3) -> Vec3 {
+        match self {
+            Self::Spectral(w) => {
+                let scale = 2.0 * rgb.component_max();
+                if scale > 0.0 {
+                    w.reflectance(rgb / scale) * scale
+                } else {
+                    Vec3::ZERO
+                }
+            }
+            Self::Rgb => rgb,
+        }
+    }
+    // path radiance back to RGB
+    #[must_use]
+    pub fn to_rgb(&self, v: Vec3) -> Vec3 {
+        match self {
+            Self::Spectral(w) => w.to_rgb(v),
+            Self::Rgb => v,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let spectral = Spectral::new(Primaries::Rec2020);
+        let colours = [
+            Vec3::new(0.8, 0.3, 0.1),
+            Vec3::new(0.05, 0.2, 0.9),
+            Vec3::new(0.9, 0.9, 0.02),
+        ];
+        let n = 3000;
+        let mut white = Vec3::ZERO;
+        let (mut rgb, mut product) = ([Vec3::ZERO; 3], [Vec3::ZERO; 3]);
+        for i in 0..n {
+            let w = Lambda::Spectral(spectral.sample((i as f32 + 0.5) / n as f32));
+            // constant colours are constant spectra
+            let grey = w.reflectance(Vec3::splat(0.5));
+            assert!(
+                (grey - Vec3::splat(0.5)).abs().component_max() < 1e-3,
+                "{grey}"
+            );
+            white += w.to_rgb(w.reflectance(Vec3::ONE));
+            for (j, &colour) in colours.iter().enumerate() {
+                let s = w.reflectance(colour);
+                // saturated colours don't go negative (or over one)
+                assert!(s.component_min() >= 0.0 && s.component_max() <= 1.0, "{s}");
+                rgb[j] += w.to_rgb(s);
+                product[j] += w.to_rgb(s * s);
+            }
+        }
+        let white = white / n as f32;
+        assert!((white - Vec3::ONE).abs().component_max() < 1e-2, "{white}");
+        for (j, colour) in colours.into_iter().enumerate() {
+            let (rgb, product) = (rgb[j] / n as f32, product[j] / n as f32);
+            assert!(
+                (rgb - colour).abs().component_max() < 1e-2,
+                "{colour}: {rgb}"
+            );
+            // light bouncing twice off the colour isn't quite colour² like with RGB
+            assert!((product - colour * colour).abs().component_max() > 1e-3);
+        }
+        assert_eq!(Lambda::Rgb.reflectance(colours[0]), colours[0]);
+
+        // radiance keeps its brightness
+        let le = Vec3::new(40.0, 10.0, 5.0);
+        let w = Lambda::Spectral(spectral.sample(0.3));
+        let scaled = w.illuminant(le * 0.5) * 2.0 - w.illuminant(le);
+        assert!(scaled.abs().component_max() < 1e-3);
+    }
+}
@@ -0,0 +1,290 @@
+use crate::prelude::*;
+use bvh::aabb::{Aabb, Aabound};
+
+#[derive(Debug, new, PartialEq)]
+pub struct Sphere {
+    pub centre: Vec3,
+    pub radius: f32,
+    pub mat: usize,
+}
+
+impl Aabound for Sphere {
+    fn aabb(&self) -> Aabb {
+        let r = Vec3::splat(self.radius * (1.0 + 1e-5));
+        Aabb::new(self.centre - r, self.centre + r)
+    }
+}
+
+impl Sphere {
+    #[must_use]
+    pub fn intersect(&self, ray: &Ray, rng: &mut impl MinRng) -> Intersection {
+        let mats = unsafe { MATERIALS.get().as_ref_unchecked() };
+
+        // ray.dir is normalised, b is half the usual b
+        let oc = ray.origin - self.centre;
+        let b = oc.dot(ray.dir);
+        let c = oc.mag_sq() - self.radius * self.radius;
+        let discriminant = b * b - c;
+        if discriminant < 0.0 {
+            return Intersection::NONE;
+        }
+        let sqrt_d = discriminant.sqrt();
+
+        for t in [-b - sqrt_d, -b + sqrt_d] {
+            if t <= crate::T_MIN {
+                continue;
+            }
+            let point = ray.origin + t * ray.dir;
+            let mut normal = (point - self.centre) / self.radius;
+            let uv = Self::uv(normal);
+            if !mats[self.mat].uv_intersect(uv, rng) {
+                continue;
+            }
+
+            let out = normal.dot(ray.dir) < 0.0;
+            if !out {
+                let single_sided = unsafe { SINGLE_SIDED.get().as_ref_unchecked() };
+                if single_sided.get(self.mat).copied().unwrap_or(false) {
+                    continue;
+                }
+                normal = -normal;
+            }
+            return Intersection::new(t, uv, point, normal, out, self.mat, 0);
+        }
+        Intersection::NONE
+    }
+    // longitude & latitude of the outward normal (Z up like the scenes)
+    #[must_use]
+    fn uv(normal: Vec3) -> Vec2 {
+        let u = 0.5 + normal.y.atan2(normal.x) * 0.5 * FRAC_1_PI;
+        let v = normal.z.clamp(-1.0, 1.0).acos() * FRAC_1_PI;
+        Vec2::new(u, v)
+    }
+    // from outside the cone of directions towards the sphere is sampled uniformly,
+    // from inside a uniform point on the surface
+    #[must_use]
+    pub fn sample_ray(&self, sect: &Intersection, rng: &mut impl MinRng) -> (Ray, Vec3) {
+        let mats = unsafe { MATERIALS.get().as_ref_unchecked() };
+        let origin = sect.shadow_origin();
+        let to_centre = self.centre - origin;
+
+        let (dir, point) = match self.cos_theta_max(origin) {
+            Some(cos_theta_max) => {
+                let cos_theta = 1.0 - rng.gen() * (1.0 - cos_theta_max);
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let (sin_phi, cos_phi) = (TAU * rng.gen()).sin_cos();
+                let local = Vec3::from_spherical(sin_theta, cos_theta, sin_phi, cos_phi);
+                let dir = crate::coord::Coordinate::new_from_z(to_centre.normalised())
+                    .local_to_global(local);
+                // nearest point on the sphere along dir
+                let along = to_centre.dot(dir);
+                let half_chord = (self.radius * self.radius - (to_centre.mag_sq() - along * along))
+                    .max(0.0)
+                    .sqrt();
+                (dir, origin + (along - half_chord) * dir)
+            }
+            None => {
+                let z = rng.gen_range(-1.0..1.0);
+                let r = (1.0 - z * z).max(0.0).sqrt();
+                let (sin_phi, cos_phi) = (TAU * rng.gen()).sin_cos();
+                let point = self.centre + self.radius * Vec3::new(r * cos_phi, r * sin_phi, z);
+                (point - origin, point)
+            }
+        };
+
+        let ray = Ray::new(origin, dir);
+        let uv = Self::uv((point - self.centre) / self.radius);
+        // the inside of the sphere is its back
+        let front = dir.dot(point - self.centre) < 0.0;
+        let le = mats[self.mat].le(point, dir, uv, front);
+        (ray, le)
+    }
+    // emitted radiance averaged over the surface
+    #[must_use]
+    pub fn mean_le(&self) -> Vec3 {
+        let mats = unsafe { MATERIALS.get().as_ref_unchecked() };
+        let mat = &mats[self.mat];
+        crate::primitive::mean_over_square(|u| {
+            let z = 1.0 - 2.0 * u[0];
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            let (sin_phi, cos_phi) = (TAU * u[1]).sin_cos();
+            let uv = Self::uv(Vec3::new(r * cos_phi, r * sin_phi, z));
+            mat.le(Vec3::ZERO, Vec3::ZERO, uv, true)
+        })
+    }
+    // solid angle pdf of sample_ray producing ray which hit the sphere at sect
+    #[must_use]
+    pub fn pdf(&self, sect: &Intersection, ray: &Ray) -> f32 {
+        match self.cos_theta_max(ray.origin) {
+            Some(cos_theta_max) => 1.0 / (TAU * (1.0 - cos_theta_max)),
+            None => (sect.pos - ray.origin).mag_sq() / (sect.nor.dot(ray.dir).abs() * self.area()),
+        }
+    }
+    // cosine of the half angle of the cone the sphere subtends, None from inside
+    #[must_use]
+    fn cos_theta_max(&self, origin: Vec3) -> Option<f32> {
+        let dist_sq = (self.centre - origin).mag_sq();
+        let r_sq = self.radius * self.radius;
+        (dist_sq > r_sq).then(|| (1.0 - r_sq / dist_sq).max(0.0).sqrt())
+    }
+    #[must_use]
+    pub fn area(&self) -> f32 {
+        4.0 * PI * self.radius * self.radius
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sphere() {
+        crate::scene::test_scene();
+        let mut rng = rand::thread_rng();
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, 40.0), 2.0, 0);
+        assert_eq!(loader::parse_sphere("0, 0, 40, 2, rest"), Ok(sphere));
+        let sphere = loader::parse_sphere("0,0,40,2,rest").unwrap();
+        assert!(loader::parse_sphere("0,0,40,-2,rest").is_err());
+        assert!(loader::parse_sphere("0,0,40,2,missing").is_err());
+        assert!(loader::parse_sphere("0,0,40,2").is_err());
+
+        let sect = sphere.intersect(&Ray::new(Vec3::new(0.0, -5.0, 40.0), Vec3::Y), &mut rng);
+        assert!((sect.t - 3.0).abs() < 1e-5 && sect.out);
+        assert!((sect.nor + Vec3::Y).mag() < 1e-5);
+        assert!((sect.uv.x - 0.25).abs() < 1e-5 && (sect.uv.y - 0.5).abs() < 1e-5);
+
+        // the far side from inside with the normal facing the ray
+        let sect = sphere.intersect(&Ray::new(Vec3::new(0.0, 0.0, 40.0), Vec3::Z), &mut rng);
+        assert!((sect.t - 2.0).abs() < 1e-5 && !sect.out);
+        assert!((sect.nor + Vec3::Z).mag() < 1e-5 && sect.uv.y < 1e-5);
+
+        assert!(sphere
+            .intersect(&Ray::new(Vec3::new(0.0, -5.0, 43.0), Vec3::Y), &mut rng)
+            .is_none());
+        assert!(sphere
+            .intersect(&Ray::new(Vec3::new(0.0, -5.0, 40.0), -Vec3::Y), &mut rng)
+            .is_none());
+
+        // sampled rays from outside all hit and the pdf integrates to 1 over the cone
+        for origin in [Vec3::new(0.0, -5.0, 40.0), Vec3::new(0.0, 0.5, 40.0)] {
+            let origin = Intersection {
+                pos: origin,
+                nor: Vec3::Z,
+                ..Intersection::NONE
+            };
+            let n = 10_000;
+            let mut inv_pdf_sum = 0.0;
+            for _ in 0..n {
+                let (ray, _) = sphere.sample_ray(&origin, &mut rng);
+                let sect = sphere.intersect(&ray, &mut rng);
+                assert!(!sect.is_none());
+                inv_pdf_sum += 1.0 / sphere.pdf(&sect, &ray);
+            }
+            // mean of 1 / pdf estimates the solid angle subtended
+            let solid_angle = if origin.pos.y < 0.0 {
+                TAU * (1.0 - (1.0 - 4.0 / 25.0f32).sqrt())
+            } else {
+                4.0 * PI
+            };
+            assert!(
+                (inv_pdf_sum / n as f32 / solid_angle - 1.0).abs() < 0.05,
+                "{} {solid_angle}",
+                inv_pdf_sum / n as f32
+            );
+        }
+    }
+
+    #[test]
+    fn light_pdf() {
+        // sampled directions are histogrammed in (cos theta, phi) about the direction to the
+        // centre, which are equal solid angle bins, and compared against the pdf integrated
+        // over each bin
+        use rand::{rngs::StdRng, SeedableRng};
+        const BINS: usize = 16;
+        const SUB: usize = 8;
+        const SAMPLES: usize = 200_000;
+
+        crate::scene::test_scene();
+        let mut rng = StdRng::seed_from_u64(0);
+        let sphere = Sphere::new(
+            Vec3::new(0.0, 0.0, 40.0),
+            2.0,
+            loader::material_idx("red_light").unwrap(),
+        );
+
+        // near, far, inside off centre and at the centre
+        let positions = [
+            Vec3::new(0.0, -3.0, 40.0),
+            Vec3::new(4.0, 6.0, 45.0),
+            Vec3::new(0.5, 1.0, 40.0),
+            Vec3::new(0.0, 0.0, 40.0),
+        ];
+        for pos in positions {
+            let sect = Intersection {
+                pos,
+                nor: Vec3::Z,
+                ..Intersection::NONE
+            };
+            let origin = sect.shadow_origin();
+            // any axis will do from the centre
+            let axis = sphere.centre - origin;
+            let axis = if axis == Vec3::ZERO {
+                Vec3::Z
+            } else {
+                axis.normalised()
+            };
+            let coord = crate::coord::Coordinate::new_from_z(axis);
+            // bins cover the cone towards the sphere from outside, or every direction
+            let min_cos = sphere
+                .cos_theta_max(origin)
+                .map_or(-1.0, |c| c - 0.05 * (1.0 - c));
+            let bin = |dir: Vec3| {
+                let l = coord.global_to_local(dir);
+                let u = (l.z - min_cos) / (1.0 - min_cos);
+                let v = (l.y.atan2(l.x) + PI) / TAU;
+                let [u, v] = [u, v].map(|x| ((x * BINS as f32) as usize).min(BINS - 1));
+                v * BINS + u
+            };
+
+            let mut sampled = vec![0.0; BINS * BINS];
+            for _ in 0..SAMPLES {
+                let (ray, _) = sphere.sample_ray(&sect, &mut rng);
+                sampled[bin(ray.dir.normalised())] += 1.0 / SAMPLES as f32;
+            }
+
+            // midpoint rule over SUB x SUB points per bin
+            let mut expected = vec![0.0; BINS * BINS];
+            let step = [
+                (1.0 - min_cos) / (BINS * SUB) as f32,
+                TAU / (BINS * SUB) as f32,
+            ];
+            for j in 0..BINS * SUB {
+                for i in 0..BINS * SUB {
+                    let cos_theta = min_cos + (i as f32 + 0.5) * step[0];
+                    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                    let (sin_phi, cos_phi) = ((j as f32 + 0.5) * step[1] - PI).sin_cos();
+                    let dir = coord.local_to_global(Vec3::from_spherical(
+                        sin_theta, cos_theta, sin_phi, cos_phi,
+                    ));
+                    let ray = Ray::new(origin, dir);
+                    let hit = sphere.intersect(&ray, &mut rng);
+                    if hit.is_none() {
+                        continue;
+                    }
+                    // dw = dcos_theta dphi
+                    expected[bin(dir)] += sphere.pdf(&hit, &ray) * step[0] * step[1];
+                }
+            }
+
+            let total: f32 = expected.iter().sum();
+            let distance: f32 = sampled
+                .iter()
+                .zip(&expected)
+                .map(|(s, e)| (s - e).abs())
+                .sum::<f32>()
+                / 2.0;
+            assert!((total - 1.0).abs() < 0.02, "{pos} {total}");
+            assert!(distance < 0.03, "{pos} {distance}");
+        }
+    }
+}
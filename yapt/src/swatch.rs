@@ -0,0 +1,118 @@
+use crate::{light_link::LightLinks, light_sampler::LightSampler, prelude::*};
+
+// fixed studio setup: unit sphere at the origin viewed from -Y (Z up like the
+// scenes), lit by a key light (a small sun) and a dim uniform environment
+const CAM_POS: Vec3 = Vec3::new(0.0, -3.5, 0.0);
+const CAM_TAN_HALF_FOV: f32 = 0.36;
+const KEY_DIR: Vec3 = Vec3::new(-0.485071, -0.485071, 0.727607);
+const KEY_IRRADIANCE: Vec3 = Vec3::splat(3.0);
+const KEY_ANGULAR_RADIUS: f32 = 0.05;
+const ENV_RADIANCE: Vec3 = Vec3::splat(0.3);
+const MAX_DEPTH: u64 = 8;
+
+// renders a size x size RGBA8 (gamma corrected) preview of material mat (index into
+// MATERIALS) with NEEMIS
+// note: the swatch scene is its own World so this only uses the MATERIALS & TEXTURES
+// globals and is safe to call while a render is in progress
+#[must_use]
+pub fn render_swatch(mat: usize, size: usize, samples: u32, rng: &mut impl MinRng) -> Vec<u8> {
+    let mut prims = [Primitive::Sphere(Sphere::new(Vec3::ZERO, 1.0, mat))];
+    let bvh = Bvh::new(&mut prims);
+    let tlas = Bvh { nodes: vec![] };
+    let sampler = LightSampler::UNIFORM;
+    let envmap = EnvMap::Solid(ENV_RADIANCE);
+    let sun = Some(DirectionalLight::from_irradiance(
+        KEY_DIR,
+        KEY_IRRADIANCE,
+        KEY_ANGULAR_RADIUS,
+    ));
+    let links = LightLinks::NONE;
+    // the sphere is the only primitive and it isn't samplable, so NEE picks between the
+    // key light & the environment
+    let world = World {
+        prims: &prims,
+        bvh: &bvh,
+        meshes: &[],
+        instances: &[],
+        tlas: &tlas,
+        sampler: &sampler,
+        envmap: &envmap,
+        env_prob: 0.5,
+        sun: &sun,
+        sun_prob: 0.5,
+        links: &links,
+        fog: &None,
+        backplate: &None,
+    };
+    let limits = PathLimits {
+        max_depth: MAX_DEPTH,
+        ..Default::default()
+    };
+
+    let mut buf = Vec::with_capacity(4 * size * size);
+    for y in 0..size {
+        for x in 0..size {
+            let mut rgb = Vec3::ZERO;
+            for _ in 0..samples {
+                let u = 2.0 * (x as f32 + rng.gen()) / size as f32 - 1.0;
+                let v = 1.0 - 2.0 * (y as f32 + rng.gen()) / size as f32;
+                let dir = Vec3::Y + CAM_TAN_HALF_FOV * (u * Vec3::X + v * Vec3::Z);
+                // swatches are previews so are always RGB
+                rgb += NEEMIS::rgb_in(
+                    &world,
+                    Ray::new(CAM_POS, dir),
+                    rng,
+                    &[],
+                    None,
+                    None,
+                    limits,
+                    &Lambda::Rgb,
+                )
+                .0;
+            }
+            rgb /= samples.max(1) as f32;
+
+            let rgb = rgb.powf(1.0 / 2.2).saturate();
+
+            buf.extend([
+                (rgb.x * 255.0) as u8,
+                (rgb.y * 255.0) as u8,
+                (rgb.z * 255.0) as u8,
+                255,
+            ]);
+        }
+    }
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+
+    #[test]
+    fn swatch() {
+        let mut rng = thread_rng();
+        crate::scene::test_scene();
+
+        let size = 16;
+        let diffuse = render_swatch(loader::material_idx("rest").unwrap(), size, 4, &mut rng);
+        let light = render_swatch(
+            loader::material_idx("red_light").unwrap(),
+            size,
+            4,
+            &mut rng,
+        );
+
+        assert_eq!(diffuse.len(), 4 * size * size);
+        assert_eq!(light.len(), 4 * size * size);
+        assert_ne!(diffuse, light);
+
+        // the corners miss the sphere so only see the environment, the centre of the
+        // light swatch is the (saturated) light
+        let env = (ENV_RADIANCE.x.powf(1.0 / 2.2) * 255.0) as u8;
+        assert_eq!(diffuse[..4], [env, env, env, 255]);
+        let centre = 4 * (size / 2 * size + size / 2);
+        assert_eq!(light[centre..centre + 4], [255, 0, 0, 255]);
+    }
+}
@@ -4,6 +4,50 @@ use crate::prelude::*;
 pub enum Texture {
     Image(Image),
     Solid(Vec3),
+    // alternating squares of a & b, scale squares per unit of uv
+    Checker { a: Vec3, b: Vec3, scale: f32 },
+    // greyscale fractal value noise, scale lattice cells per unit of uv
+    Noise { scale: f32, octaves: u32 },
+}
+
+// how texels are reconstructed between texel centres
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum TextureFilter {
+    Nearest,
+    #[default]
+    Bilinear,
+}
+
+// how uvs outside of [0, 1] are mapped back onto the image (matching glTF samplers)
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum WrapMode {
+    #[default]
+    Repeat,
+    // extend the edge texels
+    Clamp,
+    // repeat with every other copy flipped
+    Mirror,
+}
+
+impl WrapMode {
+    // texel index in 0..size
+    #[must_use]
+    fn apply(self, i: isize, size: usize) -> usize {
+        let size = size as isize;
+        let i = match self {
+            Self::Repeat => i.rem_euclid(size),
+            Self::Clamp => i.clamp(0, size - 1),
+            Self::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i < size {
+                    i
+                } else {
+                    2 * size - 1 - i
+                }
+            }
+        };
+        i as usize
+    }
 }
 
 #[derive(Debug)]
@@ -11,6 +55,9 @@ pub struct Image {
     backing: Vec<[f32; 4]>,
     width: usize,
     height: usize,
+    pub filter: TextureFilter,
+    // for u & v
+    pub wrap: [WrapMode; 2],
 }
 
 impl Image {
@@ -20,34 +67,273 @@ impl Image {
             width,
             height,
             backing: unsafe { std::mem::transmute(data) },
+            filter: TextureFilter::default(),
+            wrap: [WrapMode::default(); 2],
+        }
+    }
+    #[must_use]
+    pub fn with_wrap(mut self, wrap: [WrapMode; 2]) -> Self {
+        self.wrap = wrap;
+        self
+    }
+    #[must_use]
+    pub fn with_filter(mut self, filter: TextureFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+    #[must_use]
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+    // texel at (possibly out of range) integer coordinates
+    #[must_use]
+    fn texel(&self, x: f32, y: f32) -> [f32; 4] {
+        let x = self.wrap[0].apply(x as isize, self.width);
+        let y = self.wrap[1].apply(y as isize, self.height);
+        self.backing[x + self.width * y]
+    }
+    #[must_use]
+    fn rgba(&self, uv: Vec2) -> [f32; 4] {
+        let (x, y) = (uv.x * self.width as f32, uv.y * self.height as f32);
+        match self.filter {
+            TextureFilter::Nearest => self.texel(x.floor(), y.floor()),
+            TextureFilter::Bilinear => {
+                // texel centres are at (i + 0.5) / size
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let texel = |x, y| self.texel(x, y);
+                let (a, b) = (texel(x0, y0), texel(x0 + 1.0, y0));
+                let (c, d) = (texel(x0, y0 + 1.0), texel(x0 + 1.0, y0 + 1.0));
+                std::array::from_fn(|i| {
+                    let top = a[i] + (b[i] - a[i]) * fx;
+                    let bottom = c[i] + (d[i] - c[i]) * fx;
+                    top + (bottom - top) * fy
+                })
+            }
+        }
+    }
+}
+
+// stands in for textures that don't exist so the render still completes
+pub const MISSING_TEXTURE: Texture = Texture::Solid(Vec3::new(1.0, 0.0, 1.0));
+
+// texture lookup that falls back to MISSING_TEXTURE for dangling indices
+// (these are reported once on load by missing_textures)
+#[must_use]
+pub fn texture(idx: usize) -> &'static Texture {
+    let texs = unsafe { TEXTURES.get().as_ref_unchecked() };
+    texs.get(idx).unwrap_or(&MISSING_TEXTURE)
+}
+
+// error messages for material texture indices that are out of range
+#[must_use]
+pub fn missing_textures(
+    mats: &[Mat],
+    num_textures: usize,
+    mat_name: impl Fn(usize) -> String,
+) -> Vec<String> {
+    let mut errors = Vec::new();
+    for (i, mat) in mats.iter().enumerate() {
+        for idx in mat.textures() {
+            if idx >= num_textures {
+                errors.push(format!(
+                    "Material {} references texture {idx} which does not exist ({num_textures} textures), rendering it magenta",
+                    mat_name(i)
+                ));
+            }
         }
     }
+    errors
 }
 
 impl Texture {
     pub fn uv_value(&self, uv: Vec2) -> Vec3 {
         match self {
             Self::Image(img) => {
-                let u = uv.x.fract().abs();
-                let v = uv.y.fract().abs();
-                let x = ((img.width - 1) as f32 * u) as usize;
-                let y = ((img.height - 1) as f32 * v) as usize;
-                let [r, g, b, _a] = img.backing[x + img.width * y];
+                let [r, g, b, _a] = img.rgba(uv);
                 Vec3::new(r, g, b)
             }
             Self::Solid(v) => *v,
+            Self::Checker { a, b, scale } => {
+                let (x, y) = ((uv.x * scale).floor(), (uv.y * scale).floor());
+                if (x + y).rem_euclid(2.0) < 1.0 {
+                    *a
+                } else {
+                    *b
+                }
+            }
+            Self::Noise { scale, octaves } => Vec3::splat(fractal_noise(uv * *scale, *octaves)),
         }
     }
     pub fn does_intersect(&self, uv: Vec2, rng: &mut impl MinRng) -> bool {
         match self {
-            Self::Image(img) => {
-                let u = uv.x.fract().abs();
-                let v = uv.y.fract().abs();
-                let x = ((img.width - 1) as f32 * u) as usize;
-                let y = ((img.height - 1) as f32 * v) as usize;
-                img.backing[x + img.width * y][3] >= rng.gen()
-            }
-            Self::Solid(_v) => false,
+            Self::Image(img) => img.rgba(uv)[3] >= rng.gen(),
+            Self::Solid(_) | Self::Checker { .. } | Self::Noise { .. } => false,
+        }
+    }
+    // procedural textures of the form checker:r,g,b:r,g,b[:scale] or noise[:scale[:octaves]]
+    // (None if spec isn't a procedural texture)
+    pub fn parse_procedural(spec: &str) -> Option<Result<Self, String>> {
+        let (kind, params) = spec.split_once(':').unwrap_or((spec, ""));
+        match kind {
+            "checker" | "noise" => Some(Self::procedural(kind, params).map_err(|()| {
+                format!("Invalid procedural texture {spec}, expected checker:r,g,b:r,g,b[:scale] or noise[:scale[:octaves]]")
+            })),
+            _ => None,
+        }
+    }
+    fn procedural(kind: &str, params: &str) -> Result<Self, ()> {
+        let params: Vec<&str> = params.split(':').filter(|s| !s.is_empty()).collect();
+        let float = |s: &str| s.trim().parse::<f32>().map_err(|_| ());
+        let rgb = |s: &str| {
+            let v = s.split(',').map(float).collect::<Result<Vec<_>, _>>()?;
+            let [r, g, b] = v[..] else {
+                return Err(());
+            };
+            Ok(Vec3::new(r, g, b))
+        };
+        match (kind, &params[..]) {
+            ("checker", [a, b, scale @ ..]) if scale.len() <= 1 => Ok(Self::Checker {
+                a: rgb(a)?,
+                b: rgb(b)?,
+                scale: scale.first().map_or(Ok(8.0), |s| float(s))?,
+            }),
+            ("noise", _) if params.len() <= 2 => Ok(Self::Noise {
+                scale: params.first().map_or(Ok(8.0), |s| float(s))?,
+                octaves: params
+                    .get(1)
+                    .map_or(Ok(4), |s| s.trim().parse().map_err(|_| ()))?,
+            }),
+            _ => Err(()),
         }
     }
 }
+
+// value in [0, 1) at an integer lattice point
+#[must_use]
+fn lattice_value(x: i32, y: i32) -> f32 {
+    let mut h = (x as u32).wrapping_mul(0x8da6_b343) ^ (y as u32).wrapping_mul(0xd816_3841);
+    h = (h ^ (h >> 15)).wrapping_mul(0x2c1b_3c6d);
+    h = (h ^ (h >> 12)).wrapping_mul(0x297a_2d39);
+    h ^= h >> 15;
+    (h >> 8) as f32 / (1 << 24) as f32
+}
+
+// smoothly interpolated lattice values summed over octaves (each double the frequency
+// & half the amplitude of the last) normalised to [0, 1)
+#[must_use]
+fn fractal_noise(p: Vec2, octaves: u32) -> f32 {
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (mut sum, mut total, mut amplitude, mut frequency) = (0.0, 0.0, 1.0, 1.0);
+    for _ in 0..octaves.max(1) {
+        let (x, y) = (p.x * frequency, p.y * frequency);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (smooth(x - x0), smooth(y - y0));
+        let (xi, yi) = (x0 as i32, y0 as i32);
+        let top = lattice_value(xi, yi) + (lattice_value(xi + 1, yi) - lattice_value(xi, yi)) * fx;
+        let bottom = lattice_value(xi, yi + 1)
+            + (lattice_value(xi + 1, yi + 1) - lattice_value(xi, yi + 1)) * fx;
+        sum += amplitude * (top + (bottom - top) * fy);
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    sum / total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bilinear() {
+        // black & white texels side by side
+        let data = vec![0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0];
+        let img = Texture::Image(Image::from_rgbaf32(2, 1, data.clone()));
+        let value = |tex: &Texture, u: f32| tex.uv_value(Vec2::new(u, 0.5)).x;
+
+        // exact at texel centres and linear between them
+        assert_eq!(value(&img, 0.25), 0.0);
+        assert_eq!(value(&img, 0.75), 1.0);
+        assert!((value(&img, 0.5) - 0.5).abs() < 1e-6);
+        assert!((value(&img, 0.375) - 0.25).abs() < 1e-6);
+
+        let nearest =
+            Texture::Image(Image::from_rgbaf32(2, 1, data).with_filter(TextureFilter::Nearest));
+        assert_eq!(value(&nearest, 0.375), 0.0);
+        assert_eq!(value(&nearest, 0.6), 1.0);
+    }
+
+    #[test]
+    fn wrap_modes() {
+        // 2x2 checker tiled 4 times across a quad
+        let data = [0.0, 1.0, 1.0, 0.0]
+            .iter()
+            .flat_map(|&v| [v, v, v, 1.0])
+            .collect::<Vec<_>>();
+        let cells = |wrap| {
+            let tex = Texture::Image(
+                Image::from_rgbaf32(2, 2, data.clone())
+                    .with_filter(TextureFilter::Nearest)
+                    .with_wrap([wrap; 2]),
+            );
+            // runs of the same value along a row times those along a column
+            let runs = |along_u: bool| {
+                let values: Vec<f32> = (0..64)
+                    .map(|i| {
+                        let t = 4.0 * (i as f32 + 0.5) / 64.0;
+                        let uv = if along_u {
+                            Vec2::new(t, 0.1)
+                        } else {
+                            Vec2::new(0.1, t)
+                        };
+                        tex.uv_value(uv).x
+                    })
+                    .collect();
+                1 + values.windows(2).filter(|w| w[0] != w[1]).count()
+            };
+            runs(true) * runs(false)
+        };
+        // 16 copies of the 4 cell checker
+        assert_eq!(cells(WrapMode::Repeat), 16 * 4);
+        // one copy with the edge texels stretched
+        assert_eq!(cells(WrapMode::Clamp), 4);
+        // flipped copies merge cells at their shared edges
+        assert_eq!(cells(WrapMode::Mirror), 5 * 5);
+
+        // negative uvs repeat rather than mirror
+        let tex = Texture::Image(
+            Image::from_rgbaf32(2, 2, data.clone()).with_filter(TextureFilter::Nearest),
+        );
+        let uv = Vec2::new(0.3, 0.3);
+        assert_eq!(tex.uv_value(uv - Vec2::ONE), tex.uv_value(uv));
+    }
+
+    #[test]
+    fn procedural() {
+        let parse = |spec| Texture::parse_procedural(spec).unwrap().unwrap();
+        let checker = parse("checker:1,0,0:0,0,1:4");
+        let value = |tex: &Texture, u, v| tex.uv_value(Vec2::new(u, v));
+        assert_eq!(value(&checker, 0.1, 0.1), Vec3::X);
+        assert_eq!(value(&checker, 0.3, 0.1), Vec3::Z);
+        assert_eq!(value(&checker, 0.3, 0.3), Vec3::X);
+        assert_eq!(value(&checker, -0.1, 0.1), Vec3::Z);
+
+        let noise = parse("noise:4:3");
+        let values: Vec<f32> = (0..400)
+            .map(|i| value(&noise, (i % 20) as f32 / 20.0, (i / 20) as f32 / 20.0).x)
+            .collect();
+        assert!(values.iter().all(|v| (0.0..1.0).contains(v)));
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        assert!((0.3..0.7).contains(&mean), "{mean}");
+        // continuous
+        let (a, b) = (value(&noise, 0.5, 0.5), value(&noise, 0.5001, 0.5));
+        assert!((a - b).mag() < 1e-2);
+
+        assert!(Texture::parse_procedural("image.png").is_none());
+        assert!(Texture::parse_procedural("checker:1,0,0").unwrap().is_err());
+        assert!(Texture::parse_procedural("noise:a").unwrap().is_err());
+        assert!(matches!(parse("noise"), Texture::Noise { octaves: 4, .. }));
+    }
+}
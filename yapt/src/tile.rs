@@ -0,0 +1,187 @@
+use crate::prelude::*;
+
+// a rectangle of pixels from a frame split into a grid of tiles for distributed
+// rendering, every pixel sample has its own rng (see work_handler::pixel_rng) so
+// stitching the tiles gives exactly the same image as rendering the whole frame
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub frame_width: u32,
+    pub frame_height: u32,
+}
+
+impl Tile {
+    // i/n where the frame is split into a grid of n = rows x cols tiles (with rows
+    // being the largest divisor of n that is <= sqrt(n)) numbered row by row
+    pub fn parse(spec: &str, frame_width: u32, frame_height: u32) -> Result<Self, String> {
+        let invalid = || format!("Invalid tile {spec}, expected i/n with i < n");
+        let (i, n) = spec.split_once('/').ok_or_else(invalid)?;
+        let (i, n): (u32, u32) = (
+            i.parse().map_err(|_| invalid())?,
+            n.parse().map_err(|_| invalid())?,
+        );
+        if i >= n {
+            return Err(invalid());
+        }
+
+        let rows = (1..=n)
+            .take_while(|r| r * r <= n)
+            .filter(|r| n % r == 0)
+            .max()
+            .unwrap();
+        let cols = n / rows;
+        if cols > frame_width || rows > frame_height {
+            return Err(format!(
+                "Cannot split a {frame_width}x{frame_height} frame into {n} tiles"
+            ));
+        }
+
+        // boundaries are spread evenly so tile sizes differ by at most a pixel
+        let edge =
+            |idx: u32, count: u32, size: u32| (size as u64 * idx as u64 / count as u64) as u32;
+        let (col, row) = (i % cols, i / cols);
+        let (x, y) = (edge(col, cols, frame_width), edge(row, rows, frame_height));
+        Ok(Self {
+            x,
+            y,
+            width: edge(col + 1, cols, frame_width) - x,
+            height: edge(row + 1, rows, frame_height) - y,
+            frame_width,
+            frame_height,
+        })
+    }
+    // the pixels x0..x1, y0..y1 of a frame (used for --region)
+    pub fn from_rect(rect: [u32; 4], frame_width: u32, frame_height: u32) -> Result<Self, String> {
+        let [x0, y0, x1, y1] = rect;
+        if x0 >= x1 || y0 >= y1 || x1 > frame_width || y1 > frame_height {
+            return Err(format!(
+                "Invalid region {rect:?}, expected x0 < x1 <= {frame_width} & y0 < y1 <= {frame_height}"
+            ));
+        }
+        Ok(Self {
+            x: x0,
+            y: y0,
+            width: x1 - x0,
+            height: y1 - y0,
+            frame_width,
+            frame_height,
+        })
+    }
+    #[must_use]
+    pub fn pixels(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+    // index in the frame of a pixel index within the tile
+    #[must_use]
+    pub fn frame_pixel(&self, i: u64) -> u64 {
+        let (x, y) = (i % self.width as u64, i / self.width as u64);
+        (self.y as u64 + y) * self.frame_width as u64 + self.x as u64 + x
+    }
+    // uv within the tile of pixel i offset by jitter (see Cam::get_jittered_ray)
+    #[must_use]
+    pub fn uv(&self, i: u64, jitter: [f32; 2]) -> [f32; 2] {
+        let (x, y) = (i % self.width as u64, i / self.width as u64);
+        [
+            (x as f32 + jitter[0]) / self.width as f32,
+            (y as f32 + jitter[1]) / self.height as f32,
+        ]
+    }
+    // sidecar so a tile's image can be placed in the frame when stitching
+    #[must_use]
+    fn metadata_path(path: &str) -> String {
+        format!("{path}.tile")
+    }
+    pub fn save_metadata(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let v = [
+            self.x,
+            self.y,
+            self.width,
+            self.height,
+            self.frame_width,
+            self.frame_height,
+        ];
+        std::fs::write(Self::metadata_path(path), format!("{v:?}"))?;
+        Ok(())
+    }
+    pub fn load_metadata(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let s = std::fs::read_to_string(Self::metadata_path(path))?;
+        let v = s
+            .trim()
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .split(',')
+            .map(|v| v.trim().parse())
+            .collect::<Result<Vec<u32>, _>>()?;
+        let [x, y, width, height, frame_width, frame_height] = v[..] else {
+            return Err(format!("{path} has invalid tile metadata").into());
+        };
+        Ok(Self {
+            x,
+            y,
+            width,
+            height,
+            frame_width,
+            frame_height,
+        })
+    }
+}
+
+// places tile images (row major, width x height of the tile) into a frame
+#[must_use]
+pub fn stitch(tiles: &[(Tile, &[Vec3])]) -> Vec<Vec3> {
+    let Some((first, _)) = tiles.first() else {
+        return Vec::new();
+    };
+    let mut frame = vec![Vec3::ZERO; first.frame_width as usize * first.frame_height as usize];
+    for (tile, pixels) in tiles {
+        for (i, &rgb) in pixels.iter().enumerate() {
+            frame[tile.frame_pixel(i as u64) as usize] = rgb;
+        }
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_grid() {
+        let tiles: Vec<_> = (0..6)
+            .map(|i| Tile::parse(&format!("{i}/6"), 101, 50).unwrap())
+            .collect();
+        // 2 rows x 3 cols covering every pixel once
+        assert_eq!((tiles[1].x, tiles[1].y), (33, 0));
+        assert_eq!((tiles[3].x, tiles[3].y), (0, 25));
+        let mut covered = vec![0; 101 * 50];
+        for tile in &tiles {
+            for i in 0..(tile.width * tile.height) as u64 {
+                covered[tile.frame_pixel(i) as usize] += 1;
+            }
+        }
+        assert!(covered.iter().all(|&v| v == 1));
+
+        assert!(Tile::parse("4/4", 8, 8).is_err());
+        assert!(Tile::parse("1", 8, 8).is_err());
+        assert_eq!(Tile::parse("0/1", 8, 8).unwrap().width, 8);
+
+        let path = std::env::temp_dir().join("yapt_tile_test.exr");
+        let path = path.to_str().unwrap();
+        tiles[4].save_metadata(path).unwrap();
+        assert_eq!(Tile::load_metadata(path).unwrap(), tiles[4]);
+    }
+
+    #[test]
+    fn region() {
+        let region = Tile::from_rect([2, 1, 5, 3], 8, 4).unwrap();
+        assert_eq!((region.width, region.height, region.pixels()), (3, 2, 6));
+        assert_eq!(region.frame_pixel(0), 10);
+        assert_eq!(region.frame_pixel(5), 3 * 8 - 4);
+
+        assert!(Tile::from_rect([2, 1, 2, 3], 8, 4).is_err());
+        assert!(Tile::from_rect([0, 0, 9, 4], 8, 4).is_err());
+    }
+}
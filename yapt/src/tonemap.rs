@@ -0,0 +1,80 @@
+use crate::prelude::*;
+
+// display transforms for previewing the (linear) accumulated image
+#[derive(clap::ValueEnum, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Tonemap {
+    // no transform (linear values shown as is)
+    Linear,
+    #[default]
+    Gamma,
+    Reinhard,
+    // Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+impl Tonemap {
+    pub const ALL: [Self; 4] = [Self::Linear, Self::Gamma, Self::Reinhard, Self::Aces];
+}
+
+impl fmt::Display for Tonemap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Linear => "linear",
+            Self::Gamma => "gamma",
+            Self::Reinhard => "reinhard",
+            Self::Aces => "aces",
+        };
+        write!(f, "{s}")
+    }
+}
+
+// applies exposure (in stops) and the operator's curve, output is still linear
+#[must_use]
+pub fn tone_curve(rgb: Vec3, exposure: f32, op: Tonemap) -> Vec3 {
+    let rgb = rgb * exposure.exp2();
+    match op {
+        Tonemap::Linear | Tonemap::Gamma => rgb,
+        Tonemap::Reinhard => rgb / (rgb + Vec3::ONE),
+        Tonemap::Aces => {
+            let num = rgb * (2.51 * rgb + 0.03);
+            let den = rgb * (2.43 * rgb + 0.59) + 0.14;
+            num / den
+        }
+    }
+}
+
+// maps linear radiance to display values in [0, 1] after applying exposure (in stops)
+#[must_use]
+pub fn tonemap(rgb: Vec3, exposure: f32, op: Tonemap) -> Vec3 {
+    let rgb = tone_curve(rgb, exposure, op);
+    if op == Tonemap::Linear {
+        return rgb.saturate();
+    }
+    rgb.powf(1.0 / 2.2).saturate()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operators() {
+        let hdr = Vec3::new(4.0, 0.5, 0.05);
+
+        let out = Tonemap::ALL.map(|op| tonemap(hdr, 0.0, op));
+        for (i, a) in out.iter().enumerate() {
+            assert!([a.x, a.y, a.z].iter().all(|v| (0.0..=1.0).contains(v)));
+            for b in &out[i + 1..] {
+                assert!(a != b);
+            }
+        }
+
+        // gamma without exposure matches the old preview
+        assert_eq!(out[1], hdr.powf(1.0 / 2.2).saturate());
+        // +1 stop is the same as doubling the input
+        assert_eq!(
+            tonemap(hdr, 1.0, Tonemap::Reinhard),
+            tonemap(2.0 * hdr, 0.0, Tonemap::Reinhard)
+        );
+    }
+}
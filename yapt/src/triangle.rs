@@ -7,6 +7,9 @@ pub struct Tri {
     nor: [usize; 3],
     uv: [usize; 3],
     pub mat: usize,
+    // named mesh the triangle belongs to (see MESH_NAMES)
+    #[new(default)]
+    mesh: Option<usize>,
 }
 
 impl Aabound for Tri {
@@ -48,23 +51,82 @@ impl Aabound for Tri {
 }
 
 impl Tri {
-    // see whoop 2013 https://jcgt.org/published/0002/01/05/paper.pdf
+    #[must_use]
+    pub fn with_mesh(mut self, mesh: Option<usize>) -> Self {
+        self.mesh = mesh;
+        self
+    }
     #[must_use]
     pub fn intersect(&self, ray: &Ray, rng: &mut impl MinRng) -> Intersection {
-        let verts = unsafe { VERTICES.get().as_ref_unchecked() };
         let norms = unsafe { NORMALS.get().as_ref_unchecked() };
         let mats = unsafe { MATERIALS.get().as_ref_unchecked() };
-        let uvs = unsafe { UVS.get().as_ref_unchecked() };
 
-        let v0 = verts[self.pos[0]];
-        let v1 = verts[self.pos[1]];
-        let v2 = verts[self.pos[2]];
+        let Some((t, [b0, b1, b2])) = self.hit(ray) else {
+            return Intersection::NONE;
+        };
         let n0 = norms[self.nor[0]];
         let n1 = norms[self.nor[1]];
         let n2 = norms[self.nor[2]];
-        let uv0 = uvs[self.uv[0]];
-        let uv1 = uvs[self.uv[1]];
-        let uv2 = uvs[self.uv[2]];
+
+        let uv = self.uv_of([b0, b1, b2]);
+
+        if !mats[self.mat].uv_intersect(uv, rng) {
+            return Intersection::NONE;
+        }
+
+        let mut normal = self.front_normal([b0, b1, b2]);
+
+        let out = normal.dot(ray.dir) < 0.0;
+        if !out {
+            if self.is_single_sided() {
+                return Intersection::NONE;
+            }
+            normal = -normal;
+        }
+
+        let verts = unsafe { VERTICES.get().as_ref_unchecked() };
+        let point = b0 * verts[self.pos[0]] + b1 * verts[self.pos[1]] + b2 * verts[self.pos[2]];
+
+        let normal_maps = unsafe { NORMAL_MAPS.get().as_ref_unchecked() };
+        if let Some((tex, scale)) = normal_maps.get(self.mat).copied().flatten() {
+            let shading = (b0 * n0 + b1 * n1 + b2 * n2).normalised();
+            let map = texture(tex).uv_value(uv);
+            let mapped = perturb_normal(shading, self.tangent([b0, b1, b2]), map, scale);
+            // mirrored for the back face, normals bent below the surface are ignored
+            let mapped = if out { mapped } else { -mapped };
+            if mapped.dot(normal) > 0.0 {
+                normal = mapped;
+            }
+        }
+
+        Intersection {
+            mesh: self.mesh,
+            ..Intersection::new(t, uv, point, normal, out, self.mat, 0)
+        }
+    }
+    // distance to the hit if the ray hits the triangle, for occlusion tests this skips
+    // everything intersect computes for shading
+    #[must_use]
+    pub fn intersect_t(&self, ray: &Ray, rng: &mut impl MinRng) -> Option<f32> {
+        let mats = unsafe { MATERIALS.get().as_ref_unchecked() };
+        let (t, b) = self.hit(ray)?;
+        if !mats[self.mat].uv_intersect(self.uv_of(b), rng) {
+            return None;
+        }
+        if self.is_single_sided() && self.front_normal(b).dot(ray.dir) >= 0.0 {
+            return None;
+        }
+        Some(t)
+    }
+    // distance & barycentrics of where the ray hits the triangle
+    // see whoop 2013 https://jcgt.org/published/0002/01/05/paper.pdf
+    #[must_use]
+    fn hit(&self, ray: &Ray) -> Option<(f32, [f32; 3])> {
+        let verts = unsafe { VERTICES.get().as_ref_unchecked() };
+
+        let v0 = verts[self.pos[0]];
+        let v1 = verts[self.pos[1]];
+        let v2 = verts[self.pos[2]];
         let ro: Vec3 = Vec3::new(ray.origin.x, ray.origin.y, ray.origin.z);
 
         let mut p0t: Vec3 = v0 - ro;
@@ -116,12 +178,12 @@ impl Tri {
         }
 
         if (e0 < 0.0 || e1 < 0.0 || e2 < 0.0) && (e0 > 0.0 || e1 > 0.0 || e2 > 0.0) {
-            return Intersection::NONE;
+            return None;
         }
 
         let det = e0 + e1 + e2;
         if det == 0.0 {
-            return Intersection::NONE;
+            return None;
         }
 
         p0t *= sz;
@@ -130,7 +192,7 @@ impl Tri {
 
         let t_scaled = e0 * p0t.z + e1 * p1t.z + e2 * p2t.z;
         if (det < 0.0 && t_scaled >= 0.0) || (det > 0.0 && t_scaled <= 0.0) {
-            return Intersection::NONE;
+            return None;
         }
 
         let inv_det = 1.0 / det;
@@ -139,32 +201,44 @@ impl Tri {
         let b1 = e1 * inv_det;
         let b2 = e2 * inv_det;
 
-        let uv = b0 * uv0 + b1 * uv1 + b2 * uv2;
-
-        if !mats[self.mat].uv_intersect(uv, rng) {
-            return Intersection::NONE;
-        }
-
         let t = inv_det * t_scaled;
 
-        let mut gnormal = (v2 - v0).cross(v1 - v0).normalised();
+        Some((t, [b0, b1, b2]))
+    }
+    #[must_use]
+    fn uv_of(&self, [b0, b1, b2]: [f32; 3]) -> Vec2 {
+        let uvs = unsafe { UVS.get().as_ref_unchecked() };
+        b0 * uvs[self.uv[0]] + b1 * uvs[self.uv[1]] + b2 * uvs[self.uv[2]]
+    }
+    // geometric normal on the side the interpolated normal points to
+    #[must_use]
+    fn front_normal(&self, [b0, b1, b2]: [f32; 3]) -> Vec3 {
+        let verts = unsafe { VERTICES.get().as_ref_unchecked() };
+        let norms = unsafe { NORMALS.get().as_ref_unchecked() };
+        let [v0, v1, v2] = self.pos.map(|i| verts[i]);
+        let [n0, n1, n2] = self.nor.map(|i| norms[i]);
 
-        let normal = b0 * n0 + b1 * n1 + b2 * n2;
-        if gnormal.dot(normal) < 0.0 {
-            gnormal = -gnormal;
+        let gnormal = (v2 - v0).cross(v1 - v0).normalised();
+        if gnormal.dot(b0 * n0 + b1 * n1 + b2 * n2) < 0.0 {
+            -gnormal
+        } else {
+            gnormal
         }
-        let mut normal = gnormal;
-
-        let out = normal.dot(ray.dir) < 0.0;
-        if !out {
-            normal = -normal;
+    }
+    #[must_use]
+    fn is_single_sided(&self) -> bool {
+        let single_sided = unsafe { SINGLE_SIDED.get().as_ref_unchecked() };
+        single_sided.get(self.mat).copied().unwrap_or(false)
+    }
+    // interpolated tangent & bitangent sign, None if the mesh has no tangents
+    #[must_use]
+    fn tangent(&self, b: [f32; 3]) -> Option<(Vec3, f32)> {
+        let tangents = unsafe { TANGENTS.get().as_ref_unchecked() };
+        let mut tangent = Vec3::ZERO;
+        for (i, b) in self.nor.iter().zip(b) {
+            tangent += b * tangents.get(*i)?.0;
         }
-
-        let mut point = b0 * v0 + b1 * v1 + b2 * v2;
-
-        point += normal * 0.000001;
-
-        Intersection::new(t, uv, point, normal, out, self.mat, 0)
+        Some((tangent, tangents[self.nor[0]].1))
     }
     #[must_use]
     pub fn sample_ray(&self, sect: &Intersection, rng: &mut impl MinRng) -> (Ray, Vec3) {
@@ -178,28 +252,282 @@ impl Tri {
         let n1 = norms[self.nor[1]];
         let n2 = norms[self.nor[2]];
 
-        let uv = rng.gen().sqrt();
-        let uv = (1.0 - uv, uv * rng.gen());
+        let uv = Self::barycentrics([rng.gen(), rng.gen()]);
 
         let mut point = uv.0 * v0 + uv.1 * v1 + (1.0 - uv.0 - uv.1) * v2;
         let nor = uv.0 * n0 + uv.1 * n1 + (1.0 - uv.0 - uv.1) * n2;
         point += nor * 0.000001;
 
-        let dir = point - sect.pos;
+        let origin = sect.shadow_origin();
+        let dir = point - origin;
 
-        let ray = Ray::new(sect.pos, dir);
+        let ray = Ray::new(origin, dir);
 
-        let le = mats[self.mat].le(point, dir);
+        // same side test as intersect so both strategies agree on what's dark
+        let front = self.front_normal([uv.0, uv.1, 1.0 - uv.0 - uv.1]).dot(dir) < 0.0;
+        let le = mats[self.mat].le(point, dir, self.uv_at(uv), front);
 
         (ray, le)
     }
+    // uniformly distributed barycentrics (of v0 & v1) from a point in the unit square
+    #[must_use]
+    fn barycentrics(u: [f32; 2]) -> (f32, f32) {
+        let s = u[0].sqrt();
+        (1.0 - s, s * u[1])
+    }
+    // texture coordinates at barycentrics (of v0 & v1)
+    #[must_use]
+    fn uv_at(&self, b: (f32, f32)) -> Vec2 {
+        let uvs = unsafe { UVS.get().as_ref_unchecked() };
+        let [uv0, uv1, uv2] = self.uv.map(|i| uvs[i]);
+        b.0 * uv0 + b.1 * uv1 + (1.0 - b.0 - b.1) * uv2
+    }
+    // emitted radiance averaged over the surface
+    #[must_use]
+    pub fn mean_le(&self) -> Vec3 {
+        let mats = unsafe { MATERIALS.get().as_ref_unchecked() };
+        let mat = &mats[self.mat];
+        crate::primitive::mean_over_square(|u| {
+            mat.le(
+                Vec3::ZERO,
+                Vec3::ZERO,
+                self.uv_at(Self::barycentrics(u)),
+                true,
+            )
+        })
+    }
+    // solid angle pdf of sampling sect, uses the face normal as the intersection's normal
+    // may be bent by a normal map
     #[must_use]
     pub fn pdf(&self, sect: &Intersection, ray: &Ray) -> f32 {
+        let verts = unsafe { VERTICES.get().as_ref_unchecked() };
+        let [v0, v1, v2] = self.pos.map(|i| verts[i]);
+        let gnormal = (v1 - v0).cross(v2 - v0).normalised();
+        (sect.pos - ray.origin).mag_sq() / (gnormal.dot(ray.dir).abs() * self.area())
+    }
+    #[must_use]
+    pub fn area(&self) -> f32 {
         let verts = unsafe { VERTICES.get().as_ref_unchecked() };
         let v0 = verts[self.pos[0]];
         let v1 = verts[self.pos[1]];
         let v2 = verts[self.pos[2]];
-        let area = 0.5 * (v1 - v0).cross(v2 - v0).mag();
-        (sect.pos - ray.origin).mag_sq() / (sect.nor.dot(ray.dir).abs() * area)
+        0.5 * (v1 - v0).cross(v2 - v0).mag()
+    }
+}
+
+// normal (facing the front of the surface) bent by a value from a tangent space normal
+// map (in [0, 1]) with its xy scaled by scale, falls back to normal without a tangent
+#[must_use]
+pub fn perturb_normal(normal: Vec3, tangent: Option<(Vec3, f32)>, map: Vec3, scale: f32) -> Vec3 {
+    let Some((tangent, sign)) = tangent else {
+        return normal;
+    };
+    // gram-schmidt in case interpolation made them non orthogonal
+    let tangent = tangent - normal * normal.dot(tangent);
+    if tangent.mag_sq() < 1e-12 {
+        return normal;
+    }
+    let tangent = tangent.normalised();
+    let bitangent = normal.cross(tangent) * sign;
+    let m = 2.0 * map - Vec3::ONE;
+    let mapped = tangent * m.x * scale + bitangent * m.y * scale + normal * m.z;
+    if mapped.mag_sq() < 1e-12 {
+        return normal;
+    }
+    mapped.normalised()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_sided() {
+        crate::scene::test_scene();
+        let mut rng = rand::thread_rng();
+        let (front, back) = (Vec3::new(20.0, 0.0, 0.0), Vec3::new(20.0, 0.0, -4.0));
+
+        let sect = crate::get_intersection(&Ray::new(front, -Vec3::Z), &mut rng);
+        let single_sided = loader::material_idx("single_sided");
+        assert!(!sect.is_none() && sect.out && Some(sect.mat) == single_sided);
+        assert!(crate::get_intersection(&Ray::new(back, Vec3::Z), &mut rng).is_none());
+
+        // double sided quad is visible from behind
+        let sect = crate::get_intersection(&Ray::new(Vec3::new(0.0, 0.0, -3.0), Vec3::Z), &mut rng);
+        assert!(!sect.is_none() && !sect.out);
+    }
+
+    #[test]
+    fn normal_map() {
+        let encode = |n: Vec3| 0.5 * n + Vec3::splat(0.5);
+        let tangent = Some((Vec3::X, 1.0));
+        let tilted = Vec3::new(0.5, 0.0, 0.75f32.sqrt());
+
+        // a flat map keeps the normal
+        let flat = perturb_normal(Vec3::Z, tangent, encode(Vec3::Z), 1.0);
+        assert!((flat - Vec3::Z).mag() < 1e-5);
+        let bent = perturb_normal(Vec3::Z, tangent, encode(tilted), 1.0);
+        assert!((bent - tilted).mag() < 1e-5);
+
+        // the bitangent sign mirrors the map's y
+        let up = Vec3::new(0.0, 0.5, 0.75f32.sqrt());
+        let flipped = perturb_normal(Vec3::Z, Some((Vec3::X, -1.0)), encode(up), 1.0);
+        assert!((flipped - Vec3::new(0.0, -0.5, 0.75f32.sqrt())).mag() < 1e-5);
+
+        // non orthogonal tangents are fixed up, no tangent or a zero scale does nothing
+        let skewed = perturb_normal(
+            Vec3::Z,
+            Some((Vec3::new(1.0, 0.0, 1.0), 1.0)),
+            encode(tilted),
+            1.0,
+        );
+        assert!((skewed - tilted).mag() < 1e-5);
+        assert_eq!(perturb_normal(Vec3::Z, None, encode(tilted), 1.0), Vec3::Z);
+        let unscaled = perturb_normal(Vec3::Z, tangent, encode(tilted), 0.0);
+        assert!((unscaled - Vec3::Z).mag() < 1e-5);
+    }
+
+    #[test]
+    fn normal_mapped_light_pdf() {
+        crate::scene::test_scene();
+        let mut rng = rand::thread_rng();
+        let (tris, verts) = unsafe {
+            (
+                TRIANGLES.get().as_ref_unchecked(),
+                VERTICES.get().as_ref_unchecked(),
+            )
+        };
+        let red_light = loader::material_idx("red_light");
+        let tri = tris
+            .iter()
+            .find_map(|t| match t {
+                Primitive::Tri(t) if Some(t.mat) == red_light => Some(t),
+                _ => None,
+            })
+            .unwrap();
+        let centre = tri
+            .pos
+            .map(|i| verts[i])
+            .iter()
+            .fold(Vec3::ZERO, |a, &v| a + v)
+            / 3.0;
+        let origin = Vec3::new(-1.5, 0.2, 0.0);
+        let ray = Ray::new(origin, (centre - origin).normalised());
+        let sect = tri.intersect(&ray, &mut rng);
+        assert!(!sect.is_none());
+
+        // bending the shading normal (as a normal map does) keeps the pdf
+        let tilted = 0.5 * Vec3::new(0.5, 0.0, 0.75f32.sqrt()) + Vec3::splat(0.5);
+        let tangent = Some((sect.nor.cross(Vec3::Y), 1.0));
+        let mapped = Intersection {
+            nor: perturb_normal(sect.nor, tangent, tilted, 1.0),
+            ..sect.clone()
+        };
+        assert!(mapped.nor.dot(sect.nor) < 0.99);
+        let (pdf, mapped_pdf) = (tri.pdf(&sect, &ray), tri.pdf(&mapped, &ray));
+        assert!((pdf - mapped_pdf).abs() < 1e-5 * pdf, "{pdf} {mapped_pdf}");
+    }
+
+    #[test]
+    fn light_pdf() {
+        // sampled directions towards each triangle of a quad light are histogrammed in
+        // gnomonic coordinates (x / z, y / z) around the direction to the triangle and
+        // compared against the pdf integrated over the solid angle of each bin
+        use rand::{rngs::StdRng, SeedableRng};
+        const BINS: usize = 16;
+        const SUB: usize = 8;
+        const SAMPLES: usize = 200_000;
+
+        crate::scene::test_scene();
+        let (tris, verts) = unsafe {
+            (
+                TRIANGLES.get().as_ref_unchecked(),
+                VERTICES.get().as_ref_unchecked(),
+            )
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        // test_scene has a copy of the red light's material elsewhere so only take its mesh
+        let red_light = (
+            loader::material_idx("red_light"),
+            loader::mesh_idx("red_light"),
+        );
+
+        // above, oblique, grazing and behind the red light
+        let positions = [
+            Vec3::new(-1.5, 0.0, 0.0),
+            Vec3::new(-0.5, 0.8, -0.5),
+            Vec3::new(0.5, -0.5, -1.4),
+            Vec3::new(-1.5, 0.2, -1.9),
+        ];
+        for pos in positions {
+            let lights = tris.iter().filter_map(|t| match t {
+                Primitive::Tri(t) if (Some(t.mat), t.mesh) == red_light => Some(t),
+                _ => None,
+            });
+            for tri in lights {
+                let [a, b, c] = tri.pos.map(|i| verts[i]);
+                let centre = (a + b + c) / 3.0;
+                let sect = Intersection::new(
+                    0.0,
+                    Vec2::ZERO,
+                    pos,
+                    (centre - pos).normalised(),
+                    true,
+                    0,
+                    0,
+                );
+                let origin = sect.shadow_origin();
+                let coord = crate::coord::Coordinate::new_from_z((centre - origin).normalised());
+                let project = |dir: Vec3| {
+                    let l = coord.global_to_local(dir);
+                    [l.x / l.z, l.y / l.z]
+                };
+                // half width of the bins along each axis
+                let extent = [a, b, c].iter().fold([0.0f32; 2], |e, &v| {
+                    let p = project(v - origin);
+                    [0, 1].map(|i| e[i].max(1.05 * p[i].abs()))
+                });
+                let bin = |p: [f32; 2]| {
+                    let [u, v] = [0, 1].map(|i| {
+                        (((0.5 + 0.5 * p[i] / extent[i]) * BINS as f32) as usize).min(BINS - 1)
+                    });
+                    v * BINS + u
+                };
+
+                let mut sampled = vec![0.0; BINS * BINS];
+                for _ in 0..SAMPLES {
+                    let (ray, _) = tri.sample_ray(&sect, &mut rng);
+                    sampled[bin(project(ray.dir))] += 1.0 / SAMPLES as f32;
+                }
+
+                // midpoint rule over SUB x SUB points per bin
+                let mut expected = vec![0.0; BINS * BINS];
+                let step = extent.map(|e| 2.0 * e / (BINS * SUB) as f32);
+                for j in 0..BINS * SUB {
+                    for i in 0..BINS * SUB {
+                        let u = -extent[0] + (i as f32 + 0.5) * step[0];
+                        let v = -extent[1] + (j as f32 + 0.5) * step[1];
+                        let ray = Ray::new(origin, coord.local_to_global(Vec3::new(u, v, 1.0)));
+                        let hit = tri.intersect(&ray, &mut rng);
+                        if hit.is_none() {
+                            continue;
+                        }
+                        // solid angle per unit area in gnomonic coordinates
+                        let jacobian = (1.0 + u * u + v * v).powf(-1.5);
+                        expected[bin([u, v])] += tri.pdf(&hit, &ray) * jacobian * step[0] * step[1];
+                    }
+                }
+
+                let total: f32 = expected.iter().sum();
+                let distance: f32 = sampled
+                    .iter()
+                    .zip(&expected)
+                    .map(|(s, e)| (s - e).abs())
+                    .sum::<f32>()
+                    / 2.0;
+                assert!((total - 1.0).abs() < 0.02, "{pos} {total}");
+                assert!(distance < 0.03, "{pos} {distance}");
+            }
+        }
     }
 }
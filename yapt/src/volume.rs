@@ -0,0 +1,189 @@
+use crate::prelude::*;
+use bvh::aabb::Aabb;
+
+// heterogeneous medium defined by a density grid, sigma_t = density_scale * density
+// the medium only scatters (isotropic phase function) with the given albedo
+pub struct Volume {
+    dim: [usize; 3],
+    bounds: Aabb,
+    density: Vec<f32>,
+    density_scale: f32,
+    pub albedo: Vec3,
+    // maximum sigma_t within the grid
+    majorant: f32,
+}
+
+impl Volume {
+    const MAGIC: &'static [u8; 4] = b"YVOL";
+
+    #[must_use]
+    pub fn new(
+        dim: [usize; 3],
+        bounds: Aabb,
+        density: Vec<f32>,
+        density_scale: f32,
+        albedo: Vec3,
+    ) -> Self {
+        assert_eq!(dim[0] * dim[1] * dim[2], density.len());
+        assert!(density.iter().all(|&v| v >= 0.0));
+        let majorant = density_scale * density.iter().copied().fold(0.0, f32::max);
+        Self {
+            dim,
+            bounds,
+            density,
+            density_scale,
+            albedo,
+            majorant,
+        }
+    }
+    // raw grid file (all little endian):
+    // b"YVOL", resolution (3 x u32), bounds min (3 x f32), bounds max (3 x f32)
+    // followed by the densities (f32) with x varying fastest then y then z
+    pub fn from_path(
+        filepath: &str,
+        density_scale: f32,
+        albedo: Vec3,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let bytes = std::fs::read(filepath)?;
+
+        if bytes.len() < 40 || &bytes[0..4] != Self::MAGIC {
+            return Err(format!("{filepath} is not a YVOL density grid").into());
+        }
+        let word = |i: usize| -> [u8; 4] { bytes[4 + 4 * i..8 + 4 * i].try_into().unwrap() };
+
+        let dim = [0, 1, 2].map(|i| u32::from_le_bytes(word(i)) as usize);
+        let [min, max] = [3, 6].map(|i| {
+            Vec3::new(
+                f32::from_le_bytes(word(i)),
+                f32::from_le_bytes(word(i + 1)),
+                f32::from_le_bytes(word(i + 2)),
+            )
+        });
+
+        let density: Vec<f32> = bytes[40..]
+            .chunks_exact(4)
+            .map(|v| f32::from_le_bytes(v.try_into().unwrap()))
+            .collect();
+
+        if density.len() != dim[0] * dim[1] * dim[2] {
+            return Err(format!("{filepath} has an incorrect number of voxels").into());
+        }
+
+        Ok(Self::new(
+            dim,
+            Aabb::new(min, max),
+            density,
+            density_scale,
+            albedo,
+        ))
+    }
+    // nearest voxel lookup
+    #[must_use]
+    pub fn sigma_t(&self, pos: Vec3) -> f32 {
+        let local = (pos - self.bounds.min) / self.bounds.extent();
+        let idx = [local.x, local.y, local.z];
+        if idx.iter().any(|v| !(0.0..=1.0).contains(v)) {
+            return 0.0;
+        }
+        let [x, y, z] =
+            [0, 1, 2].map(|i| ((idx[i] * self.dim[i] as f32) as usize).min(self.dim[i] - 1));
+        self.density_scale * self.density[x + self.dim[0] * (y + self.dim[1] * z)]
+    }
+    // parametric range of the ray that overlaps the grid clipped to [0, t_max]
+    #[must_use]
+    fn overlap(&self, ray: &Ray, t_max: f32) -> Option<(f32, f32)> {
+        let t1 = (self.bounds.min - ray.origin).hadamard(ray.inv_dir);
+        let t2 = (self.bounds.max - ray.origin).hadamard(ray.inv_dir);
+        let t_near = t1.min_by_component(t2).component_max().max(0.0);
+        let t_far = t1.max_by_component(t2).component_min().min(t_max);
+        (t_near < t_far).then_some((t_near, t_far))
+    }
+    // delta tracking, returns the distance to a real collision before t_max
+    #[must_use]
+    pub fn sample_distance(&self, ray: &Ray, t_max: f32, rng: &mut impl MinRng) -> Option<f32> {
+        if self.majorant == 0.0 {
+            return None;
+        }
+        let (mut t, t_far) = self.overlap(ray, t_max)?;
+        loop {
+            t -= (1.0 - rng.gen()).ln() / self.majorant;
+            if t >= t_far {
+                return None;
+            }
+            if rng.gen() * self.majorant < self.sigma_t(ray.origin + t * ray.dir) {
+                return Some(t);
+            }
+        }
+    }
+    // ratio tracking estimate of the transmittance between the ray origin and t_max
+    #[must_use]
+    pub fn transmittance(&self, ray: &Ray, t_max: f32, rng: &mut impl MinRng) -> f32 {
+        if self.majorant == 0.0 {
+            return 1.0;
+        }
+        let Some((mut t, t_far)) = self.overlap(ray, t_max) else {
+            return 1.0;
+        };
+        let mut tr = 1.0;
+        loop {
+            t -= (1.0 - rng.gen()).ln() / self.majorant;
+            if t >= t_far {
+                return tr;
+            }
+            tr *= 1.0 - self.sigma_t(ray.origin + t * ray.dir) / self.majorant;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+
+    const SIGMA: f32 = 0.5;
+    const SAMPLES: usize = 1_000_000;
+
+    // constant density grid should match an analytic homogeneous medium
+    fn constant_grid() -> Volume {
+        let bounds = Aabb::new(Vec3::splat(-1000.0), Vec3::splat(1000.0));
+        Volume::new([4, 4, 4], bounds, vec![1.0; 64], SIGMA, Vec3::ONE)
+    }
+
+    #[test]
+    fn mean_free_path() {
+        let mut rng = thread_rng();
+        let volume = constant_grid();
+        let ray = Ray::new(Vec3::ZERO, Vec3::new(0.3, -0.2, 1.0));
+
+        let mut sum = 0.0;
+        for _ in 0..SAMPLES {
+            sum += volume
+                .sample_distance(&ray, f32::INFINITY, &mut rng)
+                .unwrap() as f64;
+        }
+        let mean = sum / SAMPLES as f64;
+        assert!((mean - 1.0 / SIGMA as f64).abs() < 0.01, "mean = {mean}");
+    }
+
+    #[test]
+    fn transmittance() {
+        let mut rng = thread_rng();
+        let volume = constant_grid();
+        let ray = Ray::new(Vec3::ZERO, Vec3::new(0.3, -0.2, 1.0));
+        let dist = 3.0;
+
+        let mut sum = 0.0;
+        let mut escaped = 0;
+        for _ in 0..SAMPLES {
+            sum += volume.transmittance(&ray, dist, &mut rng) as f64;
+            if volume.sample_distance(&ray, dist, &mut rng).is_none() {
+                escaped += 1;
+            }
+        }
+        let expected = (-SIGMA as f64 * dist as f64).exp();
+        let ratio = sum / SAMPLES as f64;
+        let delta = escaped as f64 / SAMPLES as f64;
+        assert!((ratio - expected).abs() < 0.005, "ratio tracking = {ratio}");
+        assert!((delta - expected).abs() < 0.005, "delta tracking = {delta}");
+    }
+}
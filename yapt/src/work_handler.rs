@@ -2,19 +2,81 @@ use rand_pcg::Pcg64Mcg;
 
 use std::{
     collections::VecDeque,
-    num::NonZeroUsize,
+    fmt,
+    num::{NonZeroU32, NonZeroU64, NonZeroUsize},
     sync::{
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicU64, AtomicUsize, Ordering},
         mpsc::{channel, Receiver, Sender},
-        Arc,
+        Arc, Mutex, OnceLock,
     },
     usize,
 };
 
-use crate::{IntegratorType, Naive, Splat, NEEMIS, SAMPLABLE};
+use crate::{
+    distributions::Distribution1D,
+    get_intersection, get_intersection_clipped,
+    pssmlt::{MinRng, PssState},
+    sampler::{Sampler, Sobol},
+    spectral::{Lambda, Spectral},
+    Albedo, ClipRange, Depth, IntegratorType, Intersection, Naive, PathLimits, RenderSettings,
+    Splat, Tile, AO, NEEMIS, SAMPLABLE,
+};
+use utility::{Ray, Vec3};
 
+// default workgroup size is max(MIN_WORKGROUP_SIZE, pixels / DEFAULT_WORKGROUPS)
 const MIN_WORKGROUP_SIZE: u64 = 4096;
+const DEFAULT_WORKGROUPS: u64 = 256;
 const PARK_TIME: std::time::Duration = std::time::Duration::from_millis(20);
+// maximum number of idle splat vectors kept around for reuse
+const MAX_POOLED_VECS: usize = 64;
+
+// samples with NaN (or infinite) radiance since the program started
+pub static NAN_SAMPLES: AtomicU64 = AtomicU64::new(0);
+
+// what happens to a sample with NaN (or infinite) radiance
+#[derive(clap::ValueEnum, Copy, Clone, Default, Debug, PartialEq)]
+pub enum NanPolicy {
+    // the sample contributes nothing
+    #[default]
+    Drop,
+    // only the bad channels are zeroed
+    Clamp,
+    // stop rendering (see Update::Abort)
+    Abort,
+}
+
+impl fmt::Display for NanPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Drop => "drop",
+            Self::Clamp => "clamp",
+            Self::Abort => "abort",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl NanPolicy {
+    // the single place integrator output is checked, errors (with abort) give the pixel
+    pub fn sanitise(self, rgb: Vec3, pixel: (u64, u64)) -> Result<Vec3, String> {
+        if rgb.is_finite() {
+            return Ok(rgb);
+        }
+        NAN_SAMPLES.fetch_add(1, Ordering::Relaxed);
+        match self {
+            Self::Drop => Ok(Vec3::ZERO),
+            Self::Clamp => Ok(Vec3::new(
+                if rgb.x.is_finite() { rgb.x } else { 0.0 },
+                if rgb.y.is_finite() { rgb.y } else { 0.0 },
+                if rgb.z.is_finite() { rgb.z } else { 0.0 },
+            )),
+            Self::Abort => Err(format!(
+                "Radiance of {rgb} at pixel ({}, {})",
+                pixel.0, pixel.1
+            )),
+        }
+    }
+}
 
 // ------------------------------
 // Thread Communication
@@ -24,14 +86,31 @@ pub enum Update {
     Calculation(Vec<Splat>, u8, u64),
     PssmltBootstrapDone,
     NoState,
+    // a sample was rejected by --on-nan abort, the main thread stops the render
+    Abort(String),
 }
 
 pub enum ComputeChange {
     Shutdown,
     // samples, workload_id
     WorkSamples(u64, u8),
-    WorkMutations(u64),
-    UpdateState(State),
+    // mutations per pixel, workload_id
+    WorkMutations(u64, u8),
+    // samples per pixel, rendered pixels to sample (see Adaptive), workload_id
+    WorkPixels(u64, Arc<[u64]>, u8),
+    UpdateState(Box<State>),
+}
+
+impl ComputeChange {
+    // samples (or mutations with PSSMLT) per pixel
+    #[must_use]
+    pub fn work(pssmlt: bool, samples: u64, workload_id: u8) -> Self {
+        if pssmlt {
+            Self::WorkMutations(samples, workload_id)
+        } else {
+            Self::WorkSamples(samples, workload_id)
+        }
+    }
 }
 
 pub struct State {
@@ -41,23 +120,318 @@ pub struct State {
     ctx: Option<egui::Context>,
     integrator: IntegratorType,
     base_rng_seed: u64,
+    sampler: Sampler,
+    // mesh to isolate (see MESH_NAMES), everything else is held out
+    isolate: Option<usize>,
+    // samples per workgroup, smaller groups balance load better and update the
+    // preview more often, larger groups spend less time on channel/queue overhead
+    workgroup_size: Option<NonZeroU64>,
+    // side of the tiles work is split into (runs of workgroup_size pixels without)
+    work_tile_size: Option<NonZeroU32>,
+    // camera rays & intersections of each pixel (PRIMARY_CACHE_SAMPLES of them with
+    // jitter, else one) reused by the samples of that pixel, this is reset with the state
+    // so the camera must be static (and is unused if it has a lens)
+    primary_cache: Option<Vec<OnceLock<PrimaryHit>>>,
+    limits: PathLimits,
+    clip_range: ClipRange,
+    // occlusion distance of the AO integrator
+    ao_radius: f32,
+    // discard camera ray intersections outside of clip_range
+    clip: bool,
+    // jitter camera rays within pixels (off for pixel exact debugging)
+    jitter: bool,
+    // part of the frame being rendered (width & height are the tile's)
+    tile: Option<Tile>,
+    // only pixels in the region are rendered (width & height are still the frame's)
+    region: Option<Tile>,
+    // index into CAMERAS to render from instead of CAM
+    camera: Option<usize>,
+    on_nan: NanPolicy,
+    // the first sample rejected by on_nan (with abort), sent to the main thread by the
+    // compute thread that finds it, threads may hold the lock while the state is
+    // replaced so a state is never modified once it's shared (see UpdateState)
+    nan_abort: Mutex<Option<String>>,
+    // trace paths at sampled wavelengths rather than in RGB
+    spectral: Option<Spectral>,
+    // paths traced to find the starting points (& brightness) of the PSSMLT chains
+    pssmlt_bootstrap: u64,
+}
+
+struct PrimaryHit {
+    uv: [f32; 2],
+    ray: Ray,
+    sect: Intersection,
 }
 
 impl State {
     pub fn new(
-        width: usize,
-        height: usize,
+        render_settings: &RenderSettings,
         #[cfg(feature = "gui")] ctx: Option<egui::Context>,
-        integrator: IntegratorType,
-        base_rng_seed: u64,
+        seed_offset: u64,
+        tile: Option<Tile>,
     ) -> Self {
+        let rs = render_settings;
+        let (width, height) = (rs.width.get() as usize, rs.height.get() as usize);
         State {
             width,
             height,
             #[cfg(feature = "gui")]
             ctx,
-            integrator,
-            base_rng_seed,
+            integrator: rs.integrator,
+            base_rng_seed: frame_seed(rs.seed, rs.frame, rs.lock_seed).wrapping_add(seed_offset),
+            sampler: rs.sampler,
+            isolate: rs.isolate.as_deref().and_then(crate::loader::mesh_idx),
+            workgroup_size: rs.workgroup_size,
+            work_tile_size: rs.work_tile_size,
+            primary_cache: rs.cache_primary.then(|| {
+                let per_pixel = if rs.no_jitter {
+                    1
+                } else {
+                    PRIMARY_CACHE_SAMPLES
+                };
+                (0..width * height * per_pixel as usize)
+                    .map(|_| OnceLock::new())
+                    .collect()
+            }),
+            limits: PathLimits {
+                max_depth: rs.max_depth,
+                rr_depth: rs.rr_depth,
+                clamp: rs.clamp.unwrap_or(f32::INFINITY),
+                nee_max_radiance: rs.nee_max_radiance.unwrap_or(f32::INFINITY),
+            },
+            ao_radius: rs.ao_radius.unwrap_or(f32::INFINITY),
+            clip_range: ClipRange {
+                near: rs.near,
+                far: rs.far.unwrap_or(f32::INFINITY),
+            },
+            clip: rs.clip,
+            jitter: !rs.no_jitter,
+            tile,
+            region: None,
+            camera: None,
+            on_nan: rs.on_nan,
+            nan_abort: Mutex::new(None),
+            spectral: (rs.spectral
+                && !matches!(
+                    rs.integrator,
+                    IntegratorType::Depth | IntegratorType::Albedo | IntegratorType::AO
+                ))
+            .then(|| Spectral::new(rs.working_primaries)),
+            pssmlt_bootstrap: rs.pssmlt_bootstrap,
+        }
+    }
+    #[must_use]
+    pub fn with_region(mut self, region: Option<Tile>) -> Self {
+        self.region = region;
+        self
+    }
+    // number of pixels rendered per sample
+    #[must_use]
+    fn pixels(&self) -> u64 {
+        self.region
+            .map_or(self.width as u64 * self.height as u64, |r| r.pixels())
+    }
+    // width & height of the rendered pixels
+    #[must_use]
+    fn rendered_size(&self) -> (u64, u64) {
+        self.region
+            .map_or((self.width as u64, self.height as u64), |r| {
+                (r.width as u64, r.height as u64)
+            })
+    }
+    // index in the frame of the i-th rendered pixel
+    #[must_use]
+    fn frame_pixel(&self, i: u64) -> u64 {
+        match (self.region, self.tile) {
+            (Some(region), _) => region.frame_pixel(i),
+            (None, Some(tile)) => tile.frame_pixel(i),
+            (None, None) => i,
+        }
+    }
+    #[must_use]
+    pub fn with_camera(mut self, camera: Option<usize>) -> Self {
+        self.camera = camera;
+        self
+    }
+    #[must_use]
+    fn primary_intersection(&self, ray: &Ray, rng: &mut impl MinRng) -> Intersection {
+        if self.clip {
+            get_intersection_clipped(ray, self.clip_range, rng)
+        } else {
+            get_intersection(ray, rng)
+        }
+    }
+    #[must_use]
+    fn cam(&self) -> &crate::Cam {
+        match self.camera {
+            Some(i) => unsafe { &crate::CAMERAS.get().as_ref_unchecked()[i] },
+            None => unsafe { crate::CAM.get().as_ref_unchecked() },
+        }
+    }
+    // radiance (in RGB) along ray
+    #[must_use]
+    fn radiance(
+        &self,
+        ray: Ray,
+        rng: &mut impl MinRng,
+        primary: Option<Intersection>,
+    ) -> (Vec3, u64) {
+        let lambda = match self.spectral {
+            Some(spectral) => Lambda::Spectral(spectral.sample(rng.gen())),
+            None => Lambda::Rgb,
+        };
+        let (col, rays) = self.integrate(ray, rng, primary, &lambda);
+        (lambda.to_rgb(col), rays)
+    }
+    #[must_use]
+    fn integrate(
+        &self,
+        ray: Ray,
+        rng: &mut impl MinRng,
+        primary: Option<Intersection>,
+        lambda: &Lambda,
+    ) -> (Vec3, u64) {
+        match self.integrator {
+            IntegratorType::Naive => {
+                Naive::rgb(ray, rng, self.isolate, primary, self.limits, lambda)
+            }
+            IntegratorType::NEE => NEEMIS::rgb(
+                ray,
+                rng,
+                unsafe { SAMPLABLE.get().as_ref_unchecked() },
+                self.isolate,
+                primary,
+                self.limits,
+                lambda,
+            ),
+            #[cfg(feature = "volumes")]
+            IntegratorType::Volumetric => crate::Volumetric::rgb(
+                ray,
+                rng,
+                unsafe { SAMPLABLE.get().as_ref_unchecked() },
+                self.isolate,
+                primary,
+                self.limits,
+                lambda,
+            ),
+            IntegratorType::Depth => Depth::rgb(ray, rng, self.clip_range, primary),
+            IntegratorType::Albedo => Albedo::rgb(ray, rng, primary),
+            IntegratorType::AO => AO::rgb(ray, rng, self.ao_radius, primary),
+        }
+    }
+    // applies on_nan to the radiance arriving at frame_pixel
+    #[must_use]
+    fn sanitise(&self, col: Vec3, frame_pixel: u64) -> Vec3 {
+        let frame_width = self
+            .tile
+            .map_or(self.width as u64, |t| t.frame_width as u64);
+        match self
+            .on_nan
+            .sanitise(col, (frame_pixel % frame_width, frame_pixel / frame_width))
+        {
+            Ok(col) => col,
+            // the sample contributes nothing until the main thread stops the render
+            Err(e) => {
+                self.nan_abort.lock().unwrap().get_or_insert(e);
+                Vec3::ZERO
+            }
+        }
+    }
+    // the abort found since the last call (see nan_abort)
+    #[must_use]
+    fn take_abort(&self) -> Option<Update> {
+        self.nan_abort.lock().unwrap().take().map(Update::Abort)
+    }
+    #[must_use]
+    fn workgroup_size(&self) -> u64 {
+        self.workgroup_size.map_or_else(
+            || MIN_WORKGROUP_SIZE.max(self.width as u64 * self.height as u64 / DEFAULT_WORKGROUPS),
+            u64::from,
+        )
+    }
+}
+
+#[must_use]
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// frames can't just use seed + frame (frame 1 of seed 3 would be frame 0 of seed 4)
+// so the frame is mixed in with splitmix64, frame 0 keeps the seed as is
+#[must_use]
+fn frame_seed(seed: u64, frame: u64, lock_seed: bool) -> u64 {
+    if lock_seed || frame == 0 {
+        return seed;
+    }
+    splitmix64(seed.wrapping_add(frame.wrapping_mul(0x9e37_79b9_7f4a_7c15)))
+}
+
+// every pixel sample has its own rng so the image doesn't depend on how the work
+// is split between workgroups, threads or tiles
+#[must_use]
+fn pixel_rng(seed: u64, pixel: u64, sample: u64) -> Pcg64Mcg {
+    let hi = splitmix64(seed ^ splitmix64(sample));
+    let lo = splitmix64(hi ^ pixel);
+    Pcg64Mcg::new(((hi as u128) << 64) | lo as u128)
+}
+
+// rng of a single pixel sample (path) for the chosen sampler
+enum PixelRng {
+    Random(Pcg64Mcg),
+    Sobol(Sobol),
+}
+
+impl PixelRng {
+    #[must_use]
+    fn new(sampler: Sampler, seed: u64, pixel: u64, sample: u64) -> Self {
+        match sampler {
+            Sampler::Random => Self::Random(pixel_rng(seed, pixel, sample)),
+            // the pixel picks the scramble and the sample the point in the sequence
+            Sampler::Sobol => Self::Sobol(Sobol::new(
+                sample as u32,
+                splitmix64(seed ^ splitmix64(pixel)) as u32,
+            )),
+        }
+    }
+}
+
+impl MinRng for PixelRng {
+    fn gen(&mut self) -> f32 {
+        match self {
+            Self::Random(r) => MinRng::gen(r),
+            Self::Sobol(r) => r.gen(),
+        }
+    }
+    fn gen_range(&mut self, range: std::ops::Range<f32>) -> f32 {
+        match self {
+            Self::Random(r) => MinRng::gen_range(r, range),
+            Self::Sobol(r) => r.gen_range(range),
+        }
+    }
+}
+
+// recycles splat vectors between the receiver of Update::Calculation and the
+// compute threads to avoid allocating a new vector for every workgroup
+// a vector can only be reused once it has been handed back through recycle so
+// vectors still in flight are never reused
+#[derive(Clone, Default)]
+pub struct SplatPool(Arc<Mutex<Vec<Vec<Splat>>>>);
+
+impl SplatPool {
+    #[must_use]
+    pub fn get(&self, capacity: usize) -> Vec<Splat> {
+        let mut v = self.0.lock().unwrap().pop().unwrap_or_default();
+        v.reserve(capacity);
+        v
+    }
+    pub fn recycle(&self, mut v: Vec<Splat>) {
+        v.clear();
+        let mut pool = self.0.lock().unwrap();
+        if pool.len() < MAX_POOLED_VECS {
+            pool.push(v);
         }
     }
 }
@@ -65,7 +439,30 @@ impl State {
 #[derive(Clone)]
 pub enum WorkLoad {
     Pixels(std::ops::Range<u64>),
-    Mutations(u64),
+    // w x h rendered pixels from (x, y) for a single sample
+    Tile {
+        x: u64,
+        y: u64,
+        w: u64,
+        h: u64,
+        sample: u64,
+    },
+    // the rendered pixels pixels[range] for a single sample
+    Listed {
+        pixels: Arc<[u64]>,
+        range: std::ops::Range<u64>,
+        sample: u64,
+    },
+    // each range is a markov chain, one mutation per index
+    Mutations(std::ops::Range<u64>, Arc<Bootstrap>),
+}
+
+// paths traced from uniform random points in primary sample space, chains start
+// from one of these picked in proportion to its luminance
+pub struct Bootstrap {
+    distribution: Distribution1D,
+    // average luminance of the image
+    normalisation: f32,
 }
 
 struct WorkQueue {
@@ -142,16 +539,24 @@ impl WorkQueue {
 // ------------------------------
 pub fn create_work_handler(
     num_threads: Option<NonZeroUsize>,
-) -> (Receiver<Update>, Sender<ComputeChange>) {
+) -> (Receiver<Update>, Sender<ComputeChange>, SplatPool) {
     let (gui_thread_requester, compute_thread_request_handler) = channel::<ComputeChange>();
     let (update_sender, gui_thread_receiver) = channel::<Update>();
+    let splat_pool = SplatPool::default();
+    let thread_pool = splat_pool.clone();
 
     std::thread::spawn(move || {
         let mut state: Option<Arc<State>> = None;
 
         let mut work_queue = Arc::new(WorkQueue::default());
-        // for seeding RNG, changes with each work batch
+        // identifies each work batch
         let mut work_id = 0;
+        // samples per pixel already queued for the current state, sample indices keep
+        // counting up between requests so every sample gets a different rng
+        // (mutations per pixel with PSSMLT)
+        let mut samples_queued = 0;
+        // only done once per state
+        let mut bootstrap: Option<Arc<Bootstrap>> = None;
 
         // ------------------------------
         // Spawn compute threads
@@ -161,7 +566,12 @@ pub fn create_work_handler(
             .unwrap_or_else(num_cpus::get);
         log::trace!("Spawned {num_threads} compute threads.");
         for i in 0..num_threads {
-            spawn_compute_thread(i as u64, work_queue.clone(), update_sender.clone());
+            spawn_compute_thread(
+                i as u64,
+                work_queue.clone(),
+                update_sender.clone(),
+                thread_pool.clone(),
+            );
         }
         // ------------------------------
         // Change Handling loop
@@ -178,45 +588,133 @@ pub fn create_work_handler(
                         continue;
                     };
 
-                    let workgroup_size =
-                        MIN_WORKGROUP_SIZE.max(state.width as u64 * state.height as u64 / 256);
+                    let frame_pixels = state.pixels();
+                    let start = samples_queued * frame_pixels;
+                    let end = start + samples * frame_pixels;
+                    samples_queued += samples;
+                    let work: Box<dyn Iterator<Item = WorkLoad>> = match state.work_tile_size {
+                        Some(size) => {
+                            let (width, height) = state.rendered_size();
+                            Box::new(work_tiles(
+                                samples_queued - samples..samples_queued,
+                                width,
+                                height,
+                                size.get() as u64,
+                            ))
+                        }
+                        None => Box::new(
+                            workgroups(start..end, state.workgroup_size()).map(WorkLoad::Pixels),
+                        ),
+                    };
+                    let mut deque = VecDeque::new();
+                    for work in work {
+                        deque.push_back((work, state.clone(), work_id, workload_id));
+                        work_id += 1;
+                    }
+                    unsafe { WorkQueue::add_work(&mut work_queue, deque) };
+                }
+                ComputeChange::WorkPixels(samples, pixels, workload_id) => {
+                    let Some(ref state) = state else {
+                        update_sender.send(Update::NoState).unwrap();
+                        continue;
+                    };
 
-                    let mut pixels_start = 0;
-                    let end = samples * state.width as u64 * state.height as u64;
+                    // the sample indices are shared by the listed pixels and skipped by
+                    // the rest so each pixel sample still gets a different rng
                     let mut deque = VecDeque::new();
-                    while pixels_start < end {
-                        let pixels_end = (pixels_start + workgroup_size).min(end);
+                    for sample in samples_queued..samples_queued + samples {
+                        for range in workgroups(0..pixels.len() as u64, state.workgroup_size()) {
+                            let work = WorkLoad::Listed {
+                                pixels: pixels.clone(),
+                                range,
+                                sample,
+                            };
+                            deque.push_back((work, state.clone(), work_id, workload_id));
+                            work_id += 1;
+                        }
+                    }
+                    samples_queued += samples;
+                    unsafe { WorkQueue::add_work(&mut work_queue, deque) };
+                }
+                ComputeChange::WorkMutations(mutations, workload_id) => {
+                    let Some(ref state) = state else {
+                        update_sender.send(Update::NoState).unwrap();
+                        continue;
+                    };
+
+                    let bootstrap = bootstrap
+                        .get_or_insert_with(|| {
+                            let b = Arc::new(run_bootstrap(state, num_threads));
+                            if let Some(abort) = state.take_abort() {
+                                update_sender.send(abort).unwrap();
+                            }
+                            update_sender.send(Update::PssmltBootstrapDone).unwrap();
+                            b
+                        })
+                        .clone();
+
+                    let frame_pixels = state.pixels();
+                    let start = samples_queued * frame_pixels;
+                    let end = start + mutations * frame_pixels;
+                    samples_queued += mutations;
+                    let mut deque = VecDeque::new();
+                    for chain in workgroups(start..end, state.workgroup_size()) {
                         deque.push_back((
-                            WorkLoad::Pixels(pixels_start..pixels_end),
+                            WorkLoad::Mutations(chain, bootstrap.clone()),
                             state.clone(),
                             work_id,
                             workload_id,
                         ));
-
-                        pixels_start = pixels_end;
                         work_id += 1;
                     }
                     unsafe { WorkQueue::add_work(&mut work_queue, deque) };
                 }
-                ComputeChange::WorkMutations(_) => todo!(),
                 ComputeChange::UpdateState(new_state) => {
                     // clear out work queue before modifying state
                     unsafe { WorkQueue::clear(&mut work_queue) };
-                    match state.as_mut() {
-                        None => state = Some(Arc::new(new_state)),
-                        Some(ref mut old_state) => {
-                            // work is ignore from threads that are currently running
-                            // i.e. threads that currently hold an Arc<State>
-                            unsafe {
-                                *Arc::get_mut_unchecked(old_state) = new_state;
-                            }
-                        }
-                    }
+                    samples_queued = 0;
+                    bootstrap = None;
+                    // threads that are currently running keep their Arc of the old state
+                    // (and its primary cache) until their work is done
+                    state = Some(Arc::new(*new_state));
                 }
             }
         }
     });
-    (gui_thread_receiver, gui_thread_requester)
+    (gui_thread_receiver, gui_thread_requester, splat_pool)
+}
+
+// split range into consecutive ranges of at most workgroup_size
+fn workgroups(
+    range: std::ops::Range<u64>,
+    workgroup_size: u64,
+) -> impl Iterator<Item = std::ops::Range<u64>> {
+    let end = range.end;
+    range
+        .step_by(workgroup_size as usize)
+        .map(move |start| start..(start + workgroup_size).min(end))
+}
+
+// split each sample of a width x height image into tiles of at most size x size
+fn work_tiles(
+    samples: std::ops::Range<u64>,
+    width: u64,
+    height: u64,
+    size: u64,
+) -> impl Iterator<Item = WorkLoad> {
+    samples.flat_map(move |sample| {
+        (0..height).step_by(size as usize).flat_map(move |y| {
+            (0..width)
+                .step_by(size as usize)
+                .map(move |x| WorkLoad::Tile {
+                    x,
+                    y,
+                    w: size.min(width - x),
+                    h: size.min(height - y),
+                    sample,
+                })
+        })
+    })
 }
 
 // ------------------------------
@@ -226,6 +724,7 @@ fn spawn_compute_thread(
     thread_id: u64,
     work_stealer: Arc<WorkQueue>,
     update_sender: Sender<Update>,
+    splat_pool: SplatPool,
 ) {
     std::thread::spawn(move || {
         loop {
@@ -246,17 +745,37 @@ fn spawn_compute_thread(
                 FetchState::Busy => continue,
             };
 
-            let rng = Pcg64Mcg::new((state.base_rng_seed + work_id) as u128);
-
             let work_result = match work {
-                WorkLoad::Pixels(pixels) => work_pixels(pixels, rng, state.as_ref(), workload_id),
-                WorkLoad::Mutations(_) => todo!(),
+                WorkLoad::Pixels(pixels) => {
+                    let splats = splat_pool.get((pixels.end - pixels.start) as usize);
+                    work_pixels(pixels, state.as_ref(), workload_id, splats)
+                }
+                WorkLoad::Tile { x, y, w, h, sample } => {
+                    let splats = splat_pool.get((w * h) as usize);
+                    work_tile([x, y, w, h], sample, state.as_ref(), workload_id, splats)
+                }
+                WorkLoad::Listed {
+                    pixels,
+                    range,
+                    sample,
+                } => {
+                    let splats = splat_pool.get((range.end - range.start) as usize);
+                    let pixels = &pixels[range.start as usize..range.end as usize];
+                    work_listed(pixels, sample, state.as_ref(), workload_id, splats)
+                }
+                WorkLoad::Mutations(chain, bootstrap) => {
+                    let splats = splat_pool.get((chain.end - chain.start) as usize);
+                    work_mutations(chain, &bootstrap, state.as_ref(), workload_id, splats)
+                }
             };
 
             log::trace!(
                 "Thread {thread_id} finished work {work_id} as part of workload {workload_id}."
             );
             update_sender.send(work_result).unwrap();
+            if let Some(abort) = state.take_abort() {
+                update_sender.send(abort).unwrap();
+            }
             #[cfg(feature = "gui")]
             if let Some(ctx) = &state.ctx {
                 ctx.request_repaint();
@@ -267,30 +786,834 @@ fn spawn_compute_thread(
 
 fn work_pixels(
     pixels: std::ops::Range<u64>,
-    mut rng: Pcg64Mcg,
     state: &State,
     workload_id: u8,
+    mut splats: Vec<Splat>,
 ) -> Update {
-    let mut rays = 0;
-    let mut splats = Vec::with_capacity((pixels.end - pixels.start) as usize);
-
-    let frame_pixels = (state.width * state.height) as u64;
-    for pixel_i in pixels {
-        let pixel_i = pixel_i % frame_pixels;
-        let cam = unsafe { crate::CAM.get().as_mut_unchecked() };
-        let (uv, ray) = cam.get_ray(pixel_i, &mut rng);
-        let (col, ray_count) = match state.integrator {
-            IntegratorType::Naive => Naive::rgb(ray, &mut rng),
-            IntegratorType::NEE => {
-                NEEMIS::rgb(ray, &mut rng, unsafe { SAMPLABLE.get().as_ref_unchecked() })
-            }
+    let rays = pixels.map(|i| work_pixel(i, state, &mut splats)).sum();
+    Update::Calculation(splats, workload_id, rays)
+}
+
+// pixels x..x + w, y..y + h (of the rendered pixels) for one sample
+fn work_tile(
+    [x, y, w, h]: [u64; 4],
+    sample: u64,
+    state: &State,
+    workload_id: u8,
+    mut splats: Vec<Splat>,
+) -> Update {
+    let width = state.rendered_size().0;
+    let start = sample * state.pixels();
+    let rays = (y..y + h)
+        .flat_map(|py| (x..x + w).map(move |px| start + py * width + px))
+        .map(|i| work_pixel(i, state, &mut splats))
+        .sum();
+    Update::Calculation(splats, workload_id, rays)
+}
+
+// the rendered pixels in pixels for one sample
+fn work_listed(
+    pixels: &[u64],
+    sample: u64,
+    state: &State,
+    workload_id: u8,
+    mut splats: Vec<Splat>,
+) -> Update {
+    let start = sample * state.pixels();
+    let rays = pixels
+        .iter()
+        .map(|&i| work_pixel(start + i, state, &mut splats))
+        .sum();
+    Update::Calculation(splats, workload_id, rays)
+}
+
+// subpixel positions cached per pixel by --cache-primary when jittering
+const PRIMARY_CACHE_SAMPLES: u64 = 16;
+// mixed into the seed of the cached camera rays so they're independent of the paths
+const PRIMARY_STREAM: u64 = 0x9e37_79b9_7f4a_7c15;
+
+// traces the sample pixel_i / pixels of pixel pixel_i % pixels returning the rays shot
+fn work_pixel(pixel_i: u64, state: &State, splats: &mut Vec<Splat>) -> u64 {
+    let frame_pixels = state.pixels();
+    let sample = pixel_i / frame_pixels;
+    let pixel_i = pixel_i % frame_pixels;
+    let frame_pixel = state.frame_pixel(pixel_i);
+    let mut rng = PixelRng::new(state.sampler, state.base_rng_seed, frame_pixel, sample);
+    let cam = state.cam();
+    let camera_ray = |rng: &mut PixelRng| {
+        let jitter = if state.jitter {
+            [rng.gen(), rng.gen()]
+        } else {
+            [0.5; 2]
         };
-        splats.push(Splat::new(uv, col));
+        let lens = cam.sample_lens(rng);
+        let (uv, ray) = cam.get_lens_ray(frame_pixel, jitter, lens);
+        (state.tile.map_or(uv, |tile| tile.uv(pixel_i, jitter)), ray)
+    };
+    // a lens (depth of field) needs a new camera ray every sample
+    let (uv, ray, primary) = match state.primary_cache.as_ref().filter(|_| cam.aperture == 0.0) {
+        Some(cache) => {
+            // the samples of a pixel cycle through its cached subpixel positions, each
+            // is traced with its own rng so it doesn't matter which sample gets there first
+            let per_pixel = cache.len() as u64 / frame_pixels;
+            let slot = sample % per_pixel;
+            let hit = cache[(pixel_i * per_pixel + slot) as usize].get_or_init(|| {
+                let seed = state.base_rng_seed ^ PRIMARY_STREAM;
+                let mut rng = PixelRng::new(state.sampler, seed, frame_pixel, slot);
+                let (uv, ray) = camera_ray(&mut rng);
+                let sect = state.primary_intersection(&ray, &mut rng);
+                PrimaryHit { uv, ray, sect }
+            });
+            (hit.uv, hit.ray.clone(), Some(hit.sect.clone()))
+        }
+        None => {
+            let (uv, ray) = camera_ray(&mut rng);
+            let primary = state
+                .clip
+                .then(|| state.primary_intersection(&ray, &mut rng));
+            (uv, ray, primary)
+        }
+    };
+    let (col, ray_count) = state.radiance(ray, &mut rng, primary);
+    let col = state.sanitise(col, frame_pixel);
+    splats.push(Splat::new(uv, col, sample % 2 == 1));
+    ray_count
+}
+
+// streams of pixel_rng (in place of the sample index) used by PSSMLT
+const BOOTSTRAP_STREAM: u64 = u64::MAX;
+const CHAIN_STREAM: u64 = u64::MAX - 1;
+const MUTATION_STREAM: u64 = u64::MAX - 2;
+
+// a path through primary sample space, the first number picks the pixel and the
+// next two the position within it, the rest are used by the camera & integrator
+#[must_use]
+fn mlt_path(state: &State, rng: &mut PssState<Pcg64Mcg>) -> ([f32; 2], Vec3, u64) {
+    let frame_pixels = state.pixels();
+    let pixel_i = ((rng.gen() * frame_pixels as f32) as u64).min(frame_pixels - 1);
+    let frame_pixel = state.frame_pixel(pixel_i);
+    let jitter = [rng.gen(), rng.gen()];
+    let cam = state.cam();
+    let lens = cam.sample_lens(rng);
+    let (uv, ray) = cam.get_lens_ray(frame_pixel, jitter, lens);
+    let uv = state.tile.map_or(uv, |tile| tile.uv(pixel_i, jitter));
+    let primary = state.clip.then(|| state.primary_intersection(&ray, rng));
+    let (col, rays) = state.radiance(ray, rng, primary);
+    (uv, state.sanitise(col, frame_pixel), rays)
+}
+
+#[must_use]
+fn run_bootstrap(state: &State, num_threads: usize) -> Bootstrap {
+    let samples = state.pssmlt_bootstrap;
+    let chunk = samples.div_ceil(num_threads as u64).max(1);
+    let luminance: Vec<f32> = std::thread::scope(|s| {
+        let handles: Vec<_> = workgroups(0..samples, chunk)
+            .map(|range| {
+                s.spawn(move || {
+                    range
+                        .map(|i| {
+                            let mut rng =
+                                PssState::new(pixel_rng(state.base_rng_seed, i, BOOTSTRAP_STREAM));
+                            crate::luminance(mlt_path(state, &mut rng).1)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect()
+    });
+    let distribution = Distribution1D::new(&luminance);
+    Bootstrap {
+        normalisation: distribution.func_int / samples as f32,
+        distribution,
+    }
+}
+
+// primary sample space metropolis light transport (Kelemen et al. 2002) where each
+// mutation splats the current path with weight normalisation / luminance, output
+// divides by the splats (mutations) per pixel like with samples
+fn work_mutations(
+    chain: std::ops::Range<u64>,
+    bootstrap: &Bootstrap,
+    state: &State,
+    workload_id: u8,
+    mut splats: Vec<Splat>,
+) -> Update {
+    let mut rays = 0;
+    let seed = state.base_rng_seed;
+    let mut chain_rng = pixel_rng(seed, chain.start, CHAIN_STREAM);
+
+    // replay the bootstrap path then continue with the chain's own mutations
+    let start = bootstrap.distribution.sample(&mut chain_rng) as u64;
+    let mut rng = PssState::new(pixel_rng(seed, start, BOOTSTRAP_STREAM));
+    let (mut uv, mut col, ray_count) = mlt_path(state, &mut rng);
+    rng.reseed(pixel_rng(seed, chain.start, MUTATION_STREAM));
+    let mut lum = crate::luminance(col);
+    rays += ray_count;
+
+    for mutation in chain {
+        rng.start_iteration();
+        let (new_uv, new_col, ray_count) = mlt_path(state, &mut rng);
         rays += ray_count;
+        let new_lum = crate::luminance(new_col);
+        let accept = if lum > 0.0 {
+            (new_lum / lum).min(1.0)
+        } else {
+            1.0
+        };
+        if rand::Rng::gen::<f32>(&mut chain_rng) < accept {
+            rng.accept();
+            (uv, col, lum) = (new_uv, new_col, new_lum);
+        } else {
+            rng.reject();
+        }
+
+        let weight = if lum > 0.0 {
+            bootstrap.normalisation / lum
+        } else {
+            0.0
+        };
+        splats.push(Splat::new(uv, col * weight, mutation % 2 == 1));
     }
     Update::Calculation(splats, workload_id, rays)
 }
 
-fn work_mutations(_mutations: u64, _rng: Pcg64Mcg, _state: &State, _workload_id: u64) -> Update {
-    todo!()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::{ColorTransform, Primaries};
+    use std::ops::Range;
+    use utility::Vec3;
+
+    // the canvas (sum of splats) of samples of every pixel rendered by state
+    fn render_state(state: &State, samples: Range<u64>) -> Vec<Vec3> {
+        let pixels = state.pixels();
+        let (width, height) = (state.width as u32, state.height as u32);
+        let mut canvas = vec![Vec3::ZERO; state.width * state.height];
+        let range = samples.start * pixels..samples.end * pixels;
+        let Update::Calculation(splats, ..) = work_pixels(range, state, 0, Vec::new()) else {
+            panic!();
+        };
+        for splat in splats {
+            canvas[splat.pixel(width, height)] += splat.rgb;
+        }
+        canvas
+    }
+
+    // the canvas of samples of every pixel of a render with rs
+    fn render_canvas(rs: &RenderSettings, samples: Range<u64>) -> Vec<Vec3> {
+        let state = State::new(
+            rs,
+            #[cfg(feature = "gui")]
+            None,
+            0,
+            None,
+        );
+        render_state(&state, samples)
+    }
+
+    #[test]
+    fn splat_pool_reuse() {
+        let pool = SplatPool::default();
+        let mut a = pool.get(16);
+        a.push(Splat::new([0.0; 2], Vec3::ONE, false));
+        let ptr = a.as_ptr();
+
+        // b is allocated while a is still in use
+        let b = pool.get(16);
+        assert_ne!(b.as_ptr(), ptr);
+
+        pool.recycle(a);
+        let c = pool.get(16);
+        assert!(c.is_empty());
+        assert_eq!(c.as_ptr(), ptr);
+    }
+
+    #[test]
+    fn tiles_match_frame() {
+        crate::scene::test_scene();
+        let rs = RenderSettings {
+            width: std::num::NonZeroU32::new(16).unwrap(),
+            height: std::num::NonZeroU32::new(16).unwrap(),
+            integrator: IntegratorType::Naive,
+            seed: 7,
+            ..Default::default()
+        };
+        // 4 samples per pixel (in two requests for the whole frame)
+        let render = |tile: Option<Tile>, requests: &[Range<u64>]| {
+            let (width, height) = tile.map_or((16, 16), |t| (t.width, t.height));
+            let mut rs = rs.clone();
+            rs.width = std::num::NonZeroU32::new(width).unwrap();
+            rs.height = std::num::NonZeroU32::new(height).unwrap();
+            let state = State::new(
+                &rs,
+                #[cfg(feature = "gui")]
+                None,
+                0,
+                tile,
+            );
+            let mut canvas = vec![Vec3::ZERO; (width * height) as usize];
+            for samples in requests {
+                for (c, v) in canvas.iter_mut().zip(render_state(&state, samples.clone())) {
+                    *c += v;
+                }
+            }
+            canvas
+        };
+
+        let frame = render(None, &[0..1, 1..4]);
+        let tiles: Vec<_> = (0..4)
+            .map(|i| {
+                let tile = Tile::parse(&format!("{i}/4"), 16, 16).unwrap();
+                (tile, render(Some(tile), &[0..2, 2..4]))
+            })
+            .collect();
+        let tiles: Vec<_> = tiles.iter().map(|(t, c)| (*t, &c[..])).collect();
+        let stitched = crate::tile::stitch(&tiles);
+
+        assert!(frame.iter().any(|&v| v != Vec3::ZERO));
+        assert!(stitched == frame);
+
+        // a region only touches its own pixels and matches the frame there
+        let region = Tile::from_rect([3, 5, 11, 9], 16, 16).unwrap();
+        let state = State::new(
+            &rs,
+            #[cfg(feature = "gui")]
+            None,
+            0,
+            None,
+        )
+        .with_region(Some(region));
+        let canvas = render_state(&state, 0..4);
+        for i in 0..256 {
+            let (x, y) = (i % 16, i / 16);
+            let inside = (3..11).contains(&x) && (5..9).contains(&y);
+            assert_eq!(canvas[i], if inside { frame[i] } else { Vec3::ZERO });
+        }
+    }
+
+    #[test]
+    fn work_tiles_match_pixels() {
+        crate::scene::test_scene();
+        let rs = RenderSettings {
+            width: std::num::NonZeroU32::new(16).unwrap(),
+            height: std::num::NonZeroU32::new(12).unwrap(),
+            integrator: IntegratorType::Naive,
+            ..Default::default()
+        };
+        for region in [None, Some(Tile::from_rect([3, 2, 14, 9], 16, 12).unwrap())] {
+            let state = State::new(
+                &rs,
+                #[cfg(feature = "gui")]
+                None,
+                0,
+                None,
+            )
+            .with_region(region);
+            let add = |canvas: &mut Vec<Vec3>, update| {
+                let Update::Calculation(splats, ..) = update else {
+                    panic!();
+                };
+                for splat in splats {
+                    canvas[splat.pixel(16, 12)] += splat.rgb;
+                }
+            };
+
+            let pixels = render_state(&state, 0..3);
+
+            // every sample of every pixel exactly once (5 doesn't divide the size)
+            let (width, height) = state.rendered_size();
+            let mut tiles = vec![Vec3::ZERO; 16 * 12];
+            let mut covered = 0;
+            for work in work_tiles(0..3, width, height, 5) {
+                let WorkLoad::Tile { x, y, w, h, sample } = work else {
+                    unreachable!()
+                };
+                assert!(w <= 5 && h <= 5 && x + w <= width && y + h <= height);
+                covered += w * h;
+                add(
+                    &mut tiles,
+                    work_tile([x, y, w, h], sample, &state, 0, Vec::new()),
+                );
+            }
+            assert_eq!(covered, 3 * state.pixels());
+            assert!(pixels.iter().any(|&v| v != Vec3::ZERO));
+            assert!(tiles == pixels);
+
+            // listing every pixel (as adaptive sampling's passes do) is the same too
+            let all: Vec<u64> = (0..state.pixels()).collect();
+            let mut listed = vec![Vec3::ZERO; 16 * 12];
+            for sample in 0..3 {
+                add(
+                    &mut listed,
+                    work_listed(&all, sample, &state, 0, Vec::new()),
+                );
+            }
+            assert!(listed == pixels);
+        }
+    }
+
+    #[test]
+    fn headless_render() {
+        // the same path as headless mode (with or without the gui feature)
+        crate::scene::test_scene();
+        let rs = RenderSettings {
+            width: std::num::NonZeroU32::new(16).unwrap(),
+            height: std::num::NonZeroU32::new(16).unwrap(),
+            ..Default::default()
+        };
+        let (update_recv, work_req, splat_pool) =
+            create_work_handler(Some(NonZeroUsize::new(2).unwrap()));
+        let state = State::new(
+            &rs,
+            #[cfg(feature = "gui")]
+            None,
+            0,
+            None,
+        );
+        work_req
+            .send(ComputeChange::UpdateState(Box::new(state)))
+            .unwrap();
+        work_req.send(ComputeChange::WorkSamples(2, 0)).unwrap();
+
+        let mut canvas = vec![Vec3::ZERO; 16 * 16];
+        let mut splats_done = 0;
+        while splats_done < canvas.len() * 2 {
+            if let Update::Calculation(mut splats, 0, _) = update_recv.recv().unwrap() {
+                splats_done += splats.len();
+                for splat in splats.drain(..) {
+                    canvas[splat.pixel(16, 16)] += splat.rgb;
+                }
+                splat_pool.recycle(splats);
+            }
+        }
+        work_req.send(ComputeChange::Shutdown).unwrap();
+        assert_eq!(splats_done, canvas.len() * 2);
+        assert!(canvas.iter().all(|v| v.mag() > 0.0 && v.is_finite()));
+    }
+
+    #[test]
+    fn resumed_render() {
+        crate::scene::test_scene();
+        let rs = RenderSettings {
+            width: std::num::NonZeroU32::new(16).unwrap(),
+            height: std::num::NonZeroU32::new(16).unwrap(),
+            integrator: IntegratorType::Naive,
+            seed: 3,
+            ..Default::default()
+        };
+        let pixels = 16 * 16;
+        let render = |seed_offset: u64, samples: u64| {
+            let state = State::new(
+                &rs,
+                #[cfg(feature = "gui")]
+                None,
+                seed_offset,
+                None,
+            );
+            render_state(&state, 0..samples)
+        };
+
+        // interrupted after 8 samples & checkpointed
+        let path = crate::output::checkpoint_path(
+            std::env::temp_dir()
+                .join("yapt_resume_test.png")
+                .to_str()
+                .unwrap(),
+        );
+        assert!(path.ends_with("yapt_resume_test.checkpoint.exr"));
+        let rec2020 = ColorTransform::new(Primaries::Rec2020, Primaries::Rec2020);
+        let first = render(0, 8);
+        crate::output::save_exr(&first, 16, 16, 1.0, 8 * pixels, &path, &rec2020).unwrap();
+
+        // resumed for the remaining 24
+        let (mut canvas, splats) =
+            crate::output::load_accumulation(16, 16, &path, Primaries::Rec2020).unwrap();
+        assert_eq!(splats, 8 * pixels);
+        let rest = render(splats, 24);
+        assert!(
+            rest != render(0, 24),
+            "resumed samples repeat the first ones"
+        );
+        for (c, r) in canvas.iter_mut().zip(rest) {
+            *c += r;
+        }
+
+        // has the same mean as an uninterrupted render
+        let mean = |canvas: &[Vec3]| {
+            canvas
+                .iter()
+                .map(|&v| crate::luminance(v) as f64)
+                .sum::<f64>()
+                / canvas.len() as f64
+        };
+        let (resumed, full) = (mean(&canvas), mean(&render(0, 32)));
+        assert!((resumed / full - 1.0).abs() < 0.05, "{resumed} {full}");
+    }
+
+    #[test]
+    fn pssmlt_render() {
+        crate::scene::test_scene();
+        let rs = RenderSettings {
+            width: std::num::NonZeroU32::new(16).unwrap(),
+            height: std::num::NonZeroU32::new(16).unwrap(),
+            pssmlt: true,
+            pssmlt_bootstrap: 20_000,
+            ..Default::default()
+        };
+        let new_state = || {
+            State::new(
+                &rs,
+                #[cfg(feature = "gui")]
+                None,
+                0,
+                None,
+            )
+        };
+        let (update_recv, work_req, _) = create_work_handler(Some(NonZeroUsize::new(2).unwrap()));
+        work_req
+            .send(ComputeChange::UpdateState(Box::new(new_state())))
+            .unwrap();
+        work_req.send(ComputeChange::work(rs.pssmlt, 4, 0)).unwrap();
+        assert!(matches!(
+            update_recv.recv().unwrap(),
+            Update::PssmltBootstrapDone
+        ));
+
+        // every splat has the same luminance (the image average) or none
+        let mut splats_done = 0;
+        let mut lum = 0.0;
+        while splats_done < 16 * 16 * 4 {
+            if let Update::Calculation(splats, 0, _) = update_recv.recv().unwrap() {
+                splats_done += splats.len();
+                lum += splats
+                    .iter()
+                    .map(|s| crate::luminance(s.rgb) as f64)
+                    .sum::<f64>();
+            }
+        }
+        work_req.send(ComputeChange::Shutdown).unwrap();
+        assert_eq!(splats_done, 16 * 16 * 4);
+
+        // which matches the average of regular samples
+        let state = new_state();
+        let Update::Calculation(splats, ..) = work_pixels(0..16 * 16 * 64, &state, 0, Vec::new())
+        else {
+            unreachable!()
+        };
+        let expected = splats
+            .iter()
+            .map(|s| crate::luminance(s.rgb) as f64)
+            .sum::<f64>()
+            / splats.len() as f64;
+        let lum = lum / splats_done as f64;
+        assert!((lum / expected - 1.0).abs() < 0.1, "{lum} {expected}");
+    }
+
+    #[test]
+    fn pssmlt_seeded() {
+        crate::scene::test_scene();
+        // every splat of a 2 mutations per pixel PSSMLT render on two threads, sorted as
+        // the chains finish in any order
+        let render = |seed: u64| {
+            let rs = RenderSettings {
+                width: std::num::NonZeroU32::new(16).unwrap(),
+                height: std::num::NonZeroU32::new(16).unwrap(),
+                pssmlt: true,
+                pssmlt_bootstrap: 5_000,
+                seed,
+                ..Default::default()
+            };
+            let state = State::new(
+                &rs,
+                #[cfg(feature = "gui")]
+                None,
+                0,
+                None,
+            );
+            let (update_recv, work_req, _) =
+                create_work_handler(Some(NonZeroUsize::new(2).unwrap()));
+            work_req
+                .send(ComputeChange::UpdateState(Box::new(state)))
+                .unwrap();
+            work_req.send(ComputeChange::work(rs.pssmlt, 2, 0)).unwrap();
+            let mut image = Vec::new();
+            while image.len() < 16 * 16 * 2 {
+                if let Update::Calculation(splats, 0, _) = update_recv.recv().unwrap() {
+                    image.extend(splats.iter().map(|s| {
+                        (
+                            s.pixel(16, 16),
+                            [s.rgb.x, s.rgb.y, s.rgb.z].map(f32::to_bits),
+                        )
+                    }));
+                }
+            }
+            work_req.send(ComputeChange::Shutdown).unwrap();
+            image.sort_unstable();
+            image
+        };
+        // bootstrap, mutations & acceptance all follow the seed
+        let image = render(5);
+        assert!(image == render(5));
+        assert!(image != render(6));
+    }
+
+    #[test]
+    fn cache_primary() {
+        crate::scene::test_scene();
+        let render = |camera: Option<usize>, cache_primary: bool| {
+            let rs = RenderSettings {
+                width: std::num::NonZeroU32::new(16).unwrap(),
+                height: std::num::NonZeroU32::new(16).unwrap(),
+                integrator: IntegratorType::Naive,
+                no_jitter: true,
+                cache_primary,
+                seed: 3,
+                ..Default::default()
+            };
+            let state = State::new(
+                &rs,
+                #[cfg(feature = "gui")]
+                None,
+                0,
+                None,
+            )
+            .with_camera(camera);
+            render_state(&state, 0..4)
+        };
+        // without jitter every sample of a pixel has the same camera ray so caching it
+        // changes nothing
+        let uncached = render(None, false);
+        assert!(uncached.iter().any(|&v| v != Vec3::ZERO));
+        assert!(render(None, true) == uncached);
+        // cameras with a lens still sample it (keeping depth of field)
+        assert!(render(Some(2), true) == render(Some(2), false));
+    }
+
+    #[test]
+    fn cache_primary_jitter() {
+        crate::scene::test_scene();
+        let rs = |cache_primary| RenderSettings {
+            width: std::num::NonZeroU32::new(16).unwrap(),
+            height: std::num::NonZeroU32::new(16).unwrap(),
+            integrator: IntegratorType::Naive,
+            cache_primary,
+            seed: 3,
+            ..Default::default()
+        };
+        let state = |cache_primary| {
+            State::new(
+                &rs(cache_primary),
+                #[cfg(feature = "gui")]
+                None,
+                0,
+                None,
+            )
+        };
+        let sum = |a: Vec<Vec3>, b: Vec<Vec3>| -> Vec<Vec3> {
+            a.into_iter().zip(b).map(|(a, b)| a + b).collect()
+        };
+
+        // the same seeded image whichever samples fill the cache first
+        let cached = state(true);
+        let forwards = sum(render_state(&cached, 0..32), render_state(&cached, 32..64));
+        let cached = state(true);
+        let backwards = sum(render_state(&cached, 32..64), render_state(&cached, 0..32));
+        assert!(forwards == backwards);
+
+        // 64 samples per pixel only intersected 16 camera rays (once per slot)
+        let cache = cached.primary_cache.as_ref().unwrap();
+        assert_eq!(cache.len(), 256 * PRIMARY_CACHE_SAMPLES as usize);
+        assert!(cache.iter().all(|hit| hit.get().is_some()));
+
+        // the bias from reusing subpixel positions is negligible on the diffuse scene
+        let uncached = render_state(&state(false), 0..64);
+        let mean = |canvas: &[Vec3]| {
+            canvas
+                .iter()
+                .map(|&v| crate::luminance(v) as f64)
+                .sum::<f64>()
+                / canvas.len() as f64
+        };
+        let (cached, uncached) = (mean(&forwards), mean(&uncached));
+        assert!(
+            (cached / uncached - 1.0).abs() < 0.02,
+            "{cached} {uncached}"
+        );
+    }
+
+    #[test]
+    fn isolate() {
+        crate::scene::test_scene();
+        let render = |isolate: Option<&str>| {
+            let rs = RenderSettings {
+                width: std::num::NonZeroU32::new(16).unwrap(),
+                height: std::num::NonZeroU32::new(16).unwrap(),
+                integrator: IntegratorType::Naive,
+                isolate: isolate.map(str::to_owned),
+                seed: 5,
+                ..Default::default()
+            };
+            render_canvas(&rs, 0..16)
+        };
+        let full = render(None);
+
+        // the camera sees the floor with the blue light in front of it, isolating either
+        // keeps its pixels as they were (the same paths are traced) and the rest are black
+        let floor = render(Some("floor"));
+        let light = render(Some("blue_light"));
+        for i in 0..256 {
+            assert!(floor[i] == full[i] || floor[i] == Vec3::ZERO);
+            assert!(light[i] == full[i] || light[i] == Vec3::ZERO);
+            // every pixel sees at least one of the two meshes
+            assert!(floor[i] + light[i] != Vec3::ZERO);
+        }
+        let lit = |canvas: &[Vec3]| canvas.iter().filter(|&&v| v != Vec3::ZERO).count();
+        assert!(lit(&floor) > 0 && lit(&light) > 0);
+        assert!(lit(&floor) < 256 && lit(&light) < 256);
+        // only the light's pixels are blue without any red from the red light
+        assert!(light.iter().all(|v| v.x == 0.0));
+    }
+
+    #[test]
+    fn all_cameras() {
+        // one work handler rendering each camera in CAMERAS in turn
+        crate::scene::test_scene();
+        let rs = RenderSettings {
+            width: std::num::NonZeroU32::new(16).unwrap(),
+            height: std::num::NonZeroU32::new(16).unwrap(),
+            ..Default::default()
+        };
+        let (update_recv, work_req, splat_pool) =
+            create_work_handler(Some(NonZeroUsize::new(2).unwrap()));
+
+        let render = |camera: usize| {
+            let state = State::new(
+                &rs,
+                #[cfg(feature = "gui")]
+                None,
+                0,
+                None,
+            )
+            .with_camera(Some(camera));
+            let workload_id = camera as u8;
+            work_req
+                .send(ComputeChange::UpdateState(Box::new(state)))
+                .unwrap();
+            work_req
+                .send(ComputeChange::WorkSamples(1, workload_id))
+                .unwrap();
+            let mut canvas = vec![Vec3::ZERO; 16 * 16];
+            let mut splats_done = 0;
+            while splats_done < canvas.len() {
+                if let Update::Calculation(mut splats, id, _) = update_recv.recv().unwrap() {
+                    if id == workload_id {
+                        splats_done += splats.len();
+                        for splat in splats.drain(..) {
+                            canvas[splat.pixel(16, 16)] += splat.rgb;
+                        }
+                    }
+                    splat_pool.recycle(splats);
+                }
+            }
+            canvas
+        };
+        let (first, second) = (render(0), render(1));
+        work_req.send(ComputeChange::Shutdown).unwrap();
+
+        assert!(first != second);
+        // the second camera looks straight at the holdout
+        assert_eq!(second[8 * 16 + 8], Vec3::splat(0.5));
+    }
+
+    #[test]
+    fn nan_policy() {
+        let bad = Vec3::new(f32::NAN, 2.0, f32::INFINITY);
+        let before = NAN_SAMPLES.load(Ordering::Relaxed);
+        assert_eq!(NanPolicy::Drop.sanitise(bad, (3, 4)), Ok(Vec3::ZERO));
+        assert_eq!(
+            NanPolicy::Clamp.sanitise(bad, (3, 4)),
+            Ok(Vec3::new(0.0, 2.0, 0.0))
+        );
+        let e = NanPolicy::Abort.sanitise(bad, (3, 4)).unwrap_err();
+        assert!(e.contains("(3, 4)"), "{e}");
+        assert!(NAN_SAMPLES.load(Ordering::Relaxed) >= before + 3);
+
+        // good samples are untouched by every policy
+        let good = Vec3::new(0.5, 20.0, 0.0);
+        for policy in [NanPolicy::Drop, NanPolicy::Clamp, NanPolicy::Abort] {
+            assert_eq!(policy.sanitise(good, (0, 0)), Ok(good));
+        }
+
+        // workers drop the sample and hand the abort to the main thread once
+        let rs = RenderSettings {
+            width: std::num::NonZeroU32::new(16).unwrap(),
+            height: std::num::NonZeroU32::new(16).unwrap(),
+            on_nan: NanPolicy::Abort,
+            ..Default::default()
+        };
+        let state = State::new(
+            &rs,
+            #[cfg(feature = "gui")]
+            None,
+            0,
+            None,
+        );
+        assert!(state.take_abort().is_none());
+        assert_eq!(state.sanitise(bad, 16 * 4 + 3), Vec3::ZERO);
+        let Some(Update::Abort(e)) = state.take_abort() else {
+            panic!();
+        };
+        assert!(e.contains("(3, 4)"), "{e}");
+        assert!(state.take_abort().is_none());
+    }
+
+    #[test]
+    fn frame_seeds() {
+        use crate::pssmlt::MinRng;
+        const SAMPLES: u64 = 100_000;
+        // noise of a pixel in a static scene
+        let frame = |frame| -> Vec<f64> {
+            let seed = frame_seed(3, frame, false);
+            (0..SAMPLES)
+                .map(|sample| pixel_rng(seed, 0, sample).gen() as f64)
+                .collect()
+        };
+        let mean = |v: &[f64]| v.iter().sum::<f64>() / v.len() as f64;
+
+        let (a, b) = (frame(0), frame(1));
+        let (mean_a, mean_b) = (mean(&a), mean(&b));
+        assert!((mean_a - 0.5).abs() < 0.01 && (mean_b - 0.5).abs() < 0.01);
+
+        let cov: f64 = a
+            .iter()
+            .zip(&b)
+            .map(|(x, y)| (x - mean_a) * (y - mean_b))
+            .sum::<f64>()
+            / SAMPLES as f64;
+        let correlation = cov * 12.0;
+        assert!(correlation.abs() < 0.02, "correlation = {correlation}");
+
+        // seed + frame would alias with the next seed
+        assert_eq!(frame_seed(3, 0, false), 3);
+        assert_ne!(frame_seed(3, 1, false), 4);
+        assert_eq!(frame_seed(3, 7, true), 3);
+    }
+
+    #[test]
+    fn workgroup_coverage() {
+        let end = 3 * 17 * 13;
+        for size in [1, 2, 7, 64, end, 10 * end] {
+            let mut next = 5;
+            for range in workgroups(5..end, size) {
+                assert_eq!(range.start, next);
+                assert!(!range.is_empty() && range.end - range.start <= size);
+                next = range.end;
+            }
+            assert_eq!(next, end);
+        }
+    }
 }